@@ -1,9 +1,16 @@
 use clap::{App, Arg};
 use duna_core::arch::Architecture;
 use duna_core::architectures::riscv::{Rv32, Rv64};
-use duna_core::assembler::Linker;
-use duna_core::config::AsmConfig;
-use duna_core::program_state::{Program, ProgramExecutor};
+use duna_core::assembler::{Assembler, Linker};
+use duna_core::config::{AsmConfig, SegmentStarts};
+use duna_core::elf::{self, ElfFile};
+use duna_core::image::{self, ImageFormat};
+use duna_core::program_state::{
+    HartSchedule, Program, ProgramExecutor, StdinSource, Vfs, DEFAULT_QUANTUM, DEFAULT_SEED,
+    PIPE_CAPACITY,
+};
+use std::ascii;
+use std::fs;
 use std::io;
 use std::io::Write;
 use std::process;
@@ -24,37 +31,281 @@ fn main() {
                 .long("debugger")
                 .help("Launches the interactive command line debugger."),
         )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .value_name("FILE")
+                .help(
+                    "Writes the program to FILE instead of running it, as an ELF file unless \
+                     --format chooses another format. bin and readmemh write FILE.text and \
+                     FILE.data instead.",
+                ),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .requires("output")
+                .conflicts_with("object")
+                .possible_values(&["elf", "bin", "ihex", "readmemh"])
+                .help(
+                    "The format of the output file, elf by default. bin and readmemh write the \
+                     text and data segments to FILE.text and FILE.data.",
+                ),
+        )
+        .arg(
+            Arg::with_name("object")
+                .short("c")
+                .requires("output")
+                .help("Assembles the input file into a relocatable object without linking it."),
+        )
+        .arg(
+            Arg::with_name("entry")
+                .long("entry")
+                .takes_value(true)
+                .value_name("SYMBOL")
+                .help("The global label at which execution begins. Defaults to _start or main."),
+        )
+        .arg(
+            Arg::with_name("crt0")
+                .long("crt0")
+                .help("Links a startup routine that calls main and exits with its return value."),
+        )
+        .arg(
+            Arg::with_name("stdin")
+                .long("stdin")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Reads the standard input of the program from FILE instead of the terminal."),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .value_name("N")
+                .help("Seeds the random number generator that the getrandom syscall draws from."),
+        )
+        .arg(
+            Arg::with_name("quantum")
+                .long("quantum")
+                .takes_value(true)
+                .value_name("N")
+                .help(
+                    "The number of instructions a process runs before another is switched to, \
+                     in programs that fork.",
+                ),
+        )
+        .arg(
+            Arg::with_name("harts")
+                .long("harts")
+                .takes_value(true)
+                .value_name("N")
+                .help("The number of harts that run the program, which share its memory."),
+        )
+        .arg(
+            Arg::with_name("interleave")
+                .long("interleave")
+                .takes_value(true)
+                .requires("harts")
+                .possible_values(&["round-robin", "random"])
+                .help(
+                    "How harts take turns running instructions. random draws from a generator \
+                     seeded with --seed.",
+                ),
+        )
+        .arg(
+            Arg::with_name("mount")
+                .long("mount")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("DIR[:PATH]")
+                .help(
+                    "Copies the host directory DIR into the program's filesystem at PATH, or at \
+                     /mnt if no PATH is given. The copied files are read-only.",
+                ),
+        )
         .arg(
             // TODO allow using stdin
             Arg::with_name("INPUT")
-                .help("The input assembly file to run.")
+                .help("The input assembly files or ELF executable to run.")
                 .required(true)
                 .min_values(1)
                 .index(1),
         )
+        .arg(
+            Arg::with_name("ARGS")
+                .help(
+                    "Arguments passed to the program, preceded by any environment variables of \
+                     the form NAME=VALUE.",
+                )
+                .multiple(true)
+                .last(true),
+        )
         .get_matches();
-    let isa = matches.value_of("isa").unwrap();
+    let mut isa = matches.value_of("isa").unwrap().to_string();
     let mut file_names = matches.values_of("INPUT").unwrap();
     // min_values was set to 1, so this is guaranteed
     let main_path = file_names.next().unwrap();
+    // TODO expose the rest of this
+    let config = AsmConfig {
+        entry: matches.value_of("entry").map(str::to_string),
+        crt0: matches.is_present("crt0"),
+        ..Default::default()
+    };
+    let debug = matches.is_present("debugger");
+    // as with env(1), leading words of the form NAME=VALUE set environment variables
+    let mut env = Vec::new();
+    let mut args = vec![main_path.to_string()];
+    for arg in matches.values_of("ARGS").into_iter().flatten() {
+        if args.len() == 1 && arg.find('=').is_some_and(|i| i > 0) {
+            env.push(arg.to_string());
+        } else {
+            args.push(arg.to_string());
+        }
+    }
+    let stdin = match matches.value_of("stdin") {
+        Some(path) => {
+            if let Err(e) = fs::File::open(path) {
+                eprintln!("error: could not read {}: {}", path, e);
+                process::exit(1);
+            }
+            StdinSource::File(path.into())
+        }
+        None => StdinSource::Terminal,
+    };
+    let mut vfs = Vfs::new();
+    for mount in matches.values_of("mount").into_iter().flatten() {
+        let (dir, path) = match mount.rfind(':') {
+            Some(i) => (&mount[..i], &mount[i + 1..]),
+            None => (mount, "/mnt"),
+        };
+        vfs = vfs.with_host_dir(dir.as_ref(), path).unwrap_or_else(|e| {
+            eprintln!("error: could not read {}: {}", dir, e);
+            process::exit(1);
+        });
+    }
+    let seed = match matches.value_of("seed") {
+        Some(n) => n.parse().unwrap_or_else(|_| {
+            eprintln!("error: invalid seed {}", n);
+            process::exit(1);
+        }),
+        None => DEFAULT_SEED,
+    };
+    let quantum = match matches.value_of("quantum") {
+        Some(n) => match n.parse() {
+            Ok(q) if q > 0 => q,
+            _ => {
+                eprintln!("error: invalid quantum {}", n);
+                process::exit(1);
+            }
+        },
+        None => DEFAULT_QUANTUM,
+    };
+    let harts = match matches.value_of("harts") {
+        Some(n) => match n.parse() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                eprintln!("error: invalid number of harts {}", n);
+                process::exit(1);
+            }
+        },
+        None => 1,
+    };
+    let schedule = match matches.value_of("interleave") {
+        Some("random") => HartSchedule::Random { seed },
+        _ => HartSchedule::RoundRobin,
+    };
+    let run_args = RunArgs {
+        args,
+        env,
+        stdin,
+        vfs,
+        seed,
+        quantum,
+        harts,
+        schedule,
+    };
+    let main_bytes = fs::read(main_path).unwrap_or_else(|e| {
+        eprintln!("error: could not read {}: {}", main_path, e);
+        process::exit(1);
+    });
+    if elf::is_elf(&main_bytes) {
+        if file_names.next().is_some() {
+            eprintln!("error: an ELF executable cannot be run with other input files");
+            process::exit(1);
+        }
+        // infer the ISA from the file unless one was given explicitly
+        if matches.occurrences_of("isa") == 0 {
+            if let Ok(elf) = ElfFile::parse(&main_bytes) {
+                isa = elf::arch_name(elf.class, elf.machine);
+            }
+        }
+        match isa.as_str() {
+            "rv32" => run_or_repl(load_or_exit::<Rv32>(&config, &main_bytes), run_args, debug),
+            "rv64" => run_or_repl(load_or_exit::<Rv64>(&config, &main_bytes), run_args, debug),
+            _ => {
+                eprintln!("error: unsupported ELF architecture: {}", isa);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(out_path) = matches.value_of("output") {
+        let contents = String::from_utf8_lossy(&main_bytes);
+        let format = match matches.value_of("format") {
+            Some("bin") => Some(ImageFormat::Binary),
+            Some("ihex") => Some(ImageFormat::IntelHex),
+            Some("readmemh") => Some(ImageFormat::Readmemh),
+            _ => None,
+        };
+        let bytes = if matches.is_present("object") {
+            if file_names.next().is_some() {
+                eprintln!("error: -c takes only one input file");
+                process::exit(1);
+            }
+            let result = match isa.as_str() {
+                "rv32" => Assembler::assemble_object::<Rv32>(main_path, &contents, &config),
+                "rv64" => Assembler::assemble_object::<Rv64>(main_path, &contents, &config),
+                _ => panic!("invalid ISA: {}", isa),
+            };
+            result.unwrap_or_else(|errs| {
+                errs.report();
+                process::exit(1);
+            })
+        } else {
+            let mut linker = Linker::with_main(main_path);
+            for file in file_names {
+                linker = linker.with_file(file);
+            }
+            let starts = config.machine.segment_starts;
+            match (isa.as_str(), format) {
+                ("rv32", None) => link_or_exit::<Rv32>(config, linker).to_elf(),
+                ("rv64", None) => link_or_exit::<Rv64>(config, linker).to_elf(),
+                ("rv32", Some(format)) => {
+                    let program = link_or_exit::<Rv32>(config, linker);
+                    return write_images(&program, format, &starts, out_path);
+                }
+                ("rv64", Some(format)) => {
+                    let program = link_or_exit::<Rv64>(config, linker);
+                    return write_images(&program, format, &starts, out_path);
+                }
+                _ => panic!("invalid ISA: {}", isa),
+            }
+        };
+        write_or_exit(out_path, &bytes);
+        return;
+    }
     let mut linker = Linker::with_main(main_path);
     for file in file_names {
         linker = linker.with_file(file);
     }
-    // TODO expose this
-    let config: AsmConfig = Default::default();
-    if matches.is_present("debugger") {
-        match isa {
-            "rv32" => link_and_repl::<Rv32>(config, linker),
-            "rv64" => link_and_repl::<Rv64>(config, linker),
-            _ => panic!("invalid ISA: {}", isa),
-        }
-    } else {
-        match isa {
-            "rv32" => link_and_run::<Rv32>(config, linker),
-            "rv64" => link_and_run::<Rv64>(config, linker),
-            _ => panic!("invalid ISA: {}", isa),
-        }
+    match isa.as_str() {
+        "rv32" => run_or_repl(link_or_exit::<Rv32>(config, linker), run_args, debug),
+        "rv64" => run_or_repl(link_or_exit::<Rv64>(config, linker), run_args, debug),
+        _ => panic!("invalid ISA: {}", isa),
     }
 }
 
@@ -69,17 +320,112 @@ fn link_or_exit<A: Architecture>(config: AsmConfig, linker: Linker) -> Program<A
     }
 }
 
+fn load_or_exit<A: Architecture>(config: &AsmConfig, bytes: &[u8]) -> Program<A> {
+    match Program::<A>::from_elf(bytes, &config.machine) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn write_or_exit(path: &str, bytes: &[u8]) {
+    if let Err(e) = fs::write(path, bytes) {
+        eprintln!("error: could not write {}: {}", path, e);
+        process::exit(1);
+    }
+}
+
+// Writes the text and data segments of the program as memory images.
+fn write_images<A: Architecture>(
+    program: &Program<A>,
+    format: ImageFormat,
+    starts: &SegmentStarts,
+    out_path: &str,
+) {
+    let images = program.mem_images(starts).and_then(|images| {
+        let [text, data] = &images;
+        let files = match format {
+            ImageFormat::Binary => vec![
+                (format!("{}.text", out_path), text.bytes.clone()),
+                (format!("{}.data", out_path), data.bytes.clone()),
+            ],
+            ImageFormat::Readmemh => vec![
+                (
+                    format!("{}.text", out_path),
+                    text.to_readmemh().into_bytes(),
+                ),
+                (
+                    format!("{}.data", out_path),
+                    data.to_readmemh().into_bytes(),
+                ),
+            ],
+            ImageFormat::IntelHex => {
+                let hex = image::to_intel_hex(&images, Some(program.entry()))?;
+                vec![(out_path.to_string(), hex.into_bytes())]
+            }
+        };
+        Ok(files)
+    });
+    match images {
+        Ok(files) => {
+            for (path, bytes) in files {
+                write_or_exit(&path, &bytes);
+            }
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// What the program is run with.
+struct RunArgs {
+    args: Vec<String>,
+    env: Vec<String>,
+    stdin: StdinSource,
+    vfs: Vfs,
+    seed: u64,
+    quantum: u64,
+    harts: u32,
+    schedule: HartSchedule,
+}
+
+fn run_or_repl<A: Architecture>(program: Program<A>, run_args: RunArgs, debug: bool) {
+    let program = program
+        .with_stdin(run_args.stdin)
+        .with_vfs(run_args.vfs)
+        .with_random_seed(run_args.seed)
+        .with_quantum(run_args.quantum)
+        .with_harts(run_args.harts, run_args.schedule)
+        .with_args(run_args.args, run_args.env);
+    if debug {
+        repl(program)
+    } else {
+        run(program)
+    }
+}
+
 // Runs the program to completion.
-fn link_and_run<A: Architecture>(config: AsmConfig, linker: Linker) {
-    let mut program = link_or_exit::<A>(config, linker);
+fn run<A: Architecture>(mut program: Program<A>) {
     let prog_exit_code = program.run();
     println!("Program exited with code {}", prog_exit_code);
 }
 
+// What the debugger shows the registers of.
+enum Selected {
+    Process(u32),
+    Hart(u32),
+}
+
 // Runs a gdb-like repl.
-fn link_and_repl<A: Architecture>(config: AsmConfig, linker: Linker) {
-    let mut executor = ProgramExecutor::<A>::new(link_or_exit(config, linker));
+fn repl<A: Architecture>(program: Program<A>) {
+    let mut executor = ProgramExecutor::<A>::new(program);
     let mut exited = false;
+    // the process or hart whose registers are shown, which is the running one if none was selected
+    let mut selected = None;
     println!("Running debugger.");
     while !exited {
         print!("> ");
@@ -107,13 +453,86 @@ fn link_and_repl<A: Architecture>(config: AsmConfig, linker: Linker) {
                 executor.step_to_completion(1000);
             }
             "info registers" | "i r" | "i registers" | "info r" => {
-                println!("{}", executor.program.state.regfile())
+                let state = &executor.program.state;
+                let user_state = match selected {
+                    Some(Selected::Process(pid)) => state.process_user_state(pid),
+                    Some(Selected::Hart(id)) => state.hart_user_state(id),
+                    None => None,
+                };
+                match user_state {
+                    Some(user_state) => println!("{}", user_state.regfile),
+                    None => println!("{}", state.regfile()),
+                }
+            }
+            "info processes" | "i p" | "i processes" | "info p" => {
+                let state = &executor.program.state;
+                for (pid, info) in state.processes() {
+                    let pc = state.process_user_state(pid).unwrap().pc;
+                    let marker = if pid == state.running_pid() { "*" } else { " " };
+                    println!(
+                        "{} {:>3} (parent {}) {} @ {}",
+                        marker, pid, info.ppid, info.status, pc
+                    );
+                }
+            }
+            "info pipes" | "i pi" | "i pipes" | "info pi" => {
+                for (id, pipe) in executor.program.state.pipes().iter().enumerate() {
+                    let contents: String = pipe
+                        .contents
+                        .iter()
+                        .flat_map(|&byte| ascii::escape_default(byte))
+                        .map(char::from)
+                        .collect();
+                    println!(
+                        "{:>3} ({} readers, {} writers) {}/{} \"{}\"",
+                        id,
+                        pipe.readers,
+                        pipe.writers,
+                        pipe.contents.len(),
+                        PIPE_CAPACITY,
+                        contents
+                    );
+                }
+            }
+            "info sections" | "i s" | "i sections" | "info s" => {
+                println!("{}", executor.program.layout())
+            }
+            "disas" | "disassemble" => {
+                let text = executor.program.layout().text;
+                print!("{}", executor.program.disassemble(text.start, text.end()))
             }
             // Because we called trim(), no need for new newline
             "" => (),
             "q" | "quit" => {
                 exited = true;
             }
+            _ if input.starts_with("process ") => {
+                let state = &executor.program.state;
+                match input["process ".len()..].trim().parse() {
+                    Ok(pid) if state.process_user_state(pid).is_some() => {
+                        selected = Some(Selected::Process(pid));
+                    }
+                    _ => println!("No such process."),
+                }
+            }
+            "info harts" | "i h" | "i harts" | "info h" => {
+                let state = &executor.program.state;
+                for (id, halted) in state.harts() {
+                    let pc = state.hart_user_state(id).unwrap().pc;
+                    let marker = if id == state.running_hart() { "*" } else { " " };
+                    let status = if halted { "halted" } else { "running" };
+                    println!("{} {:>3} {} @ {}", marker, id, status, pc);
+                }
+            }
+            _ if input.starts_with("hart ") => {
+                let state = &executor.program.state;
+                match input["hart ".len()..].trim().parse() {
+                    Ok(id) if state.hart_user_state(id).is_some() => {
+                        selected = Some(Selected::Hart(id));
+                    }
+                    _ => println!("No such hart."),
+                }
+            }
             _ => println!("Unrecognized commands."),
         }
     }
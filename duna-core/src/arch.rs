@@ -1,21 +1,30 @@
 //! Defines a few traits needed to add support for a new architecture to duna.
 use crate::{
-    assembler::parser::InstParser, data_structures::DataWidth, instruction::ConcreteInst,
+    assembler::parser::InstParser,
+    data_structures::DataWidth,
+    instruction::{ConcreteInst, InstDecoder},
     program_state::*,
 };
 
 /// Represents an architecture including word size, e.g. "x86-64" or "riscv-32".
 pub trait Architecture: Sized {
+    /// A short name for the architecture, e.g. "rv32".
+    const NAME: &'static str;
+    /// The value of the e_machine field of ELF files for the architecture.
+    const ELF_MACHINE: u16;
+    /// The assembly source of a startup routine that calls main and exits with its return value,
+    /// if one is available for this architecture.
+    const CRT0: Option<&'static str> = None;
     type DataWidth: DataWidth;
     type Family: ArchFamily<Self::DataWidth>;
     type ProgramBehavior: ProgramBehavior<Self::Family, Self::DataWidth>;
     type InstParser: InstParser<Self::Family, Self::DataWidth>;
+    type InstDecoder: InstDecoder<Self::Family, Self::DataWidth>;
 }
 
 /// Represents an architecture family parametrized over a word size, e.g. "x86" or "riscv".
 pub trait ArchFamily<S: DataWidth>: Sized {
     type Register: IRegister + 'static;
     type Instruction: ConcreteInst<Self, S>;
-    // TODO make SyscallConvention dynamically modifiable
     type Syscalls: SyscallConvention<Self, S>;
 }
@@ -1,4 +1,7 @@
-use super::{instruction::*, parser::MipsInstParser, program::*, registers::MipsRegister};
+use super::{
+    decoder::MipsInstDecoder, instruction::*, parser::MipsInstParser, program::*,
+    registers::MipsRegister,
+};
 use crate::{arch::*, data_structures::*};
 use std::marker::PhantomData;
 
@@ -15,8 +18,11 @@ impl<S: AtLeast32b> ArchFamily<S> for Mips<S> {
 pub struct Mips32;
 
 impl Architecture for Mips32 {
+    const NAME: &'static str = "mips32";
+    const ELF_MACHINE: u16 = 8;
     type DataWidth = W32b;
     type Family = Mips<W32b>;
     type ProgramBehavior = MipsProgramBehavior<W32b>;
     type InstParser = MipsInstParser<W32b>;
+    type InstDecoder = MipsInstDecoder<W32b>;
 }
@@ -0,0 +1,43 @@
+//! Decodes MIPS machine code back into instructions.
+use super::{arch::Mips, instruction::*, isa::*, registers::MipsRegister};
+use crate::{
+    data_structures::*,
+    instruction::{IllegalInstruction, InstDecoder},
+};
+use std::marker::PhantomData;
+
+pub struct MipsInstDecoder<S: AtLeast32b> {
+    _phantom: PhantomData<S>,
+}
+
+impl<S: AtLeast32b> InstDecoder<Mips<S>, S> for MipsInstDecoder<S> {
+    fn decode(code: u32) -> Result<MipsInst<S>, IllegalInstruction> {
+        let field = |high: u32, low: u32| (code >> low) & ((1 << (high - low + 1)) - 1);
+        let reg = |high: u32, low: u32| MipsRegister::from(field(high, low) as u8);
+        let (rs, rt, rd) = (reg(25, 21), reg(20, 16), reg(15, 11));
+        // Only R-type instructions are implemented, which have a zero opcode and shamt
+        match (field(31, 26), field(10, 6), field(5, 0)) {
+            (0, 0, 0x20) => Ok(Add::new(rd, rs, rt)),
+            _ => Err(IllegalInstruction { code }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::ConcreteInst;
+    use MipsRegister::*;
+
+    #[test]
+    fn test_decode() {
+        let inst: MipsInst<W32b> = Add::new(T0, A0, A1);
+        let code = inst.to_machine_code();
+        assert_eq!(code, 0x0085_4020);
+        assert_eq!(MipsInstDecoder::<W32b>::decode(code), Ok(inst));
+        assert_eq!(
+            MipsInstDecoder::<W32b>::decode(0x0085_4021),
+            Err(IllegalInstruction { code: 0x0085_4021 })
+        );
+    }
+}
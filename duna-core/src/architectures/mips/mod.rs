@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 mod arch;
+mod decoder;
 mod exception;
 mod instruction;
 mod isa;
@@ -8,3 +9,4 @@ mod program;
 mod registers;
 
 pub use arch::*;
+pub use decoder::MipsInstDecoder;
@@ -56,6 +56,11 @@ impl InstParser<Mips<W32b>, W32b> for MipsInstParser<W32b> {
         &REG_EXPANSION_TABLE
     }
 
+    // TODO use the canonical sll $zero, $zero, 0 once shifts are implemented
+    fn nop() -> MipsInst<W32b> {
+        super::isa::Add::new(MipsRegister::Zero, MipsRegister::Zero, MipsRegister::Zero)
+    }
+
     fn try_expand_found_inst(
         state: MipsInstParseState<'_, W32b>,
         parse_type: &ParseType<W32b>,
@@ -15,6 +15,15 @@ impl ProgramBehavior<Mips<W32b>, W32b> for MipsProgramBehavior<W32b> {
     fn return_register() -> MipsRegister {
         MipsRegister::V0
     }
+
+    fn ra_register() -> MipsRegister {
+        MipsRegister::Ra
+    }
+
+    fn arg_registers() -> Vec<MipsRegister> {
+        use MipsRegister::*;
+        vec![A0, A1, A2, A3]
+    }
 }
 
 lazy_static! {
@@ -28,9 +37,28 @@ lazy_static! {
             (4004, Write),
             (4005, Open),
             (4006, Close),
+            (4019, Lseek),
+            (4108, Fstat),
+            (4041, Dup),
+            (4063, Dup2),
+            (4327, Dup3),
+            (4328, Pipe2),
+            (4288, Openat),
             (4001, Exit),
+            (4246, ExitGroup),
+            (4078, Gettimeofday),
+            (4263, ClockGettime),
+            (4353, Getrandom),
+            (4122, Uname),
+            (4020, Getpid),
+            (4064, Getppid),
+            (4002, Fork),
+            (4120, Clone),
+            (4114, Wait4),
+            (4162, SchedYield),
             (4045, Brk),
             (4090, Mmap),
+            (4091, Munmap),
         ]
         .iter()
         .cloned()
@@ -71,6 +99,56 @@ impl<S: AtLeast32b> SyscallConvention<Mips<S>, S> for MipsSyscallConvention<S> {
     fn syscall_return_regs() -> Vec<MipsRegister> {
         vec![MipsRegister::V0, MipsRegister::V1]
     }
+
+    fn stack_pointer_reg() -> MipsRegister {
+        MipsRegister::Sp
+    }
+
+    /// See https://github.com/torvalds/linux/blob/master/arch/mips/include/uapi/asm/fcntl.h.
+    fn open_flags(raw: u64) -> OpenFlags {
+        OpenFlags {
+            append: raw & 0x0008 != 0,
+            create: raw & 0x0100 != 0,
+            trunc: raw & 0x0200 != 0,
+            excl: raw & 0x0400 != 0,
+            directory: raw & 0x10000 != 0,
+            nonblock: raw & 0x0080 != 0,
+            ..OpenFlags::from_generic(raw & 0o3)
+        }
+    }
+
+    /// Lays out the struct stat of the o32 ABI.
+    /// See https://github.com/torvalds/linux/blob/master/arch/mips/include/uapi/asm/stat.h.
+    fn stat_bytes(stat: &Stat) -> Vec<u8> {
+        let mut bytes = vec![0; 144];
+        let mut put = |offs: usize, field: u32| {
+            bytes[offs..offs + 4].copy_from_slice(&field.to_le_bytes());
+        };
+        put(16, stat.ino as u32);
+        put(20, stat.mode);
+        put(24, stat.nlink);
+        put(48, stat.size as u32);
+        put(80, stat.blksize);
+        put(84, stat.blocks as u32);
+        bytes
+    }
+
+    /// The o32 ABI has 32-bit timestamps.
+    fn time_bytes(secs: u64, frac: u64) -> Vec<u8> {
+        [(secs as u32).to_le_bytes(), (frac as u32).to_le_bytes()].concat()
+    }
+
+    fn machine_name() -> &'static str {
+        "mips"
+    }
+
+    /// See https://github.com/torvalds/linux/blob/master/arch/mips/include/uapi/asm/mman.h.
+    fn map_flags(raw: u64) -> MapFlags {
+        MapFlags {
+            anonymous: raw & 0x0800 != 0,
+            ..MapFlags::from_generic(raw & 0x1f)
+        }
+    }
 }
 
 /// Addresses of control registers implemented by the MIPS architecture. These are the same subset
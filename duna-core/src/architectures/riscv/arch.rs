@@ -1,4 +1,7 @@
-use super::{instruction::*, parser::RiscVInstParser, program::*, registers::RiscVRegister};
+use super::{
+    decoder::RiscVInstDecoder, instruction::*, parser::RiscVInstParser, program::*,
+    registers::RiscVRegister,
+};
 use crate::{arch::*, data_structures::*};
 use std::marker::PhantomData;
 
@@ -15,17 +18,25 @@ impl<S: AtLeast32b> ArchFamily<S> for RiscV<S> {
 pub struct Rv32;
 
 impl Architecture for Rv32 {
+    const NAME: &'static str = "rv32";
+    const ELF_MACHINE: u16 = 243;
+    const CRT0: Option<&'static str> = Some(include_str!("crt0_32.s"));
     type DataWidth = W32b;
     type Family = RiscV<W32b>;
     type ProgramBehavior = RiscVProgramBehavior<W32b>;
     type InstParser = RiscVInstParser<W32b>;
+    type InstDecoder = RiscVInstDecoder<W32b>;
 }
 
 pub struct Rv64;
 
 impl Architecture for Rv64 {
+    const NAME: &'static str = "rv64";
+    const ELF_MACHINE: u16 = 243;
+    const CRT0: Option<&'static str> = Some(include_str!("crt0_64.s"));
     type DataWidth = W64b;
     type Family = RiscV<W64b>;
     type ProgramBehavior = RiscVProgramBehavior<W64b>;
     type InstParser = RiscVInstParser<W64b>;
+    type InstDecoder = RiscVInstDecoder<W64b>;
 }
@@ -0,0 +1,22 @@
+# Calls main(argc, argv, envp) and exits with the value it returns.
+# On entry, sp points to argc, which is followed by the null-terminated argv and envp arrays.
+.globl _start
+.globl main
+_start:
+    ld a0, 0(sp)
+    addi a1, sp, 8
+    # envp begins after the null pointer that ends argv
+    slli t0, a0, 3
+    add a2, a1, t0
+    addi a2, a2, 8
+    call main
+    li a7, 93
+    ecall
+
+# Failed syscalls store their error number here, as the C library does. Programs may define
+# their own errno instead.
+.bss
+.weak errno
+.align 2
+errno:
+    .zero 4
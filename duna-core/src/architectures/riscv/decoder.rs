@@ -0,0 +1,550 @@
+//! Decodes RISC-V machine code back into instructions.
+use super::{arch::RiscV, instruction::*, isa::*, registers::RiscVRegister};
+use crate::{
+    data_structures::*,
+    elf::*,
+    instruction::{IllegalInstruction, InstDecoder, Relocation},
+};
+use std::marker::PhantomData;
+
+pub struct RiscVInstDecoder<S: AtLeast32b> {
+    _phantom: PhantomData<S>,
+}
+
+impl InstDecoder<RiscV<W32b>, W32b> for RiscVInstDecoder<W32b> {
+    fn decode(code: u32) -> Result<RiscVInst<W32b>, IllegalInstruction> {
+        decode_base(Word(code)).ok_or(IllegalInstruction { code })
+    }
+
+    fn relocation(code: u32, prev: Option<u32>) -> Option<Relocation> {
+        relocation(Word(code), prev.map(Word))
+    }
+}
+
+impl InstDecoder<RiscV<W64b>, W64b> for RiscVInstDecoder<W64b> {
+    fn decode(code: u32) -> Result<RiscVInst<W64b>, IllegalInstruction> {
+        decode_base(Word(code))
+            .or_else(|| decode_rv64(Word(code)))
+            .ok_or(IllegalInstruction { code })
+    }
+
+    fn relocation(code: u32, prev: Option<u32>) -> Option<Relocation> {
+        relocation(Word(code), prev.map(Word))
+    }
+}
+
+type RegRegFn<S> = fn(RiscVRegister, RiscVRegister, RiscVRegister) -> RiscVInst<S>;
+type RegImmFn<S> = fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>;
+
+const OPCODE_LUI: u32 = 0b011_0111;
+const OPCODE_AUIPC: u32 = 0b001_0111;
+const OPCODE_JAL: u32 = 0b110_1111;
+const OPCODE_JALR: u32 = 0b110_0111;
+const OPCODE_BRANCH: u32 = 0b110_0011;
+const OPCODE_LOAD: u32 = 0b000_0011;
+const OPCODE_STORE: u32 = 0b010_0011;
+const OPCODE_ARITH_IMM: u32 = 0b001_0011;
+const OPCODE_ARITH_IMM_W: u32 = 0b001_1011;
+const OPCODE_ARITH: u32 = 0b011_0011;
+const OPCODE_ARITH_W: u32 = 0b011_1011;
+const OPCODE_AMO: u32 = 0b010_1111;
+const OPCODE_FENCE: u32 = 0b000_1111;
+const OPCODE_SYSTEM: u32 = 0b111_0011;
+/// The only system instruction besides ecall with a funct3 of zero that is supported.
+const EBREAK: u32 = 0x0010_0073;
+
+/// Chooses the relocation for an instruction that references a label. A load, store, or addi
+/// following an auipc for the same label takes the lower half of the auipc's pc-relative offset.
+fn relocation(word: Word, prev: Option<Word>) -> Option<Relocation> {
+    // masks that clear the immediate of each format
+    const KEEP_U: u32 = 0x0000_0FFF;
+    const KEEP_I: u32 = 0x000F_FFFF;
+    const KEEP_SB: u32 = 0x01FF_F07F;
+    let after_auipc = prev.is_some_and(|prev| prev.opcode() == OPCODE_AUIPC);
+    let (r_type, keep) = match word.opcode() {
+        OPCODE_BRANCH => (R_RISCV_BRANCH, KEEP_SB),
+        OPCODE_JAL => (R_RISCV_JAL, KEEP_U),
+        OPCODE_AUIPC => (R_RISCV_PCREL_HI20, KEEP_U),
+        OPCODE_LUI => (R_RISCV_HI20, KEEP_U),
+        OPCODE_LOAD | OPCODE_ARITH_IMM | OPCODE_ARITH_IMM_W | OPCODE_JALR if after_auipc => {
+            (R_RISCV_PCREL_LO12_I, KEEP_I)
+        }
+        OPCODE_LOAD | OPCODE_ARITH_IMM | OPCODE_ARITH_IMM_W | OPCODE_JALR => {
+            (R_RISCV_LO12_I, KEEP_I)
+        }
+        OPCODE_STORE if after_auipc => (R_RISCV_PCREL_LO12_S, KEEP_SB),
+        OPCODE_STORE => (R_RISCV_LO12_S, KEEP_SB),
+        _ => return None,
+    };
+    Some(Relocation {
+        r_type,
+        code: word.0 & keep,
+        refers_to_prev: matches!(r_type, R_RISCV_PCREL_LO12_I | R_RISCV_PCREL_LO12_S),
+    })
+}
+
+/// An instruction word, with accessors for the fields of each format.
+#[derive(Copy, Clone)]
+struct Word(u32);
+
+impl Word {
+    fn bits(self, high: u32, low: u32) -> u32 {
+        (self.0 >> low) & ((1 << (high - low + 1)) - 1)
+    }
+
+    fn opcode(self) -> u32 {
+        self.bits(6, 0)
+    }
+
+    fn funct3(self) -> u32 {
+        self.bits(14, 12)
+    }
+
+    fn funct7(self) -> u32 {
+        self.bits(31, 25)
+    }
+
+    fn rd(self) -> RiscVRegister {
+        RiscVRegister::from(self.bits(11, 7) as u8)
+    }
+
+    fn rs1(self) -> RiscVRegister {
+        RiscVRegister::from(self.bits(19, 15) as u8)
+    }
+
+    fn rs2(self) -> RiscVRegister {
+        RiscVRegister::from(self.bits(24, 20) as u8)
+    }
+
+    fn i_imm<S: AtLeast32b>(self) -> RegValue<S> {
+        sign_ext(self.bits(31, 20), 12)
+    }
+
+    fn s_imm<S: AtLeast32b>(self) -> RegValue<S> {
+        sign_ext((self.bits(31, 25) << 5) | self.bits(11, 7), 12)
+    }
+
+    fn b_imm<S: AtLeast32b>(self) -> RegValue<S> {
+        sign_ext(
+            (self.bits(31, 31) << 12)
+                | (self.bits(7, 7) << 11)
+                | (self.bits(30, 25) << 5)
+                | (self.bits(11, 8) << 1),
+            13,
+        )
+    }
+
+    fn u_imm<S: AtLeast32b>(self) -> RegValue<S> {
+        sign_ext(self.bits(31, 12), 20)
+    }
+
+    fn j_imm<S: AtLeast32b>(self) -> RegValue<S> {
+        sign_ext(
+            (self.bits(31, 31) << 20)
+                | (self.bits(19, 12) << 12)
+                | (self.bits(20, 20) << 11)
+                | (self.bits(30, 21) << 1),
+            21,
+        )
+    }
+}
+
+fn sign_ext<S: AtLeast32b>(value: u32, len: u8) -> RegValue<S> {
+    SignedValue::<S>::from(BitStr32::new(value, len)).into()
+}
+
+/// Decodes instructions that are present in both RV32 and RV64.
+fn decode_base<S: AtLeast32b>(w: Word) -> Option<RiscVInst<S>> {
+    let (rd, rs1, rs2) = (w.rd(), w.rs1(), w.rs2());
+    let inst = match (w.opcode(), w.funct3()) {
+        (OPCODE_LUI, _) => Lui::new(rd, w.u_imm()),
+        (OPCODE_AUIPC, _) => Auipc::new(rd, w.u_imm()),
+        (OPCODE_JAL, _) => Jal::new(rd, w.j_imm()),
+        (OPCODE_JALR, 0b000) => Jalr::new(rd, rs1, w.i_imm()),
+        (OPCODE_BRANCH, f3) => {
+            let ctor: RegImmFn<S> = match f3 {
+                0b000 => Beq::new,
+                0b001 => Bne::new,
+                0b100 => Blt::new,
+                0b101 => Bge::new,
+                0b110 => Bltu::new,
+                0b111 => Bgeu::new,
+                _ => return None,
+            };
+            ctor(rs1, rs2, w.b_imm())
+        }
+        (OPCODE_LOAD, f3) => {
+            let ctor: RegImmFn<S> = match f3 {
+                0b000 => Lb::new,
+                0b001 => Lh::new,
+                0b010 => Lw::new,
+                0b100 => Lbu::new,
+                0b101 => Lhu::new,
+                _ => return None,
+            };
+            ctor(rd, rs1, w.i_imm())
+        }
+        (OPCODE_STORE, f3) => {
+            let ctor: RegImmFn<S> = match f3 {
+                0b000 => Sb::new,
+                0b001 => Sh::new,
+                0b010 => Sw::new,
+                _ => return None,
+            };
+            ctor(rs1, rs2, w.s_imm())
+        }
+        (OPCODE_ARITH_IMM, f3 @ (0b001 | 0b101)) => {
+            // RV64 shift amounts take up the lowest bit of funct7
+            let (shamt, f6) = (w.bits(25, 20), w.bits(31, 26));
+            if S::is_32() && shamt >= 32 {
+                return None;
+            }
+            let ctor: RegImmFn<S> = match (f6, f3) {
+                (0b00_0000, 0b001) => Slli::new,
+                (0b00_0000, 0b101) => Srli::new,
+                (0b01_0000, 0b101) => Srai::new,
+                _ => return None,
+            };
+            ctor(rd, rs1, sign_ext(shamt, 6))
+        }
+        (OPCODE_ARITH_IMM, f3) => {
+            let ctor: RegImmFn<S> = match f3 {
+                0b000 => Addi::new,
+                0b010 => Slti::new,
+                0b011 => Sltiu::new,
+                0b100 => Xori::new,
+                0b110 => Ori::new,
+                0b111 => Andi::new,
+                _ => unreachable!(),
+            };
+            ctor(rd, rs1, w.i_imm())
+        }
+        (OPCODE_ARITH, f3) => {
+            let ctor: RegRegFn<S> = match (w.funct7(), f3) {
+                (0b000_0000, 0b000) => Add::new,
+                (0b010_0000, 0b000) => Sub::new,
+                (0b000_0000, 0b001) => Sll::new,
+                (0b000_0000, 0b010) => Slt::new,
+                (0b000_0000, 0b011) => Sltu::new,
+                (0b000_0000, 0b100) => Xor::new,
+                (0b000_0000, 0b101) => Srl::new,
+                (0b010_0000, 0b101) => Sra::new,
+                (0b000_0000, 0b110) => Or::new,
+                (0b000_0000, 0b111) => And::new,
+                (0b000_0001, 0b000) => Mul::new,
+                (0b000_0001, 0b001) => Mulh::new,
+                (0b000_0001, 0b010) => Mulhsu::new,
+                (0b000_0001, 0b011) => Mulhu::new,
+                (0b000_0001, 0b100) => Div::new,
+                (0b000_0001, 0b101) => Divu::new,
+                (0b000_0001, 0b110) => Rem::new,
+                (0b000_0001, 0b111) => Remu::new,
+                _ => return None,
+            };
+            ctor(rd, rs1, rs2)
+        }
+        (OPCODE_AMO, 0b010) => {
+            let funct5 = w.bits(31, 27);
+            if funct5 == LR_FUNCT5 && w.bits(24, 20) != 0 {
+                return None;
+            }
+            let ctor: RegRegFn<S> = match (funct5, w.bits(26, 25)) {
+                (0b0_0010, 0b00) => Lrw::new,
+                (0b0_0010, 0b10) => Lrw::new_aq,
+                (0b0_0010, 0b01) => Lrw::new_rl,
+                (0b0_0010, 0b11) => Lrw::new_aqrl,
+                (0b0_0011, 0b00) => Scw::new,
+                (0b0_0011, 0b10) => Scw::new_aq,
+                (0b0_0011, 0b01) => Scw::new_rl,
+                (0b0_0011, 0b11) => Scw::new_aqrl,
+                (0b0_0001, 0b00) => Amoswapw::new,
+                (0b0_0001, 0b10) => Amoswapw::new_aq,
+                (0b0_0001, 0b01) => Amoswapw::new_rl,
+                (0b0_0001, 0b11) => Amoswapw::new_aqrl,
+                (0b0_0000, 0b00) => Amoaddw::new,
+                (0b0_0000, 0b10) => Amoaddw::new_aq,
+                (0b0_0000, 0b01) => Amoaddw::new_rl,
+                (0b0_0000, 0b11) => Amoaddw::new_aqrl,
+                _ => return None,
+            };
+            ctor(rd, rs1, rs2)
+        }
+        (OPCODE_FENCE, 0b000) => Fence::new(rd, rs1, w.i_imm()),
+        (OPCODE_FENCE, 0b001) => FenceI::new(rd, rs1, w.i_imm()),
+        (OPCODE_SYSTEM, 0b000) if w.0 == OPCODE_SYSTEM => <Ecall as SystemInst<S>>::new(),
+        (OPCODE_SYSTEM, 0b000) if w.0 == EBREAK => <Ebreak as SystemInst<S>>::new(),
+        (OPCODE_SYSTEM, f3) => {
+            let ctor: RegImmFn<S> = match f3 {
+                0b001 => Csrrw::new,
+                0b010 => Csrrs::new,
+                0b011 => Csrrc::new,
+                0b101 => Csrrwi::new,
+                0b110 => Csrrsi::new,
+                0b111 => Csrrci::new,
+                _ => return None,
+            };
+            ctor(rd, rs1, w.i_imm())
+        }
+        _ => return None,
+    };
+    Some(inst)
+}
+
+/// Decodes instructions that only exist in RV64.
+fn decode_rv64(w: Word) -> Option<RiscVInst<W64b>> {
+    let (rd, rs1, rs2) = (w.rd(), w.rs1(), w.rs2());
+    let inst = match (w.opcode(), w.funct3()) {
+        (OPCODE_LOAD, 0b011) => Ld::new(rd, rs1, w.i_imm()),
+        (OPCODE_LOAD, 0b110) => Lwu::new(rd, rs1, w.i_imm()),
+        (OPCODE_STORE, 0b011) => Sd::new(rs1, rs2, w.s_imm()),
+        (OPCODE_ARITH_IMM_W, 0b000) => Addiw::new(rd, rs1, w.i_imm()),
+        (OPCODE_ARITH_IMM_W, f3) => {
+            let ctor: RegImmFn<W64b> = match (w.funct7(), f3) {
+                (0b000_0000, 0b001) => Slliw::new,
+                (0b000_0000, 0b101) => Srliw::new,
+                (0b010_0000, 0b101) => Sraiw::new,
+                _ => return None,
+            };
+            ctor(rd, rs1, sign_ext(w.bits(24, 20), 6))
+        }
+        (OPCODE_ARITH_W, f3) => {
+            let ctor: RegRegFn<W64b> = match (w.funct7(), f3) {
+                (0b000_0000, 0b000) => Addw::new,
+                (0b010_0000, 0b000) => Subw::new,
+                (0b000_0000, 0b001) => Sllw::new,
+                (0b000_0000, 0b101) => Srlw::new,
+                (0b010_0000, 0b101) => Sraw::new,
+                (0b000_0001, 0b000) => Mulw::new,
+                (0b000_0001, 0b100) => Divw::new,
+                (0b000_0001, 0b101) => Divuw::new,
+                (0b000_0001, 0b110) => Remw::new,
+                (0b000_0001, 0b111) => Remuw::new,
+                _ => return None,
+            };
+            ctor(rd, rs1, rs2)
+        }
+        _ => return None,
+    };
+    Some(inst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arch::*,
+        architectures::riscv::{Rv32, Rv64},
+        assembler::Assembler,
+        instruction::ConcreteInst,
+    };
+    use RiscVRegister::*;
+
+    /// Assembles PROGRAM_TEXT, checking that decoding each instruction produces the original, and
+    /// that its disassembly reassembles to the same machine code.
+    fn check_round_trip<A: Architecture<Family = RiscV<S>, DataWidth = S>, S: AtLeast32b>(
+        program_text: &str,
+    ) {
+        let (unlinked, reporter) = Assembler::assemble_str::<A>(0, program_text);
+        assert!(reporter.is_empty());
+        let program = unlinked.try_into_program(&Default::default());
+        for inst in program.insts() {
+            let code = inst.to_machine_code();
+            let decoded = A::InstDecoder::decode(code).unwrap();
+            assert_eq!(&decoded, inst);
+            assert_eq!(format!("{}", decoded), format!("{}", inst));
+        }
+        check_reassembly::<A, S>(program_text);
+    }
+
+    /// Assembles PROGRAM, then checks that reassembling the disassembly of each instruction
+    /// produces the same machine code.
+    fn check_reassembly<A: Architecture<Family = RiscV<S>, DataWidth = S>, S: AtLeast32b>(
+        program: &str,
+    ) {
+        let (unlinked, reporter) = Assembler::assemble_str::<A>(0, program);
+        assert!(reporter.is_empty());
+        let program = unlinked.try_into_program(&Default::default());
+        for inst in program.insts() {
+            let code = inst.to_machine_code();
+            let text = format!("{}", A::InstDecoder::decode(code).unwrap());
+            let (unlinked, reporter) = Assembler::assemble_str::<A>(0, &text);
+            assert!(reporter.is_empty(), "{}", text);
+            let reassembled = unlinked.try_into_program(&Default::default());
+            let codes: Vec<u32> = reassembled
+                .insts()
+                .iter()
+                .map(|inst| inst.to_machine_code())
+                .collect();
+            assert_eq!(codes, vec![code], "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_reassemble_shifts() {
+        check_reassembly::<Rv32, W32b>("slli a0, ra, 31\nsrli a0, ra, 1\nsrai a0, ra, 31");
+        check_reassembly::<Rv64, W64b>(
+            "
+            slli a0, ra, 32
+            srli a0, ra, 63
+            srai a0, ra, 32
+            srai a0, ra, 1
+            slliw a0, ra, 31
+            sraiw a0, ra, 31
+            ",
+        );
+        // the shift amount is shown without the bits of funct6 above it
+        assert_eq!(
+            format!("{}", RiscVInstDecoder::<W64b>::decode(0x4200_d513).unwrap()),
+            "srai a0, ra, 32"
+        );
+    }
+
+    #[test]
+    fn test_round_trip_rv32() {
+        check_round_trip::<Rv32, W32b>(
+            "
+            start:
+            lui a0, 0xFEDCB
+            auipc t0, 0x80000
+            jal ra, start
+            jalr t1, -4(a0)
+            beq a0, a1, start
+            bne a0, a1, end
+            blt s0, s1, start
+            bge s0, s1, start
+            bltu s0, s1, start
+            bgeu s0, s1, start
+            lb a0, -1(sp)
+            lh a0, 2(sp)
+            lw a0, 2047(sp)
+            lbu a0, -2048(sp)
+            lhu a0, 0(sp)
+            sb a0, -1(sp)
+            sh a0, 2(sp)
+            sw a0, -2048(sp)
+            addi a0, a0, -1
+            slti a0, a0, 5
+            sltiu a0, a0, 5
+            xori a0, a0, -1
+            ori a0, a0, 0x7ff
+            andi a0, a0, 0xff
+            slli a0, a0, 31
+            srli a0, a0, 1
+            srai a0, a0, 7
+            add a0, a1, a2
+            sub a0, a1, a2
+            sll a0, a1, a2
+            slt a0, a1, a2
+            sltu a0, a1, a2
+            xor a0, a1, a2
+            srl a0, a1, a2
+            sra a0, a1, a2
+            or a0, a1, a2
+            and a0, a1, a2
+            mul a0, a1, a2
+            mulh a0, a1, a2
+            mulhsu a0, a1, a2
+            mulhu a0, a1, a2
+            div a0, a1, a2
+            divu a0, a1, a2
+            rem a0, a1, a2
+            remu a0, a1, a2
+            fence rw, w
+            fence.i
+            lr.w a0, (a1)
+            lr.w.aq t0, (sp)
+            sc.w a0, a2, (a1)
+            sc.w.rl a0, a2, (a1)
+            amoswap.w.aqrl a0, a2, (a1)
+            amoadd.w a0, zero, (a1)
+            csrrw a0, a1, 0x340
+            csrrs a0, zero, 0xC00
+            csrrc a0, a1, 0x340
+            csrrwi a0, 0x340, 31
+            csrrsi a0, 0xC00, 0
+            csrrci a0, 0x340, 1
+            ebreak
+            end:
+            ecall
+            ",
+        );
+    }
+
+    #[test]
+    fn test_round_trip_rv64() {
+        check_round_trip::<Rv64, W64b>(
+            "
+            slli a0, a0, 63
+            srai a0, a0, 32
+            ld a0, -8(sp)
+            lwu a0, 4(sp)
+            sd a0, 16(sp)
+            addiw a0, a0, -1
+            slliw a0, a0, 31
+            srliw a0, a0, 1
+            sraiw a0, a0, 3
+            addw a0, a1, a2
+            subw a0, a1, a2
+            sllw a0, a1, a2
+            srlw a0, a1, a2
+            sraw a0, a1, a2
+            mulw a0, a1, a2
+            divw a0, a1, a2
+            divuw a0, a1, a2
+            remw a0, a1, a2
+            remuw a0, a1, a2
+            ",
+        );
+    }
+
+    #[test]
+    fn test_decode_known() {
+        // addi a0, t5, -5
+        assert_eq!(
+            RiscVInstDecoder::<W32b>::decode(0xFFBF_0513),
+            Ok(Addi::new(A0, T5, DataLword::from(-5)))
+        );
+        // jal ra, -256
+        assert_eq!(
+            RiscVInstDecoder::<W32b>::decode(0xF01F_F0EF),
+            Ok(Jal::new(Ra, DataLword::from(-256)))
+        );
+        // fence iorw, iorw
+        assert_eq!(
+            format!("{}", RiscVInstDecoder::<W32b>::decode(0x0FF0_000F).unwrap()),
+            "fence iorw,iorw"
+        );
+    }
+
+    #[test]
+    fn test_decode_illegal() {
+        for code in [
+            0x0000_0000, // all zeroes is defined to be illegal
+            0xFFFF_FFFF,
+            0x0000_200F, // MISC-MEM with an unused funct3
+            0x3020_0073, // mret is unsupported
+            0x4000_1033, // sll with bad funct7
+        ] {
+            assert_eq!(
+                RiscVInstDecoder::<W32b>::decode(code),
+                Err(IllegalInstruction { code })
+            );
+            assert_eq!(
+                RiscVInstDecoder::<W64b>::decode(code),
+                Err(IllegalInstruction { code })
+            );
+        }
+        // RV64-only instructions
+        for code in [
+            0x0205_1513, // slli a0, a0, 32
+            0xFF81_3503, // ld a0, -8(sp)
+            0x00C5_853B, // addw a0, a1, a2
+        ] {
+            assert_eq!(
+                RiscVInstDecoder::<W32b>::decode(code),
+                Err(IllegalInstruction { code })
+            );
+            assert!(RiscVInstDecoder::<W64b>::decode(code).is_ok());
+        }
+    }
+}
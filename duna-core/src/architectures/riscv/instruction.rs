@@ -1,5 +1,5 @@
 #![allow(clippy::new_ret_no_self)]
-use super::{arch::*, registers::RiscVRegister};
+use super::{arch::*, isa::CSR_NAMES, registers::RiscVRegister};
 use crate::{data_structures::*, instruction::ConcreteInst, program_state::*};
 use std::fmt;
 
@@ -184,17 +184,116 @@ impl<S: AtLeast32b> fmt::LowerHex for RiscVInst<S> {
     }
 }
 
+impl<S: AtLeast32b> RiscVInst<S> {
+    /// Returns the mask of the bits that hold the amount of an immediate shift.
+    fn shamt_mask(&self) -> u32 {
+        if S::is_32() {
+            0x1F
+        } else {
+            0x3F
+        }
+    }
+}
+
 impl<S: AtLeast32b> fmt::Display for RiscVInst<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use InstFields::*;
+        let mut name = self.data.name.to_string();
         let args = match self.data.fields {
+            // atomic instructions name their ordering bits and take their address last
+            R {
+                ref fields,
+                rd,
+                rs1,
+                rs2,
+            } if fields.opcode.as_u32() == AMO_OPCODE => {
+                let funct7 = fields.funct7.as_u32();
+                name += ["", ".rl", ".aq", ".aqrl"][(funct7 & 0b11) as usize];
+                if funct7 >> 2 == LR_FUNCT5 {
+                    format!("{}, ({})", rd, rs1)
+                } else {
+                    format!("{}, {}, ({})", rd, rs2, rs1)
+                }
+            }
             R { rd, rs1, rs2, .. } => format!("{}, {}, {}", rd, rs1, rs2),
+            // ecall and ebreak take no arguments, and CSR instructions name the CSR before the
+            // source, which is an unsigned immediate held in rs1 for those ending in "i"
+            I {
+                ref fields,
+                rd,
+                rs1,
+                imm,
+            } if fields.opcode.as_u32() == 0b111_0011 => match fields.funct3.as_u32() {
+                0b000 => String::new(),
+                f3 if f3 >= 0b101 => format!("{}, {}, {}", rd, csr_name(imm), rs1 as u32),
+                _ => format!("{}, {}, {}", rd, csr_name(imm), rs1),
+            },
+            // fence names its predecessor and successor sets, and fence.i takes no arguments
+            I {
+                ref fields, imm, ..
+            } if fields.opcode.as_u32() == 0b000_1111 => {
+                if fields.funct3.as_u32() == 0b000 {
+                    let imm = imm.as_u32();
+                    format!("{},{}", fence_set(imm >> 4), fence_set(imm))
+                } else {
+                    String::new()
+                }
+            }
+            // shifts hold funct6 or funct7 above the shift amount
+            I {
+                ref fields,
+                rd,
+                rs1,
+                imm,
+            } if matches!(fields.opcode.as_u32(), 0b001_0011 | 0b001_1011)
+                && matches!(fields.funct3.as_u32(), 0b001 | 0b101) =>
+            {
+                format!("{}, {}, {}", rd, rs1, imm.as_u32() & self.shamt_mask())
+            }
+            // loads and jalr take an offset from rs1, as stores do
+            I {
+                ref fields,
+                rd,
+                rs1,
+                imm,
+            } if matches!(fields.opcode.as_u32(), 0b000_0011 | 0b110_0111) => {
+                format!("{}, {}({})", rd, i32::from(imm), rs1)
+            }
             I { rd, rs1, imm, .. } => format!("{}, {}, {}", rd, rs1, i32::from(imm)),
             S { rs1, rs2, imm, .. } => format!("{}, {}({})", rs2, i32::from(imm), rs1),
             B { rs1, rs2, imm, .. } => format!("{}, {}, {}", rs1, rs2, i32::from(imm)),
             U { rd, imm, .. } | J { rd, imm, .. } => format!("{}, {}", rd, i32::from(imm)),
         };
-        write!(f, "{} {}", self.data.name, args)
+        if args.is_empty() {
+            write!(f, "{}", name)
+        } else {
+            write!(f, "{} {}", name, args)
+        }
+    }
+}
+
+/// Returns the name of a CSR if it has one, or its number otherwise.
+fn csr_name(csr: BitStr32) -> String {
+    let csr = csr.as_usize();
+    match CSR_NAMES.iter().find(|(_, number)| *number == csr) {
+        Some((name, _)) => name.to_string(),
+        None => format!("{:#x}", csr),
+    }
+}
+
+/// Returns the letters of the accesses in the lower four bits of a fence's predecessor or
+/// successor set, e.g. "rw".
+fn fence_set(set: u32) -> String {
+    let accesses: String = "iorw"
+        .chars()
+        .enumerate()
+        .filter(|(i, _)| set & (0b1000 >> i) != 0)
+        .map(|(_, c)| c)
+        .collect();
+    if accesses.is_empty() {
+        "0".to_string()
+    } else {
+        accesses
     }
 }
 
@@ -227,6 +326,73 @@ pub trait RType<S: AtLeast32b> {
     fn eval(rs1_val: RegValue<S>, rs2_val: RegValue<S>) -> RegValue<S>;
 }
 
+/// The opcode of the instructions from the A extension.
+pub(crate) const AMO_OPCODE: u32 = 0b010_1111;
+/// The upper five bits of the funct7 of lr, which has no rs2.
+pub(crate) const LR_FUNCT5: u32 = 0b0_0010;
+
+/// Atomic memory operations, which are encoded like R-type instructions but both read and write
+/// memory. The lowest two bits of funct7 are the aq and rl bits, which are set by the
+/// constructors that end in their names.
+pub trait AmoType<S: AtLeast32b> {
+    fn new(rd: RiscVRegister, rs1: RiscVRegister, rs2: RiscVRegister) -> RiscVInst<S> {
+        Self::with_order(rd, rs1, rs2, 0b00)
+    }
+
+    fn new_aq(rd: RiscVRegister, rs1: RiscVRegister, rs2: RiscVRegister) -> RiscVInst<S> {
+        Self::with_order(rd, rs1, rs2, 0b10)
+    }
+
+    fn new_rl(rd: RiscVRegister, rs1: RiscVRegister, rs2: RiscVRegister) -> RiscVInst<S> {
+        Self::with_order(rd, rs1, rs2, 0b01)
+    }
+
+    fn new_aqrl(rd: RiscVRegister, rs1: RiscVRegister, rs2: RiscVRegister) -> RiscVInst<S> {
+        Self::with_order(rd, rs1, rs2, 0b11)
+    }
+
+    /// Creates an instance with the aq and rl bits given by ORDER.
+    fn with_order(
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        rs2: RiscVRegister,
+        order: u32,
+    ) -> RiscVInst<S> {
+        let RInstFields {
+            funct7,
+            funct3,
+            opcode,
+        } = Self::inst_fields();
+        RiscVInst {
+            eval: Box::new(move |state| Self::eval(state, rd, rs1, rs2)),
+            data: InstData::new(
+                Self::name(),
+                InstFields::R {
+                    fields: RInstFields {
+                        funct7: BitStr32::new(funct7.as_u32() | order, 7),
+                        funct3,
+                        opcode,
+                    },
+                    rd,
+                    rs1,
+                    rs2,
+                },
+            ),
+        }
+    }
+
+    fn name() -> &'static str;
+
+    fn inst_fields() -> RInstFields;
+
+    fn eval(
+        state: &ProgramState<RiscV<S>, S>,
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        rs2: RiscVRegister,
+    ) -> InstResult<RiscV<S>, S>;
+}
+
 pub trait IType<S: AtLeast32b> {
     /// Creates an instance of the IType istruction.
     fn new(rd: RiscVRegister, rs1: RiscVRegister, imm: RegValue<S>) -> RiscVInst<S> {
@@ -412,7 +578,7 @@ pub trait UType<S: AtLeast32b> {
 
 pub trait JType<S: AtLeast32b> {
     fn new(rd: RiscVRegister, imm: RegValue<S>) -> RiscVInst<S> {
-        let imm_vec = imm.to_bit_str(20);
+        let imm_vec = imm.to_bit_str(21);
         RiscVInst {
             eval: Box::new(move |state| Ok(Self::eval(&state.user_state, rd, imm_vec))),
             data: InstData::new(
@@ -437,7 +603,7 @@ pub trait JType<S: AtLeast32b> {
 #[cfg(test)]
 mod tests {
     use super::{
-        super::isa::{Addi, Nop},
+        super::isa::{Addi, Csrrwi, Ebreak, Fence, Nop, Rdcycle},
         *,
     };
     use crate::data_structures::W32b;
@@ -459,5 +625,18 @@ mod tests {
             format!("{:x}", &Addi::new(A0, T5, DataLword::from(-5))),
             "0xffbf0513"
         );
+        assert_eq!(format!("{}", <Ebreak as SystemInst<W32b>>::new()), "ebreak");
+        assert_eq!(
+            format!("{}", Fence::new(Zero, Zero, DataLword::from(0x31))),
+            "fence rw,w"
+        );
+        assert_eq!(
+            format!("{}", &Rdcycle::expand::<W32b>(A0)),
+            "csrrs a0, cycle, zero"
+        );
+        assert_eq!(
+            format!("{}", &Csrrwi::expand::<W32b>(A0, 0xFF.into(), 3.into())),
+            "csrrwi a0, 0xff, 3"
+        );
     }
 }
@@ -0,0 +1,228 @@
+//! Instructions from the A (atomic) extension that operate on words.
+//!
+//! All these instructions follow R-type encodings, with the address in rs1. Since harts take
+//! turns running whole instructions, each is atomic without further work, and every memory
+//! access is sequentially consistent whether or not the aq and rl bits are set.
+use super::f3;
+use crate::{
+    architectures::riscv::{instruction::*, RiscV, RiscVRegister},
+    data_structures::*,
+    program_state::*,
+};
+
+const A_OPCODE: BitStr32 = BitStr32::new(AMO_OPCODE, 7);
+
+/// Returns the fields of a word-sized instruction with the given funct5 and no ordering bits.
+fn amo_fields(funct5: u32) -> RInstFields {
+    RInstFields {
+        funct7: BitStr32::new(funct5 << 2, 7),
+        funct3: f3(0b010),
+        opcode: A_OPCODE,
+    }
+}
+
+/// Returns the address held in rs1, which must be aligned to a word.
+fn word_addr<S: AtLeast32b>(
+    state: &ProgramState<RiscV<S>, S>,
+    rs1: RiscVRegister,
+) -> Result<ByteAddrValue<S>, TermCause> {
+    let addr: ByteAddrValue<S> = state.user_state.regfile.read(rs1).into();
+    MemFault::check_aligned::<W32b>(addr)?;
+    Ok(addr)
+}
+
+/// Replaces the word at the address in rs1 with OP applied to it and the lower word of rs2,
+/// and writes the word that was replaced to rd, sign-extended.
+fn amo<S: AtLeast32b>(
+    state: &ProgramState<RiscV<S>, S>,
+    rd: RiscVRegister,
+    rs1: RiscVRegister,
+    rs2: RiscVRegister,
+    op: fn(u32, u32) -> u32,
+) -> InstResult<RiscV<S>, S> {
+    let addr = word_addr(state, rs1)?;
+    let (old, mut diffs) = state.memory_get::<W32b>(addr)?;
+    let rs2_val = state.user_state.regfile.read(rs2).lower_lword();
+    let new = DataLword::from(op(u32::from(old), u32::from(rs2_val)));
+    diffs.extend(state.memory_set_unsized(addr, DataEnum::Lword(new))?);
+    diffs.extend(state.release_reservations(addr.bits(), 4));
+    diffs.extend(UserDiff::reg_write_pc_p4(
+        &state.user_state,
+        rd,
+        RegValue::<S>::sign_ext_from_lword(old),
+    ));
+    Ok(diffs)
+}
+
+/// Load reserved
+/// Loads the word at the address in rs1, and reserves it for a later sc.w by the same hart.
+pub struct Lrw;
+impl<S: AtLeast32b> AmoType<S> for Lrw {
+    fn name() -> &'static str {
+        "lr.w"
+    }
+
+    fn inst_fields() -> RInstFields {
+        amo_fields(LR_FUNCT5)
+    }
+
+    fn eval(
+        state: &ProgramState<RiscV<S>, S>,
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        _rs2: RiscVRegister,
+    ) -> InstResult<RiscV<S>, S> {
+        let addr = word_addr(state, rs1)?;
+        let (val, mut diffs) = state.memory_get::<W32b>(addr)?;
+        diffs.push(state.reserve(addr.bits()));
+        diffs.extend(UserDiff::reg_write_pc_p4(
+            &state.user_state,
+            rd,
+            RegValue::<S>::sign_ext_from_lword(val),
+        ));
+        Ok(diffs)
+    }
+}
+
+/// Store conditional
+/// Stores the lower word of rs2 to the address in rs1 if the hart still holds a reservation on
+/// it, and writes 0 to rd if it did or 1 if not. The reservation is lost either way.
+pub struct Scw;
+impl<S: AtLeast32b> AmoType<S> for Scw {
+    fn name() -> &'static str {
+        "sc.w"
+    }
+
+    fn inst_fields() -> RInstFields {
+        amo_fields(0b0_0011)
+    }
+
+    fn eval(
+        state: &ProgramState<RiscV<S>, S>,
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        rs2: RiscVRegister,
+    ) -> InstResult<RiscV<S>, S> {
+        let addr = word_addr(state, rs1)?;
+        let (mut diffs, failed) = if state.holds_reservation(addr.bits()) {
+            let val = state.user_state.regfile.read(rs2).lower_lword();
+            let mut diffs = state.memory_set_unsized(addr, DataEnum::Lword(val))?;
+            diffs.extend(state.release_reservations(addr.bits(), 4));
+            (diffs, 0)
+        } else {
+            (state.release_own_reservation(), 1)
+        };
+        diffs.extend(UserDiff::reg_write_pc_p4(
+            &state.user_state,
+            rd,
+            RegValue::<S>::from(failed as u64),
+        ));
+        Ok(diffs)
+    }
+}
+
+/// Atomic swap
+/// Replaces the word at the address in rs1 with the lower word of rs2, and writes the old word to
+/// rd.
+pub struct Amoswapw;
+impl<S: AtLeast32b> AmoType<S> for Amoswapw {
+    fn name() -> &'static str {
+        "amoswap.w"
+    }
+
+    fn inst_fields() -> RInstFields {
+        amo_fields(0b0_0001)
+    }
+
+    fn eval(
+        state: &ProgramState<RiscV<S>, S>,
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        rs2: RiscVRegister,
+    ) -> InstResult<RiscV<S>, S> {
+        amo(state, rd, rs1, rs2, |_, new| new)
+    }
+}
+
+/// Atomic add
+/// Adds the lower word of rs2 to the word at the address in rs1, and writes the old word to rd.
+pub struct Amoaddw;
+impl<S: AtLeast32b> AmoType<S> for Amoaddw {
+    fn name() -> &'static str {
+        "amoadd.w"
+    }
+
+    fn inst_fields() -> RInstFields {
+        amo_fields(0b0_0000)
+    }
+
+    fn eval(
+        state: &ProgramState<RiscV<S>, S>,
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        rs2: RiscVRegister,
+    ) -> InstResult<RiscV<S>, S> {
+        amo(state, rd, rs1, rs2, u32::wrapping_add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::Sw, *};
+    use RiscVRegister::*;
+
+    const ADDR: u32 = 0xFFFF_0004;
+
+    fn get_init_state() -> ProgramState<RiscV<W32b>, W32b> {
+        let mut state = ProgramState::<RiscV<W32b>, W32b>::default();
+        state.regfile_set(A1, ADDR.into());
+        state
+    }
+
+    #[test]
+    fn test_lr_sc() {
+        let mut state = get_init_state();
+        state.memory_set_word(ADDR.into(), 0xFFFF_FFFEu32.into());
+        state.regfile_set(A2, 7.into());
+        state.apply_inst_test(&Lrw::new(A0, A1, Zero));
+        assert_eq!(state.regfile_read(A0), 0xFFFF_FFFEu32.into());
+        state.apply_inst_test(&Scw::new(A0, A1, A2));
+        assert_eq!(state.regfile_read(A0), 0.into());
+        assert_eq!(state.memory_get_word(ADDR.into()), 7.into());
+        // the reservation was used up by the last sc
+        state.regfile_set(A2, 8.into());
+        state.apply_inst_test(&Scw::new(A0, A1, A2));
+        assert_eq!(state.regfile_read(A0), 1.into());
+        assert_eq!(state.memory_get_word(ADDR.into()), 7.into());
+        // a plain store to the word between them also breaks the reservation
+        state.apply_inst_test(&Lrw::new_aq(A0, A1, Zero));
+        state.apply_inst_test(&Sw::new(A1, A0, 0.into()));
+        state.apply_inst_test(&Scw::new_rl(A0, A1, A2));
+        assert_eq!(state.regfile_read(A0), 1.into());
+        assert_eq!(state.memory_get_word(ADDR.into()), 7.into());
+    }
+
+    #[test]
+    fn test_amoswap_amoadd() {
+        let mut state = get_init_state();
+        state.memory_set_word(ADDR.into(), 0x7FFF_FFFFu32.into());
+        state.regfile_set(A2, 1.into());
+        state.apply_inst_test(&Amoaddw::new(A0, A1, A2));
+        assert_eq!(state.regfile_read(A0), 0x7FFF_FFFFu32.into());
+        // the sum wraps around
+        assert_eq!(state.memory_get_word(ADDR.into()), 0x8000_0000u32.into());
+        state.regfile_set(A2, 0x1234.into());
+        state.apply_inst_test(&Amoswapw::new_aqrl(A0, A1, A2));
+        assert_eq!(state.regfile_read(A0), 0x8000_0000u32.into());
+        assert_eq!(state.memory_get_word(ADDR.into()), 0x1234.into());
+    }
+
+    #[test]
+    fn test_misaligned() {
+        let mut state = get_init_state();
+        state.regfile_set(A1, (ADDR + 2).into());
+        assert!(state.apply_inst(&Lrw::new(A0, A1, Zero)).is_err());
+        assert!(state.apply_inst(&Scw::new(A0, A1, A2)).is_err());
+        assert!(state.apply_inst(&Amoaddw::new(A0, A1, A2)).is_err());
+    }
+}
@@ -325,7 +325,7 @@ pub struct Sltiu;
 impl<S: AtLeast32b> ITypeArith<S> for Sltiu {
     fn inst_fields() -> IInstFields {
         IInstFields {
-            funct3: f3(0b010),
+            funct3: f3(0b011),
             opcode: I_OPCODE_ARITH,
         }
     }
@@ -687,7 +687,7 @@ mod tests_32 {
                 },
                 IArithTestData {
                     imm: 2,
-                    result: RS1_VAL as i32 >> 2,
+                    result: RS1_VAL >> 2,
                 },
             ],
         );
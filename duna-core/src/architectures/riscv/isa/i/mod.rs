@@ -184,6 +184,91 @@ impl<S: AtLeast32b> SystemInst<S> for Ecall {
     }
 }
 
+/// Raises a breakpoint exception. Nothing handles it, so as on Linux without a debugger, the
+/// program is ended as though by SIGTRAP.
+pub struct Ebreak;
+impl<S: AtLeast32b> SystemInst<S> for Ebreak {
+    fn name() -> &'static str {
+        "ebreak"
+    }
+
+    fn funct12() -> BitStr32 {
+        BitStr32::new(1, 12)
+    }
+
+    fn inst_fields() -> IInstFields {
+        IInstFields {
+            opcode: SYS_OPCODE,
+            funct3: f3(0b000),
+        }
+    }
+
+    fn eval(_state: &ProgramState<RiscV<S>, S>) -> InstResult<RiscV<S>, S> {
+        Err(TermCause::Breakpoint)
+    }
+}
+
+/// Orders memory accesses. Since there is only one hart and memory accesses complete
+/// immediately, this has no effect besides advancing the PC.
+pub struct Fence;
+impl<S: AtLeast32b> IType<S> for Fence {
+    fn name() -> &'static str {
+        "fence"
+    }
+
+    fn inst_fields() -> IInstFields {
+        IInstFields {
+            opcode: BitStr32::new(0b000_1111, 7),
+            funct3: f3(0b000),
+        }
+    }
+
+    fn eval(
+        state: &ProgramState<RiscV<S>, S>,
+        _rd: RiscVRegister,
+        _rs1: RiscVRegister,
+        _imm: BitStr32,
+    ) -> InstResult<RiscV<S>, S> {
+        Ok(vec![UserDiff::pc_p4(&state.user_state).into_state_diff()])
+    }
+}
+
+/// Synchronizes instruction fetches with earlier stores. Since every instruction is fetched from
+/// memory just before it runs, this has no effect besides advancing the PC.
+pub struct FenceI;
+impl<S: AtLeast32b> IType<S> for FenceI {
+    fn name() -> &'static str {
+        "fence.i"
+    }
+
+    fn inst_fields() -> IInstFields {
+        IInstFields {
+            opcode: BitStr32::new(0b000_1111, 7),
+            funct3: f3(0b001),
+        }
+    }
+
+    fn eval(
+        state: &ProgramState<RiscV<S>, S>,
+        _rd: RiscVRegister,
+        _rs1: RiscVRegister,
+        _imm: BitStr32,
+    ) -> InstResult<RiscV<S>, S> {
+        Ok(vec![UserDiff::pc_p4(&state.user_state).into_state_diff()])
+    }
+}
+
+impl FenceI {
+    /// Builds "fence.i", whose fields are all zero.
+    pub fn expand<S: AtLeast32b>() -> RiscVInst<S> {
+        FenceI::new(
+            RiscVRegister::Zero,
+            RiscVRegister::Zero,
+            RegValue::<S>::zero(),
+        )
+    }
+}
+
 pub struct Jal;
 impl<S: AtLeast32b> JType<S> for Jal {
     fn name() -> &'static str {
@@ -402,6 +487,10 @@ mod tests_32 {
         const ECALL_HEX: u32 = 0x0000_0073;
         let ecall_inst: RiscVInst<W32b> = Ecall::new();
         assert_eq!(ecall_inst.to_machine_code(), ECALL_HEX);
+        // fence iorw, iorw
+        const FENCE_HEX: u32 = 0x0FF0_000F;
+        let fence_inst: RiscVInst<W32b> = Fence::new(Zero, Zero, DataLword::from(0xFF));
+        assert_eq!(fence_inst.to_machine_code(), FENCE_HEX);
         // jal ra, 16
         const JAL_HEX: u32 = 0x0100_00EF;
         let jal_inst: RiscVInst<W32b> = Jal::new(Ra, DataLword::from(16));
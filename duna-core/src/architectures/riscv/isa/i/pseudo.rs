@@ -8,6 +8,7 @@ use crate::{
     architectures::riscv::{instruction::*, registers::RiscVRegister},
     data_structures::*,
 };
+use std::marker::PhantomData;
 use RiscVRegister::*;
 
 pub struct La;
@@ -25,11 +26,82 @@ impl La {
     }
 
     pub fn expand_lower<S: AtLeast32b>(reg: RiscVRegister, data: RegValue<S>) -> RiscVInst<S> {
-        let imm: BitStr32 = data.to_bit_str(32);
-        // Because auipc was one instruction before us and data is the offset from ourselves,
-        // add 4 to the difference to account for the extra instruction
-        let lower = BitStr32::new(imm.slice(11, 0).as_u32() + 4, 12);
-        Addi::new(reg, reg, SignedValue::<S>::from(lower).into())
+        Addi::new(reg, reg, pcrel_lower(data))
+    }
+}
+
+/// Computes the immediate of an instruction that follows the auipc of a pc-relative pair,
+/// where data is the offset from the second instruction to the target.
+fn pcrel_lower<S: AtLeast32b>(data: RegValue<S>) -> RegValue<S> {
+    let imm: BitStr32 = data.to_bit_str(32);
+    // Because auipc was one instruction before us and data is the offset from ourselves,
+    // add 4 to the difference to account for the extra instruction
+    let lower = BitStr32::new(imm.slice(11, 0).as_u32() + 4, 12);
+    SignedValue::<S>::from(lower).into()
+}
+
+/// Computes "%hi(addr)", the upper 20 bits of an absolute address as used by lui. These are
+/// rounded up when bit 11 is set, since "%lo(addr)" is sign-extended when it is added back.
+pub fn addr_hi<S: AtLeast32b>(addr: RegValue<S>) -> RegValue<S> {
+    let imm = addr.to_bit_str(32).as_u32().wrapping_add(0x800);
+    UnsignedValue::<S>::from(BitStr32::new(imm >> 12, 20)).into()
+}
+
+/// Computes "%lo(addr)", the lower 12 bits of an absolute address as used by addi, loads, and
+/// stores.
+pub fn addr_lo<S: AtLeast32b>(addr: RegValue<S>) -> RegValue<S> {
+    SignedValue::<S>::from(addr.to_bit_str(12)).into()
+}
+
+/// Loads from a label with "lw rd, label", which expands to an auipc into rd followed by a load.
+/// The upper half is the same as that of La.
+pub struct LoadLabel<T>(PhantomData<T>);
+impl<T> LoadLabel<T> {
+    pub fn expand_lower<S: AtLeast32b>(rd: RiscVRegister, data: RegValue<S>) -> RiscVInst<S>
+    where
+        T: IType<S>,
+    {
+        T::new(rd, rd, pcrel_lower(data))
+    }
+}
+
+/// Stores to a label with "sw rs2, label, rt", which expands to an auipc into the temporary
+/// register rt followed by a store.
+pub struct StoreLabel<T>(PhantomData<T>);
+impl<T> StoreLabel<T> {
+    pub fn expand_lower<S: AtLeast32b>(
+        rt: RiscVRegister,
+        rs2: RiscVRegister,
+        data: RegValue<S>,
+    ) -> RiscVInst<S>
+    where
+        T: SType<S>,
+    {
+        T::new(rt, rs2, pcrel_lower(data))
+    }
+}
+
+/// Calls a far-away function with "auipc ra, hi; jalr ra, lo(ra)".
+pub struct Call;
+impl Call {
+    pub fn expand_upper<S: AtLeast32b>(data: RegValue<S>) -> RiscVInst<S> {
+        La::expand_upper(Ra, data)
+    }
+
+    pub fn expand_lower<S: AtLeast32b>(data: RegValue<S>) -> RiscVInst<S> {
+        Jalr::new(Ra, Ra, pcrel_lower(data))
+    }
+}
+
+/// Tail calls a far-away function with "auipc t1, hi; jalr zero, lo(t1)".
+pub struct Tail;
+impl Tail {
+    pub fn expand_upper<S: AtLeast32b>(data: RegValue<S>) -> RiscVInst<S> {
+        La::expand_upper(T1, data)
+    }
+
+    pub fn expand_lower<S: AtLeast32b>(data: RegValue<S>) -> RiscVInst<S> {
+        Jalr::new(Zero, T1, pcrel_lower(data))
     }
 }
 
@@ -60,36 +132,79 @@ impl Li32 {
     }
 }
 
-// For 64-bit literals, the literal is stored at a known location
-// and li is emmitted as lui + ld
-// TODO figure out how to emit a new label for 64-bit literals that we can ld from
+/// Builds a 64-bit constant in a register with the same sequence as GCC and LLVM.
+///
+/// Constants that fit in 32 bits need at most lui and addiw. Larger constants are built by
+/// recursively building their upper bits, then shifting them into place with slli and adding
+/// the lower 12 bits with addi, for at most 8 instructions. Positive constants may instead be
+/// built without their leading zeros, which are then restored by srli.
 pub struct Li64;
 impl Li64 {
     pub fn expand(reg: RiscVRegister, data: DataDword) -> Vec<RiscVInst<W64b>> {
-        let imm = data.to_bit_str(32);
-        let mut upper = imm.slice(32, 12);
-        let lower = imm.slice(11, 0);
-        // If upper is not all 1s (which means the number isn't just a negative addi) and the MSB
-        // of lower is high, then we need to add 1 to the upper immediate for sign extension reasons
-        // The former case (all 1s) is covered by adding 1 due to overflow being truncated
-        if lower.index(11).as_u32() > 0 {
-            // This will never overflow because upper only has 20 bits of data
-            upper = BitStr32::new(upper.as_u32() + 1, 20);
+        let val = i64::from(data);
+        let mut insts = Vec::new();
+        Self::expand_into(&mut insts, reg, val);
+        if val > 0 && insts.len() > 2 {
+            let leading_zeros = val.leading_zeros();
+            let shifted = (val as u64) << leading_zeros;
+            let ones = (1u64 << leading_zeros) - 1;
+            // Filling the shifted-out bits with ones helps masks like 0xFFFF_FFFF, and
+            // zeros help others
+            for &fill in [ones, 0].iter() {
+                let mut candidate = Vec::new();
+                Self::expand_into(&mut candidate, reg, (shifted | fill) as i64);
+                candidate.push(Srli::new(reg, reg, DataDword::from(leading_zeros as i64)));
+                if candidate.len() < insts.len() {
+                    insts = candidate;
+                }
+            }
         }
-        let no_lui = upper.is_zero();
-        let rs1 = if no_lui { Zero } else { reg };
-        let addi = Addi::new(reg, rs1, SignedValue::<W64b>::from(lower).into());
-        if upper.is_zero() {
-            vec![addi]
-        } else {
-            vec![
-                Lui::new(reg, UnsignedValue::<W64b>::from(upper).into()),
-                addi,
-            ]
+        insts
+    }
+
+    fn expand_into(insts: &mut Vec<RiscVInst<W64b>>, reg: RiscVRegister, val: i64) {
+        let lower = sign_extend(val as u64, 12);
+        if val == val as i32 as i64 {
+            // Adding 0x800 offsets the sign extension of the lower 12 bits
+            let upper = ((val + 0x800) >> 12) & 0xF_FFFF;
+            if upper != 0 {
+                insts.push(Lui::new(reg, DataDword::from(upper)));
+            }
+            if upper == 0 {
+                insts.push(Addi::new(reg, Zero, DataDword::from(lower)));
+            } else if lower != 0 {
+                // addiw truncates to 32 bits, which is needed when adding 0x800 above overflowed
+                // into the sign bit, as for 0x7FFF_FFFF
+                insts.push(Addiw::new(reg, reg, DataDword::from(lower)));
+            }
+            return;
+        }
+        let upper = (val as u64).wrapping_add(0x800) >> 12;
+        // Trailing zeros of the upper bits are folded into the shift
+        let mut shamt = 12 + upper.trailing_zeros();
+        let mut upper = sign_extend(upper >> (shamt - 12), 64 - shamt);
+        // If the upper bits are too wide for addi, shifting them left lets lui build them
+        if shamt > 12 && upper != sign_extend(upper as u64, 12) {
+            let lui_upper = upper.wrapping_shl(12);
+            if lui_upper == lui_upper as i32 as i64 {
+                shamt -= 12;
+                upper = lui_upper;
+            }
+        }
+        Self::expand_into(insts, reg, upper);
+        insts.push(Slli::new(reg, reg, DataDword::from(shamt as i64)));
+        if lower != 0 {
+            insts.push(Addi::new(reg, reg, DataDword::from(lower)));
         }
     }
 }
 
+/// Interprets the lower bits of val as a signed number of the given width.
+fn sign_extend(val: u64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    ((val << shift) as i64) >> shift
+}
+
 pub struct Mv;
 impl Mv {
     pub fn expand<S: AtLeast32b>(rd: RiscVRegister, rs: RiscVRegister) -> RiscVInst<S> {
@@ -118,6 +233,151 @@ impl Not {
     }
 }
 
+pub struct Negw;
+impl Negw {
+    pub fn expand(rd: RiscVRegister, rs: RiscVRegister) -> RiscVInst<W64b> {
+        Subw::new(rd, RiscVRegister::Zero, rs)
+    }
+}
+
+pub struct Seqz;
+impl Seqz {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister, rs: RiscVRegister) -> RiscVInst<S> {
+        Sltiu::new(rd, rs, <RegValue<S>>::from(1i64))
+    }
+}
+
+pub struct Snez;
+impl Snez {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister, rs: RiscVRegister) -> RiscVInst<S> {
+        Sltu::new(rd, Zero, rs)
+    }
+}
+
+pub struct Sltz;
+impl Sltz {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister, rs: RiscVRegister) -> RiscVInst<S> {
+        Slt::new(rd, rs, Zero)
+    }
+}
+
+pub struct Sgtz;
+impl Sgtz {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister, rs: RiscVRegister) -> RiscVInst<S> {
+        Slt::new(rd, Zero, rs)
+    }
+}
+
+pub struct Sgt;
+impl Sgt {
+    pub fn expand<S: AtLeast32b>(
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        rs2: RiscVRegister,
+    ) -> RiscVInst<S> {
+        Slt::new(rd, rs2, rs1)
+    }
+}
+
+pub struct Sgtu;
+impl Sgtu {
+    pub fn expand<S: AtLeast32b>(
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        rs2: RiscVRegister,
+    ) -> RiscVInst<S> {
+        Sltu::new(rd, rs2, rs1)
+    }
+}
+
+pub struct Beqz;
+impl Beqz {
+    pub fn expand<S: AtLeast32b>(rs: RiscVRegister, offs: RegValue<S>) -> RiscVInst<S> {
+        Beq::new(rs, Zero, offs)
+    }
+}
+
+pub struct Bnez;
+impl Bnez {
+    pub fn expand<S: AtLeast32b>(rs: RiscVRegister, offs: RegValue<S>) -> RiscVInst<S> {
+        Bne::new(rs, Zero, offs)
+    }
+}
+
+pub struct Blez;
+impl Blez {
+    pub fn expand<S: AtLeast32b>(rs: RiscVRegister, offs: RegValue<S>) -> RiscVInst<S> {
+        Bge::new(Zero, rs, offs)
+    }
+}
+
+pub struct Bgez;
+impl Bgez {
+    pub fn expand<S: AtLeast32b>(rs: RiscVRegister, offs: RegValue<S>) -> RiscVInst<S> {
+        Bge::new(rs, Zero, offs)
+    }
+}
+
+pub struct Bltz;
+impl Bltz {
+    pub fn expand<S: AtLeast32b>(rs: RiscVRegister, offs: RegValue<S>) -> RiscVInst<S> {
+        Blt::new(rs, Zero, offs)
+    }
+}
+
+pub struct Bgtz;
+impl Bgtz {
+    pub fn expand<S: AtLeast32b>(rs: RiscVRegister, offs: RegValue<S>) -> RiscVInst<S> {
+        Blt::new(Zero, rs, offs)
+    }
+}
+
+// Comparisons that are not provided by the base ISA swap the operands of their counterparts
+
+pub struct Bgt;
+impl Bgt {
+    pub fn expand<S: AtLeast32b>(
+        rs: RiscVRegister,
+        rt: RiscVRegister,
+        offs: RegValue<S>,
+    ) -> RiscVInst<S> {
+        Blt::new(rt, rs, offs)
+    }
+}
+
+pub struct Ble;
+impl Ble {
+    pub fn expand<S: AtLeast32b>(
+        rs: RiscVRegister,
+        rt: RiscVRegister,
+        offs: RegValue<S>,
+    ) -> RiscVInst<S> {
+        Bge::new(rt, rs, offs)
+    }
+}
+
+pub struct Bgtu;
+impl Bgtu {
+    pub fn expand<S: AtLeast32b>(
+        rs: RiscVRegister,
+        rt: RiscVRegister,
+        offs: RegValue<S>,
+    ) -> RiscVInst<S> {
+        Bltu::new(rt, rs, offs)
+    }
+}
+
+pub struct Bleu;
+impl Bleu {
+    pub fn expand<S: AtLeast32b>(
+        rs: RiscVRegister,
+        rt: RiscVRegister,
+        offs: RegValue<S>,
+    ) -> RiscVInst<S> {
+        Bgeu::new(rt, rs, offs)
+    }
+}
+
 pub struct JalPseudo;
 impl JalPseudo {
     pub fn expand<S: AtLeast32b>(offs: RegValue<S>) -> RiscVInst<S> {
@@ -200,6 +460,50 @@ mod tests {
         assert_eq!(num[0], Addi::new(A0, Zero, DataLword::from(-273)));
     }
 
+    #[test]
+    fn test_li_64() {
+        // (value, expected number of instructions)
+        let cases: [(u64, usize); 24] = [
+            (0, 1),
+            (1, 1),
+            (0x7FF, 1),
+            (0xFFFF_FFFF_FFFF_F800, 1),
+            (0x800, 2),
+            (0xFFFF_FFFF_FFFF_F7FF, 2),
+            (0x1000, 1),
+            (0x7FFF_F7FF, 2),
+            // adding 0x800 to these overflows into bit 31, which addiw corrects
+            (0x7FFF_F800, 2),
+            (0x7FFF_FFFF, 2),
+            (0xFFFF_FFFF_8000_0000, 1),
+            (0xFFFF_FFFF_FFFF_FFFF, 1),
+            (0x8000_0000, 2),
+            // built as -1, then shifted right
+            (0xFFFF_FFFF, 2),
+            (0x1_0000_0000, 2),
+            (0x1_0000_0001, 3),
+            (0xFFFF_FFFF_0000_0000, 2),
+            (0x8000_0000_0000_0000, 2),
+            (0x7FFF_FFFF_FFFF_FFFF, 2),
+            // built as 0xFFFF_FFFF_FFFF_F000 by lui, then shifted right
+            (0x7FFF_FFFF_FFFF_F800, 2),
+            (0x8000_0000_0000_0800, 5),
+            (0xDEAD_BEEF_CAFE_BABE, 8),
+            (0x0123_4567_89AB_CDEF, 8),
+            (0xFEDC_BA98_7654_3210, 8),
+        ];
+        for &(val, len) in cases.iter() {
+            let insts = Li64::expand(A0, DataDword::from(val));
+            let mut state: ProgramState<RiscV<W64b>, W64b> = Default::default();
+            state.regfile_set(A0, 0xAAAA_AAAA_AAAA_AAAAu64.into());
+            for inst in &insts {
+                state.apply_inst_test(inst);
+            }
+            assert_eq!(u64::from(state.regfile_read(A0)), val, "li a0, {:#x}", val);
+            assert_eq!(insts.len(), len, "li a0, {:#x}", val);
+        }
+    }
+
     #[test]
     fn test_not_neg() {
         let mut state: ProgramState<RiscV<W64b>, W64b> = Default::default();
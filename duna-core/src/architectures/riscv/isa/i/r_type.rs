@@ -198,7 +198,7 @@ impl<S: AtLeast32b> RType<S> for Sra {
 
     fn inst_fields() -> RInstFields {
         RInstFields {
-            funct7: f7(0b0100_0000),
+            funct7: f7(0b010_0000),
             funct3: f3(0b101),
             opcode: R_OPCODE,
         }
@@ -218,7 +218,7 @@ impl RType<W64b> for Sraw {
 
     fn inst_fields() -> RInstFields {
         RInstFields {
-            funct7: f7(0b0100_0000),
+            funct7: f7(0b010_0000),
             funct3: f3(0b101),
             opcode: R_W_OPCODE,
         }
@@ -278,7 +278,7 @@ impl<S: AtLeast32b> RType<S> for Sub {
 
     fn inst_fields() -> RInstFields {
         RInstFields {
-            funct7: f7(0b0100_0000),
+            funct7: f7(0b010_0000),
             funct3: f3(0),
             opcode: R_OPCODE,
         }
@@ -298,7 +298,7 @@ impl RType<W64b> for Subw {
 
     fn inst_fields() -> RInstFields {
         RInstFields {
-            funct7: f7(0b0100_0000),
+            funct7: f7(0b010_0000),
             funct3: f3(0),
             opcode: R_W_OPCODE,
         }
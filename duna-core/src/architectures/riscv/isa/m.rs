@@ -9,7 +9,10 @@ use crate::{
     },
     data_structures::*,
 };
-use num_traits::ops::checked::{CheckedDiv, CheckedRem};
+use num_traits::{
+    cast::AsPrimitive,
+    ops::checked::{CheckedDiv, CheckedRem},
+};
 
 const R_OPCODE: BitStr32 = BitStr32::new(0b011_0011, 7);
 const R_W_OPCODE: BitStr32 = BitStr32::new(0b011_1011, 7);
@@ -54,14 +57,82 @@ impl RType<W64b> for Mulw {
     }
 }
 
-// /// Performs signed * signed multiplication and returns upper bits of the product
-// pub struct Mulh;
+/// Widens a register value to 128 bits, interpreting it as signed.
+fn wide_signed<S: AtLeast32b>(val: RegValue<S>) -> i128 {
+    AsPrimitive::<i64>::as_(val.as_signed().raw()) as i128
+}
+
+/// Widens a register value to 128 bits, interpreting it as unsigned.
+fn wide_unsigned<S: AtLeast32b>(val: RegValue<S>) -> i128 {
+    AsPrimitive::<u64>::as_(val.as_unsigned().raw()) as i128
+}
+
+/// Takes the upper half of a double-width product. Only the lower 128 bits of the product are
+/// needed, so it may have wrapped.
+fn upper_half<S: AtLeast32b>(product: i128) -> RegValue<S> {
+    let width = if S::is_32() { 32 } else { 64 };
+    RegValue::<S>::from((product >> width) as u64)
+}
+
+/// Performs signed * signed multiplication and returns upper bits of the product
+pub struct Mulh;
+impl<S: AtLeast32b> RType<S> for Mulh {
+    fn name() -> &'static str {
+        "mulh"
+    }
+
+    fn inst_fields() -> RInstFields {
+        RInstFields {
+            funct7: f7(1),
+            funct3: f3(0b001),
+            opcode: R_OPCODE,
+        }
+    }
+
+    fn eval(rs1_val: RegValue<S>, rs2_val: RegValue<S>) -> RegValue<S> {
+        upper_half::<S>(wide_signed(rs1_val).wrapping_mul(wide_signed(rs2_val)))
+    }
+}
+
+/// Performs unsigned * unsigned multiplication and returns upper bits of the product
+pub struct Mulhu;
+impl<S: AtLeast32b> RType<S> for Mulhu {
+    fn name() -> &'static str {
+        "mulhu"
+    }
+
+    fn inst_fields() -> RInstFields {
+        RInstFields {
+            funct7: f7(1),
+            funct3: f3(0b011),
+            opcode: R_OPCODE,
+        }
+    }
+
+    fn eval(rs1_val: RegValue<S>, rs2_val: RegValue<S>) -> RegValue<S> {
+        upper_half::<S>(wide_unsigned(rs1_val).wrapping_mul(wide_unsigned(rs2_val)))
+    }
+}
+
+/// Performs signed * unsigned multiplication and returns upper bits of the product
+pub struct Mulhsu;
+impl<S: AtLeast32b> RType<S> for Mulhsu {
+    fn name() -> &'static str {
+        "mulhsu"
+    }
 
-// /// Performs unsigned * unsigned multiplication and returns upper bits of the product
-// pub struct Mulhu;
+    fn inst_fields() -> RInstFields {
+        RInstFields {
+            funct7: f7(1),
+            funct3: f3(0b010),
+            opcode: R_OPCODE,
+        }
+    }
 
-// /// Performs signed * unsigned multiplication and returns upper bits of the product
-// pub struct Mulhsu;
+    fn eval(rs1_val: RegValue<S>, rs2_val: RegValue<S>) -> RegValue<S> {
+        upper_half::<S>(wide_signed(rs1_val).wrapping_mul(wide_unsigned(rs2_val)))
+    }
+}
 
 pub struct Div;
 impl<S: AtLeast32b> RType<S> for Div {
@@ -270,7 +341,7 @@ impl<S: AtLeast32b> RType<S> for Remu {
     fn inst_fields() -> RInstFields {
         RInstFields {
             funct7: f7(1),
-            funct3: f3(0b110),
+            funct3: f3(0b111),
             opcode: R_OPCODE,
         }
     }
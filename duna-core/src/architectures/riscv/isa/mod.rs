@@ -1,7 +1,9 @@
+mod a;
 mod i;
 mod m;
 mod zicsr;
 
+pub use a::*;
 pub use i::*;
 pub use m::*;
 pub use zicsr::*;
@@ -10,9 +10,34 @@ use crate::{
 
 const SYS_OPCODE: BitStr32 = BitStr32::new(0b111_0011, 7);
 
-/// Checks the validity of the CSR.
-fn csr_valid_check(_csrno: usize) -> Result<(), TermCause> {
-    // TODO
+/// The control registers that may be given by name in assembly, along with their numbers.
+/// See the RISC-V privileged spec.
+pub const CSR_NAMES: &[(&str, usize)] = &[
+    ("cycle", CYCLE),
+    ("time", TIME),
+    ("instret", INSTRET),
+    ("cycleh", CYCLEH),
+    ("timeh", TIMEH),
+    ("instreth", INSTRETH),
+    ("mstatus", 0x300),
+    ("misa", 0x301),
+    ("mie", 0x304),
+    ("mtvec", 0x305),
+    ("mscratch", 0x340),
+    ("mepc", 0x341),
+    ("mcause", 0x342),
+    ("mtval", 0x343),
+    ("mip", 0x344),
+    ("mhartid", MHARTID),
+];
+
+/// Checks the validity of the CSR. Writing a CSR whose upper two bits are set, such as the
+/// counters, is an illegal instruction, since those CSRs are read-only; this is also how "unimp"
+/// traps.
+fn csr_valid_check(csrno: usize, writes: bool) -> Result<(), TermCause> {
+    if writes && csrno >> 10 == 0b11 {
+        return Err(TermCause::IllegalInstruction);
+    }
     Ok(())
 }
 
@@ -39,16 +64,12 @@ impl<S: AtLeast32b> IType<S> for Csrrw {
         imm: BitStr32,
     ) -> InstResult<RiscV<S>, S> {
         let csrno = imm.as_usize();
-        csr_valid_check(csrno)?;
+        csr_valid_check(csrno, true)?;
         Ok(if rd == RiscVRegister::Zero {
             vec![
                 // Do not read CSR if rd is x0
-                PrivDiff::csr_write(
-                    &state.priv_state,
-                    imm.as_usize(),
-                    UnsignedValue::<S>::zero().into(),
-                )
-                .into_state_diff(),
+                PrivDiff::csr_write(&state.priv_state, csrno, state.user_state.regfile.read(rs1))
+                    .into_state_diff(),
                 UserDiff::pc_p4(&state.user_state).into_state_diff(),
             ]
         } else {
@@ -90,7 +111,7 @@ impl<S: AtLeast32b> IType<S> for Csrrs {
         imm: BitStr32,
     ) -> InstResult<RiscV<S>, S> {
         let csrno = imm.as_usize();
-        csr_valid_check(csrno)?;
+        csr_valid_check(csrno, rs1 != RiscVRegister::Zero)?;
         let priv_state = &state.priv_state;
         let user_state = &state.user_state;
         Ok(if rs1 == RiscVRegister::Zero {
@@ -121,7 +142,7 @@ impl<S: AtLeast32b> IType<S> for Csrrs {
 pub struct Csrrc;
 impl<S: AtLeast32b> IType<S> for Csrrc {
     fn name() -> &'static str {
-        "csrrs"
+        "csrrc"
     }
 
     fn inst_fields() -> IInstFields {
@@ -138,7 +159,7 @@ impl<S: AtLeast32b> IType<S> for Csrrc {
         imm: BitStr32,
     ) -> InstResult<RiscV<S>, S> {
         let csrno = imm.as_usize();
-        csr_valid_check(csrno)?;
+        csr_valid_check(csrno, rs1 != RiscVRegister::Zero)?;
         let priv_state = &state.priv_state;
         let user_state = &state.user_state;
         Ok(if rs1 == RiscVRegister::Zero {
@@ -164,6 +185,288 @@ impl<S: AtLeast32b> IType<S> for Csrrc {
     }
 }
 
+/// Reads the 5-bit unsigned immediate of a CSR instruction, which is held in the rs1 field.
+fn uimm<S: AtLeast32b>(rs1: RiscVRegister) -> RegValue<S> {
+    RegValue::<S>::from(rs1 as u64)
+}
+
+/// Atomic Read/Write CSR Immediate
+/// Like csrrw, but writes a 5-bit unsigned immediate, held in the rs1 field, to the CSR.
+pub struct Csrrwi;
+impl<S: AtLeast32b> IType<S> for Csrrwi {
+    fn name() -> &'static str {
+        "csrrwi"
+    }
+
+    fn inst_fields() -> IInstFields {
+        IInstFields {
+            funct3: f3(0b101),
+            opcode: SYS_OPCODE,
+        }
+    }
+
+    fn eval(
+        state: &ProgramState<RiscV<S>, S>,
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        imm: BitStr32,
+    ) -> InstResult<RiscV<S>, S> {
+        let csrno = imm.as_usize();
+        csr_valid_check(csrno, true)?;
+        let priv_state = &state.priv_state;
+        let user_state = &state.user_state;
+        let mut diffs = Vec::new();
+        // Do not read CSR if rd is x0
+        if rd != RiscVRegister::Zero {
+            diffs.push(
+                UserDiff::reg_update(user_state, rd, priv_state.csr_read(csrno)).into_state_diff(),
+            );
+        }
+        diffs.push(PrivDiff::csr_write(priv_state, csrno, uimm(rs1)).into_state_diff());
+        diffs.push(UserDiff::pc_p4(user_state).into_state_diff());
+        Ok(diffs)
+    }
+}
+
+/// Atomic Read and Set Bits in CSR Immediate
+/// Like csrrs, but the bits to set are given by a 5-bit unsigned immediate.
+pub struct Csrrsi;
+impl<S: AtLeast32b> IType<S> for Csrrsi {
+    fn name() -> &'static str {
+        "csrrsi"
+    }
+
+    fn inst_fields() -> IInstFields {
+        IInstFields {
+            funct3: f3(0b110),
+            opcode: SYS_OPCODE,
+        }
+    }
+
+    fn eval(
+        state: &ProgramState<RiscV<S>, S>,
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        imm: BitStr32,
+    ) -> InstResult<RiscV<S>, S> {
+        let csrno = imm.as_usize();
+        csr_valid_check(csrno, rs1 != RiscVRegister::Zero)?;
+        let priv_state = &state.priv_state;
+        let user_state = &state.user_state;
+        let csrval = priv_state.csr_read(csrno);
+        let mut diffs = vec![UserDiff::reg_update(user_state, rd, csrval).into_state_diff()];
+        // Do not write CSR if the immediate is zero
+        if rs1 != RiscVRegister::Zero {
+            diffs
+                .push(PrivDiff::csr_write(priv_state, csrno, csrval | uimm(rs1)).into_state_diff());
+        }
+        diffs.push(UserDiff::pc_p4(user_state).into_state_diff());
+        Ok(diffs)
+    }
+}
+
+/// Atomic Read and Clear Bits in CSR Immediate
+/// Like csrrc, but the bits to clear are given by a 5-bit unsigned immediate.
+pub struct Csrrci;
+impl<S: AtLeast32b> IType<S> for Csrrci {
+    fn name() -> &'static str {
+        "csrrci"
+    }
+
+    fn inst_fields() -> IInstFields {
+        IInstFields {
+            funct3: f3(0b111),
+            opcode: SYS_OPCODE,
+        }
+    }
+
+    fn eval(
+        state: &ProgramState<RiscV<S>, S>,
+        rd: RiscVRegister,
+        rs1: RiscVRegister,
+        imm: BitStr32,
+    ) -> InstResult<RiscV<S>, S> {
+        let csrno = imm.as_usize();
+        csr_valid_check(csrno, rs1 != RiscVRegister::Zero)?;
+        let priv_state = &state.priv_state;
+        let user_state = &state.user_state;
+        let csrval = priv_state.csr_read(csrno);
+        let mut diffs = vec![UserDiff::reg_update(user_state, rd, csrval).into_state_diff()];
+        // Do not write CSR if the immediate is zero
+        if rs1 != RiscVRegister::Zero {
+            diffs.push(
+                PrivDiff::csr_write(priv_state, csrno, csrval & !uimm(rs1)).into_state_diff(),
+            );
+        }
+        diffs.push(UserDiff::pc_p4(user_state).into_state_diff());
+        Ok(diffs)
+    }
+}
+
+/// Builds a CSR instruction that takes an immediate, which is held in the rs1 field.
+fn with_uimm<S: AtLeast32b>(
+    inst_new: fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>,
+    rd: RiscVRegister,
+    uimm: RegValue<S>,
+    csr: RegValue<S>,
+) -> RiscVInst<S> {
+    inst_new(
+        rd,
+        RiscVRegister::from(uimm.to_bit_str(5).as_u32() as u8),
+        csr,
+    )
+}
+
+impl Csrrwi {
+    /// Builds "csrrwi rd, csr, uimm", where uimm is a 5-bit unsigned immediate.
+    pub fn expand<S: AtLeast32b>(
+        rd: RiscVRegister,
+        csr: RegValue<S>,
+        uimm: RegValue<S>,
+    ) -> RiscVInst<S> {
+        with_uimm(Csrrwi::new, rd, uimm, csr)
+    }
+}
+
+impl Csrrsi {
+    /// Builds "csrrsi rd, csr, uimm", where uimm is a 5-bit unsigned immediate.
+    pub fn expand<S: AtLeast32b>(
+        rd: RiscVRegister,
+        csr: RegValue<S>,
+        uimm: RegValue<S>,
+    ) -> RiscVInst<S> {
+        with_uimm(Csrrsi::new, rd, uimm, csr)
+    }
+}
+
+impl Csrrci {
+    /// Builds "csrrci rd, csr, uimm", where uimm is a 5-bit unsigned immediate.
+    pub fn expand<S: AtLeast32b>(
+        rd: RiscVRegister,
+        csr: RegValue<S>,
+        uimm: RegValue<S>,
+    ) -> RiscVInst<S> {
+        with_uimm(Csrrci::new, rd, uimm, csr)
+    }
+}
+
+/// Reads a CSR with "csrr rd, csr".
+pub struct Csrr;
+impl Csrr {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister, csr: RegValue<S>) -> RiscVInst<S> {
+        Csrrs::new(rd, RiscVRegister::Zero, csr)
+    }
+}
+
+/// Sets bits in a CSR with "csrs csr, rs" without reading it.
+pub struct Csrs;
+impl Csrs {
+    pub fn expand<S: AtLeast32b>(csr: RegValue<S>, rs: RiscVRegister) -> RiscVInst<S> {
+        Csrrs::new(RiscVRegister::Zero, rs, csr)
+    }
+}
+
+/// Clears bits in a CSR with "csrc csr, rs" without reading it.
+pub struct Csrc;
+impl Csrc {
+    pub fn expand<S: AtLeast32b>(csr: RegValue<S>, rs: RiscVRegister) -> RiscVInst<S> {
+        Csrrc::new(RiscVRegister::Zero, rs, csr)
+    }
+}
+
+/// Writes a CSR with "csrw csr, rs" without reading it.
+pub struct Csrw;
+impl Csrw {
+    pub fn expand<S: AtLeast32b>(csr: RegValue<S>, rs: RiscVRegister) -> RiscVInst<S> {
+        Csrrw::new(RiscVRegister::Zero, rs, csr)
+    }
+}
+
+/// Writes an immediate to a CSR with "csrwi csr, uimm" without reading it.
+pub struct Csrwi;
+impl Csrwi {
+    pub fn expand<S: AtLeast32b>(csr: RegValue<S>, uimm: RegValue<S>) -> RiscVInst<S> {
+        Csrrwi::expand(RiscVRegister::Zero, csr, uimm)
+    }
+}
+
+/// Sets bits in a CSR with "csrsi csr, uimm" without reading it.
+pub struct Csrsi;
+impl Csrsi {
+    pub fn expand<S: AtLeast32b>(csr: RegValue<S>, uimm: RegValue<S>) -> RiscVInst<S> {
+        Csrrsi::expand(RiscVRegister::Zero, csr, uimm)
+    }
+}
+
+/// Clears bits in a CSR with "csrci csr, uimm" without reading it.
+pub struct Csrci;
+impl Csrci {
+    pub fn expand<S: AtLeast32b>(csr: RegValue<S>, uimm: RegValue<S>) -> RiscVInst<S> {
+        Csrrci::expand(RiscVRegister::Zero, csr, uimm)
+    }
+}
+
+/// Traps as an illegal instruction with "unimp", which GNU as encodes as a write to the
+/// read-only cycle counter.
+pub struct Unimp;
+impl Unimp {
+    pub fn expand<S: AtLeast32b>() -> RiscVInst<S> {
+        Csrrw::new(
+            RiscVRegister::Zero,
+            RiscVRegister::Zero,
+            (CYCLE as u64).into(),
+        )
+    }
+}
+
+/// Reads the number of cycles the hart has run for with "rdcycle rd".
+pub struct Rdcycle;
+impl Rdcycle {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister) -> RiscVInst<S> {
+        Csrr::expand(rd, (CYCLE as u64).into())
+    }
+}
+
+/// Reads the upper 32 bits of the cycle counter on RV32 with "rdcycleh rd".
+pub struct Rdcycleh;
+impl Rdcycleh {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister) -> RiscVInst<S> {
+        Csrr::expand(rd, (CYCLEH as u64).into())
+    }
+}
+
+/// Reads the real-time clock with "rdtime rd".
+pub struct Rdtime;
+impl Rdtime {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister) -> RiscVInst<S> {
+        Csrr::expand(rd, (TIME as u64).into())
+    }
+}
+
+/// Reads the upper 32 bits of the real-time clock on RV32 with "rdtimeh rd".
+pub struct Rdtimeh;
+impl Rdtimeh {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister) -> RiscVInst<S> {
+        Csrr::expand(rd, (TIMEH as u64).into())
+    }
+}
+
+/// Reads the number of instructions retired with "rdinstret rd".
+pub struct Rdinstret;
+impl Rdinstret {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister) -> RiscVInst<S> {
+        Csrr::expand(rd, (INSTRET as u64).into())
+    }
+}
+
+/// Reads the upper 32 bits of the number of instructions retired on RV32 with "rdinstreth rd".
+pub struct Rdinstreth;
+impl Rdinstreth {
+    pub fn expand<S: AtLeast32b>(rd: RiscVRegister) -> RiscVInst<S> {
+        Csrr::expand(rd, (INSTRETH as u64).into())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -189,8 +492,31 @@ mod tests {
         state.apply_inst_test(&Csrrc::new(T0, Zero, csrno.into()));
         assert_eq!(state.csr_read(csrno as usize), csr_val.into());
         assert_eq!(state.regfile_read(T0), csr_val.into());
+        // Writes still happen when rd is x0
         state.apply_inst_test(&Csrrw::new(Zero, S0, csrno.into()));
-        assert_eq!(state.csr_read(csrno as usize), 0.into());
+        assert_eq!(state.csr_read(csrno as usize), 0x5678.into());
+        // When 0 is the immediate mask, no bits in the CSR get changed either
+        state.apply_inst_test(&Csrrsi::expand(T0, csrno.into(), 0.into()));
+        state.apply_inst_test(&Csrrci::expand(T0, csrno.into(), 0.into()));
+        assert_eq!(state.csr_read(csrno as usize), 0x5678.into());
+    }
+
+    #[test]
+    fn test_read_only_csr() {
+        let mut state = get_init_state();
+        // reading a counter is fine, but writing one is an illegal instruction
+        state.apply_inst_test(&Csrrs::new(T0, Zero, (CYCLE as u64).into()));
+        assert!(matches!(
+            state.apply_inst(&Unimp::expand()),
+            Err(TermCause::IllegalInstruction)
+        ));
+        state.regfile_set(A0, 1.into());
+        assert!(state
+            .apply_inst(&Csrrs::new(T0, A0, (INSTRET as u64).into()))
+            .is_err());
+        assert!(state
+            .apply_inst(&Csrrwi::expand(T0, (TIME as u64).into(), 0.into()))
+            .is_err());
     }
 
     #[test]
@@ -217,6 +543,32 @@ mod tests {
         assert_eq!(state.regfile_read(T0), 0x1100.into());
     }
 
+    #[test]
+    fn test_csr_imm() {
+        let mut state = get_init_state();
+        let csrno: u64 = 0xFF;
+        state.csr_write(csrno as usize, 0x1100.into());
+        state.apply_inst_test(&Csrrwi::expand(T0, csrno.into(), 0x1F.into()));
+        assert_eq!(state.csr_read(csrno as usize), 0x1F.into());
+        assert_eq!(state.regfile_read(T0), 0x1100.into());
+        state.apply_inst_test(&Csrrci::expand(T0, csrno.into(), 0x3.into()));
+        assert_eq!(state.csr_read(csrno as usize), 0x1C.into());
+        assert_eq!(state.regfile_read(T0), 0x1F.into());
+        state.apply_inst_test(&Csrrsi::expand(T0, csrno.into(), 0x1.into()));
+        assert_eq!(state.csr_read(csrno as usize), 0x1D.into());
+        assert_eq!(state.regfile_read(T0), 0x1C.into());
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut state = get_init_state();
+        state.priv_state.instret = 0x1_0000_0002;
+        state.apply_inst_test(&Rdinstret::expand(A0));
+        state.apply_inst_test(&Rdcycleh::expand(A1));
+        assert_eq!(state.regfile_read(A0), 2.into());
+        assert_eq!(state.regfile_read(A1), 1.into());
+    }
+
     #[test]
     fn test_csrrc() {
         let mut state = get_init_state();
@@ -228,7 +580,3 @@ mod tests {
         assert_eq!(state.regfile_read(T0), 0x1111.into());
     }
 }
-
-// pub struct Csrrwi;
-// pub struct Csrrsi;
-// pub struct Csrrci;
@@ -1,4 +1,5 @@
 mod arch;
+mod decoder;
 mod instruction;
 pub mod isa;
 mod parser;
@@ -6,6 +7,7 @@ mod program;
 mod registers;
 
 pub use arch::*;
+pub use decoder::RiscVInstDecoder;
 pub use instruction::RiscVInst;
 pub use program::*;
 pub use registers::RiscVRegister;
@@ -3,6 +3,7 @@ use crate::{
     assembler::{lexer::*, parser::*, *},
     data_structures::*,
 };
+use num_traits::cast::AsPrimitive;
 use std::{collections::HashMap, marker::PhantomData};
 
 /// Describes the arguments needed for a type of function.
@@ -14,24 +15,59 @@ pub enum ParseType<S: AtLeast32b> {
     Arith(fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>),
     // "ecall" and "ebreak"
     Env(fn() -> RiscVInst<S>),
-    MemL(fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>),
-    MemS(fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>),
+    // The second function produces the load or store that follows an auipc when the address
+    // is a label, as in "lw a0, label" or "sw a0, label, t0"
+    MemL(
+        fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>,
+        fn(RiscVRegister, RegValue<S>) -> RiscVInst<S>,
+    ),
+    MemS(
+        fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>,
+        fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>,
+    ),
     B(fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>),
     // Covers "jal ra, label", "jal label", "jal -4" etc.
     Jal,
-    // Covers "jalr ra, x1, 0", "jalr x1", etc. ("jalr ra, 0(x1)" not yet supported)
+    // Covers "jalr ra, x1, 0", "jalr ra, 0(x1)", "jalr ra, x1", "jalr x1", etc.
     Jalr,
     U(fn(RiscVRegister, RegValue<S>) -> RiscVInst<S>),
+    // Covers "fence" and "fence rw, w"
+    Fence,
     // Pseudo-instructions
     // La is special because it produces two instructions and takes a label
     La,
     // Li is split to allow for distinction between 32 and 64-bit variants
-    Li(fn(RiscVRegister, RegValue<S>) -> Vec<RiscVInst<S>>),
+    // The second function loads the constant when it is placed in a literal pool
+    Li(
+        fn(RiscVRegister, RegValue<S>) -> Vec<RiscVInst<S>>,
+        fn(RiscVRegister, RegValue<S>) -> RiscVInst<S>,
+    ),
     RegReg(fn(RiscVRegister, RiscVRegister) -> RiscVInst<S>),
     NoArgs(fn() -> RiscVInst<S>),
     OneReg(fn(RiscVRegister) -> RiscVInst<S>),
     // Covers "j label", "j -4", etc.
     LikeJ(fn(RegValue<S>) -> RiscVInst<S>),
+    // Covers "beqz a0, label", etc.
+    BZero(fn(RiscVRegister, RegValue<S>) -> RiscVInst<S>),
+    // "call label" and "tail label" produce an auipc followed by a jalr
+    LikeCall(
+        fn(RegValue<S>) -> RiscVInst<S>,
+        fn(RegValue<S>) -> RiscVInst<S>,
+    ),
+    // Covers "lr.w rd, (rs1)"
+    LoadReserved(fn(RiscVRegister, RiscVRegister, RiscVRegister) -> RiscVInst<S>),
+    // Covers "sc.w rd, rs2, (rs1)" and "amoadd.w rd, rs2, (rs1)" etc.
+    Amo(fn(RiscVRegister, RiscVRegister, RiscVRegister) -> RiscVInst<S>),
+    // Covers "csrr rd, csr"
+    CsrRead(fn(RiscVRegister, RegValue<S>) -> RiscVInst<S>),
+    // Covers "csrw csr, rs", "csrs csr, rs", and "csrc csr, rs"
+    CsrWrite(fn(RegValue<S>, RiscVRegister) -> RiscVInst<S>),
+    // Covers "csrrw rd, csr, rs1" etc., and also "csrrw rd, rs1, csr"
+    Csr(fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>),
+    // Covers "csrrwi rd, csr, uimm" etc., with the same order of arguments as "csrrw"
+    CsrImm(fn(RiscVRegister, RegValue<S>, RegValue<S>) -> RiscVInst<S>),
+    // Covers "csrwi csr, uimm", "csrsi csr, uimm", and "csrci csr, uimm"
+    CsrWriteImm(fn(RegValue<S>, RegValue<S>) -> RiscVInst<S>),
 }
 
 lazy_static! {
@@ -51,21 +87,22 @@ lazy_static! {
             ("blt", B(Blt::new)),
             ("bltu", B(Bltu::new)),
             ("bne", B(Bne::new)),
-            // ("ebreak", Env),
-            // ("fence", ???),
+            ("ebreak", Env(Ebreak::new)),
             ("ecall", Env(Ecall::new)),
+            ("fence", ParseType::Fence),
+            ("fence.i", NoArgs(FenceI::expand)),
             ("jal", ParseType::Jal),
             ("jalr", ParseType::Jalr),
-            ("lb", MemL(Lb::new)),
-            ("lbu", MemL(Lbu::new)),
-            ("lh", MemL(Lh::new)),
-            ("lhu", MemL(Lhu::new)),
+            ("lb", MemL(Lb::new, LoadLabel::<Lb>::expand_lower)),
+            ("lbu", MemL(Lbu::new, LoadLabel::<Lbu>::expand_lower)),
+            ("lh", MemL(Lh::new, LoadLabel::<Lh>::expand_lower)),
+            ("lhu", MemL(Lhu::new, LoadLabel::<Lhu>::expand_lower)),
             ("lui", U(Lui::new)),
-            ("lw", MemL(Lw::new)),
+            ("lw", MemL(Lw::new, LoadLabel::<Lw>::expand_lower)),
             ("or", R(Or::new)),
             ("ori", Arith(Ori::new)),
-            ("sb", MemS(Sb::new)),
-            ("sh", MemS(Sh::new)),
+            ("sb", MemS(Sb::new, StoreLabel::<Sb>::expand_lower)),
+            ("sh", MemS(Sh::new, StoreLabel::<Sh>::expand_lower)),
             ("sll", R(Sll::new)),
             ("slli", Arith(Slli::new)),
             ("slt", R(Slt::new)),
@@ -77,49 +114,86 @@ lazy_static! {
             ("srl", R(Srl::new)),
             ("srli", Arith(Srli::new)),
             ("sub", R(Sub::new)),
-            ("sw", MemS(Sw::new)),
+            ("sw", MemS(Sw::new, StoreLabel::<Sw>::expand_lower)),
             ("xor", R(Xor::new)),
             ("xori", Arith(Xori::new)),
             // === Pseudo ===
             ("la", ParseType::La),
-            ("li", Li(Li32::expand)),
+            ("lla", ParseType::La),
+            ("li", Li(Li32::expand, LoadLabel::<Lw>::expand_lower)),
             ("mv", RegReg(Mv::expand)),
             ("neg", RegReg(Neg::expand)),
             ("nop", NoArgs(Nop::expand)),
+            ("unimp", NoArgs(Unimp::expand)),
             ("not", RegReg(Not::expand)),
             ("j", LikeJ(J::expand)),
             ("jr", OneReg(Jr::expand)),
             ("ret", NoArgs(Ret::expand)),
+            ("seqz", RegReg(Seqz::expand)),
+            ("snez", RegReg(Snez::expand)),
+            ("sltz", RegReg(Sltz::expand)),
+            ("sgtz", RegReg(Sgtz::expand)),
+            ("sgt", R(Sgt::expand)),
+            ("sgtu", R(Sgtu::expand)),
+            ("beqz", BZero(Beqz::expand)),
+            ("bnez", BZero(Bnez::expand)),
+            ("blez", BZero(Blez::expand)),
+            ("bgez", BZero(Bgez::expand)),
+            ("bltz", BZero(Bltz::expand)),
+            ("bgtz", BZero(Bgtz::expand)),
+            ("bgt", B(Bgt::expand)),
+            ("ble", B(Ble::expand)),
+            ("bgtu", B(Bgtu::expand)),
+            ("bleu", B(Bleu::expand)),
+            ("call", LikeCall(Call::expand_upper, Call::expand_lower)),
+            ("tail", LikeCall(Tail::expand_upper, Tail::expand_lower)),
             // === M extension ===
             ("mul", R(Mul::new)),
-            // ("mulh", R(Mulh::new)),
-            // ("mulhu", R(Mulhu::new)),
-            // ("mulhsu", R(Mulsu::new)),
+            ("mulh", R(Mulh::new)),
+            ("mulhu", R(Mulhu::new)),
+            ("mulhsu", R(Mulhsu::new)),
             ("div", R(Div::new)),
             ("divu", R(Divu::new)),
             ("rem", R(Rem::new)),
             ("remu", R(Remu::new)),
+            // === A extension ===
+            ("lr.w", LoadReserved(Lrw::new)),
+            ("lr.w.aq", LoadReserved(Lrw::new_aq)),
+            ("lr.w.rl", LoadReserved(Lrw::new_rl)),
+            ("lr.w.aqrl", LoadReserved(Lrw::new_aqrl)),
+            ("sc.w", Amo(Scw::new)),
+            ("sc.w.aq", Amo(Scw::new_aq)),
+            ("sc.w.rl", Amo(Scw::new_rl)),
+            ("sc.w.aqrl", Amo(Scw::new_aqrl)),
+            ("amoswap.w", Amo(Amoswapw::new)),
+            ("amoswap.w.aq", Amo(Amoswapw::new_aq)),
+            ("amoswap.w.rl", Amo(Amoswapw::new_rl)),
+            ("amoswap.w.aqrl", Amo(Amoswapw::new_aqrl)),
+            ("amoadd.w", Amo(Amoaddw::new)),
+            ("amoadd.w.aq", Amo(Amoaddw::new_aq)),
+            ("amoadd.w.rl", Amo(Amoaddw::new_rl)),
+            ("amoadd.w.aqrl", Amo(Amoaddw::new_aqrl)),
             // === Zicsr ===
-            ("csrrw", Arith(Csrrw::new)),
-            ("csrrs", Arith(Csrrs::new)),
-            ("csrrc", Arith(Csrrc::new)),
-            // ("csrrwi", Arith(Csrrwi::new)),
-            // ("csrrsi", Arith(Csrrsi::new)),
-            // ("csrrci", Arith(Csrrci::new)),
+            ("csrrw", Csr(Csrrw::new)),
+            ("csrrs", Csr(Csrrs::new)),
+            ("csrrc", Csr(Csrrc::new)),
+            ("csrrwi", CsrImm(Csrrwi::expand)),
+            ("csrrsi", CsrImm(Csrrsi::expand)),
+            ("csrrci", CsrImm(Csrrci::expand)),
             // === Zicsr pseudo
-            // ("rdinstret", ???),
-            // ("rdinstreth", ???),
-            // ("rdcycle", ???),
-            // ("rdcycleh", ???),
-            // ("rdtime", ???),
-            // ("rdtimeh", ???),
-            // ("csrr", ???),
-            // ("csrw", ???),
-            // ("csrs", ???),
-            // ("csrc", ???),
-            // ("csrwi", ???),
-            // ("csrsi", ???),
-            // ("csrci", ???),
+            ("rdinstret", OneReg(Rdinstret::expand)),
+            ("rdinstreth", OneReg(Rdinstreth::expand)),
+            ("rdcycle", OneReg(Rdcycle::expand)),
+            ("rdcycleh", OneReg(Rdcycleh::expand)),
+            ("rdtime", OneReg(Rdtime::expand)),
+            ("rdtimeh", OneReg(Rdtimeh::expand)),
+            ("csrr", CsrRead(Csrr::expand)),
+            ("csrw", CsrWrite(Csrw::expand)),
+            ("csrs", CsrWrite(Csrs::expand)),
+            ("csrc", CsrWrite(Csrc::expand)),
+            ("csrwi", CsrWriteImm(Csrwi::expand)),
+            ("csrsi", CsrWriteImm(Csrsi::expand)),
+            ("csrci", CsrWriteImm(Csrci::expand)),
         ]
         .iter()
         .cloned()
@@ -145,24 +219,25 @@ lazy_static! {
             ("blt", B(Blt::new)),
             ("bltu", B(Bltu::new)),
             ("bne", B(Bne::new)),
-            // ("ebreak", Env),
-            // ("fence", ???),
+            ("ebreak", Env(Ebreak::new)),
             ("ecall", Env(Ecall::new)),
+            ("fence", ParseType::Fence),
+            ("fence.i", NoArgs(FenceI::expand)),
             ("jal", ParseType::Jal),
             ("jalr", ParseType::Jalr),
-            ("lb", MemL(Lb::new)),
-            ("lbu", MemL(Lbu::new)),
-            ("ld", MemL(Ld::new)),
-            ("lh", MemL(Lh::new)),
-            ("lhu", MemL(Lhu::new)),
+            ("lb", MemL(Lb::new, LoadLabel::<Lb>::expand_lower)),
+            ("lbu", MemL(Lbu::new, LoadLabel::<Lbu>::expand_lower)),
+            ("ld", MemL(Ld::new, LoadLabel::<Ld>::expand_lower)),
+            ("lh", MemL(Lh::new, LoadLabel::<Lh>::expand_lower)),
+            ("lhu", MemL(Lhu::new, LoadLabel::<Lhu>::expand_lower)),
             ("lui", U(Lui::new)),
-            ("lw", MemL(Lw::new)),
-            ("lwu", MemL(Lwu::new)),
+            ("lw", MemL(Lw::new, LoadLabel::<Lw>::expand_lower)),
+            ("lwu", MemL(Lwu::new, LoadLabel::<Lwu>::expand_lower)),
             ("or", R(Or::new)),
             ("ori", Arith(Ori::new)),
-            ("sb", MemS(Sb::new)),
-            ("sd", MemS(Sd::new)),
-            ("sh", MemS(Sh::new)),
+            ("sb", MemS(Sb::new, StoreLabel::<Sb>::expand_lower)),
+            ("sd", MemS(Sd::new, StoreLabel::<Sd>::expand_lower)),
+            ("sh", MemS(Sh::new, StoreLabel::<Sh>::expand_lower)),
             ("sll", R(Sll::new)),
             ("slli", Arith(Slli::new)),
             ("slliw", Arith(Slliw::new)),
@@ -181,26 +256,47 @@ lazy_static! {
             ("srlw", R(Srlw::new)),
             ("sub", R(Sub::new)),
             ("subw", R(Subw::new)),
-            ("sw", MemS(Sw::new)),
+            ("sw", MemS(Sw::new, StoreLabel::<Sw>::expand_lower)),
             ("xor", R(Xor::new)),
             ("xori", Arith(Xori::new)),
             // === Pseudo ===
             ("la", ParseType::La),
-            ("li", Li(Li64::expand)),
+            ("lla", ParseType::La),
+            ("li", Li(Li64::expand, LoadLabel::<Ld>::expand_lower)),
             ("mv", RegReg(Mv::expand)),
             ("neg", RegReg(Neg::expand)),
             ("nop", NoArgs(Nop::expand)),
+            ("unimp", NoArgs(Unimp::expand)),
             ("not", RegReg(Not::expand)),
             ("j", LikeJ(J::expand)),
             ("jr", OneReg(Jr::expand)),
             ("ret", NoArgs(Ret::expand)),
+            ("seqz", RegReg(Seqz::expand)),
+            ("snez", RegReg(Snez::expand)),
+            ("sltz", RegReg(Sltz::expand)),
+            ("sgtz", RegReg(Sgtz::expand)),
+            ("sgt", R(Sgt::expand)),
+            ("sgtu", R(Sgtu::expand)),
+            ("beqz", BZero(Beqz::expand)),
+            ("bnez", BZero(Bnez::expand)),
+            ("blez", BZero(Blez::expand)),
+            ("bgez", BZero(Bgez::expand)),
+            ("bltz", BZero(Bltz::expand)),
+            ("bgtz", BZero(Bgtz::expand)),
+            ("bgt", B(Bgt::expand)),
+            ("ble", B(Ble::expand)),
+            ("bgtu", B(Bgtu::expand)),
+            ("bleu", B(Bleu::expand)),
+            ("call", LikeCall(Call::expand_upper, Call::expand_lower)),
+            ("tail", LikeCall(Tail::expand_upper, Tail::expand_lower)),
+            ("negw", RegReg(Negw::expand)),
             ("sext.w", RegReg(SextW::expand)),
             // === M extension ===
             ("mul", R(Mul::new)),
             ("mulw", R(Mulw::new)),
-            // ("mulh", R(Mulh::new)),
-            // ("mulhu", R(Mulhu::new)),
-            // ("mulhsu", R(Mulsu::new)),
+            ("mulh", R(Mulh::new)),
+            ("mulhu", R(Mulhu::new)),
+            ("mulhsu", R(Mulhsu::new)),
             ("div", R(Div::new)),
             ("divw", R(Divw::new)),
             ("divu", R(Divu::new)),
@@ -209,27 +305,41 @@ lazy_static! {
             ("remw", R(Remw::new)),
             ("remu", R(Remu::new)),
             ("remuw", R(Remuw::new)),
+            // === A extension ===
+            ("lr.w", LoadReserved(Lrw::new)),
+            ("lr.w.aq", LoadReserved(Lrw::new_aq)),
+            ("lr.w.rl", LoadReserved(Lrw::new_rl)),
+            ("lr.w.aqrl", LoadReserved(Lrw::new_aqrl)),
+            ("sc.w", Amo(Scw::new)),
+            ("sc.w.aq", Amo(Scw::new_aq)),
+            ("sc.w.rl", Amo(Scw::new_rl)),
+            ("sc.w.aqrl", Amo(Scw::new_aqrl)),
+            ("amoswap.w", Amo(Amoswapw::new)),
+            ("amoswap.w.aq", Amo(Amoswapw::new_aq)),
+            ("amoswap.w.rl", Amo(Amoswapw::new_rl)),
+            ("amoswap.w.aqrl", Amo(Amoswapw::new_aqrl)),
+            ("amoadd.w", Amo(Amoaddw::new)),
+            ("amoadd.w.aq", Amo(Amoaddw::new_aq)),
+            ("amoadd.w.rl", Amo(Amoaddw::new_rl)),
+            ("amoadd.w.aqrl", Amo(Amoaddw::new_aqrl)),
             // === Zicsr ===
-            ("csrrw", Arith(Csrrw::new)),
-            ("csrrs", Arith(Csrrs::new)),
-            ("csrrc", Arith(Csrrc::new)),
-            // ("csrrwi", Arith(Csrrwi::new)),
-            // ("csrrsi", Arith(Csrrsi::new)),
-            // ("csrrci", Arith(Csrrci::new)),
+            ("csrrw", Csr(Csrrw::new)),
+            ("csrrs", Csr(Csrrs::new)),
+            ("csrrc", Csr(Csrrc::new)),
+            ("csrrwi", CsrImm(Csrrwi::expand)),
+            ("csrrsi", CsrImm(Csrrsi::expand)),
+            ("csrrci", CsrImm(Csrrci::expand)),
             // === Zicsr pseudo
-            // ("rdinstret", ???),
-            // ("rdinstreth", ???),
-            // ("rdcycle", ???),
-            // ("rdcycleh", ???),
-            // ("rdtime", ???),
-            // ("rdtimeh", ???),
-            // ("csrr", ???),
-            // ("csrw", ???),
-            // ("csrs", ???),
-            // ("csrc", ???),
-            // ("csrwi", ???),
-            // ("csrsi", ???),
-            // ("csrci", ???),
+            ("rdinstret", OneReg(Rdinstret::expand)),
+            ("rdcycle", OneReg(Rdcycle::expand)),
+            ("rdtime", OneReg(Rdtime::expand)),
+            ("csrr", CsrRead(Csrr::expand)),
+            ("csrw", CsrWrite(Csrw::expand)),
+            ("csrs", CsrWrite(Csrs::expand)),
+            ("csrc", CsrWrite(Csrc::expand)),
+            ("csrwi", CsrWriteImm(Csrwi::expand)),
+            ("csrsi", CsrWriteImm(Csrsi::expand)),
+            ("csrci", CsrWriteImm(Csrci::expand)),
         ]
         .iter()
         .cloned()
@@ -254,10 +364,26 @@ lazy_static! {
 /// Contains arguments for a memory operation (load or store).
 /// The registers correspond to the order in which they appear: for stores, RS2 precedes RS1;
 /// for loads, RD preceds RS1.
-struct MemArgs<S: AtLeast32b> {
-    first_reg: RiscVRegister,
-    second_reg: RiscVRegister,
-    imm: RegValue<S>,
+enum MemArgs<S: AtLeast32b> {
+    /// An offset from a register, e.g. "lw a0, 4(sp)".
+    Offset {
+        first_reg: RiscVRegister,
+        second_reg: RiscVRegister,
+        imm: RegValue<S>,
+    },
+    /// A label, e.g. "lw a0, label" or "sw a0, label, t0". The address is computed with an
+    /// auipc into the temporary register, which only stores need because loads reuse RD.
+    Label {
+        first_reg: RiscVRegister,
+        label: LabelRef,
+        temp_reg: Option<RiscVRegister>,
+    },
+    /// Part of an address as the offset from a register, e.g. "lw a0, %lo(label)(a5)".
+    AddrPart {
+        first_reg: RiscVRegister,
+        second_reg: RiscVRegister,
+        addr: AddrPart<S>,
+    },
 }
 
 enum ImmOrLabelRef<S: AtLeast32b> {
@@ -265,6 +391,46 @@ enum ImmOrLabelRef<S: AtLeast32b> {
     LabelRef(LabelRef),
 }
 
+/// Part of the absolute address of a label or constant, e.g. "%hi(label)" or "%lo(0x1234)".
+struct AddrPart<S: AtLeast32b> {
+    target: ImmOrLabelRef<S>,
+    part: fn(RegValue<S>) -> RegValue<S>,
+}
+
+impl<S: AtLeast32b> AddrPart<S> {
+    /// Produces an instruction with one register that takes this part of the address.
+    fn one_reg(
+        self,
+        assemble: fn(RiscVRegister, RegValue<S>) -> RiscVInst<S>,
+        reg: RiscVRegister,
+    ) -> PartialInst<RiscV<S>, S> {
+        match self.target {
+            ImmOrLabelRef::Imm(imm) => PartialInst::new_complete(assemble(reg, (self.part)(imm))),
+            ImmOrLabelRef::LabelRef(label) => {
+                PartialInst::new_one_reg_needs_label(assemble, reg, label).with_addr_part(self.part)
+            }
+        }
+    }
+
+    /// Produces an instruction with two registers that takes this part of the address.
+    fn two_reg(
+        self,
+        assemble: fn(RiscVRegister, RiscVRegister, RegValue<S>) -> RiscVInst<S>,
+        reg1: RiscVRegister,
+        reg2: RiscVRegister,
+    ) -> PartialInst<RiscV<S>, S> {
+        match self.target {
+            ImmOrLabelRef::Imm(imm) => {
+                PartialInst::new_complete(assemble(reg1, reg2, (self.part)(imm)))
+            }
+            ImmOrLabelRef::LabelRef(label) => {
+                PartialInst::new_two_reg_needs_label(assemble, reg1, reg2, label)
+                    .with_addr_part(self.part)
+            }
+        }
+    }
+}
+
 pub struct RiscVInstParser<S: AtLeast32b> {
     _phantom: PhantomData<S>,
 }
@@ -282,6 +448,10 @@ impl InstParser<RiscV<W32b>, W32b> for RiscVInstParser<W32b> {
         &REG_EXPANSION_TABLE
     }
 
+    fn nop() -> RiscVInst<W32b> {
+        Nop::expand()
+    }
+
     fn try_expand_found_inst(
         state: RvInstParseState<'_, W32b>,
         parse_type: &ParseType<W32b>,
@@ -301,6 +471,10 @@ impl InstParser<RiscV<W64b>, W64b> for RiscVInstParser<W64b> {
         &REG_EXPANSION_TABLE
     }
 
+    fn nop() -> RiscVInst<W64b> {
+        Nop::expand()
+    }
+
     fn try_expand_found_inst(
         state: RvInstParseState<'_, W64b>,
         parse_type: &ParseType<W64b>,
@@ -310,9 +484,101 @@ impl InstParser<RiscV<W64b>, W64b> for RiscVInstParser<W64b> {
 }
 
 impl<S: AtLeast32b> RiscVInstParser<S> {
+    /// Parses the number of a control register, which may also be given by name.
+    fn try_parse_csr(
+        state: &RvInstParseState<'_, S>,
+        token: Token,
+    ) -> Result<RegValue<S>, ParseError> {
+        if let TokenType::Name(name) = &token.data {
+            if let Some(&(_, csr)) = CSR_NAMES.iter().find(|(csr_name, _)| csr_name == name) {
+                return Ok((csr as i64).into());
+            }
+        }
+        state.try_parse_imm(12, token)
+    }
+
+    /// Parses the 5-bit unsigned immediate of a CSR instruction like "csrrwi".
+    fn try_parse_csr_uimm(token: Token) -> Result<RegValue<S>, ParseError> {
+        match token.data {
+            TokenType::Immediate(val, _) if (0..32).contains(&val) => Ok(val.into()),
+            TokenType::Immediate(val, radix) => Err(ParseError::imm_too_big(
+                ErrMetadata::new(&token.location),
+                5,
+                &radix.format(val),
+            )),
+            _ => Err(ParseError::unexpected_type(
+                ErrMetadata::new(&token.location),
+                "unsigned immediate",
+                token.data,
+            )),
+        }
+    }
+
+    /// Consumes a comma, if one comes next.
+    fn skip_comma(state: &mut RvInstParseState<'_, S>) {
+        if let Some(TokenType::Comma) = state.iter.peek().map(|tok| &tok.data) {
+            state.iter.next();
+        }
+    }
+
+    /// Consumes part of the address of a label or constant, i.e. "%hi(label)" or "%lo(label)",
+    /// if one comes next.
+    fn try_consume_addr_part(
+        state: &mut RvInstParseState<'_, S>,
+        needed_args: u8,
+        found_so_far: u8,
+    ) -> Result<Option<AddrPart<S>>, ParseError> {
+        match &state.try_peek_tok(needed_args, found_so_far)?.data {
+            TokenType::Name(name) if name.starts_with('%') => {}
+            _ => return Ok(None),
+        }
+        let func_tok = state.try_next_tok(needed_args, found_so_far)?;
+        let part: fn(RegValue<S>) -> RegValue<S> = match &func_tok.data {
+            TokenType::Name(name) if name == "%hi" => isa::addr_hi,
+            TokenType::Name(name) if name == "%lo" => isa::addr_lo,
+            _ => {
+                return Err(ParseError::unexpected_type(
+                    ErrMetadata::new(&func_tok.location),
+                    "%hi or %lo",
+                    func_tok.data,
+                ))
+            }
+        };
+        let lparen = state.try_next_tok(needed_args, found_so_far)?;
+        if !matches!(lparen.data, TokenType::LParen) {
+            return Err(ParseError::unexpected_type(
+                ErrMetadata::new(&lparen.location),
+                "\"(\"",
+                lparen.data,
+            ));
+        }
+        let target_tok = state.try_next_tok(needed_args, found_so_far)?;
+        let target = Self::try_parse_imm_or_label_ref(state, 32, target_tok)?;
+        Self::consume_rparen(state, needed_args, found_so_far)?;
+        Ok(Some(AddrPart { target, part }))
+    }
+
+    /// Consumes a closing parenthesis.
+    fn consume_rparen(
+        state: &mut RvInstParseState<'_, S>,
+        needed_args: u8,
+        found_so_far: u8,
+    ) -> Result<(), ParseError> {
+        let maybe_rparen = state.try_next_tok(needed_args, found_so_far)?;
+        if let TokenType::RParen = maybe_rparen.data {
+            Ok(())
+        } else {
+            Err(ParseError::unclosed_paren(
+                ErrMetadata::new(&maybe_rparen.location),
+                maybe_rparen.data,
+            ))
+        }
+    }
+
     /// Consumes tokens for arguments for a memory operation.
-    /// These are either of the form "inst reg, imm, reg)" e.g. "lw x1 -4 x2"
-    /// or "inst reg, (imm)reg" e.g "lw x1, 4(x2)" (commas optional in both cases)
+    /// These are either of the form "inst reg, imm, reg)" e.g. "lw x1 -4 x2",
+    /// "inst reg, (imm)reg" e.g "lw x1, 4(x2)" (commas optional in both cases),
+    /// or "inst reg, label[, reg]" e.g. "sw x1, label, x2".
     fn consume_mem_args(state: &mut RvInstParseState<'_, S>) -> Result<MemArgs<S>, ParseError> {
         // first consumed token must be register name
         let first_tok = state.try_next_tok(3, 0)?;
@@ -322,11 +588,71 @@ impl<S: AtLeast32b> RiscVInstParser<S> {
         if let TokenType::Comma = maybe_comma.data {
             state.iter.next();
         }
-        // must be immediate here
-        let imm_tok = state.try_next_tok(3, 1)?;
-        let imm = state.try_parse_imm(12, imm_tok)?;
+        if let Some(addr) = Self::try_consume_addr_part(state, 3, 1)? {
+            // the base register must be parenthesized, e.g. "%lo(label)(a5)"
+            let lparen = state.try_next_tok(3, 2)?;
+            if !matches!(lparen.data, TokenType::LParen) {
+                return Err(ParseError::unexpected_type(
+                    ErrMetadata::new(&lparen.location),
+                    "\"(\"",
+                    lparen.data,
+                ));
+            }
+            let reg_tok = state.try_next_tok(3, 2)?;
+            let second_reg = state.try_parse_reg(reg_tok)?;
+            Self::consume_rparen(state, 3, 2)?;
+            state.check_no_more_args(3)?;
+            return Ok(MemArgs::AddrPart {
+                first_reg,
+                second_reg,
+                addr,
+            });
+        }
+        if let TokenType::Name(..) | TokenType::Directive(..) = state.try_peek_tok(3, 1)?.data {
+            let mut args = state.consume_unbounded_commasep_args()?;
+            let argc = args.len() as u8 + 1;
+            if argc > 3 {
+                return Err(ParseError::too_many_args(
+                    ErrMetadata::new(state.head_loc),
+                    state.inst_name,
+                    3,
+                ));
+            }
+            let label = match Self::try_parse_imm_or_label_ref(state, 32, args.remove(0))? {
+                ImmOrLabelRef::LabelRef(label) => label,
+                ImmOrLabelRef::Imm(..) => unreachable!("names always parse as labels"),
+            };
+            let temp_reg = args.pop().map(|tok| state.try_parse_reg(tok)).transpose()?;
+            return Ok(MemArgs::Label {
+                first_reg,
+                label,
+                temp_reg,
+            });
+        }
+        let (imm, second_reg) = Self::consume_offset_args(state, 3, 1)?;
+        Ok(MemArgs::Offset {
+            first_reg,
+            second_reg,
+            imm,
+        })
+    }
+
+    /// Consumes an offset and base register, e.g. "4(x2)", "4 x2", or "(x2)".
+    /// No arguments may follow.
+    fn consume_offset_args(
+        state: &mut RvInstParseState<'_, S>,
+        needed_args: u8,
+        found_so_far: u8,
+    ) -> Result<(RegValue<S>, RiscVRegister), ParseError> {
+        // an omitted immediate is zero
+        let imm = if let TokenType::LParen = state.try_peek_tok(needed_args, found_so_far)?.data {
+            RegValue::<S>::zero()
+        } else {
+            let imm_tok = state.try_next_tok(needed_args, found_so_far)?;
+            state.try_parse_imm(12, imm_tok)?
+        };
         // check for lparen
-        let maybe_lparen = state.try_peek_tok(3, 2)?;
+        let maybe_lparen = state.try_peek_tok(needed_args, found_so_far + 1)?;
         let is_lparen = if let TokenType::LParen = maybe_lparen.data {
             state.iter.next();
             true
@@ -334,10 +660,10 @@ impl<S: AtLeast32b> RiscVInstParser<S> {
             false
         };
         // must be a register here
-        let reg2_tok = state.try_next_tok(3, 2)?;
-        let second_reg = state.try_parse_reg(reg2_tok)?;
+        let reg_tok = state.try_next_tok(needed_args, found_so_far + 1)?;
+        let reg = state.try_parse_reg(reg_tok)?;
         if is_lparen {
-            let maybe_rparen = state.try_next_tok(3, 2)?;
+            let maybe_rparen = state.try_next_tok(needed_args, found_so_far + 1)?;
             if let TokenType::RParen = maybe_rparen.data {
             } else {
                 return Err(ParseError::unclosed_paren(
@@ -347,11 +673,89 @@ impl<S: AtLeast32b> RiscVInstParser<S> {
             }
         }
         // Any trailing token must be a comment
-        state.check_no_more_args(3).and(Ok(MemArgs {
-            first_reg,
-            second_reg,
-            imm,
-        }))
+        state.check_no_more_args(needed_args).and(Ok((imm, reg)))
+    }
+
+    /// Consumes the address of an atomic memory operation, e.g. "(x2)" or "0(x2)", which may have
+    /// no offset other than 0. No arguments may follow.
+    fn consume_amo_addr(
+        state: &mut RvInstParseState<'_, S>,
+        needed_args: u8,
+        found_so_far: u8,
+    ) -> Result<RiscVRegister, ParseError> {
+        let (imm, reg) = Self::consume_offset_args(state, needed_args, found_so_far)?;
+        if imm != RegValue::<S>::zero() {
+            return Err(ParseError::generic(
+                ErrMetadata::new(state.head_loc),
+                &format!("atomic memory operations take no offset, got {}", imm),
+            ));
+        }
+        Ok(reg)
+    }
+
+    /// Checks that a branch offset is a multiple of two.
+    fn check_branch_imm(
+        state: &RvInstParseState<'_, S>,
+        imm: RegValue<S>,
+    ) -> Result<RegValue<S>, ParseError> {
+        if u8::from(imm.get_byte(0)) & 1 > 0 {
+            Err(ParseError::generic(
+                ErrMetadata::new(state.head_loc),
+                &format!("branch immediates must be multiples of two, got {}", imm,),
+            ))
+        } else {
+            // LSB chopping is handled by instruction
+            Ok(imm)
+        }
+    }
+
+    /// Parses the predecessor or successor set of a fence, e.g. "rw".
+    fn try_parse_fence_set(token: Token) -> Result<u32, ParseError> {
+        if let TokenType::Name(name) = &token.data {
+            let mut set = 0;
+            for c in name.chars() {
+                let bit = match c {
+                    'i' => 0b1000,
+                    'o' => 0b0100,
+                    'r' => 0b0010,
+                    'w' => 0b0001,
+                    _ => 0,
+                };
+                if bit == 0 || set & bit > 0 {
+                    set = 0;
+                    break;
+                }
+                set |= bit;
+            }
+            if set > 0 {
+                return Ok(set);
+            }
+        }
+        Err(ParseError::unexpected_type(
+            ErrMetadata::new(&token.location),
+            "fence set (some of \"iorw\")",
+            token.data,
+        ))
+    }
+
+    /// Produces an auipc followed by an instruction that adds the lower bits of the offset
+    /// to a pc-relative target.
+    fn expand_pcrel_pair(
+        target: ImmOrLabelRef<S>,
+        upper: fn(RegValue<S>) -> RiscVInst<S>,
+        lower: fn(RegValue<S>) -> RiscVInst<S>,
+    ) -> Vec<PartialInst<RiscV<S>, S>> {
+        match target {
+            ImmOrLabelRef::Imm(imm) => vec![
+                PartialInst::new_complete(upper(imm)),
+                // The second instruction is 4 bytes further from the target
+                PartialInst::new_complete(lower(imm + RegValue::<S>::from(-4i64))),
+            ],
+            ImmOrLabelRef::LabelRef(tgt_label) => vec![
+                PartialInst::new_no_reg_needs_label(upper, tgt_label.clone()),
+                PartialInst::new_no_reg_needs_label(lower, tgt_label),
+            ],
+        }
     }
 
     /// Attempts to expand a token into a label reference or an immediate of at most max_imm_len.
@@ -397,9 +801,18 @@ impl<S: AtLeast32b> RiscVInstParser<S> {
                 ok_wrap_concr(inst_new(rd, rs1, rs2))
             }
             Arith(inst_new) => {
-                let mut args = state.consume_commasep_args(3)?;
-                let rd = state.try_parse_reg(args.remove(0))?;
-                let rs1 = state.try_parse_reg(args.remove(0))?;
+                let rd_tok = state.try_next_tok(3, 0)?;
+                let rd = state.try_parse_reg(rd_tok)?;
+                Self::skip_comma(state);
+                let rs1_tok = state.try_next_tok(3, 1)?;
+                let rs1 = state.try_parse_reg(rs1_tok)?;
+                Self::skip_comma(state);
+                if let Some(addr) = Self::try_consume_addr_part(state, 3, 2)? {
+                    // e.g. "addi a0, a0, %lo(label)"
+                    state.check_no_more_args(3)?;
+                    return ok_vec(addr.two_reg(*inst_new, rd, rs1));
+                }
+                let mut args = state.consume_commasep_args(1)?;
                 let imm = state.try_parse_imm(12, args.remove(0))?;
                 ok_wrap_concr(inst_new(rd, rs1, imm))
             }
@@ -407,20 +820,58 @@ impl<S: AtLeast32b> RiscVInstParser<S> {
                 let _args = state.consume_commasep_args(0)?;
                 ok_wrap_concr(inst_new())
             }
-            MemL(inst_new) => {
-                let args = Self::consume_mem_args(state)?;
-                let rd = args.first_reg;
-                let rs1 = args.second_reg;
-                let imm = args.imm;
-                ok_wrap_concr(inst_new(rd, rs1, imm))
-            }
-            MemS(inst_new) => {
-                let args = Self::consume_mem_args(state)?;
-                let rs2 = args.first_reg;
-                let rs1 = args.second_reg;
-                let imm = args.imm;
-                ok_wrap_concr(inst_new(rs1, rs2, imm))
-            }
+            MemL(inst_new, label_lower) => match Self::consume_mem_args(state)? {
+                MemArgs::Offset {
+                    first_reg: rd,
+                    second_reg: rs1,
+                    imm,
+                } => ok_wrap_concr(inst_new(rd, rs1, imm)),
+                MemArgs::AddrPart {
+                    first_reg: rd,
+                    second_reg: rs1,
+                    addr,
+                } => ok_vec(addr.two_reg(*inst_new, rd, rs1)),
+                MemArgs::Label {
+                    first_reg: rd,
+                    label,
+                    temp_reg: None,
+                } => Ok(vec![
+                    PartialInst::new_one_reg_needs_label(isa::La::expand_upper, rd, label.clone()),
+                    PartialInst::new_one_reg_needs_label(*label_lower, rd, label),
+                ]),
+                MemArgs::Label { .. } => Err(ParseError::too_many_args(
+                    ErrMetadata::new(state.head_loc),
+                    state.inst_name,
+                    2,
+                )),
+            },
+            MemS(inst_new, label_lower) => match Self::consume_mem_args(state)? {
+                MemArgs::Offset {
+                    first_reg: rs2,
+                    second_reg: rs1,
+                    imm,
+                } => ok_wrap_concr(inst_new(rs1, rs2, imm)),
+                MemArgs::AddrPart {
+                    first_reg: rs2,
+                    second_reg: rs1,
+                    addr,
+                } => ok_vec(addr.two_reg(*inst_new, rs1, rs2)),
+                MemArgs::Label {
+                    first_reg: rs2,
+                    label,
+                    temp_reg: Some(rt),
+                } => Ok(vec![
+                    PartialInst::new_one_reg_needs_label(isa::La::expand_upper, rt, label.clone()),
+                    PartialInst::new_two_reg_needs_label(*label_lower, rt, rs2, label),
+                ]),
+                MemArgs::Label { .. } => Err(ParseError::generic(
+                    ErrMetadata::new(state.head_loc),
+                    &format!(
+                        "storing to a label requires a temporary register, e.g. \"{} a0, label, t0\"",
+                        state.inst_name
+                    ),
+                )),
+            },
             B(inst_new) => {
                 let mut args = state.consume_commasep_args(3)?;
                 let rs1 = state.try_parse_reg(args.remove(0))?;
@@ -429,21 +880,26 @@ impl<S: AtLeast32b> RiscVInstParser<S> {
                 let last_arg = Self::try_parse_imm_or_label_ref(state, 13, args.remove(0))?;
                 match last_arg {
                     ImmOrLabelRef::Imm(imm) => {
-                        if u8::from(imm.get_byte(0)) & 1 > 0 {
-                            Err(ParseError::generic(
-                                ErrMetadata::new(state.head_loc),
-                                &format!("branch immediates must be multiples of two, got {}", imm,),
-                            ))
-                        } else {
-                            // LSB chopping is handled by instruction
-                            ok_wrap_concr(inst_new(rs1, rs2, imm))
-                        }
+                        ok_wrap_concr(inst_new(rs1, rs2, Self::check_branch_imm(state, imm)?))
                     }
                     ImmOrLabelRef::LabelRef(tgt_label) => ok_vec(
                         PartialInst::new_two_reg_needs_label(*inst_new, rs1, rs2, tgt_label),
                     ),
                 }
             }
+            BZero(inst_expand) => {
+                let mut args = state.consume_commasep_args(2)?;
+                let rs = state.try_parse_reg(args.remove(0))?;
+                let last_arg = Self::try_parse_imm_or_label_ref(state, 13, args.remove(0))?;
+                match last_arg {
+                    ImmOrLabelRef::Imm(imm) => {
+                        ok_wrap_concr(inst_expand(rs, Self::check_branch_imm(state, imm)?))
+                    }
+                    ImmOrLabelRef::LabelRef(tgt_label) => ok_vec(
+                        PartialInst::new_one_reg_needs_label(*inst_expand, rs, tgt_label),
+                    ),
+                }
+            }
             Jal => {
                 let mut args = state.consume_unbounded_commasep_args()?;
                 let argc = args.len();
@@ -480,33 +936,53 @@ impl<S: AtLeast32b> RiscVInstParser<S> {
                 }
             }
             Jalr => {
-                let mut args = state.consume_unbounded_commasep_args()?;
-                let argc = args.len();
-                match argc {
-                    1 => {
+                let first_tok = state.try_next_tok(1, 0)?;
+                let first_reg = state.try_parse_reg(first_tok)?;
+                if let Some(TokenType::Comma) = state.iter.peek().map(|tok| &tok.data) {
+                    state.iter.next();
+                }
+                match state.iter.peek().map(|tok| &tok.data) {
+                    None | Some(TokenType::Comment(..)) => {
                         // "jalr rs"
-                        let rs = state.try_parse_reg(args.remove(0))?;
-                        ok_wrap_concr(JalrPseudo::expand(rs))
+                        ok_wrap_concr(JalrPseudo::expand(first_reg))
                     }
-                    3 => {
-                        // "jalr rd, rs, imm"
-                        let rd = state.try_parse_reg(args.remove(0))?;
+                    Some(TokenType::Name(..)) => {
+                        // "jalr rd, rs, imm" or "jalr rd, rs"
+                        let mut args = state.consume_unbounded_commasep_args()?;
+                        let argc = args.len() + 1;
+                        if argc != 2 && argc != 3 {
+                            return Err(ParseError::wrong_diff_argc(
+                                ErrMetadata::new(state.head_loc),
+                                state.inst_name,
+                                1,
+                                3,
+                                argc as u8,
+                            ));
+                        }
                         let rs1 = state.try_parse_reg(args.remove(0))?;
-                        let imm = state.try_parse_imm(12, args.remove(0))?;
-                        ok_wrap_concr(isa::Jalr::new(rd, rs1, imm))
+                        let imm = match args.pop() {
+                            Some(tok) => state.try_parse_imm(12, tok)?,
+                            None => RegValue::<S>::zero(),
+                        };
+                        ok_wrap_concr(isa::Jalr::new(first_reg, rs1, imm))
+                    }
+                    _ => {
+                        // "jalr rd, imm(rs)"
+                        let (imm, rs1) = Self::consume_offset_args(state, 3, 1)?;
+                        ok_wrap_concr(isa::Jalr::new(first_reg, rs1, imm))
                     }
-                    _ => Err(ParseError::wrong_diff_argc(
-                        ErrMetadata::new(state.head_loc),
-                        state.inst_name,
-                        1,
-                        3,
-                        argc as u8,
-                    )),
                 }
             }
             U(inst_new) => {
-                let mut args = state.consume_commasep_args(2)?;
-                let rd = state.try_parse_reg(args.remove(0))?;
+                let rd_tok = state.try_next_tok(2, 0)?;
+                let rd = state.try_parse_reg(rd_tok)?;
+                Self::skip_comma(state);
+                if let Some(addr) = Self::try_consume_addr_part(state, 2, 1)? {
+                    // e.g. "lui a0, %hi(label)"
+                    state.check_no_more_args(2)?;
+                    return ok_vec(addr.one_reg(*inst_new, rd));
+                }
+                let mut args = state.consume_commasep_args(1)?;
                 let imm = state.try_parse_imm(20, args.remove(0))?;
                 ok_wrap_concr(inst_new(rd, imm))
             }
@@ -535,11 +1011,37 @@ impl<S: AtLeast32b> RiscVInstParser<S> {
                     ]),
                 }
             }
-            Li(inst_expand) => {
+            Li(inst_expand, load_lower) => {
                 let mut args = state.consume_commasep_args(2)?;
                 let rd = state.try_parse_reg(args.remove(0))?;
-                let imm = state.try_parse_imm(32, args.remove(0))?;
-                ok_wrap_expanded(inst_expand(rd, imm))
+                let width = if S::is_32() { 32 } else { 64 };
+                // Converting from i64 would sign-extend constants that fit in 32 bits, such as
+                // 0xFFFF_FFFF, so the raw bits are used instead
+                let imm = RegValue::<S>::from(try_parse_imm(width, args.remove(0))? as u64);
+                let insts = inst_expand(rd, imm);
+                let head_loc = state.head_loc;
+                match state.literal_pool.as_mut() {
+                    // Loading from the pool takes an auipc and a load, so it only helps
+                    // when building the constant would take more instructions
+                    Some(pool) if insts.len() > 2 => {
+                        let raw: u64 = imm.as_unsigned().raw().as_();
+                        let val = if S::is_32() {
+                            DataEnum::Lword(DataLword::from(raw as u32))
+                        } else {
+                            DataEnum::Dword(DataDword::from(raw))
+                        };
+                        let label = pool.add(val, head_loc);
+                        Ok(vec![
+                            PartialInst::new_one_reg_needs_label(
+                                isa::La::expand_upper,
+                                rd,
+                                label.clone(),
+                            ),
+                            PartialInst::new_one_reg_needs_label(*load_lower, rd, label),
+                        ])
+                    }
+                    _ => ok_wrap_expanded(insts),
+                }
             }
             NoArgs(inst_expand) => {
                 let _args = state.consume_commasep_args(0)?;
@@ -567,6 +1069,95 @@ impl<S: AtLeast32b> RiscVInstParser<S> {
                 let rs = state.try_parse_reg(args.remove(0))?;
                 ok_wrap_concr(inst_expand(rs))
             }
+            LikeCall(upper, lower) => {
+                let mut args = state.consume_commasep_args(1)?;
+                let target = Self::try_parse_imm_or_label_ref(state, 32, args.remove(0))?;
+                Ok(Self::expand_pcrel_pair(target, *upper, *lower))
+            }
+            Fence => {
+                let mut args = state.consume_unbounded_commasep_args()?;
+                let imm = match args.len() {
+                    // a bare fence orders everything
+                    0 => 0xFF,
+                    2 => {
+                        let pred = Self::try_parse_fence_set(args.remove(0))?;
+                        let succ = Self::try_parse_fence_set(args.remove(0))?;
+                        (pred << 4) | succ
+                    }
+                    argc => {
+                        return Err(ParseError::wrong_diff_argc(
+                            ErrMetadata::new(state.head_loc),
+                            state.inst_name,
+                            0,
+                            2,
+                            argc as u8,
+                        ))
+                    }
+                };
+                ok_wrap_concr(isa::Fence::new(
+                    RiscVRegister::Zero,
+                    RiscVRegister::Zero,
+                    (imm as i64).into(),
+                ))
+            }
+            LoadReserved(inst_new) => {
+                let rd_tok = state.try_next_tok(2, 0)?;
+                let rd = state.try_parse_reg(rd_tok)?;
+                Self::skip_comma(state);
+                let rs1 = Self::consume_amo_addr(state, 2, 1)?;
+                ok_wrap_concr(inst_new(rd, rs1, RiscVRegister::Zero))
+            }
+            Amo(inst_new) => {
+                let rd_tok = state.try_next_tok(3, 0)?;
+                let rd = state.try_parse_reg(rd_tok)?;
+                Self::skip_comma(state);
+                let rs2_tok = state.try_next_tok(3, 1)?;
+                let rs2 = state.try_parse_reg(rs2_tok)?;
+                Self::skip_comma(state);
+                let rs1 = Self::consume_amo_addr(state, 3, 2)?;
+                ok_wrap_concr(inst_new(rd, rs1, rs2))
+            }
+            CsrRead(inst_expand) => {
+                let mut args = state.consume_commasep_args(2)?;
+                let rd = state.try_parse_reg(args.remove(0))?;
+                let csr = Self::try_parse_csr(state, args.remove(0))?;
+                ok_wrap_concr(inst_expand(rd, csr))
+            }
+            CsrWrite(inst_expand) => {
+                let mut args = state.consume_commasep_args(2)?;
+                let csr = Self::try_parse_csr(state, args.remove(0))?;
+                let rs = state.try_parse_reg(args.remove(0))?;
+                ok_wrap_concr(inst_expand(csr, rs))
+            }
+            Csr(inst_new) => {
+                let mut args = state.consume_commasep_args(3)?;
+                let rd = state.try_parse_reg(args.remove(0))?;
+                // the csr comes second, as in GCC output, unless the source register does
+                let (rs1, csr) = match args[1].data {
+                    TokenType::Name(ref name) if REG_EXPANSION_TABLE.contains_key(name) => {
+                        let csr = Self::try_parse_csr(state, args.remove(0))?;
+                        (state.try_parse_reg(args.remove(0))?, csr)
+                    }
+                    _ => {
+                        let rs1 = state.try_parse_reg(args.remove(0))?;
+                        (rs1, Self::try_parse_csr(state, args.remove(0))?)
+                    }
+                };
+                ok_wrap_concr(inst_new(rd, rs1, csr))
+            }
+            CsrImm(inst_expand) => {
+                let mut args = state.consume_commasep_args(3)?;
+                let rd = state.try_parse_reg(args.remove(0))?;
+                let csr = Self::try_parse_csr(state, args.remove(0))?;
+                let uimm = Self::try_parse_csr_uimm(args.remove(0))?;
+                ok_wrap_concr(inst_expand(rd, csr, uimm))
+            }
+            CsrWriteImm(inst_expand) => {
+                let mut args = state.consume_commasep_args(2)?;
+                let csr = Self::try_parse_csr(state, args.remove(0))?;
+                let uimm = Self::try_parse_csr_uimm(args.remove(0))?;
+                ok_wrap_concr(inst_expand(csr, uimm))
+            }
         }
     }
 }
@@ -619,19 +1210,20 @@ mod tests {
             Addi::new(Sp, Sp, DataLword::from(4)),
         ];
         assert_eq!(insts.len(), 3);
-        assert_eq!(insts[0].label, None);
+        assert_eq!(insts[0].labels, vec![]);
         assert_eq!(
-            insts[1].label,
-            Some(LabelDef::new(
+            insts[1].labels,
+            vec![LabelDef::new(
                 "l1".to_string(),
                 Location {
                     file_id: 0,
                     lineno: 1,
-                    offs: 0
+                    offs: 0,
+                    call_site: None,
                 }
-            ))
+            )]
         );
-        assert_eq!(insts[2].label, None);
+        assert_eq!(insts[2].labels, vec![]);
         // TODO handle label at end
         // assert_eq!(insts[3].label, Some("l2".to_string()));
         for (partial_inst, exp_inst) in insts.into_iter().zip(expected_concrete.iter()) {
@@ -711,4 +1303,192 @@ mod tests {
         let insts = parse_and_lex_concr::<Rv32>("li a0, 0xDEAD_BEEF");
         assert_eq!(insts, Li32::expand(A0, DataLword::from(0xDEAD_BEEFu32)));
     }
+
+    #[test]
+    /// Tests forms of instructions produced by GCC and objdump.
+    fn test_gcc_forms() {
+        let insts = parse_and_lex_concr::<Rv32>(
+            "jalr ra, 0(x1)\njalr t0, (a0)\nlw a0, (sp)\nbgt a0, a1, 8\nbnez a0, -4\nfence r, rw",
+        );
+        assert_eq!(
+            insts,
+            vec![
+                Jalr::new(Ra, Ra, DataLword::zero()),
+                Jalr::new(T0, A0, DataLword::zero()),
+                Lw::new(A0, Sp, DataLword::zero()),
+                Blt::new(A1, A0, DataLword::from(8)),
+                Bne::new(A0, Zero, DataLword::from(-4)),
+                Fence::new(Zero, Zero, DataLword::from(0x23)),
+            ]
+        );
+        // accesses to labels need an auipc first
+        let insts = parse_and_lex::<Rv32>("lw a0, l\nsw a0, l, t0\ncall l\ntail l\nl: nop");
+        assert_eq!(insts.len(), 9);
+        for inst in &insts[..8] {
+            assert_eq!(inst.get_needed_label().unwrap().target, "l");
+        }
+    }
+
+    #[test]
+    fn test_m_forms() {
+        let insts = parse_and_lex_concr::<Rv32>(
+            "mulh a0, a1, a2\nmulhu a0, a1, a2\nmulhsu a0, a1, a2\nebreak",
+        );
+        assert_eq!(
+            insts,
+            vec![
+                Mulh::new(A0, A1, A2),
+                Mulhu::new(A0, A1, A2),
+                Mulhsu::new(A0, A1, A2),
+                Ebreak::new(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csr_names() {
+        let insts = parse_and_lex_concr::<Rv32>(
+            "csrr a0, cycle\ncsrr a0, instreth\ncsrw mscratch, t0\ncsrrw a0, mepc, a1\n\
+             csrrsi a0, mstatus, 8\ncsrs mtvec, a1\ncsrr a0, mcause",
+        );
+        let csr = |n: u32| DataLword::from(n);
+        assert_eq!(
+            insts,
+            vec![
+                Csrrs::new(A0, Zero, csr(0xC00)),
+                Csrrs::new(A0, Zero, csr(0xC82)),
+                Csrrw::new(Zero, T0, csr(0x340)),
+                Csrrw::new(A0, A1, csr(0x341)),
+                Csrrsi::expand(A0, csr(0x300), csr(8)),
+                Csrrs::new(Zero, A1, csr(0x305)),
+                Csrrs::new(A0, Zero, csr(0x342)),
+            ]
+        );
+        // names that are not CSRs are still rejected
+        let ParseResult { reporter, .. } = Parser::<Rv32>::parse_str(0, "csrr a0, cycles");
+        assert!(!reporter.is_empty());
+    }
+
+    #[test]
+    fn test_csr_forms() {
+        let insts = parse_and_lex_concr::<Rv32>(
+            "csrw mhartid, a0\ncsrwi 0x340, 5\ncsrsi 0x340, 1\ncsrci 0x340, 31\n\
+             csrrwi a0, 0x340, 2\ncsrrsi a0, 0x340, 3\ncsrrci a0, 0x340, 4",
+        );
+        let csr = DataLword::from(0x340);
+        let uimm = |n: u32| DataLword::from(n);
+        assert_eq!(
+            insts,
+            vec![
+                Csrrw::new(Zero, A0, DataLword::from(0xF14)),
+                Csrrwi::expand(Zero, csr, uimm(5)),
+                Csrrsi::expand(Zero, csr, uimm(1)),
+                Csrrci::expand(Zero, csr, uimm(31)),
+                Csrrwi::expand(A0, csr, uimm(2)),
+                Csrrsi::expand(A0, csr, uimm(3)),
+                Csrrci::expand(A0, csr, uimm(4)),
+            ]
+        );
+        assert_eq!(format!("{}", insts[1]), "csrrwi zero, mscratch, 5");
+        // objdump puts the csr before the source register, but the reverse is also accepted
+        let insts = parse_and_lex_concr::<Rv32>(
+            "csrrwi a0, 0x340, 5\ncsrrw a0, 0x340, a1\ncsrrs a0, 0x340, zero\ncsrrc a0, a1, 0x340",
+        );
+        assert_eq!(
+            insts,
+            vec![
+                Csrrwi::expand(A0, csr, uimm(5)),
+                Csrrw::new(A0, A1, csr),
+                Csrrs::new(A0, Zero, csr),
+                Csrrc::new(A0, A1, csr),
+            ]
+        );
+        let insts = parse_and_lex_concr::<Rv32>(
+            "rdcycle a0\nrdcycleh a0\nrdtime a0\nrdtimeh a0\nrdinstret a0\nrdinstreth a0",
+        );
+        let csrs = [0xC00, 0xC80, 0xC01, 0xC81, 0xC02, 0xC82];
+        for (inst, csr) in insts.iter().zip(csrs.iter()) {
+            assert_eq!(inst, &Csrrs::new(A0, Zero, DataLword::from(*csr)));
+        }
+    }
+
+    #[test]
+    fn test_amo_forms() {
+        let insts = parse_and_lex_concr::<Rv32>(
+            "lr.w a0, (a1)\nlr.w.aq a0, 0(a1)\nsc.w.rl a0, a2, (a1)\n\
+             amoswap.w.aqrl a0, a2, (a1)\namoadd.w a0 a2 0(a1)",
+        );
+        assert_eq!(
+            insts,
+            vec![
+                Lrw::new(A0, A1, Zero),
+                Lrw::new_aq(A0, A1, Zero),
+                Scw::new_rl(A0, A1, A2),
+                Amoswapw::new_aqrl(A0, A1, A2),
+                Amoaddw::new(A0, A1, A2),
+            ]
+        );
+        assert_eq!(format!("{}", insts[1]), "lr.w.aq a0, (a1)");
+        assert_eq!(format!("{}", insts[2]), "sc.w.rl a0, a2, (a1)");
+        let programs = [
+            "lr.w a0, 4(a1)",           // offsets must be zero
+            "lr.w a0, a2, (a1)",        // lr has no rs2
+            "amoadd.w a0, (a1)",        // missing rs2
+            "amoadd.w a0, a2, (a1), 4", // too many args
+        ];
+        for prog in &programs {
+            let ParseResult { reporter, .. } = Parser::<Rv32>::parse_str(0, prog);
+            assert!(!reporter.is_empty(), "{}", prog);
+        }
+    }
+
+    #[test]
+    fn test_addr_parts() {
+        let insts = parse_and_lex::<Rv32>(
+            "lui a5, %hi(val)\nlw a0, %lo(val)(a5)\nsw a0, %lo(val)(a5)\naddi a0, a5, %lo(val)",
+        );
+        assert_eq!(insts.len(), 4);
+        for inst in &insts {
+            assert_eq!(inst.get_needed_label().unwrap().target, "val");
+            assert!(inst.needs_address());
+        }
+        // the upper part is rounded up when the lower part is negative
+        let insts = parse_and_lex_concr::<Rv32>(
+            "lui a0, %hi(0x12345FFF)\naddi a0, a0, %lo(0x12345FFF)\nlw a0, %lo(0x7FF)(a0)",
+        );
+        assert_eq!(
+            insts,
+            vec![
+                Lui::new(A0, DataLword::from(0x12346)),
+                Addi::new(A0, A0, DataLword::from(-1)),
+                Lw::new(A0, A0, DataLword::from(0x7FF)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gcc_forms_bad() {
+        let programs = [
+            "l: sw a0, l",          // stores to labels need a temporary register
+            "l: lw a0, l, t0",      // but loads do not
+            "fence rr, w",          // repeated access type
+            "fence 1, 1",           // sets must be named
+            "jalr ra, 0(x1), 4",    // extra argument
+            "bgez a0, a1, 4",       // too many registers
+            "lui a0, %pcrel_hi(l)", // unsupported relocation function
+            "lui a0, %hi l",        // missing parentheses
+            "lw a0, %lo(l)",        // missing base register
+            "lw a0, %lo(l)(a5",     // unclosed parenthesis
+            "csrwi 0x340, 32",      // immediate too large
+            "csrwi 0x340, -1",      // immediate is unsigned
+            "csrrwi a0, a1, 0x340", // immediate is not a register
+        ];
+        for prog in &programs {
+            let ParseResult { reporter, .. } = Parser::<Rv32>::parse_str(0, prog);
+            assert!(!reporter.is_empty(), "{}", prog);
+        }
+        // the upper halves of counters only exist on RV32
+        let ParseResult { reporter, .. } = Parser::<Rv64>::parse_str(0, "rdcycleh a0");
+        assert!(!reporter.is_empty());
+    }
 }
@@ -15,6 +15,15 @@ impl ProgramBehavior<RiscV<W32b>, W32b> for RiscVProgramBehavior<W32b> {
     fn return_register() -> RiscVRegister {
         RiscVRegister::A0
     }
+
+    fn ra_register() -> RiscVRegister {
+        RiscVRegister::Ra
+    }
+
+    fn arg_registers() -> Vec<RiscVRegister> {
+        use RiscVRegister::*;
+        vec![A0, A1, A2, A3, A4, A5, A6, A7]
+    }
 }
 
 impl ProgramBehavior<RiscV<W64b>, W64b> for RiscVProgramBehavior<W64b> {
@@ -25,48 +34,98 @@ impl ProgramBehavior<RiscV<W64b>, W64b> for RiscVProgramBehavior<W64b> {
     fn return_register() -> RiscVRegister {
         RiscVRegister::A0
     }
+
+    fn ra_register() -> RiscVRegister {
+        RiscVRegister::Ra
+    }
+
+    fn arg_registers() -> Vec<RiscVRegister> {
+        use RiscVRegister::*;
+        vec![A0, A1, A2, A3, A4, A5, A6, A7]
+    }
 }
 
+/// Syscall numbers shared by RV32 and RV64.
+/// See https://github.com/hrw/syscalls-table/blob/master/tables/syscalls-riscv32.
+/// See https://fedora.juszkiewicz.com.pl/syscalls.html for other ISAs
+const RISCV_SYSCALLS: &[(isize, Syscall)] = &[
+    (23, Syscall::Dup),
+    // there is no dup2, only dup3
+    (24, Syscall::Dup3),
+    (56, Syscall::Openat),
+    (57, Syscall::Close),
+    (59, Syscall::Pipe2),
+    (63, Syscall::Read),
+    (64, Syscall::Write),
+    (80, Syscall::Fstat),
+    (93, Syscall::Exit),
+    (94, Syscall::ExitGroup),
+    (160, Syscall::Uname),
+    // only RV64 has this in Linux, but newlib uses it on RV32 as well
+    (169, Syscall::Gettimeofday),
+    (124, Syscall::SchedYield),
+    (172, Syscall::Getpid),
+    (173, Syscall::Getppid),
+    (214, Syscall::Brk),
+    (215, Syscall::Munmap),
+    // there is no fork, only clone with SIGCHLD as its flags
+    (220, Syscall::Clone),
+    (222, Syscall::Mmap),
+    (260, Syscall::Wait4),
+    (278, Syscall::Getrandom),
+    // not a Linux syscall, but newlib's libgloss uses it in place of openat
+    (1024, Syscall::Open),
+];
+
 lazy_static! {
-    /// Syscall numbers for RV32.
-    /// See https://github.com/hrw/syscalls-table/blob/master/tables/syscalls-riscv32.
-    /// See https://fedora.juszkiewicz.com.pl/syscalls.html for other ISAs
-    static ref RISCV_SYSCALL_TABLE: HashMap<isize, Syscall> = {
-        use Syscall::*;
-        [
-            (63, Read),
-            (64, Write),
-            (53, Open),
-            (57, Close),
-            (93, Exit),
-            (214, Brk),
-            (222, Mmap),
-        ]
+    /// Syscall numbers for RV32, which only has the 64-bit variants of lseek and clock_gettime.
+    static ref RV32_SYSCALL_TABLE: HashMap<isize, Syscall> = RISCV_SYSCALLS
         .iter()
         .cloned()
-        .collect()
-    };
-    static ref RISCV_SYSCALL_NUMBERS: HashMap<Syscall, isize> =
-        RISCV_SYSCALL_TABLE
+        .chain([(62, Syscall::Llseek), (403, Syscall::ClockGettime)])
+        .collect();
+    /// Syscall numbers for RV64.
+    static ref RV64_SYSCALL_TABLE: HashMap<isize, Syscall> = RISCV_SYSCALLS
         .iter()
-        .map(|(n, syscall)| {(*syscall, *n)})
+        .cloned()
+        .chain([(62, Syscall::Lseek), (113, Syscall::ClockGettime)])
         .collect();
+    static ref RV32_SYSCALL_NUMBERS: HashMap<Syscall, isize> = invert(&RV32_SYSCALL_TABLE);
+    static ref RV64_SYSCALL_NUMBERS: HashMap<Syscall, isize> = invert(&RV64_SYSCALL_TABLE);
+}
+
+fn invert(table: &HashMap<isize, Syscall>) -> HashMap<Syscall, isize> {
+    table.iter().map(|(n, syscall)| (*syscall, *n)).collect()
 }
 
 pub struct RiscVSyscallConvention<S: DataWidth> {
     _phantom: PhantomData<S>,
 }
 
+impl<S: DataWidth> RiscVSyscallConvention<S> {
+    /// Returns the syscall numbers for the word size, mapped in both directions.
+    fn tables() -> (
+        &'static HashMap<isize, Syscall>,
+        &'static HashMap<Syscall, isize>,
+    ) {
+        if std::mem::size_of::<S::U>() == 4 {
+            (&RV32_SYSCALL_TABLE, &RV32_SYSCALL_NUMBERS)
+        } else {
+            (&RV64_SYSCALL_TABLE, &RV64_SYSCALL_NUMBERS)
+        }
+    }
+}
+
 /// Per the RISCV calling convention (see http://man7.org/linux/man-pages/man2/syscall.2.html),
 /// the a7 register determines which syscall is being performed, and the arguments are stored
 /// in the argument registers of user space.
 impl<S: AtLeast32b> SyscallConvention<RiscV<S>, S> for RiscVSyscallConvention<S> {
     fn number_to_syscall(n: SignedValue<S>) -> Option<Syscall> {
-        RISCV_SYSCALL_TABLE.get(&(n.raw().as_() as isize)).cloned()
+        Self::tables().0.get(&(n.raw().as_() as isize)).cloned()
     }
 
     fn syscall_to_number(syscall: Syscall) -> RegValue<S> {
-        SignedValue::<S>::from(RISCV_SYSCALL_NUMBERS.get(&syscall).copied().unwrap_or(-1)).into()
+        SignedValue::<S>::from(Self::tables().1.get(&syscall).copied().unwrap_or(-1)).into()
     }
 
     fn syscall_number_reg() -> RiscVRegister {
@@ -82,6 +141,18 @@ impl<S: AtLeast32b> SyscallConvention<RiscV<S>, S> for RiscVSyscallConvention<S>
         use RiscVRegister::*;
         vec![A0, A1]
     }
+
+    fn stack_pointer_reg() -> RiscVRegister {
+        RiscVRegister::Sp
+    }
+
+    fn machine_name() -> &'static str {
+        if std::mem::size_of::<S::U>() == 4 {
+            "riscv32"
+        } else {
+            "riscv64"
+        }
+    }
 }
 
 /// Addresses of the subset of special RISC-V control and status registers implemented.
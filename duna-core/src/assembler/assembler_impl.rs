@@ -1,10 +1,10 @@
 use super::{
     datatypes::*,
-    error::{ErrorReporter, ParseError},
+    error::{ErrorReport, ErrorReporter, ParseError},
     parser::{Label, LabelDef, LabelRef, ParseResult, Parser},
     partial_inst::{PartialInst, PartialInstType},
 };
-use crate::{arch::*, config::*, data_structures::*, program_state::*};
+use crate::{arch::*, config::*, data_structures::*, elf::is_internal_label, program_state::*};
 use num_traits::cast::AsPrimitive;
 use std::{
     collections::{HashMap, HashSet},
@@ -18,7 +18,42 @@ impl Assembler {
         file_id: FileId,
         contents: &str,
     ) -> (UnlinkedProgram<A>, ErrorReporter) {
-        Assembler::assemble(Parser::<A>::parse_str(file_id, contents))
+        let (program, mut reporter) =
+            Assembler::assemble(Parser::<A>::parse_str(file_id, contents));
+        reporter.merge(program.report_undeclared());
+        (program, reporter)
+    }
+
+    /// Assembles a file without reporting references to labels that it neither defines nor
+    /// declares global, so that the linker can point at definitions in other files.
+    pub fn assemble_file<A: Architecture>(
+        file_id: FileId,
+        contents: &str,
+        file_map: &mut FileMap,
+        config: &AsmConfig,
+    ) -> (UnlinkedProgram<A>, ErrorReporter) {
+        Assembler::assemble(Parser::<A>::parse_file(file_id, contents, file_map, config))
+    }
+
+    /// Assembles a single file into an ELF relocatable object without linking it.
+    pub fn assemble_object<A: Architecture>(
+        file_name: &str,
+        contents: &str,
+        config: &AsmConfig,
+    ) -> Result<Vec<u8>, ErrorReport> {
+        let mut file_map = vec![FileData {
+            file_name: file_name.to_string(),
+            content: contents.to_string(),
+        }];
+        let (program, mut reporter) =
+            Assembler::assemble_file::<A>(0, contents, &mut file_map, config);
+        reporter.merge(program.report_undeclared());
+        let result = if reporter.is_empty() {
+            program.into_object()
+        } else {
+            Err(reporter)
+        };
+        result.map_err(|reporter| reporter.into_report_with_file_map(file_map))
     }
 
     fn assemble<A: Architecture>(
@@ -29,24 +64,26 @@ impl Assembler {
             insts,
             sections,
             declared_globals,
+            declared_weak,
             mut reporter,
         } = parse_result;
         let (program, selflink_reporter) = UnlinkedProgram::new(
             insts.into_iter().map(|inst| (file_id, inst)).collect(),
             sections,
             declared_globals,
+            declared_weak,
         );
         reporter.merge(selflink_reporter);
         (program, reporter)
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum ProgramSection {
     Text,
     Data,
     Rodata,
-    // Bss,
+    Bss,
 }
 
 impl fmt::Display for ProgramSection {
@@ -59,6 +96,7 @@ impl fmt::Display for ProgramSection {
                 Text => "text",
                 Data => "data",
                 Rodata => "rodata",
+                Bss => "bss",
             }
         )
     }
@@ -70,8 +108,16 @@ pub struct SectionStore {
     data: Vec<u8>,
     /// Stores the contents of the .rodata section. The first element is at the lowest address.
     rodata: Vec<u8>,
+    /// The length of the .bss section, which is always zero-initialized and so takes no space.
+    bss_len: usize,
     labels: Vec<(LabelDef, ProgramSection, usize)>,
     require_align: bool,
+    /// The largest alignment in bytes required by anything in each section. Joining another
+    /// SectionStore onto this one pads each section to this alignment first.
+    text_align: usize,
+    data_align: usize,
+    rodata_align: usize,
+    bss_align: usize,
 }
 
 impl SectionStore {
@@ -79,8 +125,13 @@ impl SectionStore {
         SectionStore {
             data: Vec::new(),
             rodata: Vec::new(),
+            bss_len: 0,
             labels: Vec::new(),
             require_align: true,
+            text_align: 4,
+            data_align: 1,
+            rodata_align: 1,
+            bss_align: 1,
         }
     }
 
@@ -92,36 +143,72 @@ impl SectionStore {
         &self.rodata
     }
 
+    pub fn bss_len(&self) -> usize {
+        self.bss_len
+    }
+
     pub fn labels(&self) -> &[(LabelDef, ProgramSection, usize)] {
         &self.labels
     }
 
-    /// Adds a label to the next element in that section.
-    fn add_label_here(&mut self, section: ProgramSection, label: LabelDef) {
+    /// Returns the largest alignment in bytes required by anything in the section.
+    pub fn alignment(&self, section: ProgramSection) -> usize {
         use ProgramSection::*;
-        self.labels.push((
-            label,
-            section,
-            match section {
-                Data => &self.data,
-                Rodata => &self.rodata,
-                Text => panic!("adding data in text is currently unsupported"),
+        match section {
+            Text => self.text_align,
+            Data => self.data_align,
+            Rodata => self.rodata_align,
+            Bss => self.bss_align,
+        }
+    }
+
+    /// Requires the section to begin at an address that is a multiple of align bytes.
+    pub fn raise_alignment(&mut self, section: ProgramSection, align: usize) {
+        use ProgramSection::*;
+        let curr = match section {
+            Text => &mut self.text_align,
+            Data => &mut self.data_align,
+            Rodata => &mut self.rodata_align,
+            Bss => &mut self.bss_align,
+        };
+        *curr = (*curr).max(align);
+    }
+
+    /// Pads a data section with the fill byte until its length is a multiple of align bytes,
+    /// unless more than max_skip bytes of padding would be needed.
+    pub fn align(
+        &mut self,
+        section: ProgramSection,
+        align: usize,
+        fill: u8,
+        max_skip: Option<usize>,
+    ) {
+        self.raise_alignment(section, align);
+        let pad_len = (align - self.byte_len(section) % align) % align;
+        if max_skip.is_none_or(|max_skip| pad_len <= max_skip) {
+            for _ in 0..pad_len {
+                self.add_byte(section, fill);
             }
-            .len(),
-        ));
+        }
     }
 
-    fn byte_len(&self, section: ProgramSection) -> usize {
+    /// Adds a label to the next element in that section.
+    fn add_label_here(&mut self, section: ProgramSection, label: LabelDef) {
+        self.labels.push((label, section, self.byte_len(section)));
+    }
+
+    /// Returns the number of bytes in a data section.
+    pub fn byte_len(&self, section: ProgramSection) -> usize {
         use ProgramSection::*;
         match section {
-            Data => &self.data,
-            Rodata => &self.rodata,
+            Data => self.data.len(),
+            Rodata => self.rodata.len(),
+            Bss => self.bss_len,
             Text => panic!("adding data in text is currently unsupported"),
         }
-        .len()
     }
 
-    pub fn add(&mut self, section: ProgramSection, maybe_label: Option<LabelDef>, val: DataEnum) {
+    pub fn add(&mut self, section: ProgramSection, labels: Vec<LabelDef>, val: DataEnum) {
         // handle alignment first so the label is placed at the correct index
         if self.require_align {
             let pad_requirement = match val {
@@ -130,11 +217,9 @@ impl SectionStore {
                 Lword(_) => 4,
                 Dword(_) => 8,
             };
-            while self.byte_len(section) % pad_requirement != 0 {
-                self.add_byte(section, 0);
-            }
+            self.align(section, pad_requirement, 0, None);
         }
-        if let Some(label) = maybe_label {
+        for label in labels {
             self.add_label_here(section, label);
         }
         use DataEnum::*;
@@ -150,36 +235,53 @@ impl SectionStore {
     /// aligned to a doubleword.
     pub fn zero_pad_until_doubleword_aligned(&mut self) {
         use ProgramSection::*;
-        for &section in &[Data, Rodata] {
-            while self.byte_len(section) % 8 != 0 {
+        for &section in &[Data, Rodata, Bss] {
+            while !self.byte_len(section).is_multiple_of(8) {
                 self.add_byte(section, 0);
             }
         }
     }
 
     /// Consumes the other SectionStore, joining it with this one.
-    /// Agnostic to alignment.
+    /// Each section is first padded to the alignment required by the other store, so that
+    /// anything aligned within the other store remains aligned.
+    /// Padding the text section is left to the caller, since it holds no instructions.
     pub fn join(&mut self, other: SectionStore) {
-        let old_data_len = self.byte_len(ProgramSection::Data);
+        use ProgramSection::*;
+        self.align(Data, other.data_align, 0, None);
+        self.align(Rodata, other.rodata_align, 0, None);
+        self.align(Bss, other.bss_align, 0, None);
+        self.raise_alignment(Text, other.text_align);
+        let old_lens = [
+            self.byte_len(Data),
+            self.byte_len(Rodata),
+            self.byte_len(Bss),
+        ];
         self.data.extend(other.data);
         self.rodata.extend(other.rodata);
+        self.bss_len += other.bss_len;
         // combine labels
         for (label_def, section, idx) in other.labels.into_iter() {
-            match section {
-                ProgramSection::Data => self.labels.push((label_def, section, old_data_len + idx)),
-                _ => unimplemented!(),
-            }
+            let offs = match section {
+                Data => old_lens[0],
+                Rodata => old_lens[1],
+                Bss => old_lens[2],
+                Text => unreachable!("text labels are not stored in sections"),
+            };
+            self.labels.push((label_def, section, offs + idx));
         }
     }
 
+    /// Adds a byte to the section. Values added to bss are assumed to be zero, which the parser
+    /// is responsible for checking.
     fn add_byte(&mut self, section: ProgramSection, val: u8) {
         use ProgramSection::*;
         match section {
-            Data => &mut self.data,
-            Rodata => &mut self.rodata,
+            Data => self.data.push(val),
+            Rodata => self.rodata.push(val),
+            Bss => self.bss_len += 1,
             Text => panic!("adding data in text is currently unsupported"),
         }
-        .push(val)
     }
 
     fn add_half(&mut self, section: ProgramSection, val: u16) {
@@ -192,7 +294,6 @@ impl SectionStore {
         self.add_half(section, (val >> 16) as u16);
     }
 
-    // TODO add option for these to preserve alignment
     fn add_doubleword(&mut self, section: ProgramSection, val: u64) {
         self.add_word(section, val as u32);
         self.add_word(section, (val >> 32) as u32);
@@ -221,12 +322,26 @@ pub struct UnlinkedProgram<S: Architecture> {
     // TODO put labels in sections
     /// Maps index of an instruction to the label it needs.
     pub(super) needed_labels: HashMap<usize, LabelRef>,
+    /// Maps index of an instruction to the section and byte offset of the data it refers to, or
+    /// of the instruction whose address it needs. These are only fulfilled once the program is
+    /// laid out in memory.
+    pub(super) data_refs: HashMap<usize, (ProgramSection, usize)>,
     /// Maps global labels to its token location and program location.
     pub(super) defined_global_labels: HashMap<Label, LabelTarget>,
+    /// Global labels declared with .weak, whose definitions may be overridden by other files.
+    pub(super) weak_labels: HashSet<Label>,
+    /// Maps every label defined in this program to where it is defined and whether it is global.
+    /// This is only used to suggest fixes for references that cannot be resolved.
+    pub(super) defined_labels: FileLabels,
+    /// References to labels that were neither defined nor declared global in this program.
+    pub(super) undeclared_labels: Vec<LabelRef>,
     /// Stores literal values declared by directives, as well as labels that reference those values.
     pub(super) sections: SectionStore,
 }
 
+/// Maps the labels defined in a file to where they are defined and whether they are global.
+pub(super) type FileLabels = HashMap<Label, (Location, bool)>;
+
 /// Determintes whether the label points to an instruction or the data section.
 #[derive(Copy, Clone)]
 pub enum LabelTarget {
@@ -250,6 +365,108 @@ impl LabelTarget {
     }
 }
 
+/// Reports a reference from the file at FILE_IDX of FILE_LABELS to a label that the linker could
+/// not resolve. DECLARED indicates whether the file declared the label global.
+///
+/// Definitions of the label in other files are pointed out, since they are missing either a
+/// .globl declaration or a declaration in the referencing file. Otherwise, a similarly named label
+/// is suggested if there is one.
+pub(super) fn unresolved_label(
+    label: &LabelRef,
+    declared: bool,
+    file_idx: usize,
+    file_labels: &[FileLabels],
+) -> ParseError {
+    let mut err = if declared {
+        ParseError::undefined_label(label)
+    } else {
+        ParseError::undeclared_label(label)
+    };
+    let other_defs: Vec<&(Location, bool)> = file_labels
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != file_idx)
+        .filter_map(|(_, labels)| labels.get(&label.target))
+        .collect();
+    if !other_defs.is_empty() {
+        for (location, global) in other_defs {
+            let msg = if *global {
+                format!(
+                    "'{}' is defined in another file; declare it with .globl to use it here",
+                    label.target
+                )
+            } else {
+                format!(
+                    "'{}' is defined here, but not declared with .globl",
+                    label.target
+                )
+            };
+            err = err.with_help(&msg, Some(location));
+        }
+        return err;
+    }
+    // labels in other files are only visible if they are global
+    let candidates = file_labels
+        .iter()
+        .enumerate()
+        .flat_map(|(i, labels)| {
+            labels
+                .iter()
+                .filter(move |(_, (_, global))| i == file_idx || *global)
+        })
+        .filter(|(name, _)| !is_internal_label(name));
+    match closest_name(&label.target, candidates) {
+        Some((name, (location, _))) => err.with_help(
+            &format!("a label with a similar name exists: '{}'", name),
+            Some(location),
+        ),
+        None => err,
+    }
+}
+
+/// Orders references to labels by where they appear in source code. Pseudo-instructions like call
+/// expand to several instructions that reference the same label at the same position, so they
+/// should be reported only once.
+pub(super) fn ref_position(label: &LabelRef) -> (FileId, LineNo, LineOffs) {
+    let Location {
+        file_id,
+        lineno,
+        offs,
+        ..
+    } = label.location;
+    (file_id, lineno, offs)
+}
+
+/// Finds the candidate whose name is most similar to TARGET, if any is similar enough to be a
+/// likely typo.
+fn closest_name<'a, T>(
+    target: &str,
+    candidates: impl Iterator<Item = (&'a Label, T)>,
+) -> Option<(&'a Label, T)> {
+    let max_dist = (target.chars().count() / 3).max(1);
+    candidates
+        .map(|(name, value)| (edit_distance(target, name), name, value))
+        .filter(|(dist, _, _)| *dist <= max_dist)
+        // break ties by name, since the candidates come from hash maps
+        .min_by(|(d1, n1, _), (d2, n2, _)| d1.cmp(d2).then_with(|| n1.cmp(n2)))
+        .map(|(_, name, value)| (name, value))
+}
+
+/// Computes the Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + (ca != *cb) as usize;
+            curr.push(substitute.min(prev[j + 1] + 1).min(curr[j] + 1));
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
 impl<A: Architecture> UnlinkedProgram<A> {
     /// Constructs an instance of an UnlinkedProgram from a stream of (file name, instruction).
     /// Also attempts to match needed labels to locally defined labels, and populates the needed
@@ -260,15 +477,16 @@ impl<A: Architecture> UnlinkedProgram<A> {
         mut insts: Vec<FileIdAndInst<A>>,
         sections: SectionStore,
         declared_globals: HashSet<String>,
+        weak_labels: HashSet<String>,
     ) -> (UnlinkedProgram<A>, ErrorReporter) {
         let mut reporter = ErrorReporter::new();
         let mut local_labels: HashMap<Label, LabelTarget> = Default::default();
         // Label definitions in instructions
         for (i, (_, partial_inst)) in insts.iter().enumerate() {
-            if let Some(label_def) = &partial_inst.label {
-                if local_labels.contains_key(&label_def.name) {
+            for label_def in &partial_inst.labels {
+                if let Some(prev) = local_labels.get(&label_def.name) {
                     // If already defined, don't touch the original def
-                    reporter.add_error(ParseError::redefined_label(label_def));
+                    reporter.add_error(ParseError::redefined_label(label_def, &prev.location()));
                 } else {
                     local_labels.insert(
                         label_def.name.clone(),
@@ -282,8 +500,8 @@ impl<A: Architecture> UnlinkedProgram<A> {
         }
         // Label definitions in data sections
         for (label_def, section, idx) in sections.labels.iter().cloned() {
-            if local_labels.contains_key(&label_def.name) {
-                reporter.add_error(ParseError::redefined_label(&label_def));
+            if let Some(prev) = local_labels.get(&label_def.name) {
+                reporter.add_error(ParseError::redefined_label(&label_def, &prev.location()));
             } else {
                 local_labels.insert(
                     label_def.name.clone(),
@@ -312,72 +530,152 @@ impl<A: Architecture> UnlinkedProgram<A> {
                 }
             })
             .collect();
+        let defined_labels = local_labels
+            .iter()
+            .map(|(label, tgt)| {
+                let global = declared_globals.contains(label);
+                (label.clone(), (tgt.location(), global))
+            })
+            .collect();
         // map of labels after resolving local ones
         let mut needed_labels = HashMap::new();
+        let mut data_refs = HashMap::new();
+        let mut undeclared_labels = Vec::new();
         for (inst_index, label) in all_needed_labels.into_iter() {
-            if let Some(&target_type) = local_labels.get(&label.target) {
-                let byte_distance: i64 = match target_type {
+            if weak_labels.contains(&label.target) {
+                // a weak definition may be overridden by another file, so only the linker can
+                // resolve references to it
+                needed_labels.insert(inst_index, label);
+            } else if let Some(&target_type) = local_labels.get(&label.target) {
+                match target_type {
                     LabelTarget::Inst {
                         location: _,
                         idx: tgt_index,
                     } => {
-                        // Figure out how many instructions we need to jump
-                        let inst_distance = (tgt_index as isize) - (inst_index as isize);
-                        (inst_distance * 4) as i64
+                        if insts[inst_index].1.needs_address() {
+                            // the address is only known once the program is laid out
+                            data_refs.insert(inst_index, (ProgramSection::Text, tgt_index * 4));
+                        } else {
+                            Self::fulfill_inst_ref(&mut insts, inst_index, tgt_index)
+                        }
                     }
                     LabelTarget::Data {
                         location: _,
                         section,
                         idx: data_index,
                     } => {
-                        // If the instruction is at (TEXT_START + x) and the target occurs at
-                        // (DATA_START + y), then we can compute the offset to be
-                        // (DATA_START - TEXT_START + y - x)
-                        // TODO make this more configurable
-                        let segment_starts = SegmentStarts::default();
-                        let text_start: SignedValue<A::DataWidth> =
-                            segment_starts.text::<A::DataWidth>().into();
-                        let data_start: SignedValue<A::DataWidth> =
-                            segment_starts.data::<A::DataWidth>().into();
-                        if section != ProgramSection::Data {
-                            unimplemented!()
-                        }
-                        let data_loc = data_start + (data_index as i64).into();
-                        // inst index is in words, so multiply by 4
-                        let pc = text_start + ((inst_index * 4) as i64).into();
-                        let byte_distance = data_loc - pc;
-                        AsPrimitive::<i64>::as_(byte_distance.raw())
+                        data_refs.insert(inst_index, (section, data_index));
                     }
-                };
-                let (file_id, old_inst) = &insts[inst_index];
-                if let PartialInstType::NeedsLabelRef(inst) = &old_inst.tpe {
-                    insts[inst_index] = (
-                        *file_id,
-                        PartialInst::new_complete(inst.fulfill_label(byte_distance.into())),
-                    )
-                } else {
-                    panic!("cannot fulfill label for complete instruction")
-                };
+                }
             } else if declared_globals.contains(&label.target) {
                 needed_labels.insert(inst_index, label);
             } else {
-                reporter.add_error(ParseError::undeclared_label(&label));
+                undeclared_labels.push(label);
             }
         }
+        undeclared_labels.sort_by_key(ref_position);
+        undeclared_labels.dedup_by_key(|label| ref_position(label));
         (
             UnlinkedProgram {
                 insts,
                 needed_labels,
+                data_refs,
                 defined_global_labels,
+                weak_labels,
+                defined_labels,
+                undeclared_labels,
                 sections,
             },
             reporter,
         )
     }
 
-    /// Produces a program, or an error report if some instructions are still missing labels.
-    pub fn into_program(self, config: &MachineConfig) -> Result<Program<A>, ErrorReporter> {
+    /// Reports every reference to a label that this program neither defines nor declares global.
+    pub(super) fn report_undeclared(&self) -> ErrorReporter {
+        let mut reporter = ErrorReporter::new();
+        let file_labels = std::slice::from_ref(&self.defined_labels);
+        for label in &self.undeclared_labels {
+            reporter.add_error(unresolved_label(label, false, 0, file_labels));
+        }
+        reporter
+    }
+
+    /// Fulfills the label needed by an instruction with the offset to another instruction.
+    pub(super) fn fulfill_inst_ref(
+        insts: &mut [FileIdAndInst<A>],
+        inst_index: usize,
+        tgt_index: usize,
+    ) {
+        // Figure out how many instructions we need to jump
+        let inst_distance = (tgt_index as isize) - (inst_index as isize);
+        Self::fulfill_label(insts, inst_index, (inst_distance * 4) as i64);
+    }
+
+    fn fulfill_label(insts: &mut [FileIdAndInst<A>], inst_index: usize, byte_distance: i64) {
+        let (file_id, old_inst) = &insts[inst_index];
+        if let PartialInstType::NeedsLabelRef(inst) = &old_inst.tpe {
+            insts[inst_index] = (
+                *file_id,
+                PartialInst::new_complete(inst.fulfill_label(byte_distance.into()))
+                    .with_labels(old_inst.labels.clone()),
+            )
+        } else {
+            panic!("cannot fulfill label for complete instruction")
+        };
+    }
+
+    /// Places each section in memory, following the segment starts and page size in CONFIG.
+    pub fn layout(&self, config: &MachineConfig) -> ProgramLayout {
+        use ProgramSection::*;
+        ProgramLayout::new(
+            &config.segment_starts,
+            config.mem_config.pg_ofs_bits,
+            self.insts.len() * 4,
+            self.sections.byte_len(Rodata),
+            self.sections.byte_len(Data),
+            self.sections.byte_len(Bss),
+        )
+    }
+
+    /// Produces a program, or an error report if some instructions are still missing labels or
+    /// the entry label configured in CONFIG is not defined.
+    pub fn into_program(mut self, config: &AsmConfig) -> Result<Program<A>, ErrorReporter> {
         let mut reporter = ErrorReporter::new();
+        let layout = self.layout(&config.machine);
+        for (inst_index, (section, data_index)) in self.data_refs.into_iter() {
+            // The target is in the same address space as the instruction, so this is
+            // computed in the width of the architecture to wrap appropriately
+            let data_loc: SignedValue<A::DataWidth> =
+                (layout.section(section).start + data_index as u64).into();
+            // inst index is in words, so multiply by 4
+            let pc: SignedValue<A::DataWidth> =
+                (layout.text.start + (inst_index * 4) as u64).into();
+            let imm = if self.insts[inst_index].1.needs_address() {
+                data_loc
+            } else {
+                data_loc - pc
+            };
+            Self::fulfill_label(
+                &mut self.insts,
+                inst_index,
+                AsPrimitive::<i64>::as_(imm.raw()),
+            );
+        }
+        let mut symbols = SymbolTable::new();
+        for (i, (_, partial_inst)) in self.insts.iter().enumerate() {
+            for label in &partial_inst.labels {
+                symbols
+                    .entry(layout.text.start + (i * 4) as u64)
+                    .or_default()
+                    .push(label.name.clone());
+            }
+        }
+        for (label, section, idx) in self.sections.labels() {
+            symbols
+                .entry(layout.section(*section).start + *idx as u64)
+                .or_default()
+                .push(label.name.clone());
+        }
         let insts = self
             .insts
             .into_iter()
@@ -391,28 +689,49 @@ impl<A: Architecture> UnlinkedProgram<A> {
                 },
             )
             .collect();
-        // TODO configure _start`
-        // For now, the initial PC is set to the location of the global main label
-        // or else the location of the first instruction
-        let main_inst_idx: usize = if let Some(tgt) = self.defined_global_labels.get("main") {
-            match *tgt {
-                LabelTarget::Inst { idx, .. } => idx,
-                LabelTarget::Data { location, .. } => {
-                    reporter.add_error(ParseError::bad_main_def(&location));
-                    0
+        // The initial PC is set to the location of the configured entry label, or else of the
+        // global _start or main label, or else of the first instruction
+        let entry = match &config.entry {
+            Some(name) => match self.defined_global_labels.get(name) {
+                Some(tgt) => Some((name.as_str(), *tgt)),
+                None => {
+                    reporter.add_error(ParseError::undefined_entry(name));
+                    None
                 }
+            },
+            None => ["_start", "main"].iter().find_map(|&name| {
+                let tgt = self.defined_global_labels.get(name)?;
+                Some((name, *tgt))
+            }),
+        };
+        let main_inst_idx: usize = match entry {
+            Some((_, LabelTarget::Inst { idx, .. })) => idx,
+            Some((name, LabelTarget::Data { location, .. })) => {
+                reporter.add_error(ParseError::bad_entry_def(name, &location));
+                0
             }
-        } else {
-            0
+            None => 0,
+        };
+        // The startup routine defines errno, which failed syscalls also store to
+        let errno_addr = match self.defined_global_labels.get("errno") {
+            Some(&LabelTarget::Data { section, idx, .. }) if config.crt0 => {
+                Some(layout.section(section).start + idx as u64)
+            }
+            _ => None,
         };
         if reporter.is_empty() {
-            Ok(Program::<A>::new(
+            let program = Program::<A>::new(
                 insts,
                 main_inst_idx,
-                config.segment_starts,
+                layout,
                 self.sections,
-                config.mem_config,
-            ))
+                config.machine.mem_config,
+            )
+            .with_symbols(symbols);
+            Ok(match errno_addr {
+                Some(addr) => program.with_errno_mirror(addr),
+                None => program,
+            })
         } else {
             Err(reporter)
         }
@@ -420,7 +739,7 @@ impl<A: Architecture> UnlinkedProgram<A> {
 
     /// Attempts to produce an instance of the program. Panics if some labels are needed
     /// but not found within the body of this program.
-    pub fn try_into_program(self, config: &MachineConfig) -> Program<A> {
+    pub fn try_into_program(self, config: &AsmConfig) -> Program<A> {
         self.into_program(config).unwrap()
     }
 }
@@ -14,6 +14,27 @@ pub struct Location {
     /// Holds a reference to the line from which this came (used for error messages)
     pub lineno: LineNo,
     pub offs: LineOffs,
+    /// If this token was produced by a macro expansion, holds the location of the outermost
+    /// macro invocation. In that case, the other fields refer to the line of the macro body.
+    pub call_site: Option<CallSite>,
+}
+
+/// The location of a macro invocation that produced a token.
+#[derive(Copy, Clone, Eq, PartialEq, PartialOrd, Hash, Debug)]
+pub struct CallSite {
+    pub file_id: FileId,
+    pub lineno: LineNo,
+    pub offs: LineOffs,
+}
+
+impl From<Location> for CallSite {
+    fn from(location: Location) -> CallSite {
+        CallSite {
+            file_id: location.file_id,
+            lineno: location.lineno,
+            offs: location.offs,
+        }
+    }
 }
 
 impl fmt::Display for Location {
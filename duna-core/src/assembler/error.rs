@@ -30,6 +30,42 @@ impl ErrorReport {
 /// Line numbers are 1-indexed, as is convention for most editors.
 const FIRST_LINENO: usize = 1;
 
+impl ErrorReport {
+    /// Writes the file location and contents of a line, with a caret pointing at offs.
+    fn fmt_snippet(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        file_id: FileId,
+        lineno: LineNo,
+        offs: LineOffs,
+    ) -> fmt::Result {
+        let (file_name, line_map) = &self.line_map[file_id];
+        let lineno_string = (lineno + FIRST_LINENO).to_string();
+        let space_count = lineno_string.len();
+        // === LINE 1 (error location) ===
+        writeln!(f, " --> {}:{}:{}", file_name, lineno_string, offs)?;
+        // === LINE 2 (spacing pipe) ===
+        // Fixes spacing if lineno is more than one digit
+        for _ in 0..space_count {
+            write!(f, " ")?;
+        }
+        writeln!(f, " |")?;
+        // === LINE 3 (line number and line content) ===
+        writeln!(f, "{} | {}", lineno_string, line_map[lineno])?;
+        // === LINE 4 (spacing pipe and pointing carets) ===
+        // Fixes spacing if lineno is more than one digit
+        for _ in 0..space_count {
+            write!(f, " ")?;
+        }
+        write!(f, " |")?;
+        // inclusive because there's one space between the pipe and the string in the line above
+        for _ in 0..=offs {
+            write!(f, " ")?;
+        }
+        writeln!(f, "^\n")
+    }
+}
+
 impl fmt::Debug for ErrorReport {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for err in &self.errs {
@@ -39,31 +75,32 @@ impl fmt::Debug for ErrorReport {
                 file_id,
                 lineno,
                 offs,
-            } = err.errloc.location;
-            let (file_name, line_map) = &self.line_map[file_id];
-            let lineno_string = (lineno + FIRST_LINENO).to_string();
-            let space_count = lineno_string.len();
-            // === LINE 1 (error location) ===
-            writeln!(f, " --> {}:{}:{}", file_name, lineno_string, offs)?;
-            // === LINE 2 (spacing pipe) ===
-            // Fixes spacing if lineno is more than one digit
-            for _ in 0..space_count {
-                write!(f, " ")?;
-            }
-            writeln!(f, " |")?;
-            // === LINE 3 (line number and line content) ===
-            writeln!(f, "{} | {}", lineno_string, line_map[lineno])?;
-            // === LINE 4 (spacing pipe and pointing carets) ===
-            // Fixes spacing if lineno is more than one digit
-            for _ in 0..space_count {
-                write!(f, " ")?;
+                call_site,
+            } = match err.errloc.location {
+                Some(location) => location,
+                None => {
+                    writeln!(f)?;
+                    continue;
+                }
+            };
+            self.fmt_snippet(f, file_id, lineno, offs)?;
+            // errors inside a macro body also point at the line that invoked the macro
+            if let Some(CallSite {
+                file_id,
+                lineno,
+                offs,
+            }) = call_site
+            {
+                writeln!(f, "note: in expansion of macro invoked here")?;
+                self.fmt_snippet(f, file_id, lineno, offs)?;
             }
-            write!(f, " |")?;
-            // inclusive because there's one space between the pipe and the string in the line above
-            for _ in 0..=offs {
-                write!(f, " ")?;
+            for note in err.notes.iter().flat_map(|notes| notes.iter()) {
+                writeln!(f, "{}", note)?;
+                match note.location {
+                    Some(loc) => self.fmt_snippet(f, loc.file_id, loc.lineno, loc.offs)?,
+                    None => writeln!(f)?,
+                }
             }
-            writeln!(f, "^\n")?;
         }
         if self.errs.is_empty() {
             Ok(())
@@ -179,8 +216,14 @@ enum ParseErrorType {
     RedefinedLabelRef(String),
     /// A referenced label was not defined by any file.
     UndefinedLabelRef(String),
-    /// A global main label was inappropriately defined.
-    BadMainDef,
+    /// The label at which execution begins was inappropriately defined.
+    BadEntryDef(String),
+    /// The label at which execution begins was not defined as a global label.
+    UndefinedEntry(String),
+    /// A block-opening directive (e.g. .macro) had no matching closing directive.
+    UnterminatedBlock { start: String, end: String },
+    /// A block-closing directive (e.g. .endm) was found without a matching opening directive.
+    UnmatchedBlockEnd(String),
 }
 
 impl fmt::Display for ParseErrorType {
@@ -248,41 +291,78 @@ impl fmt::Display for ParseErrorType {
             UnexpectedType { exp_name, got } => write!(f, "expected {}, got {}", exp_name, got),
             UnclosedParen(got) => write!(f, "expected closing parentheses, got {}", got),
             UnsupportedDirective(got) => write!(f, "unsupported assembler directive {}", got),
-            // TODO hint at .globl
             UndeclaredLabelRef(label) => write!(
                 f,
                 "label '{}' was neither defined locally nor declared global",
                 label
             ),
-            // TODO hint at previous definition
             RedefinedLabelRef(label) => {
                 write!(f, "multiple definitions found for label '{}'", label)
             }
             UndefinedLabelRef(label) => {
                 write!(f, "label '{}' was declared but never defined", label)
             }
-            BadMainDef => write!(f, "the 'main' label must be used to refer to code"),
+            BadEntryDef(label) => write!(
+                f,
+                "the entry label '{}' must be used to refer to code",
+                label
+            ),
+            UndefinedEntry(label) => write!(
+                f,
+                "entry label '{}' was not defined by any file as a global label",
+                label
+            ),
+            UnterminatedBlock { start, end } => {
+                write!(f, "found .{} without matching .{}", start, end)
+            }
+            UnmatchedBlockEnd(end) => write!(f, "found .{} without matching block start", end),
         }
     }
 }
 
 #[derive(Eq, PartialEq, Debug)]
 pub struct ErrMetadata {
-    location: Location,
+    /// Where the error occurred, or None if it does not come from a particular line, such as an
+    /// error in the configuration of the assembler.
+    location: Option<Location>,
 }
 
 impl ErrMetadata {
     pub fn new(location: &Location) -> ErrMetadata {
         ErrMetadata {
-            location: *location,
+            location: Some(*location),
         }
     }
+
+    pub fn unlocated() -> ErrMetadata {
+        ErrMetadata { location: None }
+    }
+}
+
+/// Additional information attached to an error, optionally pointing at another location.
+#[derive(Eq, PartialEq, Debug)]
+struct Note {
+    /// Whether the note suggests a fix rather than explaining the error.
+    help: bool,
+    msg: String,
+    location: Option<Location>,
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.help { "help" } else { "note" };
+        write!(f, "{}: {}", kind, self.msg)
+    }
 }
 
 #[derive(Eq, PartialEq, Debug)]
 pub struct ParseError {
     errloc: ErrMetadata,
     tpe: ParseErrorType,
+    /// Notes are only attached to some errors, so they are boxed to keep results holding a
+    /// ParseError small.
+    #[allow(clippy::box_collection)]
+    notes: Option<Box<Vec<Note>>>,
 }
 
 impl ParseError {
@@ -290,9 +370,29 @@ impl ParseError {
         ParseError {
             errloc: location,
             tpe,
+            notes: None,
         }
     }
 
+    /// Attaches a note explaining the error, which points at LOCATION if one is provided.
+    pub fn with_note(self, msg: &str, location: Option<&Location>) -> Self {
+        self.add_note(false, msg, location)
+    }
+
+    /// Attaches a suggestion for fixing the error, which points at LOCATION if one is provided.
+    pub fn with_help(self, msg: &str, location: Option<&Location>) -> Self {
+        self.add_note(true, msg, location)
+    }
+
+    fn add_note(mut self, help: bool, msg: &str, location: Option<&Location>) -> Self {
+        self.notes.get_or_insert_with(Default::default).push(Note {
+            help,
+            msg: msg.to_string(),
+            location: location.copied(),
+        });
+        self
+    }
+
     pub fn generic(location: ErrMetadata, msg: &str) -> Self {
         ParseError::new(location, ParseErrorType::Generic(msg.to_string()))
     }
@@ -400,36 +500,59 @@ impl ParseError {
             ParseErrorType::UnsupportedDirective(got.to_string()),
         )
     }
+
+    pub fn unterminated_block(location: ErrMetadata, start: &str, end: &str) -> Self {
+        ParseError::new(
+            location,
+            ParseErrorType::UnterminatedBlock {
+                start: start.to_string(),
+                end: end.to_string(),
+            },
+        )
+    }
+
+    pub fn unmatched_block_end(location: ErrMetadata, end: &str) -> Self {
+        ParseError::new(location, ParseErrorType::UnmatchedBlockEnd(end.to_string()))
+    }
 }
 
 // functions for errors encountered by assembler/linker
 impl ParseError {
     pub fn undeclared_label(label: &LabelRef) -> Self {
-        ParseError {
-            errloc: ErrMetadata::new(&label.location),
-            tpe: ParseErrorType::UndeclaredLabelRef(label.target.clone()),
-        }
+        ParseError::new(
+            ErrMetadata::new(&label.location),
+            ParseErrorType::UndeclaredLabelRef(label.target.clone()),
+        )
     }
 
-    pub fn redefined_label(label: &LabelDef) -> Self {
-        ParseError {
-            errloc: ErrMetadata::new(&label.location),
-            tpe: ParseErrorType::RedefinedLabelRef(label.name.clone()),
-        }
+    /// Reports LABEL as a redefinition of a label first defined at PREV.
+    pub fn redefined_label(label: &LabelDef, prev: &Location) -> Self {
+        ParseError::new(
+            ErrMetadata::new(&label.location),
+            ParseErrorType::RedefinedLabelRef(label.name.clone()),
+        )
+        .with_note("previous definition is here", Some(prev))
     }
 
     pub fn undefined_label(label: &LabelRef) -> Self {
-        ParseError {
-            errloc: ErrMetadata::new(&label.location),
-            tpe: ParseErrorType::UndefinedLabelRef(label.target.clone()),
-        }
+        ParseError::new(
+            ErrMetadata::new(&label.location),
+            ParseErrorType::UndefinedLabelRef(label.target.clone()),
+        )
     }
 
-    pub fn bad_main_def(loc: &Location) -> Self {
-        ParseError {
-            errloc: ErrMetadata::new(loc),
-            tpe: ParseErrorType::BadMainDef,
-        }
+    pub fn bad_entry_def(label: &str, loc: &Location) -> Self {
+        ParseError::new(
+            ErrMetadata::new(loc),
+            ParseErrorType::BadEntryDef(label.to_string()),
+        )
+    }
+
+    pub fn undefined_entry(label: &str) -> Self {
+        ParseError::new(
+            ErrMetadata::unlocated(),
+            ParseErrorType::UndefinedEntry(label.to_string()),
+        )
     }
 }
 
@@ -0,0 +1,214 @@
+//! Evaluates the constant integer expressions used by directives such as .if and .equ.
+//!
+//! Operators follow C precedence. Comparisons and logical operators produce 1 for true and 0
+//! for false.
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, Debug)]
+enum ExprToken {
+    Num(i64),
+    Symbol(String),
+    Op(&'static str),
+}
+
+/// Operators ordered so that longer operators are matched first.
+const OPS: [&str; 21] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/",
+    "%", "!", "~", "(",
+];
+
+/// Binary operators by precedence level, loosest first.
+const BINARY_OPS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+fn is_symbol_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '.' || c == '$'
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+fn parse_num(s: &str) -> Option<i64> {
+    let s = s.replace('_', "");
+    let unsigned = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = s.strip_prefix("0b").or_else(|| s.strip_prefix("0B")) {
+        u64::from_str_radix(bin, 2).ok()
+    } else {
+        s.parse().ok()
+    };
+    unsigned.map(|n| n as i64)
+}
+
+fn tokenize(expr: &str) -> Result<Vec<ExprToken>, String> {
+    let mut toks = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !c.is_alphanumeric() && c != '_');
+            let len = len.unwrap_or(rest.len());
+            match parse_num(&rest[..len]) {
+                Some(n) => toks.push(ExprToken::Num(n)),
+                None => return Err(format!("bad integer literal {}", &rest[..len])),
+            }
+            len
+        } else if is_symbol_start(c) {
+            let len = rest.find(|c| !is_symbol_char(c)).unwrap_or(rest.len());
+            toks.push(ExprToken::Symbol(rest[..len].to_string()));
+            len
+        } else if c == ')' {
+            toks.push(ExprToken::Op(")"));
+            1
+        } else {
+            match OPS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => {
+                    toks.push(ExprToken::Op(op));
+                    op.len()
+                }
+                None => return Err(format!("unexpected character {} in expression", c)),
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(toks)
+}
+
+struct ExprParser<'a> {
+    toks: Vec<ExprToken>,
+    pos: usize,
+    symbols: &'a HashMap<String, i64>,
+}
+
+impl<'a> ExprParser<'a> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.toks.get(self.pos) {
+            Some(ExprToken::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == BINARY_OPS.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| BINARY_OPS[level].contains(op)) {
+            self.pos += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = match op {
+                "||" => (lhs != 0 || rhs != 0) as i64,
+                "&&" => (lhs != 0 && rhs != 0) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err("division by zero in expression".to_string()),
+                "/" => lhs.wrapping_div(rhs),
+                "%" => lhs.wrapping_rem(rhs),
+                _ => unreachable!(),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<i64, String> {
+        let tok = self.toks.get(self.pos).cloned();
+        self.pos += 1;
+        match tok {
+            Some(ExprToken::Num(n)) => Ok(n),
+            Some(ExprToken::Symbol(name)) => match self.symbols.get(&name) {
+                Some(n) => Ok(*n),
+                None => Err(format!("symbol '{}' is not a defined constant", name)),
+            },
+            Some(ExprToken::Op("-")) => Ok(self.parse_unary()?.wrapping_neg()),
+            Some(ExprToken::Op("+")) => self.parse_unary(),
+            Some(ExprToken::Op("~")) => Ok(!self.parse_unary()?),
+            Some(ExprToken::Op("!")) => Ok((self.parse_unary()? == 0) as i64),
+            Some(ExprToken::Op("(")) => {
+                let n = self.parse_binary(0)?;
+                if self.peek_op() == Some(")") {
+                    self.pos += 1;
+                    Ok(n)
+                } else {
+                    Err("expected closing parentheses in expression".to_string())
+                }
+            }
+            Some(ExprToken::Op(op)) => Err(format!("unexpected operator {} in expression", op)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+/// Evaluates an expression, looking up any symbols in the provided table of constants.
+/// Returns a description of the problem if the expression is malformed.
+pub fn eval(expr: &str, symbols: &HashMap<String, i64>) -> Result<i64, String> {
+    let mut parser = ExprParser {
+        toks: tokenize(expr)?,
+        pos: 0,
+        symbols,
+    };
+    if parser.toks.is_empty() {
+        return Err("expected expression".to_string());
+    }
+    let n = parser.parse_binary(0)?;
+    if parser.pos < parser.toks.len() {
+        Err(format!(
+            "unexpected trailing tokens in expression {}",
+            expr.trim()
+        ))
+    } else {
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        let mut symbols = HashMap::new();
+        symbols.insert("XLEN".to_string(), 64);
+        let cases = [
+            ("1 + 2 * 3", 7),
+            ("(1 + 2) * 3", 9),
+            ("-0x10 >> 2", -4),
+            ("XLEN == 64 && !0", 1),
+            ("XLEN < 64 || 0b10 != 2", 0),
+            ("~0 & 0xFF", 0xFF),
+            ("7 % 4 - 1", 2),
+        ];
+        for (expr, exp) in cases.iter() {
+            assert_eq!(eval(expr, &symbols), Ok(*exp), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        let symbols = HashMap::new();
+        for expr in ["", "1 +", "(1", "1 / 0", "UNDEFINED", "1 2", "3 @ 4"].iter() {
+            assert!(eval(expr, &symbols).is_err(), "{}", expr);
+        }
+    }
+}
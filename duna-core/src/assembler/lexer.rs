@@ -1,6 +1,7 @@
 use super::{
     datatypes::*,
     error::{ErrMetadata, ErrorReporter, ParseError},
+    preprocessor::{Preprocessor, SourceLine},
 };
 use std::{
     fmt,
@@ -108,7 +109,9 @@ fn is_delim(c: char) -> bool {
 fn is_name_start(c: char) -> bool {
     // minus signs and numbers cannot start names; for now we're conservative
     // dollar signs prefix registers in MIPS
-    c == '$' || c == '_' || c.is_alphabetic()
+    // at signs prefix section and symbol types, as in .section .bss,"aw",@nobits
+    // percent signs prefix relocation functions, as in %hi(label)
+    c == '$' || c == '_' || c == '@' || c == '%' || c.is_alphabetic()
 }
 
 fn is_imm_start(c: char) -> bool {
@@ -118,6 +121,7 @@ fn is_imm_start(c: char) -> bool {
 struct LineLexer<'a> {
     file_id: FileId,
     lineno: LineNo,
+    call_site: Option<CallSite>,
     iter: LineIter<'a>,
     reporter: &'a mut ErrorReporter,
 }
@@ -187,6 +191,33 @@ impl<'a> LineLexer<'a> {
         Ok(TokenType::Name(string_from_utf8(cs)))
     }
 
+    /// Attempts to lex a numeric local label definition (e.g. "1:") or reference (e.g. "1b" or
+    /// "1f"). Nothing is consumed if the upcoming characters form neither.
+    fn try_build_numeric_label(&mut self, head: char) -> Option<TokenType> {
+        if !head.is_ascii_digit() {
+            return None;
+        }
+        let mut lookahead = self.iter.clone();
+        let mut cs = vec![head];
+        while let Some(&(_, c)) = lookahead.peek() {
+            if !c.is_ascii_digit() {
+                break;
+            }
+            cs.push(c);
+            lookahead.next();
+        }
+        let tok = match lookahead.next() {
+            Some((_, ':')) => TokenType::LabelDef(string_from_chars(cs)),
+            Some((_, c @ ('b' | 'f'))) if lookahead.peek().is_none_or(|&(_, c)| is_delim(c)) => {
+                cs.push(c);
+                TokenType::Name(string_from_chars(cs))
+            }
+            _ => return None,
+        };
+        self.iter = lookahead;
+        Some(tok)
+    }
+
     fn build_imm(&mut self, state: &LexState) -> Result<TokenType, ParseError> {
         let LexState { head, .. } = *state;
         // determines whether we negate at end
@@ -360,14 +391,22 @@ impl<'a> LineLexer<'a> {
         LineLexer {
             file_id,
             lineno,
+            call_site: None,
             iter: content.chars().enumerate().peekable(),
             reporter,
         }
     }
 
+    /// Marks all tokens of this line as having been produced by the macro invoked at call_site.
+    fn with_call_site(mut self, call_site: Option<CallSite>) -> LineLexer<'a> {
+        self.call_site = call_site;
+        self
+    }
+
     /// Generates a TokenStream for a line in a file.
     fn lex(mut self) -> TokenStream {
         let mut toks = Vec::<Token>::new();
+        let prev_err_count = self.reporter.errs.len();
         let lineno = self.lineno;
         while let Some((start_offs, c)) = self.iter.next() {
             let state = LexState {
@@ -376,10 +415,13 @@ impl<'a> LineLexer<'a> {
                     file_id: self.file_id,
                     lineno,
                     offs: start_offs,
+                    call_site: self.call_site,
                 },
             };
             let maybe_tok = if is_name_start(c) {
                 self.build_name(&state)
+            } else if let Some(tok) = self.try_build_numeric_label(c) {
+                Ok(tok)
             } else if is_imm_start(c) {
                 self.build_imm(&state)
             } else {
@@ -399,11 +441,7 @@ impl<'a> LineLexer<'a> {
             };
             match maybe_tok {
                 Ok(tok) => toks.push(Token {
-                    location: Location {
-                        file_id: self.file_id,
-                        lineno,
-                        offs: start_offs,
-                    },
+                    location: state.location,
                     data: tok,
                 }),
                 Err(err) => self.reporter.add_error(err),
@@ -413,7 +451,7 @@ impl<'a> LineLexer<'a> {
         // return any of the lexed tokens.
         // However, if there are labels, we still emit them so the error doesn't propagate to the
         // assembler/linker.
-        if self.reporter.is_empty() {
+        if self.reporter.errs.len() == prev_err_count {
             toks
         } else {
             toks.into_iter()
@@ -426,19 +464,59 @@ impl<'a> LineLexer<'a> {
 pub struct Lexer<'a> {
     file_id: FileId,
     contents: &'a str,
+    file_map: Option<&'a mut FileMap>,
+    symbols: Vec<(String, i64)>,
 }
 
 impl<'a> Lexer<'a> {
+    pub fn new(file_id: FileId, contents: &'a str) -> Lexer<'a> {
+        Lexer {
+            file_id,
+            contents,
+            file_map: None,
+            symbols: Vec::new(),
+        }
+    }
+
     pub fn lex_str(file_id: FileId, contents: &'a str) -> LexResult<'a> {
-        Lexer { file_id, contents }.lex()
+        Lexer::new(file_id, contents).lex()
+    }
+
+    /// Registers any files added through .include in the provided map, which must also hold
+    /// the name of the file being lexed.
+    pub fn with_file_map(mut self, file_map: &'a mut FileMap) -> Lexer<'a> {
+        self.file_map = Some(file_map);
+        self
+    }
+
+    /// Predefines constants for use by conditional directives.
+    pub fn with_symbols(mut self, symbols: Vec<(String, i64)>) -> Lexer<'a> {
+        self.symbols = symbols;
+        self
     }
 
     /// Consume the lexer's iterator to produce a stream of tokens and any possible errors.
-    fn lex(self) -> LexResult<'a> {
+    pub fn lex(self) -> LexResult<'a> {
         let mut toks = Vec::<TokenStream>::new();
         let mut reporter = ErrorReporter::new();
-        for (lineno, line) in self.contents.lines().enumerate() {
-            toks.push(LineLexer::new(self.file_id, line, lineno, &mut reporter).lex());
+        let mut preprocessor = Preprocessor::new(self.file_id, self.contents, &mut reporter)
+            .with_symbols(self.symbols);
+        if let Some(file_map) = self.file_map {
+            preprocessor = preprocessor.with_file_map(file_map);
+        }
+        let lines = preprocessor.expand();
+        for SourceLine {
+            file_id,
+            content,
+            lineno,
+            call_site,
+        } in lines
+        {
+            toks.push(
+                LineLexer::new(file_id, &content, lineno, &mut reporter)
+                    .with_call_site(call_site)
+                    .lex(),
+            );
         }
         LexResult {
             file_id: self.file_id,
@@ -472,6 +550,7 @@ mod tests {
                     file_id: 0,
                     lineno: 0,
                     offs: 0,
+                    call_site: None,
                 },
             },
         )
@@ -503,6 +582,19 @@ mod tests {
         assert_eq!(toks[5].location.offs, 13);
     }
 
+    #[test]
+    fn test_numeric_labels() {
+        let LexResult {
+            lines, reporter, ..
+        } = Lexer::lex_str(0, "10: beq a0, a1, 10f # 0b\nj 0b\nli a0, 0b1");
+        assert!(reporter.is_empty());
+        use TokenType::*;
+        assert_eq!(lines[0][0].data, LabelDef("10".to_string()));
+        assert_eq!(lines[0][6].data, Name("10f".to_string()));
+        assert_eq!(lines[1][1].data, Name("0b".to_string()));
+        assert_eq!(lines[2][3].data, Immediate(1, ImmRenderType::Bin));
+    }
+
     #[test]
     fn test_imm_builder_good() {
         use ImmRenderType::*;
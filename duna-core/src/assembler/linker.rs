@@ -1,11 +1,25 @@
 use super::{
-    assembler_impl::{Assembler, LabelTarget, SectionStore, UnlinkedProgram},
+    assembler_impl::{
+        ref_position, unresolved_label, Assembler, FileLabels, LabelTarget, ProgramSection,
+        SectionStore, UnlinkedProgram,
+    },
     datatypes::*,
-    error::{ErrorReport, ErrorReporter, ParseError},
-    parser::{Label, LabelDef},
+    error::{ErrMetadata, ErrorReport, ErrorReporter, ParseError},
+    parser::{InstParser, Label, LabelDef, LabelRef},
+    partial_inst::PartialInst,
 };
 use crate::{arch::*, config::*, program_state::Program};
-use std::{collections::HashMap, fs};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
+
+/// Sections whose contents are stored in a SectionStore rather than as instructions.
+const DATA_SECTIONS: [ProgramSection; 3] = [
+    ProgramSection::Data,
+    ProgramSection::Rodata,
+    ProgramSection::Bss,
+];
 
 /// Links programs together.
 ///
@@ -64,99 +78,173 @@ impl Linker {
     }
 
     /// Attempts to link the provided programs together into a single executable.
-    pub fn link<A: Architecture>(self, config: AsmConfig) -> Result<Program<A>, ErrorReport> {
+    pub fn link<A: Architecture>(mut self, config: AsmConfig) -> Result<Program<A>, ErrorReport> {
         assert!(
             !self.file_map.is_empty(),
             "Linker is missing a main program"
         );
         let mut reporter = ErrorReporter::new();
+        if config.crt0 {
+            match A::CRT0 {
+                // The startup routine is placed first, so that falling off the end of the last
+                // file still ends the program
+                Some(crt0) => self.file_map.insert(
+                    0,
+                    FileData {
+                        file_name: "<crt0>".to_string(),
+                        content: crt0.to_string(),
+                    },
+                ),
+                None => reporter.add_error(ParseError::generic(
+                    ErrMetadata::unlocated(),
+                    &format!("no startup routine is available for {}", A::NAME),
+                )),
+            }
+        }
         // Link other programs' local labels
         let mut programs: Vec<UnlinkedProgram<A>> = Vec::new();
-        for (i, FileData { content, .. }) in self.file_map.iter().enumerate() {
-            let (prog, new_reporter) = Assembler::assemble_str(i, content);
+        // Files registered by .include are appended to the file map during assembly, and should
+        // not be assembled on their own
+        let input_count = self.file_map.len();
+        for i in 0..input_count {
+            let content = self.file_map[i].content.clone();
+            let (prog, new_reporter) =
+                Assembler::assemble_file(i, &content, &mut self.file_map, &config);
             programs.push(prog);
             reporter.merge(new_reporter);
         }
         // Even if errors have so far been reported, we can proceed to try to link anyway
+        let file_labels: Vec<FileLabels> = programs
+            .iter_mut()
+            .map(|program| std::mem::take(&mut program.defined_labels))
+            .collect();
+        let mut undeclared_labels = Vec::new();
 
         // We essentially produce a single giant unlinked program from all constituent programs.
-        // First, resolve all local labels, then combine all the programs together and consider
-        // the union of all the global symbol tables as the new "local" symbol table.
+        // Local labels were already resolved, so combine all the programs together and resolve
+        // references to global labels against the union of all the global symbol tables.
         let mut all_insts = Vec::new();
-        let mut needed_labels: HashMap<usize, Label> = Default::default();
+        let mut needed_labels: HashMap<usize, LabelRef> = Default::default();
+        let mut data_refs: HashMap<usize, (ProgramSection, usize)> = Default::default();
         let mut defined_global_labels: HashMap<Label, LabelTarget> = Default::default();
+        // Global labels whose definition so far is weak, and may be replaced by a strong one
+        let mut weak_defs: HashSet<Label> = Default::default();
         let mut combined_sections = SectionStore::new();
 
-        for program in programs.into_iter() {
+        for (file_id, program) in programs.into_iter().enumerate() {
             let UnlinkedProgram {
                 insts: mut new_insts,
                 needed_labels: new_needed_labels,
+                data_refs: new_data_refs,
                 defined_global_labels: new_global_labels,
+                weak_labels,
+                undeclared_labels: new_undeclared_labels,
                 sections,
                 ..
             } = program;
-            // TODO implement for other sections
+            undeclared_labels.extend(new_undeclared_labels.into_iter().map(|l| (file_id, l)));
+            let new_lens = DATA_SECTIONS.map(|section| sections.byte_len(section));
             // Ensure that the section is properly aligned for the next file
             combined_sections.zero_pad_until_doubleword_aligned();
-            let prev_data_size = combined_sections.data().len();
+            // Pad the text section with nops to preserve any alignment within this file
+            let text_align = sections.alignment(ProgramSection::Text);
+            while !(all_insts.len() * 4).is_multiple_of(text_align) {
+                all_insts.push((file_id, PartialInst::new_complete(A::InstParser::nop())));
+            }
             combined_sections.join(sections);
+            // Where this file's contents begin in each data section
+            let prev_lens: HashMap<ProgramSection, usize> = DATA_SECTIONS
+                .iter()
+                .zip(new_lens.iter())
+                .map(|(&section, new_len)| (section, combined_sections.byte_len(section) - new_len))
+                .collect();
             let prev_inst_size = all_insts.len();
             all_insts.append(&mut new_insts);
             for (idx, label) in new_needed_labels.into_iter() {
-                needed_labels.insert(idx + prev_inst_size, label.target);
+                needed_labels.insert(idx + prev_inst_size, label);
+            }
+            for (idx, (section, data_idx)) in new_data_refs.into_iter() {
+                let prev_len = match section {
+                    ProgramSection::Text => prev_inst_size * 4,
+                    _ => prev_lens[&section],
+                };
+                data_refs.insert(idx + prev_inst_size, (section, data_idx + prev_len));
             }
             for (label, target_type) in new_global_labels {
-                // Check for previous definition and preserve original
-                if defined_global_labels.contains_key(&label) {
-                    reporter.add_error(ParseError::redefined_label(&LabelDef {
-                        name: label.clone(),
-                        location: target_type.location(),
-                    }))
-                } else {
-                    match target_type {
-                        LabelTarget::Inst { location, idx } => {
-                            defined_global_labels.insert(
-                                label.clone(),
-                                LabelTarget::Inst {
-                                    location,
-                                    idx: idx + prev_inst_size,
-                                },
-                            );
-                        }
-                        LabelTarget::Data {
-                            location,
-                            section,
-                            idx,
-                        } => {
-                            defined_global_labels.insert(
-                                label.clone(),
-                                LabelTarget::Data {
-                                    location,
-                                    section,
-                                    idx: idx + prev_data_size,
-                                },
-                            );
-                        }
+                let weak = weak_labels.contains(&label);
+                // Check for previous definition and preserve original, unless it was weak
+                if let Some(prev) = defined_global_labels.get(&label) {
+                    if weak {
+                        continue;
                     }
+                    if !weak_defs.remove(&label) {
+                        reporter.add_error(ParseError::redefined_label(
+                            &LabelDef {
+                                name: label,
+                                location: target_type.location(),
+                            },
+                            &prev.location(),
+                        ));
+                        continue;
+                    }
+                } else if weak {
+                    weak_defs.insert(label.clone());
                 }
+                let target_type = match target_type {
+                    LabelTarget::Inst { location, idx } => LabelTarget::Inst {
+                        location,
+                        idx: idx + prev_inst_size,
+                    },
+                    LabelTarget::Data {
+                        location,
+                        section,
+                        idx,
+                    } => LabelTarget::Data {
+                        location,
+                        section,
+                        idx: idx + prev_lens[&section],
+                    },
+                };
+                defined_global_labels.insert(label, target_type);
             }
         }
-        if reporter.is_empty() {
-            let (linked, errs) = UnlinkedProgram::<A>::new(
-                all_insts,
-                combined_sections,
-                // Need to keep track of definitions for _start/main
-                defined_global_labels.keys().cloned().collect(),
-            );
-            if errs.is_empty() {
-                // handles errantly undefined labels, although they should've already been caught
-                linked
-                    .into_program(&config.machine)
-                    .map_err(|r| r.into_report_with_file_map(self.file_map))
-            } else {
-                // handles undeclared labels
-                Err(errs.into_report_with_file_map(self.file_map))
+        // Resolve references to global labels
+        let mut undefined_labels = Vec::new();
+        for (inst_index, label) in needed_labels.into_iter() {
+            match defined_global_labels.get(&label.target) {
+                Some(&LabelTarget::Inst { idx, .. }) if all_insts[inst_index].1.needs_address() => {
+                    data_refs.insert(inst_index, (ProgramSection::Text, idx * 4));
+                }
+                Some(&LabelTarget::Inst { idx, .. }) => {
+                    UnlinkedProgram::<A>::fulfill_inst_ref(&mut all_insts, inst_index, idx)
+                }
+                Some(&LabelTarget::Data { section, idx, .. }) => {
+                    data_refs.insert(inst_index, (section, idx));
+                }
+                None => undefined_labels.push((all_insts[inst_index].0, label)),
             }
+        }
+        for (mut labels, declared) in [(undeclared_labels, false), (undefined_labels, true)] {
+            labels.sort_by_key(|(_, label)| ref_position(label));
+            labels.dedup_by_key(|(_, label)| ref_position(label));
+            for (file_idx, label) in labels {
+                reporter.add_error(unresolved_label(&label, declared, file_idx, &file_labels));
+            }
+        }
+        if reporter.is_empty() {
+            let linked = UnlinkedProgram::<A> {
+                insts: all_insts,
+                needed_labels: Default::default(),
+                data_refs,
+                defined_global_labels,
+                weak_labels: Default::default(),
+                defined_labels: Default::default(),
+                undeclared_labels: Vec::new(),
+                sections: combined_sections,
+            };
+            linked
+                .into_program(&config)
+                .map_err(|r| r.into_report_with_file_map(self.file_map))
         } else {
             Err(reporter.into_report_with_file_map(self.file_map))
         }
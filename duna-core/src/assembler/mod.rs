@@ -1,15 +1,18 @@
 mod assembler_impl;
 mod datatypes;
+mod error;
+mod expr;
 pub mod lexer;
 mod linker;
-mod error;
+mod object;
 pub mod parser;
 pub mod partial_inst;
+mod preprocessor;
 
 pub use assembler_impl::{Assembler, ProgramSection, SectionStore, UnlinkedProgram};
-pub use datatypes::{FileData, FileId, Location};
+pub use datatypes::{CallSite, FileData, FileId, Location};
 // pub use lexer::*;
+pub use error::{ErrMetadata, ErrorReport, ErrorReporter, ParseError};
 pub use linker::Linker;
-pub use error::{ErrMetadata, ParseError, ErrorReport, ErrorReporter};
 pub use partial_inst::PartialInst;
 // pub use parser::*;
@@ -0,0 +1,358 @@
+//! Writes an assembled but unlinked program as an ELF relocatable object.
+
+use super::{
+    assembler_impl::{ProgramSection, UnlinkedProgram},
+    error::{ErrMetadata, ErrorReporter, ParseError},
+    parser::LabelRef,
+    partial_inst::PartialInstType,
+};
+use crate::{
+    arch::*,
+    data_structures::*,
+    elf::*,
+    instruction::{ConcreteInst, InstDecoder},
+};
+use std::collections::HashMap;
+
+impl<A: Architecture> UnlinkedProgram<A> {
+    /// Produces an ELF relocatable object holding this program.
+    ///
+    /// References to labels in other sections or files become relocations, and labels declared
+    /// with .globl or .weak become global or weak symbols. An error is reported for any reference
+    /// that the architecture cannot express as a relocation.
+    pub fn into_object(self) -> Result<Vec<u8>, ErrorReporter> {
+        use ProgramSection::*;
+        let mut reporter = ErrorReporter::new();
+        let class =
+            ElfClass::with_word_bytes(std::mem::size_of::<<A::DataWidth as DataWidth>::U>());
+        let mut builder = ElfBuilder::new(class, ET_REL, A::ELF_MACHINE);
+
+        // Instructions that still need a label are encoded with an offset of zero for now
+        let codes: Vec<(u32, Option<&LabelRef>)> = self
+            .insts
+            .iter()
+            .map(|(_, partial_inst)| match &partial_inst.tpe {
+                PartialInstType::Complete(inst) => (inst.to_machine_code(), None),
+                PartialInstType::NeedsLabelRef(needs) => (
+                    needs
+                        .fulfill_label(RegValue::<A::DataWidth>::from(0i64))
+                        .to_machine_code(),
+                    partial_inst.get_needed_label(),
+                ),
+            })
+            .collect();
+        let mut text: Vec<u8> = codes
+            .iter()
+            .flat_map(|(code, _)| code.to_le_bytes().to_vec())
+            .collect();
+
+        let mut section_indices = HashMap::new();
+        for &(section, name, flags) in &[
+            (Text, ".text", SHF_ALLOC | SHF_EXECINSTR),
+            (Data, ".data", SHF_ALLOC | SHF_WRITE),
+            (Rodata, ".rodata", SHF_ALLOC),
+            (Bss, ".bss", SHF_ALLOC | SHF_WRITE),
+        ] {
+            let align = self.sections.alignment(section) as u64;
+            let out_section = match section {
+                Text => OutSection::progbits(name, flags, 0, Vec::new(), align),
+                Data => OutSection::progbits(name, flags, 0, self.sections.data().to_vec(), align),
+                Rodata => {
+                    OutSection::progbits(name, flags, 0, self.sections.rodata().to_vec(), align)
+                }
+                Bss => OutSection::nobits(name, flags, 0, self.sections.bss_len() as u64, align),
+            };
+            let shndx = builder.add_section(out_section);
+            let symbol = builder.add_symbol(Symbol {
+                name: String::new(),
+                value: 0,
+                size: 0,
+                info: (STB_LOCAL << 4) | STT_SECTION,
+                shndx,
+            });
+            section_indices.insert(section, (shndx, symbol));
+        }
+
+        let binding = |name: &str| {
+            if self.weak_labels.contains(name) {
+                STB_WEAK
+            } else if self.defined_global_labels.contains_key(name) {
+                STB_GLOBAL
+            } else {
+                STB_LOCAL
+            }
+        };
+        // Labels defined in this file
+        let mut label_symbols: HashMap<&str, usize> = HashMap::new();
+        let text_labels = self.insts.iter().enumerate().flat_map(|(i, (_, inst))| {
+            inst.labels
+                .iter()
+                .map(move |label| (&label.name, Text, (i * 4) as u64))
+        });
+        let data_labels = self
+            .sections
+            .labels()
+            .iter()
+            .map(|(label, section, idx)| (&label.name, *section, *idx as u64));
+        for (name, section, value) in text_labels.chain(data_labels) {
+            if is_internal_label(name) {
+                continue;
+            }
+            let symbol = builder.add_symbol(Symbol {
+                name: name.clone(),
+                value,
+                size: 0,
+                info: (binding(name) << 4) | STT_NOTYPE,
+                shndx: section_indices[&section].0,
+            });
+            label_symbols.insert(name, symbol);
+        }
+
+        // Relocations for references that could not be resolved within the text section
+        let mut relocs = Vec::new();
+        let mut undefined_symbols: HashMap<&str, usize> = HashMap::new();
+        let mut pcrel_hi_symbols: HashMap<usize, usize> = HashMap::new();
+        for (i, &(code, label)) in codes.iter().enumerate() {
+            let label = match label {
+                Some(label) => label,
+                None => continue,
+            };
+            let prev = match i.checked_sub(1).map(|prev| codes[prev]) {
+                Some((prev_code, Some(prev_label))) if prev_label.target == label.target => {
+                    Some(prev_code)
+                }
+                _ => None,
+            };
+            let reloc = match A::InstDecoder::relocation(code, prev) {
+                Some(reloc) => reloc,
+                None => {
+                    reporter.add_error(ParseError::generic(
+                        ErrMetadata::new(&label.location),
+                        &format!(
+                            "cannot emit a relocation for the reference to label {}",
+                            label.target
+                        ),
+                    ));
+                    continue;
+                }
+            };
+            text[i * 4..i * 4 + 4].copy_from_slice(&reloc.code.to_le_bytes());
+            let (symbol, addend) = if reloc.refers_to_prev {
+                // the lower half of a pc-relative pair points at the instruction with the upper half
+                let count = pcrel_hi_symbols.len();
+                let symbol = *pcrel_hi_symbols.entry(i - 1).or_insert_with(|| {
+                    builder.add_symbol(Symbol {
+                        name: format!(".Lpcrel_hi{}", count),
+                        value: ((i - 1) * 4) as u64,
+                        size: 0,
+                        info: (STB_LOCAL << 4) | STT_NOTYPE,
+                        shndx: section_indices[&Text].0,
+                    })
+                });
+                (symbol, 0)
+            } else if let Some((section, idx)) = self.data_refs.get(&i) {
+                (section_indices[section].1, *idx as i64)
+            } else if let Some(&symbol) = label_symbols.get(label.target.as_str()) {
+                // references to a weak label defined here are resolved by the linker
+                (symbol, 0)
+            } else if self.needed_labels.contains_key(&i) {
+                let weak = self.weak_labels.contains(&label.target);
+                let symbol = *undefined_symbols.entry(&label.target).or_insert_with(|| {
+                    builder.add_symbol(Symbol {
+                        name: label.target.clone(),
+                        value: 0,
+                        size: 0,
+                        info: (if weak { STB_WEAK } else { STB_GLOBAL } << 4) | STT_NOTYPE,
+                        shndx: SHN_UNDEF,
+                    })
+                });
+                (symbol, 0)
+            } else {
+                // the assembler already reported the label as undeclared
+                continue;
+            };
+            relocs.push(Rela {
+                offset: (i * 4) as u64,
+                symbol,
+                r_type: reloc.r_type,
+                addend,
+            });
+        }
+        let text_section = builder.section_mut(section_indices[&Text].0);
+        text_section.size = text.len() as u64;
+        text_section.data = text;
+        text_section.relocs = relocs;
+        if reporter.is_empty() {
+            Ok(builder.build())
+        } else {
+            Err(reporter)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        architectures::riscv::{Rv32, Rv64},
+        assembler::Assembler,
+    };
+
+    const PROGRAM: &str = "
+        .globl main
+        .globl helper
+        .data
+        val: .word 5
+        .text
+        main:
+            la a0, val
+            sw a0, val, t0
+            beq a0, zero, done
+            jal ra, helper
+            beq a0, zero, helper
+        done:
+            call helper
+        ";
+
+    fn assemble<A: Architecture>() -> ElfFile {
+        let bytes = Assembler::assemble_object::<A>("test", PROGRAM, &Default::default())
+            .unwrap_or_else(|report| {
+                report.report();
+                panic!("failed to assemble")
+            });
+        ElfFile::parse(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_object_symbols() {
+        let obj = assemble::<Rv32>();
+        assert_eq!(obj.class, ElfClass::Elf32);
+        assert_eq!(obj.elf_type, ET_REL);
+        assert!(obj.segments.is_empty());
+        let find = |name: &str| obj.symbols.iter().find(|sym| sym.name == name).unwrap();
+        // .text is the first section
+        assert_eq!(find("main").info, (STB_GLOBAL << 4) | STT_NOTYPE);
+        assert_eq!((find("main").shndx, find("main").value), (1, 0));
+        assert_eq!(find("done").info, (STB_LOCAL << 4) | STT_NOTYPE);
+        assert_eq!((find("done").shndx, find("done").value), (1, 28));
+        assert_eq!((find("val").shndx, find("val").value), (2, 0));
+        assert_eq!(find("helper").shndx, SHN_UNDEF);
+        // locals come before globals
+        let first_global = obj
+            .symbols
+            .iter()
+            .position(|sym| sym.info >> 4 == STB_GLOBAL);
+        assert!(obj.symbols[first_global.unwrap()..]
+            .iter()
+            .all(|sym| sym.info >> 4 == STB_GLOBAL));
+        assert_eq!(assemble::<Rv64>().class, ElfClass::Elf64);
+    }
+
+    #[test]
+    fn test_object_weak_symbols() {
+        let program = "
+            .weak handler
+            .weak fallback
+            call handler
+            call fallback
+            handler:
+                ret
+            ";
+        let bytes = Assembler::assemble_object::<Rv32>("test", program, &Default::default())
+            .unwrap_or_else(|_| panic!("failed to assemble"));
+        let obj = ElfFile::parse(&bytes).unwrap();
+        let find = |name: &str| obj.symbols.iter().find(|sym| sym.name == name).unwrap();
+        assert_eq!(find("handler").info, (STB_WEAK << 4) | STT_NOTYPE);
+        assert_eq!((find("handler").shndx, find("handler").value), (1, 16));
+        assert_eq!(find("fallback").info, (STB_WEAK << 4) | STT_NOTYPE);
+        assert_eq!(find("fallback").shndx, SHN_UNDEF);
+        // the reference to handler may resolve to a definition in another file
+        let (_, rela) = &obj.relocations[0];
+        assert_eq!(obj.symbols[rela.symbol - 1].name, "handler");
+    }
+
+    #[test]
+    fn test_object_relocations() {
+        let obj = assemble::<Rv32>();
+        let name = |rela: &Rela| obj.symbols[rela.symbol - 1].name.clone();
+        let relocs: Vec<(u64, u32, String, i64)> = obj
+            .relocations
+            .iter()
+            .map(|(section, rela)| {
+                assert_eq!(*section, 1);
+                (rela.offset, rela.r_type, name(rela), rela.addend)
+            })
+            .collect();
+        let expected = vec![
+            // la a0, val is relative to the start of .data, whose section symbol has no name
+            (0, R_RISCV_PCREL_HI20, "", 0),
+            (4, R_RISCV_PCREL_LO12_I, ".Lpcrel_hi0", 0),
+            (8, R_RISCV_PCREL_HI20, "", 0),
+            (12, R_RISCV_PCREL_LO12_S, ".Lpcrel_hi1", 0),
+            // beq to done is resolved by the assembler
+            (20, R_RISCV_JAL, "helper", 0),
+            (24, R_RISCV_BRANCH, "helper", 0),
+            (28, R_RISCV_PCREL_HI20, "helper", 0),
+            (32, R_RISCV_PCREL_LO12_I, ".Lpcrel_hi2", 0),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(ofs, r_type, name, addend)| (ofs, r_type, name.to_string(), addend))
+            .collect();
+        assert_eq!(relocs, expected);
+    }
+
+    /// Tests that the %hi and %lo relocation functions become absolute relocations, laid out as
+    /// llvm-mc would.
+    #[test]
+    fn test_object_addr_part_relocations() {
+        let program = "
+            .globl ext
+            .data
+            pad: .word 1
+            val: .word 5
+            .text
+            start:
+                lui a5, %hi(val)
+                lw a0, %lo(val)(a5)
+                addi a0, a0, 1
+                sw a0, %lo(val)(a5)
+                lui a1, %hi(start)
+                addi a1, a1, %lo(start)
+                lui a2, %hi(ext)
+                addi a2, a2, %lo(ext)
+            ";
+        let bytes = Assembler::assemble_object::<Rv32>("test", program, &Default::default())
+            .unwrap_or_else(|report| {
+                report.report();
+                panic!("failed to assemble")
+            });
+        let obj = ElfFile::parse(&bytes).unwrap();
+        let relocs: Vec<(u64, u32, String, i64)> = obj
+            .relocations
+            .iter()
+            .map(|(_, rela)| {
+                let symbol = &obj.symbols[rela.symbol - 1];
+                (rela.offset, rela.r_type, symbol.name.clone(), rela.addend)
+            })
+            .collect();
+        let expected = vec![
+            // references to local labels are relative to their section symbols
+            (0, R_RISCV_HI20, "", 4),
+            (4, R_RISCV_LO12_I, "", 4),
+            (12, R_RISCV_LO12_S, "", 4),
+            (16, R_RISCV_HI20, "", 0),
+            (20, R_RISCV_LO12_I, "", 0),
+            (24, R_RISCV_HI20, "ext", 0),
+            (28, R_RISCV_LO12_I, "ext", 0),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(ofs, r_type, name, addend)| (ofs, r_type, name.to_string(), addend))
+            .collect();
+        assert_eq!(relocs, expected);
+        // the first two are against .data, and the next two against .text
+        let shndx = |i: usize| obj.symbols[obj.relocations[i].1.symbol - 1].shndx;
+        assert_eq!((shndx(0), shndx(2), shndx(3)), (2, 2, 1));
+    }
+}
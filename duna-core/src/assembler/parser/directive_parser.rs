@@ -9,7 +9,36 @@ pub struct DirectiveParser<'a> {
     head_directive: &'a str,
 }
 
-type DirectiveParseResult = Result<Option<DirectiveLiterals>, ParseError>;
+type DirectiveParseResult = Result<Option<DirectiveOutput>, ParseError>;
+
+/// The largest power of two accepted by alignment directives.
+const MAX_ALIGN_POW: i64 = 16;
+
+/// Determines which section holds the contents of a section declared with .section.
+fn section_for_name(name: &str, flags: Option<&str>, nobits: bool) -> ProgramSection {
+    use ProgramSection::*;
+    const NAMED: [(&str, ProgramSection); 7] = [
+        (".text", Text),
+        (".rodata", Rodata),
+        (".srodata", Rodata),
+        (".data", Data),
+        (".sdata", Data),
+        (".bss", Bss),
+        (".sbss", Bss),
+    ];
+    let named = NAMED.iter().find(|(prefix, _)| {
+        name.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    });
+    match (named, flags) {
+        (Some(&(_, section)), _) => section,
+        _ if nobits => Bss,
+        (None, Some(flags)) if flags.contains('x') => Text,
+        (None, Some(flags)) if flags.contains('w') => Data,
+        (None, Some(_)) => Rodata,
+        (None, None) => Data,
+    }
+}
 
 impl<'a> DirectiveParser<'a> {
     pub fn new(
@@ -27,6 +56,28 @@ impl<'a> DirectiveParser<'a> {
     }
 
     pub fn parse(self) -> DirectiveParseResult {
+        let head_loc = self.head_loc;
+        let output = self.parse_directive()?;
+        let nonzero_bss = match &output {
+            Some(DirectiveOutput::Literals(data)) => {
+                data.section == ProgramSection::Bss && !data.is_zero()
+            }
+            Some(DirectiveOutput::Padding(Padding::Align { section, fill, .. })) => {
+                *section == ProgramSection::Bss && *fill != 0
+            }
+            _ => false,
+        };
+        if nonzero_bss {
+            Err(ParseError::generic(
+                ErrMetadata::new(head_loc),
+                "the .bss section can only hold zeroes",
+            ))
+        } else {
+            Ok(output)
+        }
+    }
+
+    fn parse_directive(self) -> DirectiveParseResult {
         match self.head_directive {
             // sections
             "section" => self.parse_section(),
@@ -42,17 +93,32 @@ impl<'a> DirectiveParser<'a> {
                 self.state.curr_section = ProgramSection::Rodata;
                 self.ok(0)
             }
+            "bss" => {
+                self.state.curr_section = ProgramSection::Bss;
+                self.ok(0)
+            }
             // literal insertions
             "byte" => self.parse_data(DataWidthEnum::Byte),
             "2byte" | "half" | "short" => self.parse_data(DataWidthEnum::Half),
             "4byte" | "word" | "long" => self.parse_data(DataWidthEnum::Lword),
             "8byte" | "dword" | "quad" => self.parse_data(DataWidthEnum::Dword),
             "zero" => self.parse_zero(),
+            // alignment and reservation
+            "align" | "p2align" => self.parse_align(true),
+            "balign" => self.parse_align(false),
+            "space" | "skip" => self.parse_space(),
+            "fill" => self.parse_fill(),
             "ascii" => self.parse_string(false),
             "asciz" | "string" => self.parse_string(true),
             // symbol declarations
-            "global" | "globl" => self.parse_global_label(),
-            // TODO: equ, set, equiv (refactor symbol table to have enum value)
+            "global" | "globl" | "extern" => self.parse_global_label(false),
+            "weak" => self.parse_global_label(true),
+            // metadata emitted by compilers, which has no effect on the program
+            "file" | "ident" | "type" | "size" | "attribute" | "option" | "loc" | "addrsig" => {
+                Ok(None)
+            }
+            name if name.starts_with("cfi_") => Ok(None),
+            // .equ, .set, and .equiv are handled by the preprocessor
             _ => Err(ParseError::unsupported_directive(
                 ErrMetadata::new(self.head_loc),
                 self.head_directive,
@@ -94,41 +160,54 @@ impl<'a> DirectiveParser<'a> {
                 }
                 // should never fail since we've consumed the whole iterator
                 self.ok(0)?;
-                Ok(Some(data))
+                Ok(Some(DirectiveOutput::Literals(data)))
             }
         }
     }
 
+    /// Switches to a section given by name, as in .section name[, "flags"[, @type]].
+    ///
+    /// Conventional names like .rodata, and their subsections like .rodata.str1.1, are placed in
+    /// the section they name. Other sections are placed by their flags and type: @nobits sections
+    /// go in bss, and otherwise "x" means text, "w" means data, and anything else rodata.
+    /// Sections without flags are treated as data.
     fn parse_section(mut self) -> DirectiveParseResult {
-        let next_tok = self.try_next_tok(1, 0)?;
-        if let TokenType::Directive(s) = &next_tok.data {
-            match s.as_str() {
-                "text" => {
-                    self.state.curr_section = ProgramSection::Text;
-                    return self.ok(1);
-                }
-                "data" => {
-                    self.state.curr_section = ProgramSection::Data;
-                    return self.ok(1);
-                }
-                "rodata" => {
-                    self.state.curr_section = ProgramSection::Rodata;
-                    return self.ok(1);
-                }
-                "bss" => {
-                    return Err(ParseError::unimplemented(
-                        ErrMetadata::new(&next_tok.location),
-                        "bss section",
-                    ))
-                }
-                _ => {}
+        let mut args = self.consume_optional_args(4)?.into_iter();
+        let name_tok = match args.next().flatten() {
+            Some(tok) => tok,
+            None => return Err(self.wrong_argc(1, 0)),
+        };
+        let name = match name_tok.data {
+            TokenType::Directive(s) => format!(".{}", s),
+            TokenType::Name(s) | TokenType::StringLiteral(s) => s,
+            data => {
+                return Err(ParseError::unexpected_type(
+                    ErrMetadata::new(&name_tok.location),
+                    "section name",
+                    data,
+                ))
             }
-        }
-        Err(ParseError::unexpected_type(
-            ErrMetadata::new(&next_tok.location),
-            "one of [.text, .bss, .data, .rodata]",
-            next_tok.data,
-        ))
+        };
+        let flags = match args.next().flatten() {
+            Some(Token {
+                data: TokenType::StringLiteral(flags),
+                ..
+            }) => Some(flags),
+            Some(tok) => {
+                return Err(ParseError::unexpected_type(
+                    ErrMetadata::new(&tok.location),
+                    "string of section flags",
+                    tok.data,
+                ))
+            }
+            None => None,
+        };
+        let nobits = matches!(
+            args.next().flatten(),
+            Some(Token { data: TokenType::Name(tpe), .. }) if tpe == "@nobits"
+        );
+        self.state.curr_section = section_for_name(&name, flags.as_deref(), nobits);
+        Ok(None)
     }
 
     /// Emits zero or more string literals.
@@ -160,7 +239,7 @@ impl<'a> DirectiveParser<'a> {
             }
         }
         self.ok(0)?;
-        Ok(Some(data))
+        Ok(Some(DirectiveOutput::Literals(data)))
     }
 
     fn parse_zero(mut self) -> DirectiveParseResult {
@@ -185,7 +264,7 @@ impl<'a> DirectiveParser<'a> {
                     data.add_byte(0);
                 }
                 self.ok(1)?;
-                Ok(Some(data))
+                Ok(Some(DirectiveOutput::Literals(data)))
             }
         } else {
             Err(ParseError::unexpected_type(
@@ -196,10 +275,141 @@ impl<'a> DirectiveParser<'a> {
         }
     }
 
-    /// Indicates that a symbol is declared globally.
-    fn parse_global_label(mut self) -> DirectiveParseResult {
+    /// Pads the current section to an alignment, given either as a power of two (.p2align,
+    /// and .align as on RISC-V and MIPS) or as a number of bytes (.balign).
+    /// Takes the form .p2align align[, fill[, max_skip]].
+    ///
+    /// See https://sourceware.org/binutils/docs/as/P2align.html
+    fn parse_align(mut self, is_pow: bool) -> DirectiveParseResult {
+        let args = self.consume_optional_args(3)?;
+        let mut args = args.into_iter();
+        let align_tok = match args.next().flatten() {
+            Some(tok) => tok,
+            None => return Err(self.wrong_argc(1, 0)),
+        };
+        let align_loc = align_tok.location;
+        let align = self.try_parse_imm(32, align_tok)?;
+        let align = if is_pow {
+            if !(0..=MAX_ALIGN_POW).contains(&align) {
+                return Err(ParseError::generic(
+                    ErrMetadata::new(&align_loc),
+                    &format!("alignment power must be between 0 and {}", MAX_ALIGN_POW),
+                ));
+            }
+            1 << align
+        } else {
+            if align <= 0 || align > (1 << MAX_ALIGN_POW) || (align & (align - 1)) != 0 {
+                return Err(ParseError::generic(
+                    ErrMetadata::new(&align_loc),
+                    &format!(
+                        "alignment must be a power of two no larger than {}",
+                        1 << MAX_ALIGN_POW
+                    ),
+                ));
+            }
+            align
+        } as usize;
+        let fill = match args.next().flatten() {
+            Some(tok) => self.try_parse_imm(8, tok)? as u8,
+            None => 0,
+        };
+        let max_skip = match args.next().flatten() {
+            Some(tok) => Some(self.try_parse_nonnegative(tok)?),
+            None => None,
+        };
+        Ok(Some(DirectiveOutput::Padding(Padding::Align {
+            section: self.state.curr_section,
+            align,
+            fill,
+            max_skip,
+        })))
+    }
+
+    /// Reserves some number of bytes, optionally filled with a byte value.
+    /// In the text section, the size must be a multiple of 4, and the space is filled with nops.
+    /// Takes the form .space size[, fill].
+    fn parse_space(mut self) -> DirectiveParseResult {
+        let args = self.consume_optional_args(2)?;
+        let mut args = args.into_iter();
+        let size_tok = match args.next().flatten() {
+            Some(tok) => tok,
+            None => return Err(self.wrong_argc(1, 0)),
+        };
+        let size_loc = size_tok.location;
+        let size = self.try_parse_nonnegative(size_tok)?;
+        let fill = match args.next().flatten() {
+            Some(tok) => Some(self.try_parse_imm(8, tok)? as u8),
+            None => None,
+        };
+        if let ProgramSection::Text = self.state.curr_section {
+            if size % 4 != 0 || fill.is_some_and(|fill| fill != 0) {
+                return Err(ParseError::generic(
+                    ErrMetadata::new(&size_loc),
+                    "space in the .text section must be a multiple of 4 bytes, and is filled with nops",
+                ));
+            }
+            return Ok(Some(DirectiveOutput::Padding(Padding::Nops(size / 4))));
+        }
+        let mut data = DirectiveLiterals::new(self.state.curr_section);
+        for _ in 0..size {
+            data.add_byte(fill.unwrap_or(0));
+        }
+        Ok(Some(DirectiveOutput::Literals(data)))
+    }
+
+    /// Emits repeat copies of a value that is size bytes wide (at most 8).
+    /// Takes the form .fill repeat[, size[, value]]; size defaults to 1 and value to 0.
+    ///
+    /// See https://sourceware.org/binutils/docs/as/Fill.html
+    fn parse_fill(mut self) -> DirectiveParseResult {
+        if let ProgramSection::Text = self.state.curr_section {
+            return Err(ParseError::unimplemented(
+                ErrMetadata::new(self.head_loc),
+                ".fill in the .text section (use .space or .align for nop padding)",
+            ));
+        }
+        let args = self.consume_optional_args(3)?;
+        let mut args = args.into_iter();
+        let repeat = match args.next().flatten() {
+            Some(tok) => self.try_parse_nonnegative(tok)?,
+            None => return Err(self.wrong_argc(1, 0)),
+        };
+        let size = match args.next().flatten() {
+            Some(tok) => {
+                let size_loc = tok.location;
+                let size = self.try_parse_nonnegative(tok)?;
+                if size > 8 {
+                    return Err(ParseError::generic(
+                        ErrMetadata::new(&size_loc),
+                        ".fill size cannot be larger than 8 bytes",
+                    ));
+                }
+                size
+            }
+            None => 1,
+        };
+        let value = match args.next().flatten() {
+            Some(tok) => self.try_parse_imm(64, tok)? as u64,
+            None => 0,
+        };
+        let mut data = DirectiveLiterals::new(self.state.curr_section);
+        for _ in 0..repeat {
+            // little endian, without any alignment
+            for i in 0..size {
+                data.add_byte((value >> (8 * i)) as u8);
+            }
+        }
+        Ok(Some(DirectiveOutput::Literals(data)))
+    }
+
+    /// Indicates that a symbol is declared globally. A weak symbol may be defined by multiple
+    /// files, in which case a definition that is not weak takes precedence.
+    fn parse_global_label(mut self, weak: bool) -> DirectiveParseResult {
         let next_tok = self.try_next_tok(1, 0)?;
         if let TokenType::Name(name) = next_tok.data {
+            if weak {
+                self.state.declared_weak.insert(name.clone());
+            }
             // announcing a variable as global multiple times is ok, so just insert without checking
             self.state.declared_globals.insert(name);
             self.ok(1)
@@ -229,6 +439,60 @@ impl<'a> DirectiveParser<'a> {
         self.iter.consume_unbounded_commasep_args()
     }
 
+    /// Consumes up to max comma-separated arguments, any of which may be omitted by leaving
+    /// the space between commas empty (e.g. ".p2align 3,,7").
+    fn consume_optional_args(&mut self, max: u8) -> Result<Vec<Option<Token>>, ParseError> {
+        let mut args = vec![None];
+        for tok in &mut self.iter {
+            match tok.data {
+                TokenType::Comma => {
+                    if args.len() == max as usize {
+                        return Err(ParseError::too_many_args(
+                            ErrMetadata::new(&tok.location),
+                            self.head_directive,
+                            max,
+                        ));
+                    }
+                    args.push(None);
+                }
+                TokenType::Comment(..) => break,
+                _ => {
+                    let last = args.last_mut().unwrap();
+                    if last.is_some() {
+                        return Err(ParseError::bad_arg(
+                            ErrMetadata::new(&tok.location),
+                            &format!("{:?}", tok.data),
+                        ));
+                    }
+                    *last = Some(tok);
+                }
+            }
+        }
+        Ok(args)
+    }
+
+    fn try_parse_nonnegative(&self, token: Token) -> Result<usize, ParseError> {
+        let location = token.location;
+        let n = self.try_parse_imm(32, token)?;
+        if n < 0 {
+            Err(ParseError::generic(
+                ErrMetadata::new(&location),
+                &format!("expected non-negative integer, got {}", n),
+            ))
+        } else {
+            Ok(n as usize)
+        }
+    }
+
+    fn wrong_argc(&self, needed: u8, got: u8) -> ParseError {
+        ParseError::wrong_argc(
+            ErrMetadata::new(self.head_loc),
+            self.head_directive,
+            needed,
+            got,
+        )
+    }
+
     /// Checks that the iterator has run out of tokens; returns ok if so.
     /// needed_argc is the number of arguments that were needed in total, not the number that
     /// still need to be consumed (which is always 0, since we're checking if we ran out of args).
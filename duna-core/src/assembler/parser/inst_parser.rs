@@ -6,6 +6,8 @@ pub struct InstParseState<'a, F: ArchFamily<S>, S: DataWidth, ParseType: 'static
     pub iter: TokenIter,
     pub head_loc: &'a Location,
     pub inst_name: &'a str,
+    /// Where constants that are loaded from memory are placed, if enabled.
+    pub literal_pool: Option<&'a mut LiteralPool>,
 }
 
 impl<'a, F, S, ParseType> InstParseState<'a, F, S, ParseType>
@@ -19,12 +21,14 @@ where
         iter: TokenIter,
         head_loc: &'a Location,
         inst_name: &'a str,
+        literal_pool: Option<&'a mut LiteralPool>,
     ) -> Self {
         Self {
             data,
             iter,
             head_loc,
             inst_name,
+            literal_pool,
         }
     }
 
@@ -139,6 +143,9 @@ pub trait InstParser<F: ArchFamily<S>, S: DataWidth> {
     fn inst_expansion_table() -> &'static HashMap<String, Self::ParseType>;
     fn reg_expansion_table() -> &'static HashMap<String, F::Register>;
 
+    /// The instruction used to pad the text section, e.g. for alignment directives.
+    fn nop() -> F::Instruction;
+
     fn try_expand_found_inst(
         state: InstParseState<'_, F, S, Self::ParseType>,
         parse_type: &Self::ParseType,
@@ -5,7 +5,7 @@ use crate::{arch::*, assembler::ErrMetadata};
 pub struct LineParser<'a, A: Architecture> {
     data: &'a _ParserData<A>,
     iter: TokenIter,
-    label: Option<LabelDef>,
+    labels: Vec<LabelDef>,
     state: &'a mut ParseState,
 }
 
@@ -13,46 +13,49 @@ impl<'a, A> LineParser<'a, A>
 where
     A: Architecture,
 {
-    /// Creates a LineParser, with a label possibly inherited from the previous line.
+    /// Creates a LineParser, with labels possibly inherited from previous lines.
+    /// Any labels at the start of this line are added to them.
     pub fn new(
         data: &'a _ParserData<A>,
         tokens: TokenStream,
-        maybe_label: &'a Option<LabelDef>,
+        mut labels: Vec<LabelDef>,
         state: &'a mut ParseState,
     ) -> Self {
         let mut iter = tokens.into_iter().peekable();
-        let label_passed_in = maybe_label.is_some();
-        // Check the first token for a label
-        let this_label = match maybe_label {
-            Some(l) => Some(l.clone()),
-            None => {
-                if let Some(first_tok) = iter.peek() {
-                    if let TokenType::LabelDef(label_name) = &first_tok.data {
-                        Some(LabelDef::new(label_name.to_string(), first_tok.location))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
+        while let Some(Token {
+            data: TokenType::LabelDef(..),
+            ..
+        }) = iter.peek()
+        {
+            if let Some(Token {
+                data: TokenType::LabelDef(name),
+                location,
+            }) = iter.next()
+            {
+                labels.push(LabelDef::new(state.define_label(name), location));
             }
-        };
-        // should consume token
-        // can't put this in the above if stmt due to lifetime rules
-        if this_label.is_some() && !label_passed_in {
-            iter.next();
         }
+        // Point references to numeric labels (e.g. 1b) at the definition they refer to
+        let tokens: Vec<Token> = iter
+            .map(|tok| match tok.data {
+                TokenType::Name(name) => Token {
+                    data: TokenType::Name(state.numeric_label_ref(&name).unwrap_or(name)),
+                    ..tok
+                },
+                _ => tok,
+            })
+            .collect();
         LineParser {
             data,
-            iter: TokenIter(iter),
-            label: this_label,
+            iter: TokenIter(tokens.into_iter().peekable()),
+            labels,
             state,
         }
     }
 
-    pub fn parse(mut self) -> (Option<LabelDef>, LineParseResult<A::Family, A::DataWidth>) {
+    pub fn parse(mut self) -> (Vec<LabelDef>, LineParseResult<A::Family, A::DataWidth>) {
         (
-            self.label,
+            self.labels,
             if let Some(head_tok) = self.iter.next() {
                 let errloc = ErrMetadata::new(&head_tok.location);
                 use TokenType::*;
@@ -64,6 +67,7 @@ where
                                 self.iter,
                                 &head_tok.location,
                                 &name,
+                                self.state.literal_pool.as_mut(),
                             ))
                             .map(OkParseResult::Insts)
                         } else {
@@ -76,15 +80,8 @@ where
                             ))
                         }
                     }
-                    // first label def is handled by contructor
-                    // TODO handle multiple labels on same line
-                    LabelDef(label_name) => Err(ParseError::unimplemented(
-                        errloc,
-                        &format!(
-                            "(found label {}) multiple labels on the same line",
-                            label_name
-                        ),
-                    )),
+                    // leading label defs are handled by the constructor
+                    LabelDef(..) => unreachable!(),
                     Directive(section_name) => DirectiveParser::new(
                         self.iter,
                         self.state,
@@ -92,12 +89,12 @@ where
                         &section_name,
                     )
                     .parse()
-                    .map(|option| {
-                        if let Some(literals) = option {
+                    .map(|option| match option {
+                        Some(DirectiveOutput::Literals(literals)) => {
                             OkParseResult::Literals(literals)
-                        } else {
-                            OkParseResult::None
                         }
+                        Some(DirectiveOutput::Padding(padding)) => OkParseResult::Padding(padding),
+                        None => OkParseResult::None,
                     }),
                     Comment(..) => Ok(OkParseResult::None), // deliberate no-op
                     Comma => Err(ParseError::bad_head(errloc, ",")),
@@ -13,7 +13,7 @@ use super::{
     lexer::*,
     partial_inst::PartialInst,
 };
-use crate::{arch::*, data_structures::*, program_state::IRegister};
+use crate::{arch::*, config::*, data_structures::*, program_state::IRegister};
 pub(crate) use directive_parser::DirectiveParser;
 pub use inst_parser::*;
 use line_parser::*;
@@ -86,11 +86,42 @@ impl DirectiveLiterals {
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
+
+    /// Returns true if every literal is zero.
+    pub fn is_zero(&self) -> bool {
+        self.data.iter().all(|&val| match val {
+            DataEnum::Byte(n) => u8::from(n) == 0,
+            DataEnum::Half(n) => u16::from(n) == 0,
+            DataEnum::Lword(n) => u32::from(n) == 0,
+            DataEnum::Dword(n) => u64::from(n) == 0,
+        })
+    }
+}
+
+/// Padding requested by an alignment or space directive such as .align or .space.
+pub enum Padding {
+    /// Pads a section until its length is a multiple of align bytes, unless more than max_skip
+    /// bytes would be needed. The text section is padded with nops instead of the fill byte.
+    Align {
+        section: ProgramSection,
+        align: usize,
+        fill: u8,
+        max_skip: Option<usize>,
+    },
+    /// Inserts some number of nops into the text section.
+    Nops(usize),
+}
+
+/// The result of successfully parsing a directive.
+pub enum DirectiveOutput {
+    Literals(DirectiveLiterals),
+    Padding(Padding),
 }
 
 pub enum OkParseResult<F: ArchFamily<S>, S: DataWidth> {
     Insts(ParsedInstStream<F, S>),
     Literals(DirectiveLiterals),
+    Padding(Padding),
     None,
 }
 
@@ -107,6 +138,7 @@ where
     pub insts: ParsedInstStream<F, S>,
     pub sections: SectionStore,
     pub declared_globals: HashSet<String>,
+    pub declared_weak: HashSet<String>,
     pub reporter: ErrorReporter,
 }
 
@@ -119,6 +151,13 @@ pub struct ParseState {
     /// current file defined the symbol and is making it visible to the linker, or
     /// the current file will look for the symbol in another file.
     pub declared_globals: HashSet<String>,
+    /// These labels were given to a .weak declaration. They are also in declared_globals.
+    pub declared_weak: HashSet<String>,
+    /// The number of times each numeric local label (e.g. "1:") has been defined so far.
+    numeric_label_counts: HashMap<String, usize>,
+    /// Constants to be loaded from memory by instructions. This is only present when li is
+    /// configured to use a literal pool.
+    pub literal_pool: Option<LiteralPool>,
 }
 
 impl ParseState {
@@ -126,10 +165,79 @@ impl ParseState {
         ParseState {
             curr_section: ProgramSection::Text,
             declared_globals: HashSet::new(),
+            declared_weak: HashSet::new(),
+            numeric_label_counts: HashMap::new(),
+            literal_pool: None,
+        }
+    }
+
+    /// Returns the name under which a label definition is recorded.
+    /// Numeric local labels can be redefined, so each definition gets a distinct name that
+    /// cannot be written in source code.
+    pub fn define_label(&mut self, name: String) -> String {
+        if name.chars().all(|c| c.is_ascii_digit()) {
+            let count = self.numeric_label_counts.entry(name.clone()).or_insert(0);
+            *count += 1;
+            numeric_label_name(&name, *count)
+        } else {
+            name
+        }
+    }
+
+    /// If NAME refers to a numeric local label, as in "1b" or "1f", returns the name of the
+    /// nearest definition before or after this point, respectively.
+    /// A backward reference with no previous definition is left alone, so that it is reported
+    /// as undefined.
+    pub fn numeric_label_ref(&self, name: &str) -> Option<String> {
+        let (num, forward) = match name.strip_suffix('b') {
+            Some(num) => (num, false),
+            None => (name.strip_suffix('f')?, true),
+        };
+        if num.is_empty() || !num.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let count = self.numeric_label_counts.get(num).copied().unwrap_or(0);
+        if forward {
+            Some(numeric_label_name(num, count + 1))
+        } else if count > 0 {
+            Some(numeric_label_name(num, count))
+        } else {
+            None
         }
     }
 }
 
+/// Names the nth definition of a numeric local label.
+fn numeric_label_name(num: &str, n: usize) -> String {
+    format!("{}^{}", num, n)
+}
+
+/// Constants that instructions load from memory rather than build in registers.
+/// The constants are placed in .rodata after the rest of the file.
+#[derive(Default)]
+pub struct LiteralPool {
+    /// Each distinct constant, along with the label that points to it.
+    entries: Vec<(DataEnum, LabelDef)>,
+}
+
+impl LiteralPool {
+    /// Returns a reference to a label pointing to the constant, adding it to the pool if it
+    /// is not already present.
+    pub fn add(&mut self, val: DataEnum, location: &Location) -> LabelRef {
+        let name = match self.entries.iter().find(|(other, _)| *other == val) {
+            Some((_, label)) => label.name.clone(),
+            None => {
+                // The name cannot be written in source code, so it cannot collide
+                let name = format!("pool^{}", self.entries.len());
+                self.entries
+                    .push((val, LabelDef::new(name.clone(), *location)));
+                name
+            }
+        };
+        LabelRef::new(name, *location)
+    }
+}
+
 impl Default for ParseState {
     fn default() -> Self {
         Self::new()
@@ -204,7 +312,7 @@ impl TokenIter {
         let mut was_comma = true;
         for tok in self {
             match tok.data {
-                Name(..) | Immediate(..) | StringLiteral(..) => {
+                Name(..) | Immediate(..) | StringLiteral(..) | Directive(..) => {
                     was_comma = false;
                     toks.push(tok)
                 }
@@ -257,10 +365,55 @@ where
     A: Architecture,
 {
     pub fn parse_str(file_id: FileId, contents: &str) -> ParseResult<A::Family, A::DataWidth> {
-        Self::parse_lex_result(Lexer::lex_str(file_id, contents))
+        Self::parse_lex_result(
+            Lexer::new(file_id, contents)
+                .with_symbols(Self::builtin_symbols())
+                .lex(),
+        )
+    }
+
+    /// Parses a file whose name is stored in the provided file map. Any files added through
+    /// .include are registered in the map.
+    pub fn parse_file(
+        file_id: FileId,
+        contents: &str,
+        file_map: &mut FileMap,
+        config: &AsmConfig,
+    ) -> ParseResult<A::Family, A::DataWidth> {
+        let mut parser = Self::new(
+            Lexer::new(file_id, contents)
+                .with_file_map(file_map)
+                .with_symbols(Self::builtin_symbols())
+                .lex(),
+        );
+        if config.li_strategy == LiStrategy::LiteralPool {
+            parser.state.literal_pool = Some(LiteralPool::default());
+        }
+        parser.parse()
+    }
+
+    fn nop() -> PartialInst<A::Family, A::DataWidth> {
+        PartialInst::new_complete(<A::InstParser as InstParser<A::Family, A::DataWidth>>::nop())
+    }
+
+    /// Symbols predefined for every file: __XLEN holds the register width in bits, and
+    /// __ARCH_<NAME> is defined as 1, where NAME is the uppercased name of the architecture
+    /// (e.g. __ARCH_RV64).
+    pub fn builtin_symbols() -> Vec<(String, i64)> {
+        vec![
+            (
+                "__XLEN".to_string(),
+                (std::mem::size_of::<<A::DataWidth as DataWidth>::U>() * 8) as i64,
+            ),
+            (format!("__ARCH_{}", A::NAME.to_uppercase()), 1),
+        ]
     }
 
     pub fn parse_lex_result(lex_result: LexResult) -> ParseResult<A::Family, A::DataWidth> {
+        Self::new(lex_result).parse()
+    }
+
+    fn new(lex_result: LexResult) -> Self {
         Self {
             file_id: lex_result.file_id,
             lines: lex_result.lines,
@@ -273,57 +426,90 @@ where
                     <A::InstParser as InstParser<A::Family, A::DataWidth>>::reg_expansion_table(),
             },
         }
-        .parse()
     }
 
     fn parse(mut self) -> ParseResult<A::Family, A::DataWidth> {
         let mut insts = Vec::<PartialInst<A::Family, A::DataWidth>>::new();
-        let mut last_label: Option<LabelDef> = None;
+        // Labels that have not yet been attached to an instruction or literal
+        let mut pending_labels: Vec<LabelDef> = Vec::new();
         let mut sections = SectionStore::new();
         let parser_data = &self.parser_data;
         for line in self.lines {
             // line is an iterator over tokens
             // contents is the raw string
-            let (found_label, parse_result) =
-                LineParser::<A>::new(parser_data, line, &last_label, &mut self.state).parse();
+            let (found_labels, parse_result) =
+                LineParser::<A>::new(parser_data, line, pending_labels, &mut self.state).parse();
             match parse_result {
                 Ok(ok_result) => {
                     // each branch should return the label to apply to the next instruction
-                    last_label = match ok_result {
+                    pending_labels = match ok_result {
                         OkParseResult::Insts(mut new_insts) => {
-                            // if insts is not empty, then that means the label gets used
+                            // if insts is not empty, then that means the labels get used
                             if new_insts.is_empty() {
-                                found_label
+                                found_labels
                             } else {
-                                // stick label onto first inst
-                                if let Some(new_label) = found_label {
-                                    let head_inst = new_insts.remove(0).with_label(new_label);
-                                    new_insts.insert(0, head_inst);
-                                }
+                                // stick labels onto first inst
+                                let head_inst = new_insts.remove(0).with_labels(found_labels);
+                                new_insts.insert(0, head_inst);
                                 insts.extend(new_insts);
-                                None
+                                Vec::new()
                             }
                         }
                         OkParseResult::Literals(DirectiveLiterals { section, data }) => {
                             let mut data_iter = data.into_iter();
                             let first: Option<DataEnum> = data_iter.next();
-                            // if literals is not empty, then the label is going to be used
+                            // if literals is not empty, then the labels are going to be used
                             match first {
-                                None => found_label,
+                                None => found_labels,
                                 Some(first_val) => {
-                                    // stick label onto the first literal
-                                    sections.add(section, found_label, first_val);
+                                    // stick labels onto the first literal
+                                    sections.add(section, found_labels, first_val);
                                     for val in data_iter {
-                                        sections.add(section, None, val);
+                                        sections.add(section, Vec::new(), val);
                                     }
-                                    None
+                                    Vec::new()
                                 }
                             }
                         }
-                        OkParseResult::None => found_label,
+                        OkParseResult::Padding(Padding::Align {
+                            section,
+                            align,
+                            fill,
+                            max_skip,
+                        }) => {
+                            if section == ProgramSection::Text {
+                                sections.raise_alignment(section, align);
+                                let pad_len = (align - (insts.len() * 4) % align) % align;
+                                if max_skip.is_none_or(|max_skip| pad_len <= max_skip) {
+                                    insts.extend((0..pad_len / 4).map(|_| Self::nop()));
+                                }
+                            } else {
+                                sections.align(section, align, fill, max_skip);
+                            }
+                            // the labels belong to whatever follows the padding
+                            found_labels
+                        }
+                        OkParseResult::Padding(Padding::Nops(count)) => {
+                            if count == 0 {
+                                found_labels
+                            } else {
+                                insts.push(Self::nop().with_labels(found_labels));
+                                insts.extend((1..count).map(|_| Self::nop()));
+                                Vec::new()
+                            }
+                        }
+                        OkParseResult::None => found_labels,
                     }
                 }
-                Err(new_err) => self.reporter.add_error(new_err),
+                Err(new_err) => {
+                    self.reporter.add_error(new_err);
+                    pending_labels = found_labels;
+                }
+            }
+        }
+        if let Some(pool) = self.state.literal_pool {
+            for (val, label) in pool.entries {
+                sections.add(ProgramSection::Rodata, vec![label], val);
             }
         }
         ParseResult {
@@ -331,6 +517,7 @@ where
             insts,
             sections,
             declared_globals: self.state.declared_globals,
+            declared_weak: self.state.declared_weak,
             reporter: self.reporter,
         }
     }
@@ -400,7 +587,7 @@ pub mod tests {
     use super::*;
 
     /// Lexes a program. Asserts that the lex has no errors.
-    pub fn lex(prog: &str) -> LexResult {
+    pub fn lex(prog: &str) -> LexResult<'_> {
         let result = Lexer::lex_str(0, prog);
         assert_eq!(result.reporter.get_errs(), &[]);
         result
@@ -425,4 +612,82 @@ pub mod tests {
             .map(|inst| inst.try_into_concrete_inst())
             .collect()
     }
+
+    #[test]
+    fn test_text_padding() {
+        use crate::architectures::riscv::Rv32;
+        let insts = parse_and_lex::<Rv32>(
+            "addi a0, a0, 1\n.p2align 4\nl: addi a0, a0, 2\n.space 8\n.balign 4\n",
+        );
+        // 3 nops pad to 16 bytes, then .space adds 2 more
+        assert_eq!(insts.len(), 7);
+        assert_eq!(insts[4].labels[0].name, "l");
+    }
+
+    #[test]
+    fn test_padding_errors() {
+        use crate::architectures::riscv::Rv32;
+        for prog in [
+            ".p2align 17",
+            ".balign 6",
+            ".space 6",
+            ".space 4, 1",
+            ".fill 1",
+            ".data\n.fill 1, 9",
+            ".data\n.space -1",
+            ".data\n.p2align 2, 0, 0, 0",
+        ]
+        .iter()
+        {
+            let ParseResult { reporter, .. } = Parser::<Rv32>::parse_lex_result(lex(prog));
+            assert_eq!(reporter.get_errs().len(), 1, "{}", prog);
+        }
+    }
+
+    #[test]
+    fn test_bss() {
+        use crate::architectures::riscv::Rv32;
+        let ParseResult {
+            sections, reporter, ..
+        } = Parser::<Rv32>::parse_lex_result(lex(
+            ".bss\n.zero 4\n.section .bss.x\n.word 0\n.section x,\"aw\",@nobits\n.space 3",
+        ));
+        assert!(reporter.is_empty());
+        assert_eq!(sections.bss_len(), 11);
+        assert!(sections.data().is_empty());
+        for prog in [".bss\n.byte 1", ".bss\n.space 2, 1", ".bss\n.p2align 2, 1"].iter() {
+            let ParseResult { reporter, .. } = Parser::<Rv32>::parse_lex_result(lex(prog));
+            assert_eq!(reporter.get_errs().len(), 1, "{}", prog);
+        }
+    }
+
+    #[test]
+    fn test_named_sections() {
+        use crate::architectures::riscv::Rv32;
+        let ParseResult {
+            sections, reporter, ..
+        } = Parser::<Rv32>::parse_lex_result(lex(concat!(
+            ".section .rodata.str1.1,\"aMS\",@progbits,1\n.byte 1\n",
+            ".section .sdata\n.byte 2\n",
+            ".section \".consts\", \"a\"\n.byte 3\n",
+            ".section custom\n.byte 4\n",
+            ".section .text.startup,\"ax\"\nnop\n",
+        )));
+        assert!(reporter.is_empty(), "{:?}", reporter);
+        assert_eq!(sections.rodata(), &[1, 3]);
+        assert_eq!(sections.data(), &[2, 4]);
+    }
+
+    #[test]
+    fn test_numeric_labels() {
+        use crate::architectures::riscv::Rv32;
+        let insts = parse_and_lex::<Rv32>("1: 2:\nnop\n1: j 1b\nj 1f\n1: nop");
+        let names =
+            |i: usize| -> Vec<String> { insts[i].labels.iter().map(|l| l.name.clone()).collect() };
+        assert_eq!(names(0), vec!["1^1", "2^1"]);
+        assert_eq!(names(1), vec!["1^2"]);
+        assert_eq!(names(3), vec!["1^3"]);
+        assert_eq!(insts[1].get_needed_label().unwrap().target, "1^2");
+        assert_eq!(insts[2].get_needed_label().unwrap().target, "1^3");
+    }
 }
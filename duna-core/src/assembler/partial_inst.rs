@@ -19,12 +19,20 @@ pub(crate) enum NeededRegs<F: ArchFamily<S>, S: DataWidth> {
 pub(crate) struct NeedsLabel<F: ArchFamily<S>, S: DataWidth> {
     tpe: NeededRegs<F, S>,
     needed_label: LabelRef,
+    /// Takes the part of the absolute address of the label that the instruction needs, as in
+    /// "%hi(label)". The label is otherwise given as an offset from the instruction.
+    addr_part: Option<fn(RegValue<S>) -> RegValue<S>>,
 }
 
 impl<F: ArchFamily<S>, S: DataWidth> NeedsLabel<F, S> {
-    /// Attempts to replace the needed label with the provided immediate
+    /// Attempts to replace the needed label with the provided immediate, which is the address of
+    /// the label if the instruction needs part of it, or else the offset to it.
     pub fn fulfill_label(&self, imm: RegValue<S>) -> F::Instruction {
         use NeededRegs::*;
+        let imm = match self.addr_part {
+            Some(part) => part(imm),
+            None => imm,
+        };
         match self.tpe {
             Two {
                 assemble,
@@ -44,22 +52,22 @@ pub(crate) enum PartialInstType<F: ArchFamily<S>, S: DataWidth> {
 
 pub struct PartialInst<F: ArchFamily<S>, S: DataWidth> {
     pub(crate) tpe: PartialInstType<F, S>,
-    /// A label pointing to this instructions.
-    pub label: Option<LabelDef>,
+    /// Labels pointing to this instruction.
+    pub labels: Vec<LabelDef>,
 }
 
 impl<F: ArchFamily<S>, S: DataWidth> PartialInst<F, S> {
     pub fn new_complete(inst: F::Instruction) -> PartialInst<F, S> {
         PartialInst {
             tpe: PartialInstType::Complete(inst),
-            label: None,
+            labels: Vec::new(),
         }
     }
 
     fn new_needs_label(data: NeedsLabel<F, S>) -> PartialInst<F, S> {
         PartialInst {
             tpe: PartialInstType::NeedsLabelRef(data),
-            label: None,
+            labels: Vec::new(),
         }
     }
 
@@ -76,6 +84,7 @@ impl<F: ArchFamily<S>, S: DataWidth> PartialInst<F, S> {
                 reg2,
             },
             needed_label: needed,
+            addr_part: None,
         })
     }
 
@@ -87,6 +96,7 @@ impl<F: ArchFamily<S>, S: DataWidth> PartialInst<F, S> {
         PartialInst::new_needs_label(NeedsLabel {
             tpe: NeededRegs::One { assemble, reg },
             needed_label: needed,
+            addr_part: None,
         })
     }
 
@@ -97,18 +107,35 @@ impl<F: ArchFamily<S>, S: DataWidth> PartialInst<F, S> {
         PartialInst::new_needs_label(NeedsLabel {
             tpe: NeededRegs::Zero { assemble },
             needed_label: needed,
+            addr_part: None,
         })
     }
 
-    /// Attaches a label to this instruction. Panics if there's already a label.
-    pub fn with_label(self, label: LabelDef) -> PartialInst<F, S> {
-        match self.label {
-            None => PartialInst {
-                tpe: self.tpe,
-                label: Some(label),
-            },
-            Some(_) => panic!("instruction already had label"),
+    /// Attaches labels to this instruction.
+    pub fn with_labels(mut self, labels: Vec<LabelDef>) -> PartialInst<F, S> {
+        self.labels.extend(labels);
+        self
+    }
+
+    /// Makes this instruction take part of the absolute address of its needed label, which PART
+    /// computes from the address, rather than the offset to it.
+    pub fn with_addr_part(mut self, part: fn(RegValue<S>) -> RegValue<S>) -> PartialInst<F, S> {
+        if let PartialInstType::NeedsLabelRef(needs) = &mut self.tpe {
+            needs.addr_part = Some(part);
         }
+        self
+    }
+
+    /// Returns whether this instruction needs the absolute address of a label rather than the
+    /// offset to it.
+    pub fn needs_address(&self) -> bool {
+        matches!(
+            &self.tpe,
+            PartialInstType::NeedsLabelRef(NeedsLabel {
+                addr_part: Some(..),
+                ..
+            })
+        )
     }
 
     pub fn get_needed_label(&self) -> Option<&LabelRef> {
@@ -0,0 +1,1206 @@
+//! Expands macros, repetition blocks (.macro, .rept, .irp, .irpc), file inclusions
+//! (.include, .incbin), and conditional blocks (.if, .ifdef, .else, .endif) before a file is
+//! lexed. Constants defined by .equ, .set, and .equiv are also substituted here.
+//!
+//! As in GNU as, expansion is textual: the body of a block is copied with its parameters
+//! substituted, and the resulting lines are lexed as though they had been written in place of
+//! the invocation.
+//!
+//! See https://sourceware.org/binutils/docs/as/Macro.html
+use super::{
+    datatypes::*,
+    error::{ErrMetadata, ErrorReporter, ParseError},
+    expr,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The maximum depth of nested expansions, used to catch runaway recursive macros.
+const MAX_EXPANSION_DEPTH: usize = 100;
+
+/// A line of source code after expansion.
+pub struct SourceLine {
+    /// The file from which this line came, which differs from the file being lexed if the line
+    /// was included.
+    pub file_id: FileId,
+    pub content: String,
+    /// The line of the file from which this line came. For lines produced by a macro, this is
+    /// the line in the body of the macro definition.
+    pub lineno: LineNo,
+    /// The outermost macro invocation that produced this line, if any.
+    pub call_site: Option<CallSite>,
+}
+
+struct MacroParam {
+    name: String,
+    default: Option<String>,
+    /// Set by the :req qualifier.
+    required: bool,
+    /// Set by the :vararg qualifier; the parameter absorbs all remaining arguments.
+    vararg: bool,
+}
+
+struct MacroDef {
+    params: Vec<MacroParam>,
+    body: Vec<SourceLine>,
+}
+
+/// Directives whose arguments name symbols, and therefore should not have constants substituted.
+const SYMBOL_DIRECTIVES: [&str; 8] = [
+    ".globl", ".global", ".local", ".weak", ".extern", ".type", ".size", ".section",
+];
+
+/// The state of an open conditional block.
+struct CondFrame {
+    /// The directive that opened the block, without the leading period.
+    directive: String,
+    location: Location,
+    /// Whether the lines surrounding this block are being emitted.
+    parent_active: bool,
+    /// Whether lines in the current branch of this block are being emitted.
+    active: bool,
+    /// Whether any branch of this block has been taken yet.
+    taken: bool,
+    seen_else: bool,
+}
+
+/// A line split into its leading label definitions, its first word, and the remainder.
+struct LineParts<'a> {
+    /// Any labels defined before the head, including their colons.
+    labels: &'a str,
+    head: &'a str,
+    head_offs: LineOffs,
+    /// Everything after the head, with comments removed.
+    args: &'a str,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Removes a trailing comment from a line, ignoring any # within string literals.
+fn strip_comment(line: &str) -> &str {
+    let mut in_quote = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if in_quote => {
+                escaped = !escaped;
+                continue;
+            }
+            '\"' if !escaped => in_quote = !in_quote,
+            '#' if !in_quote => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn split_line(line: &str) -> LineParts<'_> {
+    let code = strip_comment(line);
+    let mut labels_end = 0;
+    loop {
+        let rest = &code[labels_end..];
+        let trimmed = rest.trim_start();
+        let head_offs = labels_end + (rest.len() - trimmed.len());
+        let head_len = trimmed
+            .find(|c: char| c.is_whitespace() || c == ',' || c == ':')
+            .unwrap_or(trimmed.len());
+        if head_len > 0 && trimmed[head_len..].starts_with(':') {
+            labels_end = head_offs + head_len + 1;
+            continue;
+        }
+        return LineParts {
+            labels: &code[..labels_end],
+            head: &trimmed[..head_len],
+            head_offs,
+            args: &trimmed[head_len..],
+        };
+    }
+}
+
+/// Splits the arguments to a macro or directive. Arguments may be separated by commas or
+/// whitespace, and whitespace around an = is ignored so that keyword arguments stay together.
+/// Commas and whitespace within string literals or parentheses do not separate arguments.
+fn split_args(args: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut curr = String::new();
+    let mut in_quote = false;
+    let mut escaped = false;
+    let mut paren_depth = 0;
+    // a comma was seen, so the next argument exists even if it's empty
+    let mut saw_comma = false;
+    let mut pending_space = false;
+    for c in args.chars() {
+        if in_quote {
+            curr.push(c);
+            match c {
+                '\\' => escaped = !escaped,
+                '\"' if !escaped => in_quote = false,
+                _ => escaped = false,
+            }
+            continue;
+        }
+        match c {
+            ',' if paren_depth == 0 => {
+                result.push(std::mem::take(&mut curr));
+                saw_comma = true;
+                pending_space = false;
+            }
+            c if c.is_whitespace() && paren_depth == 0 => pending_space = !curr.is_empty(),
+            _ => {
+                if pending_space && c != '=' && !curr.ends_with('=') {
+                    result.push(std::mem::take(&mut curr));
+                }
+                pending_space = false;
+                match c {
+                    '\"' => in_quote = true,
+                    '(' => paren_depth += 1,
+                    ')' => paren_depth -= 1,
+                    _ => {}
+                }
+                curr.push(c);
+            }
+        }
+    }
+    if !curr.is_empty() || saw_comma {
+        result.push(curr);
+    }
+    result
+}
+
+/// Replaces each \name in the line with the value bound to name. If counter is provided, \@ is
+/// replaced by its value. \() is removed, which allows a parameter to be followed directly by
+/// identifier characters.
+fn substitute(line: &str, bindings: &[(String, String)], counter: Option<usize>) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut iter = line.char_indices().peekable();
+    while let Some((i, c)) = iter.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match iter.peek() {
+            Some((_, '@')) if counter.is_some() => {
+                iter.next();
+                result.push_str(&counter.unwrap().to_string());
+            }
+            Some((_, '(')) if line[i + 1..].starts_with("()") => {
+                iter.next();
+                iter.next();
+            }
+            Some(&(start, c2)) if is_ident_char(c2) => {
+                let end = line[start..]
+                    .find(|c| !is_ident_char(c))
+                    .map_or(line.len(), |len| start + len);
+                match bindings.iter().find(|(name, _)| name == &line[start..end]) {
+                    Some((_, value)) => {
+                        result.push_str(value);
+                        while iter.peek().is_some_and(|&(j, _)| j < end) {
+                            iter.next();
+                        }
+                    }
+                    None => result.push('\\'),
+                }
+            }
+            _ => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Replaces each symbol in text that names a constant with the value of that constant.
+/// String literals and comments are left untouched.
+fn replace_constants(text: &str, constants: &HashMap<String, i64>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '#' => rest.len(),
+            '\"' => {
+                let mut escaped = false;
+                rest.char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| {
+                        let end = c == '\"' && !escaped;
+                        escaped = c == '\\' && !escaped;
+                        end
+                    })
+                    .map_or(rest.len(), |(i, _)| i + 1)
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '$' => {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.' || c == '$'))
+                    .unwrap_or(rest.len());
+                if let Some(n) = constants.get(&rest[..len]) {
+                    result.push_str(&n.to_string());
+                    rest = &rest[len..];
+                    continue;
+                }
+                len
+            }
+            c => c.len_utf8(),
+        };
+        result.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    result
+}
+
+/// Removes the quotes surrounding a file name.
+fn unquote(arg: &str) -> Option<&str> {
+    arg.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Parses a repetition count for .rept.
+fn parse_count(arg: &str) -> Option<usize> {
+    let arg = arg.trim().replace('_', "");
+    if let Some(hex) = arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")) {
+        usize::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = arg.strip_prefix("0b").or_else(|| arg.strip_prefix("0B")) {
+        usize::from_str_radix(bin, 2).ok()
+    } else {
+        arg.parse().ok()
+    }
+}
+
+/// Parses a parameter declaration of the form name[:req|:vararg][=default].
+fn parse_param(spec: &str) -> Option<MacroParam> {
+    let (decl, default) = match spec.split_once('=') {
+        Some((decl, default)) => (decl, Some(default.to_string())),
+        None => (spec, None),
+    };
+    let (name, required, vararg) = match decl.split_once(':') {
+        Some((name, "req")) => (name, true, false),
+        Some((name, "vararg")) => (name, false, true),
+        Some(_) => return None,
+        None => (decl, false, false),
+    };
+    if name.is_empty() || !name.chars().all(is_ident_char) {
+        return None;
+    }
+    Some(MacroParam {
+        name: name.to_string(),
+        default,
+        required,
+        vararg,
+    })
+}
+
+/// Expands the macros and repetition blocks of a file.
+pub struct Preprocessor<'a> {
+    file_id: FileId,
+    contents: &'a str,
+    reporter: &'a mut ErrorReporter,
+    /// Files added by .include are registered here. Without a file map, .include is an error.
+    file_map: Option<&'a mut FileMap>,
+    /// The files currently being included, used to detect cycles.
+    include_stack: Vec<PathBuf>,
+    macros: HashMap<String, MacroDef>,
+    /// The number of macros expanded so far, which is substituted for \@.
+    expansion_count: usize,
+    /// Constants defined by .equ and friends, as well as any predefined symbols.
+    constants: HashMap<String, i64>,
+    /// Labels defined so far, which are considered defined by .ifdef.
+    labels: HashSet<String>,
+}
+
+impl<'a> Preprocessor<'a> {
+    pub fn new(
+        file_id: FileId,
+        contents: &'a str,
+        reporter: &'a mut ErrorReporter,
+    ) -> Preprocessor<'a> {
+        Preprocessor {
+            file_id,
+            contents,
+            reporter,
+            file_map: None,
+            include_stack: Vec::new(),
+            macros: HashMap::new(),
+            expansion_count: 0,
+            constants: HashMap::new(),
+            labels: HashSet::new(),
+        }
+    }
+
+    /// Predefines constants that may be tested by conditional directives or used as
+    /// immediates, e.g. the register width of the target architecture.
+    pub fn with_symbols(mut self, symbols: Vec<(String, i64)>) -> Preprocessor<'a> {
+        self.constants.extend(symbols);
+        self
+    }
+
+    /// Allows .include to register the files it reads in the provided map. Paths given to
+    /// .include and .incbin are then resolved relative to the directory of the including file.
+    pub fn with_file_map(mut self, file_map: &'a mut FileMap) -> Preprocessor<'a> {
+        self.file_map = Some(file_map);
+        self
+    }
+
+    /// Produces the lines of the file with all macros and repetitions expanded. Errors are
+    /// reported to the reporter, and the offending lines are dropped.
+    pub fn expand(mut self) -> Vec<SourceLine> {
+        let lines = self
+            .contents
+            .lines()
+            .enumerate()
+            .map(|(lineno, content)| SourceLine {
+                file_id: self.file_id,
+                content: content.to_string(),
+                lineno,
+                call_site: None,
+            })
+            .collect();
+        if let Some(file_map) = &self.file_map {
+            if let Ok(path) = fs::canonicalize(&file_map[self.file_id].file_name) {
+                self.include_stack.push(path);
+            }
+        }
+        let mut expanded = Vec::new();
+        self.expand_lines(lines, 0, false, &mut expanded);
+        expanded
+    }
+
+    fn location(&self, line: &SourceLine, offs: LineOffs) -> Location {
+        Location {
+            file_id: line.file_id,
+            lineno: line.lineno,
+            offs,
+            call_site: line.call_site,
+        }
+    }
+
+    /// Expands lines into out. Returns true if a .exitm was encountered, in which case the
+    /// rest of the enclosing macro should be skipped.
+    fn expand_lines(
+        &mut self,
+        lines: Vec<SourceLine>,
+        depth: usize,
+        in_macro: bool,
+        out: &mut Vec<SourceLine>,
+    ) -> bool {
+        let mut iter = lines.into_iter();
+        let mut conds = Vec::new();
+        while let Some(line) = iter.next() {
+            let LineParts {
+                labels,
+                head,
+                head_offs,
+                args,
+            } = split_line(&line.content);
+            let location = self.location(&line, head_offs);
+            if self.handle_conditional(&mut conds, head, args, &location) {
+                continue;
+            }
+            if !conds.iter().all(|frame: &CondFrame| frame.active) {
+                continue;
+            }
+            self.labels.extend(
+                labels
+                    .split(':')
+                    .map(str::trim)
+                    .filter(|label| !label.is_empty())
+                    .map(str::to_string),
+            );
+            let is_expanded = matches!(
+                head,
+                ".macro" | ".rept" | ".irp" | ".irpc" | ".include" | ".incbin"
+            ) || self.macros.contains_key(head);
+            if !labels.is_empty() && is_expanded {
+                out.push(SourceLine {
+                    file_id: line.file_id,
+                    content: labels.to_string(),
+                    lineno: line.lineno,
+                    call_site: line.call_site,
+                });
+            }
+            match head {
+                ".macro" => {
+                    let args = args.to_string();
+                    if let Some(body) = self.collect_block(&mut iter, &location, "macro", "endm") {
+                        self.define_macro(&args, body, &location);
+                    }
+                }
+                ".rept" | ".irp" | ".irpc" => {
+                    let directive = head[1..].to_string();
+                    let args = args.to_string();
+                    if let Some(body) = self.collect_block(&mut iter, &location, &directive, "endr")
+                    {
+                        if let Some(expanded) = self.repeat(&directive, &args, body, &location) {
+                            if self.expand_lines(expanded, depth + 1, in_macro, out) {
+                                return true;
+                            }
+                        }
+                    }
+                }
+                ".include" => {
+                    let args = args.to_string();
+                    if self.include(&line, &args, &location, depth, in_macro, out) {
+                        return true;
+                    }
+                }
+                ".incbin" => {
+                    let args = args.to_string();
+                    self.incbin(&line, &args, &location, out);
+                }
+                ".endm" | ".endr" => self.reporter.add_error(ParseError::unmatched_block_end(
+                    ErrMetadata::new(&location),
+                    &head[1..],
+                )),
+                ".exitm" => {
+                    if in_macro {
+                        return true;
+                    }
+                    self.reporter.add_error(ParseError::generic(
+                        ErrMetadata::new(&location),
+                        "found .exitm outside of a macro",
+                    ));
+                }
+                ".equ" | ".set" | ".equiv" => {
+                    let args = args.to_string();
+                    self.define_constant(head, &args, &location);
+                }
+                ".purgem" => {
+                    let name = args.trim();
+                    if self.macros.remove(name).is_none() {
+                        self.reporter.add_error(ParseError::generic(
+                            ErrMetadata::new(&location),
+                            &format!("cannot purge undefined macro '{}'", name),
+                        ));
+                    }
+                }
+                _ if self.macros.contains_key(head) => {
+                    if depth >= MAX_EXPANSION_DEPTH {
+                        self.reporter.add_error(ParseError::generic(
+                            ErrMetadata::new(&location),
+                            &format!(
+                                "macro expansion exceeded maximum depth of {} (is '{}' recursive?)",
+                                MAX_EXPANSION_DEPTH, head
+                            ),
+                        ));
+                        continue;
+                    }
+                    match self.invoke_macro(head, args, &location) {
+                        Ok(expanded) => {
+                            self.expand_lines(expanded, depth + 1, true, out);
+                        }
+                        Err(err) => self.reporter.add_error(err),
+                    }
+                }
+                _ if SYMBOL_DIRECTIVES.contains(&head) => out.push(line),
+                _ => {
+                    let args_offs = head_offs + head.len();
+                    out.push(self.substitute_constants(line, args_offs));
+                }
+            }
+        }
+        for frame in conds {
+            self.reporter.add_error(ParseError::unterminated_block(
+                ErrMetadata::new(&frame.location),
+                &frame.directive,
+                "endif",
+            ));
+        }
+        false
+    }
+
+    /// Updates the stack of conditional blocks if head is a conditional directive, returning
+    /// true if it was one.
+    fn handle_conditional(
+        &mut self,
+        conds: &mut Vec<CondFrame>,
+        head: &str,
+        args: &str,
+        location: &Location,
+    ) -> bool {
+        let parent_active = conds.iter().all(|frame| frame.active);
+        match head {
+            ".if" | ".ifne" | ".ifeq" | ".ifgt" | ".ifge" | ".iflt" | ".ifle" | ".ifdef"
+            | ".ifndef" | ".ifnotdef" | ".ifc" | ".ifnc" | ".ifb" | ".ifnb" => {
+                // conditions in skipped blocks are not evaluated, so they can't produce errors
+                let cond = parent_active && self.eval_condition(head, args, location);
+                conds.push(CondFrame {
+                    directive: head[1..].to_string(),
+                    location: *location,
+                    parent_active,
+                    active: cond,
+                    taken: cond,
+                    seen_else: false,
+                });
+            }
+            ".elseif" | ".else" => match conds.last_mut() {
+                Some(frame) => {
+                    if frame.seen_else {
+                        self.report(location, &format!("found {} after .else", head));
+                    }
+                    let cond = frame.parent_active
+                        && !frame.taken
+                        && (head == ".else" || self.eval_condition(".if", args, location));
+                    frame.active = cond;
+                    frame.taken |= cond;
+                    frame.seen_else |= head == ".else";
+                }
+                None => self.reporter.add_error(ParseError::unmatched_block_end(
+                    ErrMetadata::new(location),
+                    &head[1..],
+                )),
+            },
+            ".endif" => {
+                if conds.pop().is_none() {
+                    self.reporter.add_error(ParseError::unmatched_block_end(
+                        ErrMetadata::new(location),
+                        "endif",
+                    ));
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Evaluates the condition of a conditional directive. Malformed conditions are reported
+    /// and treated as false.
+    fn eval_condition(&mut self, directive: &str, args: &str, location: &Location) -> bool {
+        let result = match directive {
+            ".ifdef" | ".ifndef" | ".ifnotdef" => {
+                let symbol = args.trim();
+                if symbol.is_empty() {
+                    Err(format!("{} expected a symbol name", directive))
+                } else {
+                    let defined =
+                        self.constants.contains_key(symbol) || self.labels.contains(symbol);
+                    Ok(defined == (directive == ".ifdef"))
+                }
+            }
+            ".ifc" | ".ifnc" => match split_args(args).as_slice() {
+                [lhs, rhs] => {
+                    let lhs = unquote(lhs).unwrap_or(lhs);
+                    let rhs = unquote(rhs).unwrap_or(rhs);
+                    Ok((lhs == rhs) == (directive == ".ifc"))
+                }
+                _ => Err(format!("{} expected two strings to compare", directive)),
+            },
+            ".ifb" => Ok(args.trim().is_empty()),
+            ".ifnb" => Ok(!args.trim().is_empty()),
+            _ => expr::eval(args, &self.constants).map(|n| match directive {
+                ".ifeq" => n == 0,
+                ".ifgt" => n > 0,
+                ".ifge" => n >= 0,
+                ".iflt" => n < 0,
+                ".ifle" => n <= 0,
+                _ => n != 0,
+            }),
+        };
+        result.unwrap_or_else(|msg| {
+            self.report(location, &msg);
+            false
+        })
+    }
+
+    /// Defines a constant through .equ symbol, expression (or .set and .equiv, which have the
+    /// same form). Unlike the others, .equiv does not allow the symbol to be redefined.
+    fn define_constant(&mut self, directive: &str, args: &str, location: &Location) {
+        let (name, value) = match args.split_once(',') {
+            Some((name, value)) => (name.trim(), value),
+            None => {
+                self.report(
+                    location,
+                    &format!("{} expected a symbol name and a value", directive),
+                );
+                return;
+            }
+        };
+        if name.is_empty() || !name.chars().all(|c| is_ident_char(c) || c == '.') {
+            self.report(location, &format!("bad symbol name '{}'", name));
+            return;
+        }
+        if directive == ".equiv"
+            && (self.constants.contains_key(name) || self.labels.contains(name))
+        {
+            self.report(location, &format!("symbol '{}' is already defined", name));
+            return;
+        }
+        match expr::eval(value, &self.constants) {
+            Ok(n) => {
+                self.constants.insert(name.to_string(), n);
+            }
+            Err(msg) => self.report(location, &msg),
+        }
+    }
+
+    /// Substitutes the values of constants into the arguments of a line, which begin at
+    /// args_offs.
+    fn substitute_constants(&self, line: SourceLine, args_offs: usize) -> SourceLine {
+        if self.constants.is_empty() {
+            return line;
+        }
+        let (before, args) = line.content.split_at(args_offs);
+        SourceLine {
+            content: format!("{}{}", before, replace_constants(args, &self.constants)),
+            ..line
+        }
+    }
+
+    fn report(&mut self, location: &Location, msg: &str) {
+        self.reporter
+            .add_error(ParseError::generic(ErrMetadata::new(location), msg));
+    }
+
+    /// Resolves a path given to .include or .incbin relative to the file containing the
+    /// directive. If there is no file map, the path is left relative to the working directory.
+    fn resolve_path(&self, file_id: FileId, path: &str) -> PathBuf {
+        match &self.file_map {
+            Some(file_map) => match Path::new(&file_map[file_id].file_name).parent() {
+                Some(dir) => dir.join(path),
+                None => PathBuf::from(path),
+            },
+            None => PathBuf::from(path),
+        }
+    }
+
+    /// Textually includes another file, registering it in the file map so that errors within
+    /// it are attributed to the correct file. Returns true if the included file hit a .exitm.
+    fn include(
+        &mut self,
+        line: &SourceLine,
+        args: &str,
+        location: &Location,
+        depth: usize,
+        in_macro: bool,
+        out: &mut Vec<SourceLine>,
+    ) -> bool {
+        let args = split_args(args);
+        let path = match args.as_slice() {
+            [path] => match unquote(path) {
+                Some(path) => path,
+                None => {
+                    self.report(location, ".include expected a quoted file name");
+                    return false;
+                }
+            },
+            _ => {
+                self.report(location, ".include expected exactly one file name");
+                return false;
+            }
+        };
+        if self.file_map.is_none() {
+            self.report(
+                location,
+                ".include can only be used when assembling files through the linker",
+            );
+            return false;
+        }
+        let path = self.resolve_path(line.file_id, path);
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if self.include_stack.contains(&canonical) {
+            self.report(
+                location,
+                &format!("circular .include of file {}", path.display()),
+            );
+            return false;
+        }
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) => {
+                self.report(
+                    location,
+                    &format!("could not read included file {}: {}", path.display(), err),
+                );
+                return false;
+            }
+        };
+        let file_name = path.to_string_lossy().into_owned();
+        let file_map = self.file_map.as_mut().unwrap();
+        let file_id = match file_map.iter().position(|data| data.file_name == file_name) {
+            Some(file_id) => file_id,
+            None => {
+                file_map.push(FileData {
+                    file_name,
+                    content: content.clone(),
+                });
+                file_map.len() - 1
+            }
+        };
+        let lines = content
+            .lines()
+            .enumerate()
+            .map(|(lineno, content)| SourceLine {
+                file_id,
+                content: content.to_string(),
+                lineno,
+                call_site: line.call_site,
+            })
+            .collect();
+        self.include_stack.push(canonical);
+        let exited = self.expand_lines(lines, depth + 1, in_macro, out);
+        self.include_stack.pop();
+        exited
+    }
+
+    /// Inserts the raw bytes of a file as though they had been declared with .byte.
+    /// Takes the form .incbin "file"[, skip[, count]].
+    fn incbin(
+        &mut self,
+        line: &SourceLine,
+        args: &str,
+        location: &Location,
+        out: &mut Vec<SourceLine>,
+    ) {
+        let args = split_args(args);
+        let path = match args.first().and_then(|path| unquote(path)) {
+            Some(path) if args.len() <= 3 => path,
+            _ => {
+                self.report(
+                    location,
+                    ".incbin expected a quoted file name, followed by an optional skip and count",
+                );
+                return;
+            }
+        };
+        let mut bounds = Vec::new();
+        for arg in &args[1..] {
+            match parse_count(arg) {
+                Some(n) => bounds.push(n),
+                None => {
+                    self.report(
+                        location,
+                        &format!(".incbin expected non-negative integer, got '{}'", arg),
+                    );
+                    return;
+                }
+            }
+        }
+        let path = self.resolve_path(line.file_id, path);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                self.report(
+                    location,
+                    &format!("could not read file {}: {}", path.display(), err),
+                );
+                return;
+            }
+        };
+        let skip = bounds.first().copied().unwrap_or(0).min(bytes.len());
+        let end = match bounds.get(1) {
+            Some(count) => (skip + count).min(bytes.len()),
+            None => bytes.len(),
+        };
+        for chunk in bytes[skip..end].chunks(16) {
+            let literals: Vec<String> = chunk.iter().map(|b| format!("{:#04X}", b)).collect();
+            out.push(SourceLine {
+                file_id: line.file_id,
+                content: format!(".byte {}", literals.join(", ")),
+                lineno: line.lineno,
+                call_site: line.call_site,
+            });
+        }
+    }
+
+    /// Consumes lines up to and including the directive closing a block, returning the lines
+    /// in between. Nested blocks of the same kind are included in the body.
+    fn collect_block(
+        &mut self,
+        iter: &mut impl Iterator<Item = SourceLine>,
+        start_location: &Location,
+        start: &str,
+        end: &str,
+    ) -> Option<Vec<SourceLine>> {
+        let openers: &[&str] = if end == "endm" {
+            &[".macro"]
+        } else {
+            &[".rept", ".irp", ".irpc"]
+        };
+        let mut nesting = 1;
+        let mut body = Vec::new();
+        for line in iter {
+            let head = split_line(&line.content).head;
+            if openers.contains(&head) {
+                nesting += 1;
+            } else if head.strip_prefix('.') == Some(end) {
+                nesting -= 1;
+                if nesting == 0 {
+                    return Some(body);
+                }
+            }
+            body.push(line);
+        }
+        self.reporter.add_error(ParseError::unterminated_block(
+            ErrMetadata::new(start_location),
+            start,
+            end,
+        ));
+        None
+    }
+
+    fn define_macro(&mut self, args: &str, body: Vec<SourceLine>, location: &Location) {
+        let mut specs = split_args(args).into_iter();
+        let name = match specs.next() {
+            Some(name) if !name.is_empty() => name,
+            _ => {
+                self.reporter.add_error(ParseError::generic(
+                    ErrMetadata::new(location),
+                    "expected macro name",
+                ));
+                return;
+            }
+        };
+        let mut params = Vec::new();
+        for spec in specs {
+            match parse_param(&spec) {
+                Some(param) => params.push(param),
+                None => {
+                    self.reporter.add_error(ParseError::generic(
+                        ErrMetadata::new(location),
+                        &format!("bad parameter '{}' for macro '{}'", spec, name),
+                    ));
+                    return;
+                }
+            }
+        }
+        if self.macros.contains_key(&name) {
+            self.reporter.add_error(ParseError::generic(
+                ErrMetadata::new(location),
+                &format!("macro '{}' was already defined", name),
+            ));
+            return;
+        }
+        self.macros.insert(name, MacroDef { params, body });
+    }
+
+    /// Produces the body of a macro with its arguments substituted.
+    fn invoke_macro(
+        &mut self,
+        name: &str,
+        args: &str,
+        location: &Location,
+    ) -> Result<Vec<SourceLine>, ParseError> {
+        let def = &self.macros[name];
+        let mut values: Vec<Option<String>> = vec![None; def.params.len()];
+        let mut next_positional = 0;
+        for arg in split_args(args) {
+            if let Some((key, value)) = arg.split_once('=') {
+                if let Some(idx) = def.params.iter().position(|param| param.name == key) {
+                    values[idx] = Some(value.to_string());
+                    continue;
+                }
+            }
+            match def.params.get(next_positional) {
+                Some(param) if param.vararg => {
+                    let value = &mut values[next_positional];
+                    *value = Some(match value.take() {
+                        Some(prev) => format!("{}, {}", prev, arg),
+                        None => arg,
+                    });
+                }
+                Some(_) => {
+                    values[next_positional] = Some(arg);
+                    next_positional += 1;
+                }
+                None => {
+                    return Err(ParseError::generic(
+                        ErrMetadata::new(location),
+                        &format!(
+                            "macro '{}' takes at most {} arguments",
+                            name,
+                            def.params.len()
+                        ),
+                    ))
+                }
+            }
+        }
+        let mut bindings = Vec::new();
+        for (param, value) in def.params.iter().zip(values) {
+            let value = match value.filter(|value| !value.is_empty()) {
+                Some(value) => value,
+                None => match &param.default {
+                    Some(default) => default.clone(),
+                    None if param.required => {
+                        return Err(ParseError::generic(
+                            ErrMetadata::new(location),
+                            &format!(
+                                "missing value for required parameter '{}' of macro '{}'",
+                                param.name, name
+                            ),
+                        ))
+                    }
+                    None => String::new(),
+                },
+            };
+            bindings.push((param.name.clone(), value));
+        }
+        let counter = self.expansion_count;
+        let call_site = location.call_site.or_else(|| Some((*location).into()));
+        let expanded = def
+            .body
+            .iter()
+            .map(|line| SourceLine {
+                file_id: line.file_id,
+                content: substitute(&line.content, &bindings, Some(counter)),
+                lineno: line.lineno,
+                call_site,
+            })
+            .collect();
+        self.expansion_count += 1;
+        Ok(expanded)
+    }
+
+    /// Produces the lines of a .rept, .irp, or .irpc block.
+    fn repeat(
+        &mut self,
+        directive: &str,
+        args: &str,
+        body: Vec<SourceLine>,
+        location: &Location,
+    ) -> Option<Vec<SourceLine>> {
+        let copy_with = |bindings: &[(String, String)]| -> Vec<SourceLine> {
+            body.iter()
+                .map(|line| SourceLine {
+                    file_id: line.file_id,
+                    content: substitute(&line.content, bindings, None),
+                    lineno: line.lineno,
+                    call_site: line.call_site,
+                })
+                .collect()
+        };
+        if directive == "rept" {
+            return match parse_count(args) {
+                Some(count) => Some((0..count).flat_map(|_| copy_with(&[])).collect()),
+                None => {
+                    self.reporter.add_error(ParseError::generic(
+                        ErrMetadata::new(location),
+                        &format!(
+                            "expected non-negative repetition count, got '{}'",
+                            args.trim()
+                        ),
+                    ));
+                    None
+                }
+            };
+        }
+        let mut args = split_args(args).into_iter();
+        let symbol = match args.next() {
+            Some(symbol) if !symbol.is_empty() && symbol.chars().all(is_ident_char) => symbol,
+            _ => {
+                self.reporter.add_error(ParseError::generic(
+                    ErrMetadata::new(location),
+                    &format!(".{} expected a symbol name", directive),
+                ));
+                return None;
+            }
+        };
+        let mut values: Vec<String> = if directive == "irpc" {
+            args.next()
+                .map(|chars| chars.chars().map(String::from).collect())
+                .unwrap_or_default()
+        } else {
+            args.collect()
+        };
+        // with no values, the body is emitted once with the symbol empty
+        if values.is_empty() {
+            values.push(String::new());
+        }
+        Some(
+            values
+                .into_iter()
+                .flat_map(|value| copy_with(&[(symbol.clone(), value)]))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expands a program, asserting that there are no errors.
+    fn expand(prog: &str) -> Vec<String> {
+        let mut reporter = ErrorReporter::new();
+        let lines = Preprocessor::new(0, prog, &mut reporter).expand();
+        assert!(reporter.is_empty(), "{:?}", reporter);
+        lines
+            .into_iter()
+            .map(|line| line.content.trim().to_string())
+            .collect()
+    }
+
+    fn expand_err_count(prog: &str) -> usize {
+        let mut reporter = ErrorReporter::new();
+        Preprocessor::new(0, prog, &mut reporter).expand();
+        reporter.errs.len()
+    }
+
+    #[test]
+    fn test_macro_params() {
+        let prog = "
+            .macro push reg, size=4
+            addi sp, sp, -\\size
+            sw \\reg, 0(sp) # comment stays
+            .endm
+            push ra
+            push reg=s0, size=8
+            ";
+        assert_eq!(
+            expand(prog),
+            vec![
+                "",
+                "addi sp, sp, -4",
+                "sw ra, 0(sp) # comment stays",
+                "addi sp, sp, -8",
+                "sw s0, 0(sp) # comment stays",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_macro_counter_and_concat() {
+        let prog = "
+            .macro spin name
+            \\name\\()_\\@: j \\name\\()_\\@
+            .endm
+            spin loop
+            spin loop
+            ";
+        assert_eq!(
+            expand(prog),
+            vec!["", "loop_0: j loop_0", "loop_1: j loop_1", ""]
+        );
+    }
+
+    #[test]
+    fn test_nested_and_exitm() {
+        let prog = "
+            .macro outer
+            .macro inner x
+            nop \\x
+            .endm
+            inner 1
+            .exitm
+            inner 2
+            .endm
+            outer
+            ";
+        assert_eq!(expand(prog), vec!["", "nop 1", ""]);
+    }
+
+    #[test]
+    fn test_rept_irp_irpc() {
+        let prog = "
+            .rept 2
+            nop
+            .endr
+            .irp reg, a0, a1
+            mv \\reg, zero
+            .endr
+            .irpc n, 12
+            li t\\n, \\n
+            .endr
+            ";
+        assert_eq!(
+            expand(prog),
+            vec![
+                "",
+                "nop",
+                "nop",
+                "mv a0, zero",
+                "mv a1, zero",
+                "li t1, 1",
+                "li t2, 2",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_label_before_invocation() {
+        let prog = ".macro two\nnop\nnop\n.endm\nstart: two";
+        assert_eq!(expand(prog), vec!["start:", "nop", "nop"]);
+    }
+
+    #[test]
+    fn test_macro_errors() {
+        // unterminated
+        assert_eq!(expand_err_count(".macro m\nnop"), 1);
+        assert_eq!(expand_err_count(".rept 3\nnop"), 1);
+        // unmatched
+        assert_eq!(expand_err_count("nop\n.endm\n.endr"), 2);
+        // missing required argument and too many arguments
+        assert_eq!(
+            expand_err_count(".macro m a:req\nnop \\a\n.endm\nm\nm 1, 2"),
+            2
+        );
+        // infinite recursion
+        assert_eq!(expand_err_count(".macro m\nm\n.endm\nm"), 1);
+    }
+
+    #[test]
+    fn test_include_without_file_map() {
+        assert_eq!(expand_err_count(".include \"other.s\""), 1);
+        assert_eq!(expand_err_count(".incbin missing_quotes.bin"), 1);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let prog = "
+            .equ FLAG, 2
+            .set COUNT, FLAG * 3
+            .if COUNT == 6
+                .ifdef MISSING
+                    bad
+                .elseif FLAG > 1
+                    one # FLAG
+                .else
+                    bad
+                .endif
+            .else
+                .if 1 / 0
+                    bad
+                .endif
+            .endif
+            label:
+            .ifdef label
+                li a0, COUNT
+            .endif
+            .ifc \"a\", a
+                two
+            .endif
+            ";
+        let lines: Vec<String> = expand(prog)
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .collect();
+        assert_eq!(lines, vec!["one # FLAG", "label:", "li a0, 6", "two"]);
+    }
+
+    #[test]
+    fn test_conditional_errors() {
+        assert_eq!(expand_err_count(".if 1\nnop"), 1);
+        assert_eq!(expand_err_count(".else\n.endif"), 2);
+        assert_eq!(expand_err_count(".if 1\n.else\n.else\n.endif"), 1);
+        assert_eq!(expand_err_count(".if UNDEFINED\n.endif"), 1);
+        assert_eq!(expand_err_count(".equiv A, 1\n.equiv A, 2"), 1);
+    }
+
+    #[test]
+    fn test_call_site() {
+        let mut reporter = ErrorReporter::new();
+        let prog = ".macro m\nnop\n.endm\n\n  m";
+        let lines = Preprocessor::new(0, prog, &mut reporter).expand();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].lineno, 1);
+        assert_eq!(
+            lines[1].call_site,
+            Some(CallSite {
+                file_id: 0,
+                lineno: 4,
+                offs: 2
+            })
+        );
+    }
+}
@@ -5,6 +5,25 @@ use crate::{data_structures::*, program_state::*};
 pub struct AsmConfig {
     /// Parameters for the machine being emulated.
     pub machine: MachineConfig,
+    /// How li loads constants that take more than two instructions to build.
+    pub li_strategy: LiStrategy,
+    /// The global label at which execution begins. If none is given, execution begins at _start
+    /// or main, whichever is defined first, or otherwise at the first instruction.
+    pub entry: Option<String>,
+    /// Whether to link a startup routine, which defines _start to call main with argc, argv, and
+    /// envp, and then exits with the value main returns.
+    pub crt0: bool,
+}
+
+/// Determines how li loads a constant that takes more than two instructions to build, which
+/// only happens for 64-bit constants.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum LiStrategy {
+    /// Builds the constant with a sequence of lui, addiw, slli, and addi as GCC does.
+    #[default]
+    Sequence,
+    /// Places the constant in .rodata and loads it with auipc and ld.
+    LiteralPool,
 }
 
 /// Configures the start of the text, stack, and data segments.
@@ -2,6 +2,7 @@
 /// disk blocks or memory sectors.
 ///
 /// The map itself is backed by a vector of u64s that are preallocated to 0.
+#[derive(Clone)]
 pub struct Bitmap {
     bit_cnt: usize,
     bits: Vec<u64>,
@@ -21,7 +22,7 @@ impl Bitmap {
     pub fn clear(&mut self) {
         // if we need 0 bits then we allocate 0 ints, but if we need 1 bit we need to allocate
         // 1 int even though 1 / 64 = 0
-        let round_up = self.bit_cnt % 64 != 0;
+        let round_up = !self.bit_cnt.is_multiple_of(64);
         self.bits = vec![0; self.bit_cnt / 64 + (if round_up { 1 } else { 0 })];
     }
 
@@ -57,12 +58,7 @@ impl Bitmap {
     pub fn get_lowest_zero(&self) -> Option<usize> {
         // more efficient way would be to loop over vec entries to minimize lookups, but we don't
         // care that much about performance
-        for i in 0..self.bit_cnt {
-            if !self.read(i) {
-                return Some(i);
-            }
-        }
-        None
+        (0..self.bit_cnt).find(|&i| !self.read(i))
     }
 }
 
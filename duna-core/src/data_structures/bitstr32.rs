@@ -58,7 +58,7 @@ impl BitStr32 {
         if self.len == 32 {
             return DataLword::from(self.value);
         }
-        let sign_mask = u32::max_value() << self.len as u32;
+        let sign_mask = u32::MAX << self.len as u32;
         DataLword::from(if self.index(self.len - 1).value == 1 {
             self.value | sign_mask
         } else {
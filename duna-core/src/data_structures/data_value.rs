@@ -165,7 +165,7 @@ impl DataWidth for W16b {
     }
 
     fn is_aligned(&self) -> bool {
-        self.value % 2 == 0
+        self.value.is_multiple_of(2)
     }
 
     fn as_enum(&self) -> DataEnum {
@@ -202,7 +202,7 @@ impl DataWidth for W32b {
     }
 
     fn is_aligned(&self) -> bool {
-        self.value % 4 == 0
+        self.value.is_multiple_of(4)
     }
 
     fn as_enum(&self) -> DataEnum {
@@ -237,7 +237,7 @@ impl DataWidth for W64b {
     }
 
     fn is_aligned(&self) -> bool {
-        self.value % 8 == 0
+        self.value.is_multiple_of(8)
     }
 
     fn as_enum(&self) -> DataEnum {
@@ -760,8 +760,7 @@ impl<S: AtLeast32b, T: DataInterp> DataValue<S, T> {
         let mask: u64 = !(0xFF << (i * 8));
         let other_raw_val: u64 = val.as_unsigned().raw().as_();
         Self::new(S::from_u64(
-            (AsPrimitive::<u64>::as_(self.as_unsigned().raw()) & mask)
-                | ((other_raw_val as u64) << (i * 8)),
+            (AsPrimitive::<u64>::as_(self.as_unsigned().raw()) & mask) | (other_raw_val << (i * 8)),
         ))
     }
 
@@ -805,7 +804,7 @@ impl DataDword {
     }
 
     pub fn upper_lword(self) -> DataLword {
-        DataLword::from_unsigned((self.value().as_u() as u64 >> 32) as u32)
+        DataLword::from_unsigned((self.value().as_u() >> 32) as u32)
     }
 }
 
@@ -1,9 +1,9 @@
+mod bitmap;
 mod bitstr32;
 mod data_enum;
 mod data_value;
-mod bitmap;
 
+pub use bitmap::*;
 pub use bitstr32::*;
 pub use data_enum::*;
 pub use data_value::*;
-pub use bitmap::*;
@@ -0,0 +1,382 @@
+//! Builds a program from the loadable segments of an ELF executable.
+
+use super::*;
+use crate::{
+    arch::*,
+    config::{MachineConfig, PtKind},
+    data_structures::DataWidth,
+    program_state::{Program, ProgramLayout, Region, SymbolTable},
+};
+
+impl<A: Architecture> Program<A> {
+    /// Loads a statically linked ELF executable.
+    ///
+    /// Each PT_LOAD segment is copied into memory at its virtual address, and execution begins at
+    /// the entry point of the file. The executable segment becomes the text section, a read-only
+    /// segment becomes rodata, and the zero-filled tail of the writable segment becomes bss.
+    /// Named symbols from the symbol table are kept so the debugger can display them.
+    ///
+    /// RISC-V files must be built without the C extension and for a soft-float ABI.
+    pub fn from_elf(bytes: &[u8], config: &MachineConfig) -> Result<Self, ElfError> {
+        let elf = ElfFile::parse(bytes)?;
+        let class =
+            ElfClass::with_word_bytes(std::mem::size_of::<<A::DataWidth as DataWidth>::U>());
+        if elf.class != class || elf.machine != A::ELF_MACHINE {
+            return Err(ElfError::WrongArch {
+                expected: A::NAME.to_string(),
+                found: arch_name(elf.class, elf.machine),
+            });
+        }
+        if elf.elf_type != ET_EXEC {
+            return Err(ElfError::Unsupported(
+                "only statically linked executables can be loaded".to_string(),
+            ));
+        }
+        if elf.machine == EM_RISCV && elf.flags & (EF_RISCV_RVC | EF_RISCV_FLOAT_ABI) != 0 {
+            let abi = if elf.class == ElfClass::Elf32 {
+                "ilp32"
+            } else {
+                "lp64"
+            };
+            return Err(ElfError::Unsupported(format!(
+                "compressed instructions and floating point registers are not supported; build \
+                with -march={}im -mabi={}",
+                A::NAME,
+                abi
+            )));
+        }
+        let layout = elf_layout(&elf, config)?;
+        let image = elf
+            .segments
+            .iter()
+            .map(|seg| (seg.vaddr, seg.data.clone()))
+            .collect();
+        let mut symbols = SymbolTable::new();
+        for sym in &elf.symbols {
+            let is_label = matches!(sym.sym_type(), STT_NOTYPE | STT_OBJECT | STT_FUNC);
+            // names beginning with $ are mapping symbols that mark code and data, not labels
+            if is_label && sym.shndx != 0 && !sym.name.is_empty() && !sym.name.starts_with('$') {
+                symbols.entry(sym.value).or_default().push(sym.name.clone());
+            }
+        }
+        Ok(Program::from_image(elf.entry, layout, image, config.mem_config).with_symbols(symbols))
+    }
+}
+
+/// Determines where each section of a program lies from the segments of an ELF file.
+fn elf_layout(elf: &ElfFile, config: &MachineConfig) -> Result<ProgramLayout, ElfError> {
+    let page_size = 1u64 << config.mem_config.pg_ofs_bits;
+    // Returns the region spanning from the page containing the first segment to the end of the
+    // last, or an empty region at DEFAULT_START if there are no segments
+    let region = |segs: &[&Segment], end: fn(&Segment) -> u64, default_start: u64| {
+        let start = segs.iter().map(|seg| seg.vaddr).min();
+        let start = start.map_or(default_start, |start| start / page_size * page_size);
+        let end = segs.iter().map(|seg| end(seg)).max().unwrap_or(start);
+        Region {
+            start,
+            len: end - start,
+            writable: false,
+        }
+    };
+    let text_segs: Vec<_> = elf.segments.iter().filter(|s| s.is_executable()).collect();
+    let rodata_segs: Vec<_> = elf
+        .segments
+        .iter()
+        .filter(|s| !s.is_executable() && !s.is_writable())
+        .collect();
+    let data_segs: Vec<_> = elf
+        .segments
+        .iter()
+        .filter(|s| !s.is_executable() && s.is_writable())
+        .collect();
+    if text_segs.is_empty() {
+        return Err(ElfError::Unsupported("no executable segment".to_string()));
+    }
+    let text = region(&text_segs, Segment::mem_end, 0);
+    let rodata = region(&rodata_segs, Segment::mem_end, text.end());
+    // a writable segment may hold both data and bss, or only one of them
+    let file_segs: Vec<_> = data_segs
+        .iter()
+        .copied()
+        .filter(|s| !s.data.is_empty())
+        .collect();
+    let bss_segs: Vec<_> = data_segs
+        .iter()
+        .filter(|s| s.mem_size > s.data.len() as u64)
+        .collect();
+    let data = Region {
+        writable: true,
+        ..region(&file_segs, Segment::file_end, rodata.end().max(text.end()))
+    };
+    // bss is the zero-filled memory past the end of the file data of the writable segments
+    let bss_start = bss_segs
+        .iter()
+        .map(|seg| seg.file_end())
+        .min()
+        .unwrap_or_else(|| data.end());
+    let bss_end = bss_segs
+        .iter()
+        .map(|seg| seg.mem_end())
+        .max()
+        .unwrap_or(bss_start);
+    let bss = Region {
+        start: bss_start,
+        len: bss_end - bss_start,
+        writable: true,
+    };
+    if let PtKind::FifoLinearPaged = config.mem_config.kind {
+        // Besides the segments, the first pages of the stack and heap are mapped at startup
+        let needed = static_pages(&[text, rodata, data, bss], page_size) + 2;
+        let available = 1u64 << config.mem_config.phys_pn_bits;
+        if needed > available {
+            return Err(ElfError::Unsupported(format!(
+                "segments need {} pages of memory, but only {} are available",
+                needed, available
+            )));
+        }
+    }
+    let static_end = [text.end(), rodata.end(), data.end(), bss.end()]
+        .iter()
+        .copied()
+        .max()
+        .unwrap();
+    Ok(ProgramLayout {
+        text,
+        rodata,
+        data,
+        bss,
+        heap_start: static_end.div_ceil(page_size) * page_size,
+        stack_start: config.segment_starts.stack_start,
+        page_size,
+    })
+}
+
+/// Counts the pages overlapped by any of the regions, each of which may share pages with the
+/// others.
+fn static_pages(regions: &[Region], page_size: u64) -> u64 {
+    let mut spans: Vec<(u64, u64)> = regions
+        .iter()
+        .filter(|region| region.len > 0)
+        .map(|region| (region.start / page_size, region.end().div_ceil(page_size)))
+        .collect();
+    spans.sort_unstable();
+    let mut count = 0;
+    let mut counted_to = 0;
+    for (first, end) in spans {
+        let first = first.max(counted_to);
+        if end > first {
+            count += end - first;
+            counted_to = end;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architectures::{
+        mips::Mips32,
+        riscv::{RiscVRegister, Rv32, Rv64},
+    };
+
+    const TEXT_ADDR: u64 = 0x1_0000;
+    const DATA_ADDR: u64 = 0x1_1000;
+
+    /// Loads 41 from data, then stores 42 to bss and reads it back.
+    /// The first word is an illegal instruction that is skipped by the entry point.
+    const CODE: [u32; 6] = [
+        0x0000_0000, // illegal
+        0x0001_12b7, // lui t0, 0x11
+        0x0002_a503, // lw a0, 0(t0)
+        0x0015_0513, // addi a0, a0, 1
+        0x00a2_a223, // sw a0, 4(t0)
+        0x0042_a583, // lw a1, 4(t0)
+    ];
+
+    /// Builds an executable with a text segment holding CODE, a data segment holding the
+    /// word 41 followed by a word of bss, and symbols for _start and the data.
+    fn build_elf(class: ElfClass, machine: u16) -> Vec<u8> {
+        let is64 = class == ElfClass::Elf64;
+        let (ehsize, phentsize, shentsize, symentsize) = if is64 {
+            (64, 56, 64, 24)
+        } else {
+            (52, 32, 40, 16)
+        };
+        let text: Vec<u8> = CODE.iter().flat_map(|w| w.to_le_bytes().to_vec()).collect();
+        let data = 41u32.to_le_bytes().to_vec();
+        let strtab = b"\0_start\0value\0".to_vec();
+        let text_ofs = ehsize + 2 * phentsize;
+        let data_ofs = text_ofs + text.len();
+        let symtab_ofs = data_ofs + data.len();
+        let strtab_ofs = symtab_ofs + 3 * symentsize;
+        let shoff = strtab_ofs + strtab.len();
+
+        let mut out = Vec::new();
+        let push = |out: &mut Vec<u8>, value: u64, len: usize| {
+            out.extend_from_slice(&value.to_le_bytes()[..len]);
+        };
+        let addr_len = class.addr_bytes();
+        // ELF header
+        out.extend_from_slice(&ELF_MAGIC);
+        out.extend_from_slice(&[if is64 { 2 } else { 1 }, 1, 1, 0]);
+        out.resize(16, 0);
+        push(&mut out, ET_EXEC as u64, 2);
+        push(&mut out, machine as u64, 2);
+        push(&mut out, 1, 4);
+        push(&mut out, TEXT_ADDR + 4, addr_len);
+        push(&mut out, ehsize as u64, addr_len);
+        push(&mut out, shoff as u64, addr_len);
+        push(&mut out, 0, 4);
+        for &half in &[ehsize, phentsize, 2, shentsize, 4, 0] {
+            push(&mut out, half as u64, 2);
+        }
+        // program headers
+        for &(ofs, vaddr, filesz, memsz, flags) in &[
+            (text_ofs, TEXT_ADDR, text.len(), text.len(), PF_R | PF_X),
+            (data_ofs, DATA_ADDR, data.len(), data.len() + 4, PF_R | PF_W),
+        ] {
+            push(&mut out, PT_LOAD as u64, 4);
+            if is64 {
+                push(&mut out, flags as u64, 4);
+            }
+            push(&mut out, ofs as u64, addr_len);
+            push(&mut out, vaddr, addr_len);
+            push(&mut out, vaddr, addr_len);
+            push(&mut out, filesz as u64, addr_len);
+            push(&mut out, memsz as u64, addr_len);
+            if !is64 {
+                push(&mut out, flags as u64, 4);
+            }
+            push(&mut out, 4, addr_len);
+        }
+        out.extend_from_slice(&text);
+        out.extend_from_slice(&data);
+        // symbol table: null, _start in section 1, value in section 2
+        for &(name, value, info, shndx) in &[
+            (0, 0, 0, 0),
+            (1, TEXT_ADDR + 4, 0x10 | STT_FUNC, 1),
+            (8, DATA_ADDR, STT_OBJECT, 2),
+        ] {
+            push(&mut out, name, 4);
+            if is64 {
+                push(&mut out, info as u64, 1);
+                push(&mut out, 0, 1);
+                push(&mut out, shndx, 2);
+                push(&mut out, value, 8);
+                push(&mut out, 0, 8);
+            } else {
+                push(&mut out, value, 4);
+                push(&mut out, 0, 4);
+                push(&mut out, info as u64, 1);
+                push(&mut out, 0, 1);
+                push(&mut out, shndx, 2);
+            }
+        }
+        out.extend_from_slice(&strtab);
+        // section headers: null, .symtab linked to .strtab, .strtab
+        // the sections named by the symbols are never read, so they are left out
+        for &(sh_type, ofs, size, link, entsize) in &[
+            (0, 0, 0, 0, 0),
+            (0, 0, 0, 0, 0),
+            (SHT_SYMTAB, symtab_ofs, 3 * symentsize, 3, symentsize),
+            (SHT_STRTAB, strtab_ofs, strtab.len(), 0, 0),
+        ] {
+            push(&mut out, 0, 4);
+            push(&mut out, sh_type as u64, 4);
+            push(&mut out, 0, addr_len);
+            push(&mut out, 0, addr_len);
+            push(&mut out, ofs as u64, addr_len);
+            push(&mut out, size as u64, addr_len);
+            push(&mut out, link, 4);
+            push(&mut out, 0, 4);
+            push(&mut out, 1, addr_len);
+            push(&mut out, entsize as u64, addr_len);
+        }
+        out
+    }
+
+    /// Loads and runs the test executable, returning the program after it exits.
+    fn check_run<A: Architecture>(class: ElfClass) -> Program<A> {
+        let bytes = build_elf(class, A::ELF_MACHINE);
+        let mut program = Program::<A>::from_elf(&bytes, &Default::default()).unwrap();
+        let layout = *program.layout();
+        assert_eq!(layout.text.start, TEXT_ADDR);
+        assert_eq!(layout.text.end(), TEXT_ADDR + 4 * CODE.len() as u64);
+        assert_eq!(layout.data.start, DATA_ADDR);
+        assert_eq!(layout.bss.start, DATA_ADDR + 4);
+        assert_eq!(layout.bss.len, 4);
+        assert_eq!(layout.heap_start, DATA_ADDR + 0x1000);
+        assert_eq!(
+            program.symbols().get(&(TEXT_ADDR + 4)),
+            Some(&vec!["_start".to_string()])
+        );
+        assert_eq!(
+            program.symbols().get(&DATA_ADDR),
+            Some(&vec!["value".to_string()])
+        );
+        assert_eq!(program.run(), 42);
+        program
+    }
+
+    #[test]
+    fn test_load_elf32() {
+        let program = check_run::<Rv32>(ElfClass::Elf32);
+        assert_eq!(u32::from(program.state.regfile_read(RiscVRegister::A1)), 42);
+    }
+
+    #[test]
+    fn test_load_elf64() {
+        let program = check_run::<Rv64>(ElfClass::Elf64);
+        assert_eq!(u64::from(program.state.regfile_read(RiscVRegister::A1)), 42);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let config = Default::default();
+        let elf32 = build_elf(ElfClass::Elf32, Rv32::ELF_MACHINE);
+        assert_eq!(
+            Program::<Rv32>::from_elf(b"#!/bin/sh", &config).err(),
+            Some(ElfError::NotElf)
+        );
+        assert_eq!(
+            Program::<Rv32>::from_elf(&elf32[..40], &config).err(),
+            Some(ElfError::Truncated)
+        );
+        assert_eq!(
+            Program::<Rv64>::from_elf(&elf32, &config).err(),
+            Some(ElfError::WrongArch {
+                expected: "rv64".to_string(),
+                found: "rv32".to_string()
+            })
+        );
+        assert_eq!(
+            Program::<Mips32>::from_elf(&elf32, &config).err(),
+            Some(ElfError::WrongArch {
+                expected: "mips32".to_string(),
+                found: "rv32".to_string()
+            })
+        );
+        let mut huge = elf32.clone();
+        // the memsz of the data segment, which is given more pages than physical memory holds
+        huge[104..108].copy_from_slice(&0x1000_0000u32.to_le_bytes());
+        assert!(matches!(
+            Program::<Rv32>::from_elf(&huge, &config).err(),
+            Some(ElfError::Unsupported(_))
+        ));
+        for &flags in &[EF_RISCV_RVC, 0x2, 0x4] {
+            let mut rvc = elf32.clone();
+            rvc[36..40].copy_from_slice(&flags.to_le_bytes());
+            assert!(matches!(
+                Program::<Rv32>::from_elf(&rvc, &config).err(),
+                Some(ElfError::Unsupported(_))
+            ));
+        }
+        let mut relocatable = elf32;
+        relocatable[16] = ET_REL as u8;
+        assert!(matches!(
+            Program::<Rv32>::from_elf(&relocatable, &config).err(),
+            Some(ElfError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,151 @@
+//! Reads and loads ELF files.
+//!
+//! Only little-endian files are supported, since every architecture duna simulates is
+//! little-endian. See the System V ABI for a description of the format.
+mod loader;
+mod reader;
+mod writer;
+
+use std::fmt;
+
+pub use reader::*;
+pub use writer::*;
+
+pub const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+
+// e_ident fields
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+
+// e_type values
+pub const ET_REL: u16 = 1;
+pub const ET_EXEC: u16 = 2;
+
+// e_machine values
+pub const EM_RISCV: u16 = 243;
+
+// RISC-V e_flags values
+pub const EF_RISCV_RVC: u32 = 0x1;
+pub const EF_RISCV_FLOAT_ABI: u32 = 0x6;
+
+// p_type and p_flags values
+pub const PT_LOAD: u32 = 1;
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+// sh_type and sh_flags values
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const SHT_RELA: u32 = 4;
+pub const SHT_NOBITS: u32 = 8;
+pub const SHF_WRITE: u64 = 0x1;
+pub const SHF_ALLOC: u64 = 0x2;
+pub const SHF_EXECINSTR: u64 = 0x4;
+pub const SHF_INFO_LINK: u64 = 0x40;
+
+// special section indices
+pub const SHN_UNDEF: u16 = 0;
+pub const SHN_ABS: u16 = 0xFFF1;
+
+// st_info bindings
+pub const STB_LOCAL: u8 = 0;
+pub const STB_GLOBAL: u8 = 1;
+pub const STB_WEAK: u8 = 2;
+
+// st_info types
+pub const STT_NOTYPE: u8 = 0;
+pub const STT_OBJECT: u8 = 1;
+pub const STT_FUNC: u8 = 2;
+pub const STT_SECTION: u8 = 3;
+pub const STT_FILE: u8 = 4;
+
+// RISC-V relocation types
+pub const R_RISCV_BRANCH: u32 = 16;
+pub const R_RISCV_JAL: u32 = 17;
+pub const R_RISCV_PCREL_HI20: u32 = 23;
+pub const R_RISCV_PCREL_LO12_I: u32 = 24;
+pub const R_RISCV_PCREL_LO12_S: u32 = 25;
+pub const R_RISCV_HI20: u32 = 26;
+pub const R_RISCV_LO12_I: u32 = 27;
+pub const R_RISCV_LO12_S: u32 = 28;
+
+/// Returns true if the bytes begin with the ELF magic number.
+pub fn is_elf(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ELF_MAGIC)
+}
+
+/// Returns the short name of the architecture an ELF file of the given class and machine
+/// targets, e.g. "rv32".
+pub fn arch_name(class: ElfClass, machine: u16) -> String {
+    match (class, machine) {
+        (ElfClass::Elf32, 243) => "rv32".to_string(),
+        (ElfClass::Elf64, 243) => "rv64".to_string(),
+        (ElfClass::Elf32, 8) => "mips32".to_string(),
+        _ => format!("{} machine {}", class, machine),
+    }
+}
+
+/// The word size of an ELF file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ElfClass {
+    Elf32,
+    Elf64,
+}
+
+impl ElfClass {
+    /// Returns the class of ELF files targeting an architecture with words of WIDTH bytes.
+    pub fn with_word_bytes(width: usize) -> Self {
+        if width == 4 {
+            ElfClass::Elf32
+        } else {
+            ElfClass::Elf64
+        }
+    }
+
+    /// The size of an address in bytes.
+    pub fn addr_bytes(self) -> usize {
+        match self {
+            ElfClass::Elf32 => 4,
+            ElfClass::Elf64 => 8,
+        }
+    }
+}
+
+impl fmt::Display for ElfClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfClass::Elf32 => write!(f, "ELF32"),
+            ElfClass::Elf64 => write!(f, "ELF64"),
+        }
+    }
+}
+
+/// Describes why an ELF file could not be read or loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ElfError {
+    /// The file does not begin with the ELF magic number.
+    NotElf,
+    /// A header or table extends past the end of the file.
+    Truncated,
+    /// The file is valid, but uses a feature that duna cannot handle.
+    Unsupported(String),
+    /// The file was built for a different architecture than the one requested.
+    WrongArch { expected: String, found: String },
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ElfError::*;
+        match self {
+            NotElf => write!(f, "not an ELF file"),
+            Truncated => write!(f, "ELF file is truncated"),
+            Unsupported(what) => write!(f, "unsupported ELF file: {}", what),
+            WrongArch { expected, found } => {
+                write!(f, "ELF file targets {}, but expected {}", found, expected)
+            }
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Parses the headers, loadable segments, symbol table, and relocations of an ELF file.
+
+use super::*;
+
+/// A PT_LOAD segment of an ELF file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// The address the segment is loaded at.
+    pub vaddr: u64,
+    /// The bytes of the segment stored in the file.
+    pub data: Vec<u8>,
+    /// The size of the segment in memory. Bytes past the end of the file data are zeroed.
+    pub mem_size: u64,
+    pub flags: u32,
+}
+
+impl Segment {
+    pub fn is_executable(&self) -> bool {
+        self.flags & PF_X != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.flags & PF_W != 0
+    }
+
+    /// Returns the address one past the last byte of file data.
+    pub fn file_end(&self) -> u64 {
+        self.vaddr + self.data.len() as u64
+    }
+
+    /// Returns the address one past the last byte of the segment in memory.
+    pub fn mem_end(&self) -> u64 {
+        self.vaddr + self.mem_size
+    }
+}
+
+/// An entry of the symbol table of an ELF file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u64,
+    pub size: u64,
+    /// The binding in the upper nibble and the type in the lower nibble.
+    pub info: u8,
+    /// The index of the section the symbol is defined in, or 0 if the symbol is undefined.
+    pub shndx: u16,
+}
+
+impl Symbol {
+    pub fn sym_type(&self) -> u8 {
+        self.info & 0xF
+    }
+}
+
+/// The contents of an ELF file needed to load it.
+#[derive(Clone, Debug)]
+pub struct ElfFile {
+    pub class: ElfClass,
+    pub elf_type: u16,
+    pub machine: u16,
+    /// Processor-specific flags, such as the ABI a RISC-V file was built for.
+    pub flags: u32,
+    pub entry: u64,
+    /// The PT_LOAD segments of the file, in the order they appear in the program header table.
+    pub segments: Vec<Segment>,
+    /// The entries of the symbol table, excluding the null symbol at index 0.
+    pub symbols: Vec<Symbol>,
+    /// The entries of every SHT_RELA section, each paired with the index of the section it
+    /// applies to.
+    pub relocations: Vec<(u16, Rela)>,
+}
+
+/// The offsets of header fields that differ between ELF32 and ELF64.
+struct Offsets {
+    e_entry: usize,
+    e_phoff: usize,
+    e_shoff: usize,
+    e_flags: usize,
+    e_phentsize: usize,
+    e_phnum: usize,
+    e_shentsize: usize,
+    e_shnum: usize,
+    p_type: usize,
+    p_flags: usize,
+    p_offset: usize,
+    p_vaddr: usize,
+    p_filesz: usize,
+    p_memsz: usize,
+    sh_type: usize,
+    sh_offset: usize,
+    sh_size: usize,
+    sh_link: usize,
+    sh_info: usize,
+    sh_entsize: usize,
+    st_name: usize,
+    st_value: usize,
+    st_size: usize,
+    st_info: usize,
+    st_shndx: usize,
+}
+
+const OFFSETS_32: Offsets = Offsets {
+    e_entry: 24,
+    e_phoff: 28,
+    e_shoff: 32,
+    e_flags: 36,
+    e_phentsize: 42,
+    e_phnum: 44,
+    e_shentsize: 46,
+    e_shnum: 48,
+    p_type: 0,
+    p_flags: 24,
+    p_offset: 4,
+    p_vaddr: 8,
+    p_filesz: 16,
+    p_memsz: 20,
+    sh_type: 4,
+    sh_offset: 16,
+    sh_size: 20,
+    sh_link: 24,
+    sh_info: 28,
+    sh_entsize: 36,
+    st_name: 0,
+    st_value: 4,
+    st_size: 8,
+    st_info: 12,
+    st_shndx: 14,
+};
+
+const OFFSETS_64: Offsets = Offsets {
+    e_entry: 24,
+    e_phoff: 32,
+    e_shoff: 40,
+    e_flags: 48,
+    e_phentsize: 54,
+    e_phnum: 56,
+    e_shentsize: 58,
+    e_shnum: 60,
+    p_type: 0,
+    p_flags: 4,
+    p_offset: 8,
+    p_vaddr: 16,
+    p_filesz: 32,
+    p_memsz: 40,
+    sh_type: 4,
+    sh_offset: 24,
+    sh_size: 32,
+    sh_link: 40,
+    sh_info: 44,
+    sh_entsize: 56,
+    st_name: 0,
+    st_value: 8,
+    st_size: 16,
+    st_info: 4,
+    st_shndx: 6,
+};
+
+/// Reads little-endian fields out of a byte slice, failing if a field extends past its end.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    class: ElfClass,
+}
+
+impl<'a> Reader<'a> {
+    fn slice(&self, offset: u64, len: u64) -> Result<&'a [u8], ElfError> {
+        let end = offset.checked_add(len).ok_or(ElfError::Truncated)?;
+        if end > self.bytes.len() as u64 {
+            Err(ElfError::Truncated)
+        } else {
+            Ok(&self.bytes[offset as usize..end as usize])
+        }
+    }
+
+    fn uint(&self, offset: u64, len: u64) -> Result<u64, ElfError> {
+        Ok(self
+            .slice(offset, len)?
+            .iter()
+            .rev()
+            .fold(0, |acc, &b| (acc << 8) | b as u64))
+    }
+
+    fn u8(&self, offset: u64) -> Result<u8, ElfError> {
+        Ok(self.uint(offset, 1)? as u8)
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16, ElfError> {
+        Ok(self.uint(offset, 2)? as u16)
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32, ElfError> {
+        Ok(self.uint(offset, 4)? as u32)
+    }
+
+    /// Reads an address or offset, whose size depends on the class of the file.
+    fn addr(&self, offset: u64) -> Result<u64, ElfError> {
+        self.uint(offset, self.class.addr_bytes() as u64)
+    }
+}
+
+impl ElfFile {
+    /// Parses the bytes of an ELF file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ElfError> {
+        if !is_elf(bytes) {
+            return Err(ElfError::NotElf);
+        }
+        let mut r = Reader {
+            bytes,
+            class: ElfClass::Elf32,
+        };
+        r.class = match r.u8(4)? {
+            ELFCLASS32 => ElfClass::Elf32,
+            ELFCLASS64 => ElfClass::Elf64,
+            c => return Err(ElfError::Unsupported(format!("unknown class {}", c))),
+        };
+        if r.u8(5)? != ELFDATA2LSB {
+            return Err(ElfError::Unsupported("big-endian byte order".to_string()));
+        }
+        let ofs = match r.class {
+            ElfClass::Elf32 => &OFFSETS_32,
+            ElfClass::Elf64 => &OFFSETS_64,
+        };
+        let at = |field: usize| field as u64;
+        let elf_type = r.u16(16)?;
+        let machine = r.u16(18)?;
+        let flags = r.u32(at(ofs.e_flags))?;
+        let entry = r.addr(at(ofs.e_entry))?;
+
+        let phoff = r.addr(at(ofs.e_phoff))?;
+        let phentsize = r.u16(at(ofs.e_phentsize))? as u64;
+        let phnum = r.u16(at(ofs.e_phnum))? as u64;
+        let mut segments = Vec::new();
+        for i in 0..phnum {
+            let ph = phoff + i * phentsize;
+            if r.u32(ph + at(ofs.p_type))? != PT_LOAD {
+                continue;
+            }
+            let offset = r.addr(ph + at(ofs.p_offset))?;
+            let filesz = r.addr(ph + at(ofs.p_filesz))?;
+            let mem_size = r.addr(ph + at(ofs.p_memsz))?;
+            if filesz > mem_size {
+                return Err(ElfError::Unsupported(
+                    "segment is larger in the file than in memory".to_string(),
+                ));
+            }
+            let vaddr = r.addr(ph + at(ofs.p_vaddr))?;
+            if vaddr.checked_add(mem_size).is_none() {
+                return Err(ElfError::Unsupported(
+                    "segment extends past the end of the address space".to_string(),
+                ));
+            }
+            segments.push(Segment {
+                vaddr,
+                data: r.slice(offset, filesz)?.to_vec(),
+                mem_size,
+                flags: r.u32(ph + at(ofs.p_flags))?,
+            });
+        }
+
+        let shoff = r.addr(at(ofs.e_shoff))?;
+        let shentsize = r.u16(at(ofs.e_shentsize))? as u64;
+        let shnum = r.u16(at(ofs.e_shnum))? as u64;
+        let mut symbols = Vec::new();
+        let mut relocations = Vec::new();
+        for i in 0..shnum {
+            let sh = shoff + i * shentsize;
+            let sh_type = r.u32(sh + at(ofs.sh_type))?;
+            if sh_type == SHT_RELA {
+                let target = r.u32(sh + at(ofs.sh_info))? as u16;
+                let rela_offset = r.addr(sh + at(ofs.sh_offset))?;
+                let rela_size = r.addr(sh + at(ofs.sh_size))?;
+                let addr_len = r.class.addr_bytes() as u64;
+                for j in 0..rela_size / (3 * addr_len) {
+                    let rela = rela_offset + j * 3 * addr_len;
+                    let info = r.addr(rela + addr_len)?;
+                    let (symbol, r_type) = match r.class {
+                        ElfClass::Elf32 => (info >> 8, info & 0xFF),
+                        ElfClass::Elf64 => (info >> 32, info & 0xFFFF_FFFF),
+                    };
+                    // sign extend the addend from the width of an address
+                    let shift = 64 - 8 * addr_len;
+                    let addend = ((r.addr(rela + 2 * addr_len)? << shift) as i64) >> shift;
+                    relocations.push((
+                        target,
+                        Rela {
+                            offset: r.addr(rela)?,
+                            symbol: symbol as usize,
+                            r_type: r_type as u32,
+                            addend,
+                        },
+                    ));
+                }
+            }
+            if sh_type != SHT_SYMTAB {
+                continue;
+            }
+            let sym_offset = r.addr(sh + at(ofs.sh_offset))?;
+            let sym_size = r.addr(sh + at(ofs.sh_size))?;
+            let entsize = r.addr(sh + at(ofs.sh_entsize))?;
+            if entsize == 0 {
+                return Err(ElfError::Unsupported(
+                    "symbol table has entries of size 0".to_string(),
+                ));
+            }
+            // sh_link holds the index of the string table with the symbol names
+            let strtab = shoff + r.u32(sh + at(ofs.sh_link))? as u64 * shentsize;
+            let str_offset = r.addr(strtab + at(ofs.sh_offset))?;
+            let strings = r.slice(str_offset, r.addr(strtab + at(ofs.sh_size))?)?;
+            // index 0 is always the null symbol
+            for j in 1..sym_size / entsize {
+                let sym = sym_offset + j * entsize;
+                let name_start = r.u32(sym + at(ofs.st_name))? as usize;
+                let name_bytes = strings.get(name_start..).ok_or(ElfError::Truncated)?;
+                let name_len = name_bytes
+                    .iter()
+                    .position(|&b| b == 0)
+                    .ok_or(ElfError::Truncated)?;
+                symbols.push(Symbol {
+                    name: String::from_utf8_lossy(&name_bytes[..name_len]).into_owned(),
+                    value: r.addr(sym + at(ofs.st_value))?,
+                    size: r.addr(sym + at(ofs.st_size))?,
+                    info: r.u8(sym + at(ofs.st_info))?,
+                    shndx: r.u16(sym + at(ofs.st_shndx))?,
+                });
+            }
+        }
+
+        Ok(ElfFile {
+            class: r.class,
+            elf_type,
+            machine,
+            flags,
+            entry,
+            segments,
+            symbols,
+            relocations,
+        })
+    }
+}
@@ -0,0 +1,499 @@
+//! Writes ELF executables and relocatable objects.
+
+use super::*;
+use crate::{arch::Architecture, data_structures::DataWidth, program_state::Program};
+
+/// A section to be written to an ELF file.
+#[derive(Clone, Debug)]
+pub struct OutSection {
+    pub name: String,
+    pub sh_type: u32,
+    pub flags: u64,
+    /// The address of the section in memory, which is 0 in a relocatable object.
+    pub addr: u64,
+    pub data: Vec<u8>,
+    /// The size of the section in memory, which only differs from the length of the data for
+    /// SHT_NOBITS sections.
+    pub size: u64,
+    pub align: u64,
+    /// Relocations to apply to the contents of this section.
+    pub relocs: Vec<Rela>,
+}
+
+impl OutSection {
+    /// Creates a section whose contents are stored in the file.
+    pub fn progbits(name: &str, flags: u64, addr: u64, data: Vec<u8>, align: u64) -> Self {
+        OutSection {
+            name: name.to_string(),
+            sh_type: SHT_PROGBITS,
+            flags,
+            addr,
+            size: data.len() as u64,
+            data,
+            align,
+            relocs: Vec::new(),
+        }
+    }
+
+    /// Creates a zero-initialized section that takes no space in the file.
+    pub fn nobits(name: &str, flags: u64, addr: u64, size: u64, align: u64) -> Self {
+        OutSection {
+            name: name.to_string(),
+            sh_type: SHT_NOBITS,
+            flags,
+            addr,
+            data: Vec::new(),
+            size,
+            align,
+            relocs: Vec::new(),
+        }
+    }
+}
+
+/// A relocation with an explicit addend.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rela {
+    /// The offset of the relocated bytes from the start of their section.
+    pub offset: u64,
+    /// The index of the symbol in the symbol table.
+    pub symbol: usize,
+    pub r_type: u32,
+    pub addend: i64,
+}
+
+/// Collects the sections and symbols of an ELF file, then lays them out into bytes.
+///
+/// Executables get one PT_LOAD segment per allocated section, placed in the file at an offset
+/// congruent to its address modulo the page size so that the file can be mapped directly.
+pub struct ElfBuilder {
+    class: ElfClass,
+    elf_type: u16,
+    machine: u16,
+    entry: u64,
+    page_size: u64,
+    sections: Vec<OutSection>,
+    symbols: Vec<Symbol>,
+}
+
+impl ElfBuilder {
+    pub fn new(class: ElfClass, elf_type: u16, machine: u16) -> Self {
+        ElfBuilder {
+            class,
+            elf_type,
+            machine,
+            entry: 0,
+            page_size: 0x1000,
+            sections: Vec::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    pub fn with_entry(mut self, entry: u64) -> Self {
+        self.entry = entry;
+        self
+    }
+
+    pub fn with_page_size(mut self, page_size: u64) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Adds a section, returning its index in the section header table.
+    pub fn add_section(&mut self, section: OutSection) -> u16 {
+        self.sections.push(section);
+        self.sections.len() as u16
+    }
+
+    /// Returns the section at an index returned by add_section.
+    pub fn section_mut(&mut self, index: u16) -> &mut OutSection {
+        &mut self.sections[index as usize - 1]
+    }
+
+    /// Adds a symbol, returning the index to use for it in a Rela.
+    ///
+    /// Symbols may be added in any order. Local symbols are moved before global ones when the
+    /// file is built, as the format requires, and relocations are renumbered to match.
+    pub fn add_symbol(&mut self, symbol: Symbol) -> usize {
+        self.symbols.push(symbol);
+        self.symbols.len()
+    }
+
+    /// Lays out the file, returning its bytes.
+    pub fn build(self) -> Vec<u8> {
+        let ElfBuilder {
+            class,
+            elf_type,
+            machine,
+            entry,
+            page_size,
+            mut sections,
+            symbols,
+        } = self;
+        let is64 = class == ElfClass::Elf64;
+        let addr_len = class.addr_bytes();
+        let (ehsize, phentsize, shentsize, symentsize, relaentsize) = if is64 {
+            (64, 56, 64, 24, 24)
+        } else {
+            (52, 32, 40, 16, 12)
+        };
+        let put = |out: &mut Vec<u8>, value: u64, len: usize| {
+            out.extend_from_slice(&value.to_le_bytes()[..len]);
+        };
+        let pad_to = |out: &mut Vec<u8>, align: u64| {
+            while !(out.len() as u64).is_multiple_of(align.max(1)) {
+                out.push(0);
+            }
+        };
+
+        // Order symbols with locals first, remembering where each one moved
+        let mut order: Vec<usize> = (0..symbols.len()).collect();
+        order.sort_by_key(|&i| symbols[i].info >> 4 != STB_LOCAL);
+        let mut new_index = vec![0; symbols.len() + 1];
+        for (new, &old) in order.iter().enumerate() {
+            new_index[old + 1] = new + 1;
+        }
+        let first_global = 1 + order
+            .iter()
+            .take_while(|&&i| symbols[i].info >> 4 == STB_LOCAL)
+            .count();
+        let mut strtab = vec![0u8];
+        let mut symtab = vec![0u8; symentsize];
+        for &i in &order {
+            let sym = &symbols[i];
+            let name = if sym.name.is_empty() {
+                0
+            } else {
+                strtab.len() as u64
+            };
+            if !sym.name.is_empty() {
+                strtab.extend_from_slice(sym.name.as_bytes());
+                strtab.push(0);
+            }
+            put(&mut symtab, name, 4);
+            if is64 {
+                put(&mut symtab, sym.info as u64, 1);
+                put(&mut symtab, 0, 1);
+                put(&mut symtab, sym.shndx as u64, 2);
+                put(&mut symtab, sym.value, 8);
+                put(&mut symtab, sym.size, 8);
+            } else {
+                put(&mut symtab, sym.value, 4);
+                put(&mut symtab, sym.size, 4);
+                put(&mut symtab, sym.info as u64, 1);
+                put(&mut symtab, 0, 1);
+                put(&mut symtab, sym.shndx as u64, 2);
+            }
+        }
+
+        // Tables come after the sections passed in: the relocations of each section, then the
+        // symbol table and the string tables
+        let user_count = sections.len();
+        let rela_count = sections.iter().filter(|s| !s.relocs.is_empty()).count();
+        let symtab_idx = user_count + rela_count + 1;
+        let mut tables = Vec::new();
+        for (i, section) in sections.iter().enumerate() {
+            if section.relocs.is_empty() {
+                continue;
+            }
+            let mut data = Vec::new();
+            for rela in &section.relocs {
+                let sym = new_index[rela.symbol] as u64;
+                put(&mut data, rela.offset, addr_len);
+                if is64 {
+                    put(&mut data, (sym << 32) | rela.r_type as u64, 8);
+                } else {
+                    put(&mut data, (sym << 8) | (rela.r_type as u64 & 0xFF), 4);
+                }
+                put(&mut data, rela.addend as u64, addr_len);
+            }
+            tables.push((
+                OutSection {
+                    sh_type: SHT_RELA,
+                    flags: SHF_INFO_LINK,
+                    align: addr_len as u64,
+                    ..OutSection::progbits(&format!(".rela{}", section.name), 0, 0, data, 0)
+                },
+                symtab_idx as u32,
+                i as u32 + 1,
+                relaentsize,
+            ));
+        }
+        tables.push((
+            OutSection {
+                sh_type: SHT_SYMTAB,
+                ..OutSection::progbits(".symtab", 0, 0, symtab, addr_len as u64)
+            },
+            symtab_idx as u32 + 1,
+            first_global as u32,
+            symentsize,
+        ));
+        tables.push((
+            OutSection {
+                sh_type: SHT_STRTAB,
+                ..OutSection::progbits(".strtab", 0, 0, strtab, 1)
+            },
+            0,
+            0,
+            0,
+        ));
+        let mut shstrtab = vec![0u8];
+        let mut name_offsets = Vec::new();
+        let all_names = sections
+            .iter()
+            .map(|s| &s.name)
+            .chain(tables.iter().map(|(s, ..)| &s.name))
+            .cloned()
+            .chain(std::iter::once(".shstrtab".to_string()))
+            .collect::<Vec<_>>();
+        for name in &all_names {
+            name_offsets.push(shstrtab.len() as u64);
+            shstrtab.extend_from_slice(name.as_bytes());
+            shstrtab.push(0);
+        }
+        tables.push((
+            OutSection {
+                sh_type: SHT_STRTAB,
+                ..OutSection::progbits(".shstrtab", 0, 0, shstrtab, 1)
+            },
+            0,
+            0,
+            0,
+        ));
+
+        // Place the contents of every section after the headers
+        let segments: Vec<usize> = if elf_type == ET_EXEC {
+            (0..user_count)
+                .filter(|&i| sections[i].flags & SHF_ALLOC != 0 && sections[i].size > 0)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut out = vec![0u8; ehsize + segments.len() * phentsize];
+        let mut offsets = Vec::new();
+        for (i, section) in sections
+            .iter_mut()
+            .chain(tables.iter_mut().map(|(s, ..)| s))
+            .enumerate()
+        {
+            if section.sh_type == SHT_NOBITS {
+                offsets.push(out.len() as u64);
+                continue;
+            }
+            if segments.contains(&i) {
+                // keep the offset congruent to the address, modulo the page size
+                while out.len() as u64 % page_size != section.addr % page_size {
+                    out.push(0);
+                }
+            } else {
+                pad_to(&mut out, section.align);
+            }
+            offsets.push(out.len() as u64);
+            out.append(&mut section.data);
+        }
+        pad_to(&mut out, addr_len as u64);
+        let shoff = out.len() as u64;
+        let shnum = 1 + sections.len() + tables.len();
+
+        // Section headers
+        out.resize(out.len() + shentsize, 0);
+        let all_sections = sections.iter().map(|s| (s, 0, 0, 0)).chain(
+            tables
+                .iter()
+                .map(|(s, link, info, ent)| (s, *link, *info, *ent)),
+        );
+        for (i, (section, link, info, entsize)) in all_sections.enumerate() {
+            put(&mut out, name_offsets[i], 4);
+            put(&mut out, section.sh_type as u64, 4);
+            put(&mut out, section.flags, addr_len);
+            put(&mut out, section.addr, addr_len);
+            put(&mut out, offsets[i], addr_len);
+            put(&mut out, section.size, addr_len);
+            put(&mut out, link as u64, 4);
+            put(&mut out, info as u64, 4);
+            put(&mut out, section.align, addr_len);
+            put(&mut out, entsize as u64, addr_len);
+        }
+
+        // ELF header and program headers
+        let mut header = Vec::new();
+        header.extend_from_slice(&ELF_MAGIC);
+        header.extend_from_slice(&[if is64 { ELFCLASS64 } else { ELFCLASS32 }, ELFDATA2LSB, 1]);
+        header.resize(16, 0);
+        put(&mut header, elf_type as u64, 2);
+        put(&mut header, machine as u64, 2);
+        put(&mut header, 1, 4);
+        put(&mut header, entry, addr_len);
+        put(
+            &mut header,
+            if segments.is_empty() {
+                0
+            } else {
+                ehsize as u64
+            },
+            addr_len,
+        );
+        put(&mut header, shoff, addr_len);
+        put(&mut header, 0, 4);
+        for &half in &[
+            ehsize,
+            phentsize,
+            segments.len(),
+            shentsize,
+            shnum,
+            shnum - 1,
+        ] {
+            put(&mut header, half as u64, 2);
+        }
+        for &i in &segments {
+            let section = &sections[i];
+            let mut flags = PF_R;
+            if section.flags & SHF_EXECINSTR != 0 {
+                flags |= PF_X;
+            }
+            if section.flags & SHF_WRITE != 0 {
+                flags |= PF_W;
+            }
+            let filesz = if section.sh_type == SHT_NOBITS {
+                0
+            } else {
+                section.size
+            };
+            put(&mut header, PT_LOAD as u64, 4);
+            if is64 {
+                put(&mut header, flags as u64, 4);
+            }
+            put(&mut header, offsets[i], addr_len);
+            put(&mut header, section.addr, addr_len);
+            put(&mut header, section.addr, addr_len);
+            put(&mut header, filesz, addr_len);
+            put(&mut header, section.size, addr_len);
+            if !is64 {
+                put(&mut header, flags as u64, 4);
+            }
+            put(&mut header, page_size, addr_len);
+        }
+        out[..header.len()].copy_from_slice(&header);
+        out
+    }
+}
+
+/// Returns true for labels the assembler generates for itself, such as numeric local labels and
+/// literal pool entries, which are left out of symbol tables.
+pub(crate) fn is_internal_label(name: &str) -> bool {
+    name.contains('^') || name.starts_with(".L")
+}
+
+impl<A: Architecture> Program<A> {
+    /// Writes this program as an ELF executable with .text, .rodata, .data, and .bss sections
+    /// placed where the program was laid out, along with a symbol table of its labels.
+    pub fn to_elf(&self) -> Vec<u8> {
+        let class =
+            ElfClass::with_word_bytes(std::mem::size_of::<<A::DataWidth as DataWidth>::U>());
+        let layout = self.layout();
+        let mut builder = ElfBuilder::new(class, ET_EXEC, A::ELF_MACHINE)
+            .with_entry(self.entry())
+            .with_page_size(layout.page_size);
+        let mut regions = Vec::new();
+        for &(name, region, flags) in &[
+            (".text", layout.text, SHF_ALLOC | SHF_EXECINSTR),
+            (".rodata", layout.rodata, SHF_ALLOC),
+            (".data", layout.data, SHF_ALLOC | SHF_WRITE),
+        ] {
+            if region.len > 0 {
+                let data = self.image_bytes(region.start, region.end());
+                let section = OutSection::progbits(name, flags, region.start, data, 8);
+                regions.push((region, builder.add_section(section)));
+            }
+        }
+        let bss = layout.bss;
+        if bss.len > 0 {
+            let section = OutSection::nobits(".bss", SHF_ALLOC | SHF_WRITE, bss.start, bss.len, 8);
+            regions.push((bss, builder.add_section(section)));
+        }
+        for (&addr, names) in self.symbols() {
+            let shndx = regions
+                .iter()
+                .find(|(region, _)| region.contains(addr))
+                .map_or(SHN_ABS, |&(_, index)| index);
+            for name in names.iter().filter(|name| !is_internal_label(name)) {
+                builder.add_symbol(Symbol {
+                    name: name.clone(),
+                    value: addr,
+                    size: 0,
+                    info: (STB_LOCAL << 4) | STT_NOTYPE,
+                    shndx,
+                });
+            }
+        }
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        architectures::riscv::{RiscVRegister, Rv32, Rv64},
+        assembler::Linker,
+    };
+
+    const PROGRAM: &str = "
+        .data
+        val: .word 20
+        .bss
+        buf: .zero 8
+        .rodata
+        msg: .string \"hi\"
+        .text
+        .globl main
+        setup:
+            li a0, 1
+        main:
+            lw a0, val
+            sw a0, buf, t0
+            lw a1, buf
+            add a0, a0, a1
+            lbu a1, msg
+            add a0, a0, a1
+        ";
+
+    fn check_round_trip<A: Architecture>() -> Program<A> {
+        let mut program = Linker::with_main_str(PROGRAM)
+            .link::<A>(Default::default())
+            .unwrap();
+        let bytes = program.to_elf();
+        let elf = ElfFile::parse(&bytes).unwrap();
+        assert_eq!(elf.elf_type, ET_EXEC);
+        assert_eq!(elf.entry, program.entry());
+        let names: Vec<&str> = elf.symbols.iter().map(|sym| sym.name.as_str()).collect();
+        assert_eq!(names, vec!["setup", "main", "msg", "val", "buf"]);
+        let mut loaded = Program::<A>::from_elf(&bytes, &Default::default()).unwrap();
+        assert_eq!(loaded.symbols(), program.symbols());
+        for &(loaded_region, region) in &[
+            (loaded.layout().text, program.layout().text),
+            (loaded.layout().rodata, program.layout().rodata),
+            (loaded.layout().data, program.layout().data),
+            (loaded.layout().bss, program.layout().bss),
+        ] {
+            assert_eq!(loaded_region, region);
+        }
+        // a0 = 20 + 20 + 'h' = 144, truncated to 7 bits for the exit code
+        assert_eq!(program.run(), 144 & 0x7F);
+        assert_eq!(loaded.run(), 144 & 0x7F);
+        loaded
+    }
+
+    #[test]
+    fn test_executable_round_trip() {
+        let program = check_round_trip::<Rv32>();
+        assert_eq!(
+            u32::from(program.state.regfile_read(RiscVRegister::A0)),
+            144
+        );
+        let program = check_round_trip::<Rv64>();
+        assert_eq!(
+            u64::from(program.state.regfile_read(RiscVRegister::A0)),
+            144
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Exports the memory of a program as images for loading onto hardware, such as the
+//! instruction and data memories of a core on an FPGA.
+use crate::{
+    arch::Architecture,
+    config::SegmentStarts,
+    program_state::{Program, Region},
+};
+use std::{fmt, fmt::Write};
+
+/// A contiguous block of memory beginning at a base address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemImage {
+    pub base: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// The file formats a memory image can be written in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// The raw bytes of the image.
+    Binary,
+    /// Intel HEX records, which hold absolute addresses.
+    IntelHex,
+    /// One little-endian 32-bit word per line, as read by Verilog's $readmemh.
+    Readmemh,
+}
+
+/// Describes why a program could not be exported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    /// A segment begins below the base address of its image.
+    BelowBase {
+        segment: &'static str,
+        start: u64,
+        base: u64,
+    },
+    /// An address does not fit in the 32 bits that Intel HEX can express.
+    AddressTooLarge(u64),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ImageError::*;
+        match self {
+            BelowBase {
+                segment,
+                start,
+                base,
+            } => write!(
+                f,
+                "{} segment begins at {:#x}, below its base address {:#x}",
+                segment, start, base
+            ),
+            AddressTooLarge(addr) => {
+                write!(f, "address {:#x} does not fit in an Intel HEX file", addr)
+            }
+        }
+    }
+}
+
+impl<A: Architecture> Program<A> {
+    /// Returns images of the text segment, which holds text and rodata, and of the data segment,
+    /// which holds data and bss.
+    ///
+    /// Each image begins at the configured start of its segment rather than at its first byte,
+    /// so that an offset into the image is also an offset into the memory it is loaded into.
+    pub fn mem_images(&self, starts: &SegmentStarts) -> Result<[MemImage; 2], ImageError> {
+        let layout = self.layout();
+        // an empty region may be placed past the end of the one before it, so it is skipped
+        let end = |first: Region, second: Region| {
+            if second.len > 0 {
+                first.end().max(second.end())
+            } else {
+                first.end()
+            }
+        };
+        let text_end = end(layout.text, layout.rodata);
+        let data_end = end(layout.data, layout.bss);
+        let image = |segment: &'static str, start: u64, end: u64, base: u64| {
+            if start < base && start < end {
+                Err(ImageError::BelowBase {
+                    segment,
+                    start,
+                    base,
+                })
+            } else {
+                Ok(MemImage {
+                    base,
+                    bytes: self.image_bytes(base, end),
+                })
+            }
+        };
+        Ok([
+            image("text", layout.text.start, text_end, starts.text_start)?,
+            image("data", layout.data.start, data_end, starts.data_start)?,
+        ])
+    }
+}
+
+impl MemImage {
+    /// Formats the image for $readmemh, with one word per line starting from the base address.
+    /// A partial word at the end is padded with zeroes.
+    pub fn to_readmemh(&self) -> String {
+        let mut out = String::new();
+        for word in self.bytes.chunks(4) {
+            let mut padded = [0; 4];
+            padded[..word.len()].copy_from_slice(word);
+            writeln!(out, "{:08x}", u32::from_le_bytes(padded)).unwrap();
+        }
+        out
+    }
+}
+
+/// Formats images as Intel HEX, with 16 data bytes per record. If an entry point is provided,
+/// it is written as a start linear address record.
+pub fn to_intel_hex(images: &[MemImage], entry: Option<u64>) -> Result<String, ImageError> {
+    let mut out = String::new();
+    let mut record = |rec_type: u8, addr: u16, data: &[u8]| {
+        let mut sum = data.len() as u8;
+        sum = sum.wrapping_add((addr >> 8) as u8).wrapping_add(addr as u8);
+        sum = sum.wrapping_add(rec_type);
+        write!(out, ":{:02X}{:04X}{:02X}", data.len(), addr, rec_type).unwrap();
+        for &b in data {
+            write!(out, "{:02X}", b).unwrap();
+            sum = sum.wrapping_add(b);
+        }
+        writeln!(out, "{:02X}", sum.wrapping_neg()).unwrap();
+    };
+    let mut upper = 0;
+    for image in images {
+        let last = image.base + (image.bytes.len() as u64).max(1) - 1;
+        if last > u32::MAX as u64 {
+            return Err(ImageError::AddressTooLarge(last));
+        }
+        let mut addr = image.base;
+        let mut rest = image.bytes.as_slice();
+        while !rest.is_empty() {
+            // a record may not cross into the next 64 KiB, whose upper address bits differ
+            if addr >> 16 != upper {
+                upper = addr >> 16;
+                record(4, 0, &(upper as u16).to_be_bytes());
+            }
+            let len = rest
+                .len()
+                .min(16)
+                .min((0x1_0000 - (addr & 0xFFFF)) as usize);
+            record(0, addr as u16, &rest[..len]);
+            rest = &rest[len..];
+            addr += len as u64;
+        }
+    }
+    if let Some(entry) = entry {
+        if entry > u32::MAX as u64 {
+            return Err(ImageError::AddressTooLarge(entry));
+        }
+        record(5, 0, &(entry as u32).to_be_bytes());
+    }
+    record(1, 0, &[]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{architectures::riscv::Rv32, assembler::Linker};
+
+    #[test]
+    fn test_intel_hex() {
+        // the example from the Intel HEX specification
+        let image = MemImage {
+            base: 0x0100,
+            bytes: vec![
+                0x21, 0x46, 0x01, 0x36, 0x01, 0x21, 0x47, 0x01, 0x36, 0x00, 0x7E, 0xFE, 0x09, 0xD2,
+                0x19, 0x01,
+            ],
+        };
+        assert_eq!(
+            to_intel_hex(&[image], None).unwrap(),
+            ":10010000214601360121470136007EFE09D2190140\n:00000001FF\n"
+        );
+        // records split at 64 KiB boundaries, and addresses above them need their upper bits
+        let image = MemImage {
+            base: 0x1000_FFFE,
+            bytes: vec![1, 2, 3],
+        };
+        assert_eq!(
+            to_intel_hex(&[image], Some(0x1000_0000)).unwrap(),
+            ":020000041000EA\n:02FFFE000102FE\n:020000041001E9\n:0100000003FC\n\
+             :0400000510000000E7\n:00000001FF\n"
+        );
+        let image = MemImage {
+            base: 0x1_0000_0000,
+            bytes: vec![0],
+        };
+        assert_eq!(
+            to_intel_hex(&[image], None),
+            Err(ImageError::AddressTooLarge(0x1_0000_0000))
+        );
+    }
+
+    #[test]
+    fn test_mem_images() {
+        let program = Linker::with_main_str(
+            "
+            .data
+            .byte 0xAB
+            .text
+            addi a0, zero, 1
+            ",
+        )
+        .link::<Rv32>(Default::default())
+        .unwrap();
+        let starts = SegmentStarts::default();
+        let [text, data] = program.mem_images(&starts).unwrap();
+        assert_eq!(text.base, starts.text_start);
+        assert_eq!(text.bytes, 0x0010_0513u32.to_le_bytes().to_vec());
+        assert_eq!(text.to_readmemh(), "00100513\n");
+        assert_eq!(data.base, starts.data_start);
+        assert_eq!(data.to_readmemh(), "000000ab\n");
+        let high_starts = SegmentStarts {
+            text_start: starts.text_start + 0x1000,
+            ..starts
+        };
+        assert_eq!(
+            program.mem_images(&high_starts),
+            Err(ImageError::BelowBase {
+                segment: "text",
+                start: starts.text_start,
+                base: starts.text_start + 0x1000,
+            })
+        );
+    }
+}
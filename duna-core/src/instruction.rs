@@ -13,3 +13,45 @@ where
     fn to_machine_code(&self) -> u32;
     fn apply(&self, state: &ProgramState<F, S>) -> InstResult<F, S>;
 }
+
+/// Turns machine code back into the instructions that produce it.
+pub trait InstDecoder<F, S>
+where
+    F: ArchFamily<S>,
+    S: DataWidth,
+{
+    /// Decodes a single instruction word. Decoding the result of ConcreteInst::to_machine_code
+    /// produces the same instruction that the parser would have built.
+    fn decode(code: u32) -> Result<F::Instruction, IllegalInstruction>;
+
+    /// Determines how a linker should fill in the label referenced by the instruction CODE.
+    /// PREV is the preceding instruction if it references the same label, since the two may form
+    /// a pc-relative pair. Returns None if the instruction cannot be relocated.
+    fn relocation(_code: u32, _prev: Option<u32>) -> Option<Relocation> {
+        None
+    }
+}
+
+/// Describes how a linker fills in a label referenced by an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    /// The ELF relocation type, e.g. R_RISCV_JAL.
+    pub r_type: u32,
+    /// The machine code of the instruction with the field holding the label zeroed.
+    pub code: u32,
+    /// Whether the relocation refers to the preceding instruction rather than to the label, as
+    /// the lower half of a pc-relative pair does.
+    pub refers_to_prev: bool,
+}
+
+/// A word that does not encode any instruction supported by the architecture.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IllegalInstruction {
+    pub code: u32,
+}
+
+impl fmt::Display for IllegalInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "illegal instruction {:#010x}", self.code)
+    }
+}
@@ -7,5 +7,7 @@ pub mod architectures;
 pub mod assembler;
 pub mod config;
 pub mod data_structures;
+pub mod elf;
+pub mod image;
 pub mod instruction;
 pub mod program_state;
@@ -0,0 +1,23 @@
+//! Error numbers returned by syscalls, negated, when they fail.
+//! See https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/errno-base.h and
+//! errno.h beside it.
+
+pub const ENOENT: i64 = 2;
+pub const EIO: i64 = 5;
+pub const EBADF: i64 = 9;
+pub const ECHILD: i64 = 10;
+pub const EAGAIN: i64 = 11;
+pub const ENOMEM: i64 = 12;
+pub const EFAULT: i64 = 14;
+pub const EEXIST: i64 = 17;
+pub const ENODEV: i64 = 19;
+pub const ENOTDIR: i64 = 20;
+pub const EISDIR: i64 = 21;
+pub const EINVAL: i64 = 22;
+pub const EMFILE: i64 = 24;
+pub const EFBIG: i64 = 27;
+pub const ESPIPE: i64 = 29;
+pub const EROFS: i64 = 30;
+pub const EPIPE: i64 = 32;
+pub const ENAMETOOLONG: i64 = 36;
+pub const ENOSYS: i64 = 38;
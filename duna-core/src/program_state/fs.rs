@@ -0,0 +1,358 @@
+//! Implements the syscalls that operate on file descriptors.
+
+use super::{
+    errno::*,
+    priv_s::PrivDiff,
+    program::{DiffStack, ProgramState, SyscallConvention},
+    syscall::*,
+    vfs::*,
+};
+use crate::{arch::*, data_structures::*};
+use num_traits::cast::AsPrimitive;
+
+/// Reads a register as a file descriptor. Negative values become indices that are never open.
+fn fd_index<S: DataWidth>(fd: RegValue<S>) -> usize {
+    AsPrimitive::<usize>::as_(fd.as_unsigned().raw())
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
+    /// Opens the file at a path, and returns the lowest file descriptor not already open.
+    /// * dirfd - the directory that a relative path is resolved from, or AT_FDCWD for the root
+    /// * pathname - pointer to the null-terminated path
+    /// * flags - how the file is opened, and whether it is created or truncated
+    /// * mode - the permissions of a created file, which are ignored
+    pub(super) fn syscall_openat(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        self.openat(args.signed(0), args.addr(1), args.unsigned(2))
+    }
+
+    /// Behaves like openat with AT_FDCWD, so a relative path is resolved from the root.
+    /// * pathname, flags, mode - see openat
+    pub(super) fn syscall_open(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        self.openat(AT_FDCWD, args.addr(0), args.unsigned(1))
+    }
+
+    fn openat(&self, dirfd: i64, pathname: ByteAddrValue<S>, flags: u64) -> SyscallResult<F, S> {
+        let (name, mut diffs) = self.memory_get_cstring(pathname)?;
+        let name = String::from_utf8_lossy(&name).into_owned();
+        if name.is_empty() {
+            return Err(ENOENT);
+        }
+        let vfs = &self.priv_state.vfs;
+        let dir = if name.starts_with('/') || dirfd == AT_FDCWD {
+            "/".to_string()
+        } else {
+            match usize::try_from(dirfd)
+                .ok()
+                .and_then(|fd| self.priv_state.open_file(fd))
+            {
+                Some(OpenFile::Vfs { path, inode, .. }) if vfs.inode(*inode).is_dir() => {
+                    path.clone()
+                }
+                Some(_) => return Err(ENOTDIR),
+                None => return Err(EBADF),
+            }
+        };
+        let path = normalize(&dir, &name);
+        let flags = <F::Syscalls as SyscallConvention<F, S>>::open_flags(flags);
+        let inode = match vfs.lookup(&path) {
+            Some(ino) => {
+                let node = vfs.inode(ino);
+                if flags.create && flags.excl {
+                    return Err(EEXIST);
+                } else if node.is_dir() && flags.writable {
+                    return Err(EISDIR);
+                } else if !node.is_dir() && flags.directory {
+                    return Err(ENOTDIR);
+                } else if node.readonly && flags.writable {
+                    return Err(EROFS);
+                }
+                if flags.trunc && flags.writable && !node.data().is_empty() {
+                    diffs.push(
+                        PrivDiff::FileSplice {
+                            inode: ino,
+                            offset: 0,
+                            old: node.data().to_vec(),
+                            new: Vec::new(),
+                        }
+                        .into_state_diff(),
+                    );
+                }
+                ino
+            }
+            None => {
+                if !flags.create {
+                    return Err(ENOENT);
+                }
+                let dir = vfs.lookup(parent(&path)).ok_or(ENOENT)?;
+                if !vfs.inode(dir).is_dir() {
+                    return Err(ENOTDIR);
+                } else if vfs.inode(dir).readonly {
+                    return Err(EROFS);
+                }
+                let ino = vfs.next_inode();
+                diffs.push(
+                    PrivDiff::FileCreate {
+                        path: path.clone(),
+                        inode: ino,
+                    }
+                    .into_state_diff(),
+                );
+                ino
+            }
+        };
+        let fd = (0..MAX_FDS)
+            .find(|&fd| self.priv_state.open_file(fd).is_none())
+            .ok_or(EMFILE)?;
+        let description = self.priv_state.offsets.len();
+        diffs.push(PrivDiff::DescriptionCreate.into_state_diff());
+        let file = OpenFile::Vfs {
+            path,
+            inode,
+            description,
+            readable: flags.readable,
+            writable: flags.writable,
+            append: flags.append,
+        };
+        diffs.push(
+            PrivDiff::FdUpdate {
+                fd,
+                old: None,
+                new: Some(file),
+            }
+            .into_state_diff(),
+        );
+        Ok(SyscallReturn::new(diffs, fd as i64))
+    }
+
+    /// Closes a file descriptor, so that it may be reused.
+    /// * fd - file descriptor
+    pub(super) fn syscall_close(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let fd = args.fd(0);
+        let file = self.priv_state.open_file(fd).ok_or(EBADF)?;
+        let update = PrivDiff::FdUpdate {
+            fd,
+            old: Some(file.clone()),
+            new: None,
+        };
+        Ok(SyscallReturn::new(vec![update.into_state_diff()], 0))
+    }
+
+    /// Reads up to LEN bytes from a file descriptor into a buffer, and returns the number of bytes
+    /// read. Reads from stdin or an empty pipe block until input is available, and every read
+    /// returns 0 at the end of its file.
+    /// * fd - file descriptor
+    /// * buf - pointer to the buffer to be filled
+    /// * len - the maximum number of bytes to read
+    pub(super) fn syscall_read(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let (fd, buf, count) = (args.reg(0), args.addr(1), args.size(2));
+        let file = self.priv_state.open_file(fd_index(fd));
+        let (data, update) = match file {
+            Some(OpenFile::Stdin) => {
+                let data = self.priv_state.stdin.peek(count).map_err(|_| EIO)?;
+                let update = PrivDiff::FileRead {
+                    fd,
+                    data: data.clone(),
+                };
+                (data, update)
+            }
+            Some(OpenFile::Vfs {
+                inode,
+                description,
+                readable: true,
+                ..
+            }) => {
+                let offset = self.priv_state.offsets[*description];
+                let node = self.priv_state.vfs.inode(*inode);
+                if node.is_dir() {
+                    return Err(EISDIR);
+                }
+                let contents = node.data();
+                let start = (offset as usize).min(contents.len());
+                let end = start.saturating_add(count).min(contents.len());
+                let data = contents[start..end].to_vec();
+                (
+                    data,
+                    self.seek_update(*description, offset + (end - start) as u64),
+                )
+            }
+            Some(OpenFile::PipeRead { pipe, nonblock }) => {
+                return self.pipe_read(*pipe, *nonblock, buf, count);
+            }
+            _ => return Err(EBADF),
+        };
+        // nothing is consumed if the buffer is invalid
+        let mut diffs = self.memory_set_bytes(buf, &data).map_err(|_| EFAULT)?;
+        diffs.push(update.into_state_diff());
+        Ok(SyscallReturn::new(diffs, data.len() as i64))
+    }
+
+    /// Writes LEN bytes from a buffer to a file descriptor, and returns the number of bytes
+    /// written. Writes to a file in the filesystem begin at its offset, or at its end if it was
+    /// opened for appending, and grow it as needed up to MAX_FILE_SIZE, past which they are cut
+    /// short. Writes to a full pipe block until it has room.
+    /// * fd - file descriptor
+    /// * buf - pointer to the buffer to be written
+    /// * len - the number of bytes to write
+    pub(super) fn syscall_write(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let (fd, buf, count) = (args.reg(0), args.addr(1), args.size(2));
+        let file = match self.priv_state.open_file(fd_index(fd)) {
+            Some(file @ (OpenFile::Stdout | OpenFile::Stderr)) => file,
+            Some(file @ OpenFile::Vfs { writable: true, .. }) => file,
+            Some(OpenFile::PipeWrite { pipe, nonblock }) => {
+                return self.pipe_write(*pipe, *nonblock, buf, count);
+            }
+            _ => return Err(EBADF),
+        };
+        if let OpenFile::Vfs {
+            inode,
+            description,
+            append,
+            ..
+        } = file
+        {
+            let contents = self.priv_state.vfs.inode(*inode).data();
+            let size = contents.len() as u64;
+            let offset = if *append {
+                size
+            } else {
+                self.priv_state.offsets[*description]
+            };
+            if offset >= MAX_FILE_SIZE {
+                return Err(EFBIG);
+            }
+            let count = count.min((MAX_FILE_SIZE - offset) as usize);
+            let (data, mut diffs) = self.memory_get_bytes(buf, count).map_err(|_| EFAULT)?;
+            // writing past the end of the file fills the gap with zeroes
+            let start = offset.min(size);
+            let mut new = vec![0; (offset - start) as usize];
+            new.extend(&data);
+            let end = (start as usize + new.len()).min(contents.len());
+            let old = contents[start as usize..end].to_vec();
+            diffs.push(
+                PrivDiff::FileSplice {
+                    inode: *inode,
+                    offset: start,
+                    old,
+                    new,
+                }
+                .into_state_diff(),
+            );
+            diffs.push(
+                self.seek_update(*description, offset + count as u64)
+                    .into_state_diff(),
+            );
+            Ok(SyscallReturn::new(diffs, count as i64))
+        } else {
+            let (data, mut diffs) = self.memory_get_bytes(buf, count).map_err(|_| EFAULT)?;
+            diffs.push(PrivDiff::FileWrite { fd, data }.into_state_diff());
+            Ok(SyscallReturn::new(diffs, count as i64))
+        }
+    }
+
+    /// Moves the offset of a file descriptor, and returns the new offset.
+    /// * fd - file descriptor
+    /// * offset - the offset relative to the position given by whence
+    /// * whence - SEEK_SET for the start of the file, SEEK_CUR for the current offset, or
+    ///   SEEK_END for the end of the file
+    pub(super) fn syscall_lseek(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let (new_offset, diffs) = self.lseek(args.reg(0), args.signed(1), args.unsigned(2))?;
+        Ok(SyscallReturn::new(diffs, new_offset as i64))
+    }
+
+    /// Behaves like lseek, except that the 64-bit offset is split across two registers and the new
+    /// offset is stored to memory, which lets 32-bit architectures seek past 4 GiB. Returns 0.
+    /// * fd - file descriptor
+    /// * offset_high - the upper 32 bits of the offset
+    /// * offset_low - the lower 32 bits of the offset
+    /// * result - pointer to the 64-bit word that receives the new offset
+    /// * whence - see lseek
+    pub(super) fn syscall_llseek(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let offset = ((args.unsigned(1) << 32) | (args.unsigned(2) & 0xFFFF_FFFF)) as i64;
+        let (new_offset, mut diffs) = self.lseek(args.reg(0), offset, args.unsigned(4))?;
+        let stored = self
+            .memory_set_bytes(args.addr(3), &new_offset.to_le_bytes())
+            .map_err(|_| EFAULT)?;
+        diffs.extend(stored);
+        Ok(SyscallReturn::new(diffs, 0))
+    }
+
+    fn lseek(
+        &self,
+        fd: RegValue<S>,
+        offset: i64,
+        whence: u64,
+    ) -> Result<(u64, DiffStack<F, S>), i64> {
+        let (inode, description) = match self.priv_state.open_file(fd_index(fd)) {
+            Some(OpenFile::Vfs {
+                inode, description, ..
+            }) => (*inode, *description),
+            Some(_) => return Err(ESPIPE),
+            None => return Err(EBADF),
+        };
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => self.priv_state.offsets[description],
+            SEEK_END => self.priv_state.vfs.inode(inode).data().len() as u64,
+            _ => return Err(EINVAL),
+        };
+        let new_offset = (base as i64).checked_add(offset).ok_or(EINVAL)?;
+        if new_offset < 0 {
+            return Err(EINVAL);
+        }
+        let update = self.seek_update(description, new_offset as u64);
+        Ok((new_offset as u64, vec![update.into_state_diff()]))
+    }
+
+    /// Describes the file a file descriptor refers to.
+    /// * fd - file descriptor
+    /// * statbuf - pointer to the struct stat to be filled
+    pub(super) fn syscall_fstat(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let stat = match self.priv_state.open_file(args.fd(0)) {
+            Some(OpenFile::Vfs { inode, .. }) => {
+                let node = self.priv_state.vfs.inode(*inode);
+                let (kind, perms) = if node.is_dir() {
+                    (S_IFDIR, 0o755)
+                } else {
+                    (S_IFREG, 0o644)
+                };
+                let size = node.data().len() as u64;
+                Stat {
+                    ino: *inode as u64 + 1,
+                    // nobody may write to files copied from the host
+                    mode: kind | if node.readonly { perms & 0o555 } else { perms },
+                    nlink: 1,
+                    size,
+                    blksize: 4096,
+                    blocks: size.div_ceil(512),
+                }
+            }
+            Some(OpenFile::PipeRead { .. } | OpenFile::PipeWrite { .. }) => Stat {
+                mode: S_IFIFO | 0o600,
+                nlink: 1,
+                blksize: 4096,
+                ..Default::default()
+            },
+            Some(_) => Stat {
+                mode: S_IFCHR | 0o620,
+                nlink: 1,
+                blksize: 1024,
+                ..Default::default()
+            },
+            None => return Err(EBADF),
+        };
+        let bytes = <F::Syscalls as SyscallConvention<F, S>>::stat_bytes(&stat);
+        let diffs = self
+            .memory_set_bytes(args.addr(1), &bytes)
+            .map_err(|_| EFAULT)?;
+        Ok(SyscallReturn::new(diffs, 0))
+    }
+
+    /// Returns the diff that moves open file description DESCRIPTION to OFFSET.
+    fn seek_update(&self, description: usize, offset: u64) -> PrivDiff<S> {
+        PrivDiff::Seek {
+            description,
+            old: self.priv_state.offsets[description],
+            new: offset,
+        }
+    }
+}
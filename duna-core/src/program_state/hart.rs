@@ -0,0 +1,346 @@
+//! Models several harts, or hardware threads, that run the same program in a single address
+//! space.
+//!
+//! Each hart has its own registers, program counter, and control registers, while memory and
+//! everything the kernel keeps track of is shared. As with processes, the context of the running
+//! hart lives in the user and privileged state, and those of the others are saved in the hart
+//! table. After every instruction, a deterministic scheduler picks the hart that runs the next
+//! one, so that the interleavings that expose data races can be reproduced and stepped through.
+//!
+//! Harts synchronize through shared memory with the lr.w, sc.w, and AMO instructions. Each hart
+//! may reserve one word with lr.w, which it loses once any hart stores to that word; every access
+//! is sequentially consistent, so the ordering bits of these instructions have no effect.
+//!
+//! Every hart runs in the first process, which the kernel cannot suspend without suspending all
+//! of them. So with more than one hart, a syscall that would block, such as a read from an empty
+//! pipe, fails with EAGAIN instead, and harts must wait on one another by spinning on memory.
+//! Since only the running hart would be copied, fork and clone fail with ENOSYS, and so wait4
+//! always fails with ECHILD rather than waiting for another hart to exit.
+
+use super::{
+    priv_s::TermCause,
+    program::{DiffStack, ProgramState, StateDiff},
+    sysinfo::{next_random, DEFAULT_SEED},
+    user::UserState,
+};
+use crate::{arch::*, data_structures::*};
+use std::{collections::HashMap, mem};
+
+/// The number of the control register that holds the ID of the hart reading it.
+pub const MHARTID: usize = 0xF14;
+
+/// Determines which hart runs after each instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum HartSchedule {
+    /// Switches to the next hart in order of ID.
+    #[default]
+    RoundRobin,
+    /// Switches to a hart chosen by a random number generator with the given seed, which may be
+    /// the one that ran last.
+    Random { seed: u64 },
+}
+
+/// The parts of the program state that belong to a single hart.
+struct HartContext<F: ArchFamily<S>, S: DataWidth> {
+    user_state: UserState<F, S>,
+    csrs: HashMap<usize, RegValue<S>>,
+}
+
+struct Hart<F: ArchFamily<S>, S: DataWidth> {
+    /// Whether the hart has exited. Only harts other than the first may, since the program ends
+    /// when the first one exits.
+    halted: bool,
+    /// The address of the word reserved by the last lr.w, if no store has been made to it since.
+    reservation: Option<u64>,
+    /// The saved context of the hart, which is none while it runs.
+    context: Option<HartContext<F, S>>,
+}
+
+/// Tracks every hart, and which one is running.
+pub struct HartTable<F: ArchFamily<S>, S: DataWidth> {
+    harts: Vec<Hart<F, S>>,
+    current: u32,
+    /// The number of harts the program starts with.
+    count: u32,
+    schedule: HartSchedule,
+    /// The state of the random number generator of a random schedule.
+    rng: u64,
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> Default for HartTable<F, S> {
+    fn default() -> Self {
+        let mut table = HartTable {
+            harts: Vec::new(),
+            current: 0,
+            count: 1,
+            schedule: HartSchedule::default(),
+            rng: DEFAULT_SEED,
+        };
+        table.reset();
+        table
+    }
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> HartTable<F, S> {
+    /// Discards every hart but the first, which is made to run. The other harts are added again
+    /// when the program is loaded.
+    pub fn reset(&mut self) {
+        self.harts.clear();
+        self.harts.push(Hart {
+            halted: false,
+            reservation: None,
+            context: None,
+        });
+        self.current = 0;
+        self.rng = match self.schedule {
+            HartSchedule::RoundRobin => DEFAULT_SEED,
+            HartSchedule::Random { seed } => seed,
+        };
+    }
+
+    /// Returns the number of harts the program starts with.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the ID of the next hart to run after the running one, or the running one if no
+    /// other can run, along with the new state of the random number generator.
+    fn next(&self) -> (u32, u64) {
+        let runnable: Vec<u32> = (0..self.harts.len() as u32)
+            .filter(|&id| !self.harts[id as usize].halted)
+            .collect();
+        match self.schedule {
+            HartSchedule::RoundRobin => {
+                let next = runnable
+                    .iter()
+                    .copied()
+                    .find(|&id| id > self.current)
+                    .or_else(|| runnable.first().copied())
+                    .unwrap_or(self.current);
+                (next, self.rng)
+            }
+            HartSchedule::Random { .. } => {
+                let (rng, bytes) = next_random(self.rng);
+                let pick = u64::from_le_bytes(bytes) % runnable.len() as u64;
+                (runnable[pick as usize], rng)
+            }
+        }
+    }
+}
+
+/// Encodes a change to the hart table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HartDiff {
+    /// Represents the scheduler choosing the hart that runs the next instruction, which saves
+    /// the context of one hart and restores that of the other if they differ.
+    Schedule {
+        from: u32,
+        to: u32,
+        old_rng: u64,
+        new_rng: u64,
+    },
+    /// Represents a hart other than the first exiting.
+    Halt { hart: u32 },
+    /// Represents a hart reserving a word with lr.w, or losing its reservation.
+    Reserve {
+        hart: u32,
+        old: Option<u64>,
+        new: Option<u64>,
+    },
+}
+
+impl HartDiff {
+    pub fn into_state_diff<F: ArchFamily<S>, S: DataWidth>(self) -> StateDiff<F, S> {
+        StateDiff::Hart(self)
+    }
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
+    /// Sets how many harts the program starts with, and how they take turns. Takes effect when
+    /// the program is next reset.
+    pub fn set_harts(&mut self, count: u32, schedule: HartSchedule) {
+        self.harts.count = count.max(1);
+        self.harts.schedule = schedule;
+    }
+
+    /// Adds a hart for every context in USER_STATES after the first, which is running. Each hart
+    /// reads its position in the list from mhartid.
+    pub(super) fn start_harts(&mut self, user_states: Vec<UserState<F, S>>) {
+        self.priv_state.csrs.clear();
+        for (id, user_state) in user_states.into_iter().enumerate().skip(1) {
+            let mhartid = RegValue::<S>::from(id as i64);
+            let csrs = [(MHARTID, mhartid)].into_iter().collect();
+            self.harts.harts.push(Hart {
+                halted: false,
+                reservation: None,
+                context: Some(HartContext { user_state, csrs }),
+            });
+        }
+    }
+
+    /// Saves the context of hart FROM, which is running, and restores that of TO.
+    fn switch_hart(&mut self, from: u32, to: u32) {
+        if from == to {
+            return;
+        }
+        let harts = &mut self.harts.harts;
+        let mut ctx = harts[to as usize]
+            .context
+            .take()
+            .expect("only harts that are not running are switched to");
+        mem::swap(&mut self.user_state, &mut ctx.user_state);
+        mem::swap(&mut self.priv_state.csrs, &mut ctx.csrs);
+        harts[from as usize].context = Some(ctx);
+        self.harts.current = to;
+    }
+
+    pub(super) fn apply_hart_diff(&mut self, diff: &HartDiff) {
+        match *diff {
+            HartDiff::Schedule {
+                from, to, new_rng, ..
+            } => {
+                self.switch_hart(from, to);
+                self.harts.rng = new_rng;
+            }
+            HartDiff::Halt { hart } => self.harts.harts[hart as usize].halted = true,
+            HartDiff::Reserve { hart, new, .. } => {
+                self.harts.harts[hart as usize].reservation = new
+            }
+        }
+    }
+
+    pub(super) fn revert_hart_diff(&mut self, diff: &HartDiff) {
+        match *diff {
+            HartDiff::Schedule {
+                from, to, old_rng, ..
+            } => {
+                self.switch_hart(to, from);
+                self.harts.rng = old_rng;
+            }
+            HartDiff::Halt { hart } => self.harts.harts[hart as usize].halted = false,
+            HartDiff::Reserve { hart, old, .. } => {
+                self.harts.harts[hart as usize].reservation = old
+            }
+        }
+    }
+
+    /// Returns the ID of the running hart.
+    pub fn running_hart(&self) -> u32 {
+        self.harts.current
+    }
+
+    /// Returns the ID of every hart, along with whether it has halted.
+    pub fn harts(&self) -> Vec<(u32, bool)> {
+        (0..)
+            .zip(&self.harts.harts)
+            .map(|(id, hart)| (id, hart.halted))
+            .collect()
+    }
+
+    /// Returns the registers and program counter of a hart, whether or not it is running.
+    pub fn hart_user_state(&self, id: u32) -> Option<&UserState<F, S>> {
+        if id == self.harts.current {
+            return Some(&self.user_state);
+        }
+        let hart = self.harts.harts.get(id as usize)?;
+        hart.context.as_ref().map(|ctx| &ctx.user_state)
+    }
+
+    /// Returns whether the program has more than one hart, in which case every hart shares the
+    /// first process and fork is not supported.
+    pub(super) fn is_multihart(&self) -> bool {
+        self.harts.harts.len() > 1
+    }
+
+    /// Returns whether a hart other than the first has run off the end of the text section,
+    /// which makes it halt as though it had exited.
+    pub(super) fn hart_has_returned(&self) -> bool {
+        self.harts.current != 0
+            && self
+                .priv_state
+                .layout
+                .is_some_and(|layout| self.user_state.pc.bits() == layout.text.end())
+    }
+
+    /// Returns the diffs that end the running hart for CAUSE. A hart other than the first halts
+    /// when it exits; otherwise, the whole process ends.
+    pub(super) fn end_hart(&self, cause: TermCause) -> DiffStack<F, S> {
+        match cause {
+            TermCause::Exit(_) if self.harts.current != 0 => {
+                let hart = self.harts.current;
+                vec![HartDiff::Halt { hart }.into_state_diff()]
+            }
+            _ => self.end_process(cause),
+        }
+    }
+
+    /// Returns the diff that makes the running hart reserve the word at ADDR.
+    pub fn reserve(&self, addr: u64) -> StateDiff<F, S> {
+        let hart = self.harts.current;
+        HartDiff::Reserve {
+            hart,
+            old: self.harts.harts[hart as usize].reservation,
+            new: Some(addr),
+        }
+        .into_state_diff()
+    }
+
+    /// Returns whether the running hart holds a reservation on the word at ADDR.
+    pub fn holds_reservation(&self, addr: u64) -> bool {
+        self.harts.harts[self.harts.current as usize].reservation == Some(addr)
+    }
+
+    /// Returns the diffs that take away the reservations on any word overlapping the LEN bytes
+    /// at ADDR, which are being stored to.
+    pub fn release_reservations(&self, addr: u64, len: u64) -> DiffStack<F, S> {
+        (0..)
+            .zip(&self.harts.harts)
+            .filter_map(|(hart, h)| match h.reservation {
+                Some(word) if word < addr.saturating_add(len) && addr < word + 4 => Some(
+                    HartDiff::Reserve {
+                        hart,
+                        old: Some(word),
+                        new: None,
+                    }
+                    .into_state_diff(),
+                ),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the diff that takes away the reservation of the running hart, if it has one.
+    pub fn release_own_reservation(&self) -> DiffStack<F, S> {
+        let hart = self.harts.current;
+        match self.harts.harts[hart as usize].reservation {
+            Some(word) => vec![HartDiff::Reserve {
+                hart,
+                old: Some(word),
+                new: None,
+            }
+            .into_state_diff()],
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the diff that switches to the hart that runs the next instruction, if there is
+    /// more than one.
+    pub(super) fn schedule_hart(&self) -> DiffStack<F, S> {
+        if !self.is_multihart() {
+            return Vec::new();
+        }
+        let harts = &self.harts;
+        let from = harts.current;
+        let (to, new_rng) = harts.next();
+        if to == from && new_rng == harts.rng {
+            return Vec::new();
+        }
+        vec![HartDiff::Schedule {
+            from,
+            to,
+            old_rng: harts.rng,
+            new_rng,
+        }
+        .into_state_diff()]
+    }
+}
@@ -0,0 +1,165 @@
+//! Describes where the linker places each section of a program in memory.
+
+use crate::{assembler::ProgramSection, config::SegmentStarts};
+use std::fmt;
+
+/// A contiguous range of memory holding one section of a program.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// The address of the first byte of the region. Always aligned to a page boundary.
+    pub start: u64,
+    /// The length of the region in bytes, which is not padded to a multiple of the page size.
+    pub len: u64,
+    /// Whether the program may store to this region.
+    pub writable: bool,
+}
+
+impl Region {
+    /// Returns the address one past the last byte of the region.
+    pub fn end(&self) -> u64 {
+        self.start + self.len
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        self.start <= addr && addr < self.end()
+    }
+}
+
+/// The placement of the sections of a linked program in memory.
+///
+/// Sections are placed in the order text, rodata, data, bss, each starting on its own page so
+/// that read-only sections can be protected without affecting their neighbors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ProgramLayout {
+    pub text: Region,
+    pub rodata: Region,
+    pub data: Region,
+    pub bss: Region,
+    /// The first page after the static sections, where the heap begins.
+    pub heap_start: u64,
+    /// The initial value of the stack pointer.
+    pub stack_start: u64,
+    pub page_size: u64,
+}
+
+impl ProgramLayout {
+    /// Lays out sections of the provided lengths in bytes.
+    ///
+    /// The text section begins at the configured text start. Rodata begins on the page after the
+    /// end of text, and data begins at the configured data start unless rodata extends past it, in
+    /// which case it begins on the next page after rodata. Bss begins on the page after data.
+    pub fn new(
+        segment_starts: &SegmentStarts,
+        pg_ofs_bits: usize,
+        text_len: usize,
+        rodata_len: usize,
+        data_len: usize,
+        bss_len: usize,
+    ) -> Self {
+        let page_size = 1u64 << pg_ofs_bits;
+        let page_align = |addr: u64| addr.div_ceil(page_size) * page_size;
+        let region = |start: u64, len: usize, writable: bool| Region {
+            start: page_align(start),
+            len: len as u64,
+            writable,
+        };
+        let text = region(segment_starts.text_start, text_len, false);
+        let rodata = region(text.end(), rodata_len, false);
+        let data = region(segment_starts.data_start.max(rodata.end()), data_len, true);
+        let bss = region(data.end(), bss_len, true);
+        ProgramLayout {
+            text,
+            rodata,
+            data,
+            bss,
+            heap_start: page_align(bss.end()),
+            stack_start: segment_starts.stack_start,
+            page_size,
+        }
+    }
+
+    /// Returns the region occupied by a section.
+    pub fn section(&self, section: ProgramSection) -> Region {
+        use ProgramSection::*;
+        match section {
+            Text => self.text,
+            Rodata => self.rodata,
+            Data => self.data,
+            Bss => self.bss,
+        }
+    }
+
+    /// Returns the regions of all sections, in order of increasing address.
+    pub fn regions(&self) -> [(ProgramSection, Region); 4] {
+        use ProgramSection::*;
+        [Text, Rodata, Data, Bss].map(|section| (section, self.section(section)))
+    }
+
+    /// Returns the start address of every page overlapping the region.
+    pub fn pages(&self, region: Region) -> impl Iterator<Item = u64> {
+        let first_page = region.start / self.page_size * self.page_size;
+        (first_page..region.end()).step_by(self.page_size as usize)
+    }
+
+    /// Returns whether the address falls in a page that may not be stored to.
+    pub fn is_readonly(&self, addr: u64) -> bool {
+        self.regions().iter().any(|(_, region)| {
+            !region.writable
+                && region.start <= addr
+                && addr < region.end().div_ceil(self.page_size) * self.page_size
+        })
+    }
+}
+
+impl fmt::Display for ProgramLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<8} {:>18} {:>18} {:>10}  perms",
+            "section", "start", "end", "size"
+        )?;
+        for (section, region) in self.regions().iter() {
+            writeln!(
+                f,
+                "{:<8} {:>#18x} {:>#18x} {:>#10x}  r{}{}",
+                format!(".{}", section),
+                region.start,
+                region.end(),
+                region.len,
+                if region.writable { "w" } else { "-" },
+                if *section == ProgramSection::Text {
+                    "x"
+                } else {
+                    "-"
+                },
+            )?;
+        }
+        writeln!(f, "{:<8} {:>#18x}", "heap", self.heap_start)?;
+        write!(f, "{:<8} {:>#18x}", "stack", self.stack_start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let starts = SegmentStarts::default();
+        let layout = ProgramLayout::new(&starts, 12, 0x1004, 3, 0x10, 0x2001);
+        assert_eq!(layout.text.start, 0x1000_0000);
+        assert_eq!(layout.rodata.start, 0x1000_2000);
+        assert_eq!(layout.data.start, 0x2000_0000);
+        assert_eq!(layout.bss.start, 0x2000_1000);
+        assert_eq!(layout.heap_start, 0x2000_4000);
+        assert_eq!(layout.pages(layout.bss).count(), 3);
+        // the rest of a read-only page is also protected
+        assert!(layout.is_readonly(0x1000_2FFC));
+        assert!(!layout.is_readonly(0x1000_3000));
+        assert!(!layout.is_readonly(0x2000_0000));
+        // rodata that runs past the configured data start pushes data back
+        let layout = ProgramLayout::new(&starts, 12, 4, 0x1000_0000, 4, 0);
+        assert_eq!(layout.data.start, 0x2000_1000);
+        assert_eq!(layout.pages(layout.bss).count(), 0);
+    }
+}
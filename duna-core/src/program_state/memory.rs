@@ -30,6 +30,12 @@ pub enum PtUpdate {
     SwapRemove { vpn: VirtPn, pte: PtEntry },
     /// An entry in the free page bitmap was flipped.
     BitmapFlip(usize),
+    /// A physical page was freed, discarding its contents so that the next page mapped to it
+    /// reads as zero. The contents are kept so that the update can be reverted.
+    PageFree {
+        ppn: PhysPn,
+        contents: Option<MemPage>,
+    },
 }
 
 impl PtUpdate {
@@ -151,16 +157,27 @@ pub trait PageTable<S: DataWidth> {
     /// bits, SCL, etc.) must be performed through lookup_page.
     fn map_page(&self, vaddr: ByteAddrValue<S>) -> Result<Vec<PtUpdate>, MemFault<S>>;
 
+    /// Maps the pages containing each of the provided addresses, none of which may already be
+    /// mapped. Unlike calling map_page repeatedly, each page accounts for the updates needed to
+    /// map the ones before it, so the returned sequence can be applied all at once.
+    ///
+    /// If any page cannot be mapped, then a MemFault is returned and nothing is mapped.
+    fn map_pages(&self, vaddrs: &[ByteAddrValue<S>]) -> Result<Vec<PtUpdate>, MemFault<S>>;
+
     /// Unmaps the page containing the provided address.
     /// An implementor may choose to make this a noop, as in the case where all pags are considered
     /// mapped for simplicity.
-    fn unmap_page(&self, vaddr: ByteAddrValue<S>) -> Vec<PtUpdate>;
+    fn unmap_page(&self, mem: &PhysMem, vaddr: ByteAddrValue<S>) -> Vec<PtUpdate>;
 
     /// Looks up the page at the associated address. Raises a page fault if not found.
     ///
     /// If the page was already mapped, then the sequence will be empty unless touching a page
     /// updates state, like with a second chance list.
     fn lookup_page(&self, vaddr: ByteAddrValue<S>) -> Result<PtLookupData, MemFault<S>>;
+
+    /// Copies this page table, along with the mappings and bookkeeping it holds, for a forked
+    /// process.
+    fn clone_box(&self) -> Box<dyn PageTable<S>>;
 }
 
 /// A simple memory of a single page; all addresses except the null address are considered to be
@@ -199,10 +216,21 @@ impl<S: DataWidth> PageTable<S> for AllMappedPt<S> {
         }
     }
 
-    fn unmap_page(&self, _vaddr: ByteAddrValue<S>) -> Vec<PtUpdate> {
+    fn map_pages(&self, vaddrs: &[ByteAddrValue<S>]) -> Result<Vec<PtUpdate>, MemFault<S>> {
+        for &vaddr in vaddrs {
+            self.map_page(vaddr)?;
+        }
+        Ok(vec![])
+    }
+
+    fn unmap_page(&self, _mem: &PhysMem, _vaddr: ByteAddrValue<S>) -> Vec<PtUpdate> {
         vec![]
     }
 
+    fn clone_box(&self) -> Box<dyn PageTable<S>> {
+        Box::new(AllMappedPt::new())
+    }
+
     fn lookup_page(&self, vaddr: ByteAddrValue<S>) -> Result<PtLookupData, MemFault<S>> {
         let bits = vaddr.bits();
         if bits != 0 {
@@ -229,6 +257,7 @@ impl<S: DataWidth> Default for AllMappedPt<S> {
 /// address space with 4 KiB pages would require 2 ^ (32 - 12) entries.
 ///
 /// The zero page is never considered mapped, and all accesses must be aligned.
+#[derive(Clone)]
 pub struct FifoLinearPt<S> {
     page_offs_len: usize,
     page_table: HashMap<VirtPn, PtEntry>,
@@ -244,9 +273,9 @@ impl<S: DataWidth> FifoLinearPt<S> {
     /// Initializes the memory. The number of pages is computed from the physical address and
     /// page sizes.
     /// * phys_pn_bits: The number of bits needed to address a physical page. The number of pages of
-    ///                 available physical memory is given by 2 to the power of this number.
-    /// * pg_ofs_bits:  The number of bits needed to index a page. The number of bytes in a page is
-    ///                 likewise 2 to the power of this number.
+    ///   available physical memory is given by 2 to the power of this number.
+    /// * pg_ofs_bits: The number of bits needed to index a page. The number of bytes in a page is
+    ///   likewise 2 to the power of this number.
     pub fn new(phys_pn_bits: usize, pg_ofs_bits: usize) -> Self {
         let phys_pg_count = 1 << phys_pn_bits;
         FifoLinearPt {
@@ -294,10 +323,41 @@ impl<S: DataWidth> PageTable<S> for FifoLinearPt<S> {
             &BitmapFlip(n) => {
                 self.freemap.flip(n);
             }
+            PageFree { ppn, .. } => {
+                mem.remove(ppn);
+            }
         }
     }
 
-    fn revert_update(&mut self, _mem: &mut PhysMem, _update: &PtUpdate) {}
+    fn revert_update(&mut self, mem: &mut PhysMem, update: &PtUpdate) {
+        use PtUpdate::*;
+        match update {
+            &Entry { vpn, old, .. } => {
+                self.page_table.insert(vpn, old);
+            }
+            Replacement(update) => match update {
+                ReplacementUpdate::ClockTick => {
+                    self.fifo_ctr -= 1;
+                }
+            },
+            SwapAdd { vpn, pte } => {
+                // The evicted page gets its physical page back
+                mem.insert(pte.ppn, self.swapfile.remove(vpn).unwrap().1);
+            }
+            &SwapRemove { vpn, pte } => {
+                let page = mem.remove(&pte.ppn).unwrap_or_default();
+                self.swapfile.insert(vpn, (pte, page));
+            }
+            &BitmapFlip(n) => {
+                self.freemap.flip(n);
+            }
+            PageFree { ppn, contents } => {
+                if let Some(page) = contents {
+                    mem.insert(*ppn, page.clone());
+                }
+            }
+        }
+    }
 
     fn reset(&mut self) {
         self.page_table.clear();
@@ -329,7 +389,11 @@ impl<S: DataWidth> PageTable<S> for FifoLinearPt<S> {
             diffs.push(PtUpdate::BitmapFlip(ppn));
             ppn
         } else {
-            let old_pte = self.page_table.get(&self.fifo_ctr).unwrap();
+            // Physical memory may be full of pages that the FIFO counter has not reached
+            let old_pte = self
+                .page_table
+                .get(&self.fifo_ctr)
+                .ok_or_else(|| MemFault::<S>::pagefault_at_addr(addr))?;
             // Send old page to swap and claim its ppn instead
             diffs.push(PtUpdate::SwapAdd {
                 vpn: self.fifo_ctr,
@@ -356,7 +420,37 @@ impl<S: DataWidth> PageTable<S> for FifoLinearPt<S> {
         Ok(diffs)
     }
 
-    fn unmap_page(&self, addr: ByteAddrValue<S>) -> Vec<PtUpdate> {
+    /// Since pages are mapped one at a time, running out of physical memory is an error rather
+    /// than a reason to evict pages mapped earlier in the same call.
+    fn map_pages(&self, vaddrs: &[ByteAddrValue<S>]) -> Result<Vec<PtUpdate>, MemFault<S>> {
+        let mut freemap = self.freemap.clone();
+        let mut diffs = Vec::new();
+        for &vaddr in vaddrs {
+            let vpn = self.get_vpn(vaddr);
+            if vpn == 0 {
+                return Err(MemFault::<S>::segfault_at_addr(vaddr));
+            }
+            let old = self.page_table.get(&vpn).copied().unwrap_or_default();
+            assert!(
+                !old.valid,
+                "Attempted to map already-mapped page at VPN {:?}",
+                vpn
+            );
+            let ppn = freemap
+                .get_lowest_zero()
+                .ok_or_else(|| MemFault::<S>::pagefault_at_addr(vaddr))?;
+            freemap.flip(ppn);
+            diffs.push(PtUpdate::BitmapFlip(ppn));
+            diffs.push(PtUpdate::Entry {
+                vpn,
+                old,
+                new: PtEntry { valid: true, ppn },
+            });
+        }
+        Ok(diffs)
+    }
+
+    fn unmap_page(&self, mem: &PhysMem, addr: ByteAddrValue<S>) -> Vec<PtUpdate> {
         // This screws up FIFO a little because if we unmap a page in the middle of the VAS
         // then that page should be evicted later...
         let mut diffs = Vec::new();
@@ -372,10 +466,18 @@ impl<S: DataWidth> PageTable<S> for FifoLinearPt<S> {
                 },
             });
             diffs.push(PtUpdate::BitmapFlip(old_pte.ppn));
+            diffs.push(PtUpdate::PageFree {
+                ppn: old_pte.ppn,
+                contents: mem.get(&old_pte.ppn).cloned(),
+            });
         }
         diffs
     }
 
+    fn clone_box(&self) -> Box<dyn PageTable<S>> {
+        Box::new(self.clone())
+    }
+
     fn lookup_page(&self, vaddr: ByteAddrValue<S>) -> Result<PtLookupData, MemFault<S>> {
         let vpn = self.get_vpn(vaddr);
         if vpn != 0 {
@@ -456,4 +558,19 @@ mod tests {
             MemFault::pagefault_at_addr(0u32.into())
         );
     }
+
+    /// Tests that mapping more pages than physical memory holds fails rather than panicking.
+    #[test]
+    fn test_linear_pt_full() {
+        // 4 KiB page size, 2 pages of physical memory
+        let mut pt = FifoLinearPt::<W32b>::new(1, 12);
+        let mut dummy_mem = Default::default();
+        assert!(pt.force_map_page(&mut dummy_mem, 0x1000u32.into()).is_ok());
+        assert!(pt.force_map_page(&mut dummy_mem, 0x2000u32.into()).is_ok());
+        assert_eq!(
+            pt.force_map_page(&mut dummy_mem, 0x5000u32.into())
+                .unwrap_err(),
+            MemFault::pagefault_at_addr(0x5000u32.into())
+        );
+    }
 }
@@ -0,0 +1,258 @@
+//! Implements the syscalls that manage the address space of a process: brk, mmap, and munmap.
+
+use super::{
+    errno::*,
+    memory::PtUpdate,
+    priv_s::PrivDiff,
+    program::{DiffStack, ProgramState, StateDiff, SyscallConvention},
+    syscall::*,
+};
+use crate::{arch::*, data_structures::*};
+
+/// Protection bits passed to mmap.
+pub const PROT_READ: u64 = 0x1;
+pub const PROT_WRITE: u64 = 0x2;
+pub const PROT_EXEC: u64 = 0x4;
+
+/// The space left below the top of the stack for it to grow into, which mmap never places pages
+/// in. This is the default stack size limit on Linux.
+pub const STACK_LIMIT: u64 = 8 << 20;
+
+/// The accesses allowed to a page mapped by mmap.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Prot {
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+}
+
+impl Prot {
+    /// Decodes the PROT_* bits passed to mmap. As on Linux, pages that can be written or
+    /// executed can also be read.
+    pub fn from_bits(raw: u64) -> Self {
+        Prot {
+            read: raw & (PROT_READ | PROT_WRITE | PROT_EXEC) != 0,
+            write: raw & PROT_WRITE != 0,
+            exec: raw & PROT_EXEC != 0,
+        }
+    }
+}
+
+/// The flags passed to mmap, decoded from the architecture's encoding.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MapFlags {
+    pub shared: bool,
+    pub private: bool,
+    /// Whether the mapping must be placed at exactly the requested address.
+    pub fixed: bool,
+    /// Whether the mapping is backed by zeroed memory rather than a file.
+    pub anonymous: bool,
+}
+
+impl MapFlags {
+    /// Decodes flags in the encoding shared by most architectures, including RISC-V.
+    /// See https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/mman-common.h.
+    pub fn from_generic(raw: u64) -> Self {
+        MapFlags {
+            shared: raw & 0x01 != 0,
+            private: raw & 0x02 != 0,
+            fixed: raw & 0x10 != 0,
+            anonymous: raw & 0x20 != 0,
+        }
+    }
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
+    fn page_size(&self) -> u64 {
+        self.priv_state
+            .layout
+            .expect("syscalls are only made by loaded programs")
+            .page_size
+    }
+
+    fn page_align(&self, addr: u64) -> u64 {
+        let page_size = self.page_size();
+        addr.div_ceil(page_size) * page_size
+    }
+
+    /// Returns the end of the pages holding the heap when the program break is at BRK. The first
+    /// page of the heap is always mapped, even while the heap is empty.
+    fn heap_end(&self, brk: u64) -> u64 {
+        let heap_start = self.priv_state.heap_start.bits();
+        self.page_align(brk).max(heap_start + self.page_size())
+    }
+
+    /// Returns the end of the region that mmap places pages in, which lies below the stack.
+    fn mmap_top(&self) -> u64 {
+        let stack_start = self.priv_state.layout.unwrap().stack_start;
+        let page_size = self.page_size();
+        stack_start.saturating_sub(STACK_LIMIT) / page_size * page_size
+    }
+
+    /// Returns the address of each page from START up to END.
+    fn pages(&self, start: u64, end: u64) -> Vec<ByteAddrValue<S>> {
+        (start..end)
+            .step_by(self.page_size() as usize)
+            .map(ByteAddrValue::<S>::from)
+            .collect()
+    }
+
+    /// Returns whether the pages from START up to END are free to be mapped by brk or mmap.
+    fn is_free(&self, start: u64, end: u64) -> bool {
+        let heap_end = self.heap_end(self.priv_state.brk.bits());
+        heap_end <= start
+            && start <= end
+            && end <= self.mmap_top()
+            && self.priv_state.mmaps.range(start..end).next().is_none()
+    }
+
+    /// Maps every page from START up to END, or returns none if physical memory runs out.
+    fn map_range(&self, start: u64, end: u64) -> Option<DiffStack<F, S>> {
+        let updates = self
+            .priv_state
+            .page_table
+            .map_pages(&self.pages(start, end))
+            .ok()?;
+        Some(updates.into_iter().map(PtUpdate::into_state_diff).collect())
+    }
+
+    /// Unmaps every page from START up to END.
+    fn unmap_range(&self, start: u64, end: u64) -> DiffStack<F, S> {
+        self.pages(start, end)
+            .into_iter()
+            .flat_map(|page| {
+                self.priv_state
+                    .page_table
+                    .unmap_page(&self.phys_state.phys_mem, page)
+            })
+            .map(PtUpdate::into_state_diff)
+            .collect()
+    }
+
+    /// Moves the program break, the end of the heap, to ADDR, mapping or unmapping every page the
+    /// heap gains or loses. Returns the new program break, or the old one if it cannot be moved,
+    /// so passing 0 queries the program break.
+    /// * addr - the new program break
+    pub(super) fn syscall_brk(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let (old, addr) = (self.priv_state.brk, args.addr(0));
+        Ok(match self.move_brk(old.bits(), addr.bits()) {
+            Some(mut diffs) => {
+                diffs.push(PrivDiff::BrkUpdate { old, new: addr }.into_state_diff());
+                SyscallReturn::new(diffs, addr.bits() as i64)
+            }
+            None => SyscallReturn::value(old.bits() as i64),
+        })
+    }
+
+    fn move_brk(&self, old: u64, new: u64) -> Option<DiffStack<F, S>> {
+        if new < self.priv_state.heap_start.bits() {
+            return None;
+        }
+        let old_end = self.heap_end(old);
+        let new_end = self.heap_end(new);
+        if new_end > old_end {
+            if !self.is_free(old_end, new_end) {
+                return None;
+            }
+            self.map_range(old_end, new_end)
+        } else {
+            Some(self.unmap_range(new_end, old_end))
+        }
+    }
+
+    /// Maps zeroed pages into the address space, and returns the address of the first. Only
+    /// anonymous mappings are supported, and shared mappings behave like private ones: fork gives
+    /// the child its own copy of every page, so a write to a MAP_SHARED mapping by the parent or
+    /// the child is not seen by the other. Unlike on Linux, a MAP_FIXED mapping never replaces
+    /// pages that are already mapped, and fails with EEXIST instead.
+    /// * addr - where to place the mapping, which is only a hint unless MAP_FIXED is given
+    /// * length - the number of bytes to map, which is rounded up to a whole number of pages
+    /// * prot - the PROT_* bits describing which accesses are allowed
+    /// * flags - the MAP_* bits describing the kind of mapping
+    pub(super) fn syscall_mmap(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let (addr, length) = (args.unsigned(0), args.unsigned(1));
+        let (prot, flags) = (args.unsigned(2), args.unsigned(3));
+        let flags = <F::Syscalls as SyscallConvention<F, S>>::map_flags(flags);
+        let page_size = self.page_size();
+        if length == 0 || flags.shared == flags.private {
+            return Err(EINVAL);
+        } else if !flags.anonymous {
+            return Err(ENODEV);
+        }
+        let len = length.checked_next_multiple_of(page_size).ok_or(ENOMEM)?;
+        let start = if flags.fixed {
+            if !addr.is_multiple_of(page_size) {
+                return Err(EINVAL);
+            }
+            let end = addr.checked_add(len).ok_or(ENOMEM)?;
+            if !self.is_free(addr, end) {
+                return Err(EEXIST);
+            }
+            addr
+        } else if addr.is_multiple_of(page_size)
+            && addr
+                .checked_add(len)
+                .is_some_and(|end| self.is_free(addr, end))
+        {
+            addr
+        } else {
+            self.find_gap(len).ok_or(ENOMEM)?
+        };
+        let mut diffs = self.map_range(start, start + len).ok_or(ENOMEM)?;
+        let prot = Prot::from_bits(prot);
+        for page in (start..start + len).step_by(page_size as usize) {
+            diffs.push(
+                PrivDiff::MmapUpdate {
+                    page,
+                    old: None,
+                    new: Some(prot),
+                }
+                .into_state_diff(),
+            );
+        }
+        Ok(SyscallReturn::new(diffs, start as i64))
+    }
+
+    /// Returns the highest address below the stack at which LEN bytes of pages are free.
+    fn find_gap(&self, len: u64) -> Option<u64> {
+        let page_size = self.page_size();
+        let mut end = self.mmap_top();
+        for &page in self.priv_state.mmaps.keys().rev() {
+            if page + page_size + len <= end {
+                break;
+            }
+            end = page;
+        }
+        let start = end.checked_sub(len)?;
+        self.is_free(start, end).then_some(start)
+    }
+
+    /// Removes the pages mapped by mmap in a range. Any pages in the range that were not mapped
+    /// by mmap are left alone.
+    /// * addr - the start of the range, which must be page-aligned
+    /// * length - the length of the range in bytes
+    pub(super) fn syscall_munmap(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let (addr, length) = (args.unsigned(0), args.unsigned(1));
+        if length == 0 || !addr.is_multiple_of(self.page_size()) {
+            return Err(EINVAL);
+        }
+        let diffs = self.munmap_range(addr, addr.saturating_add(length));
+        Ok(SyscallReturn::new(diffs, 0))
+    }
+
+    fn munmap_range(&self, start: u64, end: u64) -> DiffStack<F, S> {
+        let mut diffs: Vec<StateDiff<F, S>> = Vec::new();
+        for (&page, &prot) in self.priv_state.mmaps.range(start..end) {
+            diffs.extend(self.unmap_range(page, page + self.page_size()));
+            diffs.push(
+                PrivDiff::MmapUpdate {
+                    page,
+                    old: Some(prot),
+                    new: None,
+                }
+                .into_state_diff(),
+            );
+        }
+        diffs
+    }
+}
@@ -1,10 +1,32 @@
+pub mod errno;
+mod fs;
+mod hart;
+mod layout;
 mod memory;
+mod mm;
 mod phys;
+mod pipe;
 mod priv_s;
+mod process;
 mod program;
 mod registers;
+mod startup;
+mod stdin;
+mod syscall;
+mod sysinfo;
 mod user;
+mod vfs;
 
+pub use hart::*;
+pub use layout::*;
 pub use memory::*;
+pub use mm::*;
+pub use pipe::*;
+pub use process::*;
 pub use program::*;
 pub use registers::{IRegister, RegFile};
+pub use startup::*;
+pub use stdin::{Stdin, StdinSource};
+pub use syscall::*;
+pub use sysinfo::*;
+pub use vfs::*;
@@ -120,9 +120,9 @@ impl PhysState {
         if !self.require_aligned
             || match width {
                 Byte => true,
-                Half => offs % 2 == 0,
-                Lword => offs % 4 == 0,
-                Dword => offs % 8 == 0,
+                Half => offs.is_multiple_of(2),
+                Lword => offs.is_multiple_of(4),
+                Dword => offs.is_multiple_of(8),
             }
         {
             Ok(())
@@ -269,18 +269,13 @@ impl PhysState {
 
 /// Represents the order that bytes in a word are stored.
 /// See https://en.wikipedia.org/wiki/Endianness for more information.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 pub enum Endianness {
     Big,
+    #[default]
     Little,
 }
 
-impl Default for Endianness {
-    fn default() -> Self {
-        Endianness::Little
-    }
-}
-
 /// Represents a page of memory.
 /// TODO implement default value (currently 0)
 #[derive(Clone)]
@@ -296,6 +291,12 @@ pub struct MemPage {
     backing: HashMap<PageOffs, u8>,
 }
 
+impl fmt::Debug for MemPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MemPage {{ <{} bytes set> }}", self.backing.len())
+    }
+}
+
 impl Default for MemPage {
     /// Produces a little-endian page of 4KiB.
     fn default() -> Self {
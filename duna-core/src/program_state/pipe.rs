@@ -0,0 +1,240 @@
+//! Implements pipes, and the syscalls that create them and duplicate file descriptors.
+//!
+//! A pipe is a buffer in the kernel with two ends, each referred to by file descriptors: bytes
+//! written to one end are read from the other, in order. Pipes hold a page of bytes at most, so
+//! a writer that fills one blocks until a reader makes room. A read from an empty pipe blocks
+//! until a writer fills it, unless no file descriptor refers to its write end, in which case it
+//! returns 0 for the end of the file.
+//!
+//! Blocking only makes sense when another process can unblock the pipe. In a program with a
+//! single process, a read or write that would block ends the program with a deadlock; in one
+//! with several harts, which cannot block, it fails with EAGAIN as for a nonblocking pipe.
+
+use super::{
+    errno::*,
+    priv_s::PrivDiff,
+    process::ProcStatus,
+    program::{ProgramState, SyscallConvention},
+    syscall::*,
+    vfs::*,
+};
+use crate::{arch::*, data_structures::*};
+use std::collections::VecDeque;
+
+/// The most bytes a pipe holds before writes to it block.
+pub const PIPE_CAPACITY: usize = 4096;
+/// The only flag accepted by dup3, which is ignored since programs are never replaced by exec.
+const O_CLOEXEC: u64 = 0o2000000;
+
+/// The bytes written to a pipe that have not yet been read.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pipe {
+    pub(crate) buffer: VecDeque<u8>,
+}
+
+impl Pipe {
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.buffer.len() >= PIPE_CAPACITY
+    }
+}
+
+/// Describes a pipe for the debugger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PipeInfo {
+    /// The bytes that have been written but not read.
+    pub contents: Vec<u8>,
+    /// The number of file descriptors, in every process, that refer to each end.
+    pub readers: usize,
+    pub writers: usize,
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
+    /// Returns every pipe that was created, in order of ID.
+    pub fn pipes(&self) -> Vec<PipeInfo> {
+        (0..self.priv_state.pipes.len())
+            .map(|id| PipeInfo {
+                contents: self.priv_state.pipes[id].buffer.iter().copied().collect(),
+                readers: self.pipe_ends(id, false),
+                writers: self.pipe_ends(id, true),
+            })
+            .collect()
+    }
+
+    /// Counts the file descriptors that refer to the read or write end of pipe ID.
+    fn pipe_ends(&self, id: usize, write: bool) -> usize {
+        self.all_open_files()
+            .filter(|file| match file {
+                OpenFile::PipeRead { pipe, .. } => !write && *pipe == id,
+                OpenFile::PipeWrite { pipe, .. } => write && *pipe == id,
+                _ => false,
+            })
+            .count()
+    }
+
+    pub(super) fn pipe_has_readers(&self, id: usize) -> bool {
+        self.pipe_ends(id, false) > 0
+    }
+
+    pub(super) fn pipe_has_writers(&self, id: usize) -> bool {
+        self.pipe_ends(id, true) > 0
+    }
+
+    /// Returns the lowest file descriptors not already open, or EMFILE if there are not COUNT
+    /// of them.
+    fn free_fds(&self, count: usize) -> Result<Vec<usize>, i64> {
+        let fds: Vec<usize> = (0..MAX_FDS)
+            .filter(|&fd| self.priv_state.open_file(fd).is_none())
+            .take(count)
+            .collect();
+        if fds.len() < count {
+            return Err(EMFILE);
+        }
+        Ok(fds)
+    }
+
+    /// Returns the result of a read or write that cannot complete until another process reads
+    /// from or writes to the pipe. If no other process can, the scheduler ends the program.
+    fn pipe_block(&self, status: ProcStatus, nonblock: bool) -> SyscallResult<F, S> {
+        if nonblock || self.is_multihart() {
+            Err(EAGAIN)
+        } else {
+            self.block(status)
+        }
+    }
+
+    /// Reads up to COUNT bytes from the front of pipe ID into a buffer, and returns the number
+    /// of bytes read.
+    pub(super) fn pipe_read(
+        &self,
+        id: usize,
+        nonblock: bool,
+        buf: ByteAddrValue<S>,
+        count: usize,
+    ) -> SyscallResult<F, S> {
+        let pipe = &self.priv_state.pipes[id];
+        if count == 0 {
+            return Ok(SyscallReturn::value(0));
+        } else if pipe.is_empty() {
+            if !self.pipe_has_writers(id) {
+                return Ok(SyscallReturn::value(0));
+            }
+            return self.pipe_block(ProcStatus::ReadingPipe { pipe: id }, nonblock);
+        }
+        let data: Vec<u8> = pipe.buffer.iter().take(count).copied().collect();
+        let mut diffs = self.memory_set_bytes(buf, &data).map_err(|_| EFAULT)?;
+        let len = data.len() as i64;
+        diffs.push(PrivDiff::PipeRead { pipe: id, data }.into_state_diff());
+        Ok(SyscallReturn::new(diffs, len))
+    }
+
+    /// Writes up to COUNT bytes from a buffer to the back of pipe ID, and returns the number of
+    /// bytes written, which is less than COUNT if the pipe fills up. Fails with EPIPE if no
+    /// file descriptor refers to the read end.
+    pub(super) fn pipe_write(
+        &self,
+        id: usize,
+        nonblock: bool,
+        buf: ByteAddrValue<S>,
+        count: usize,
+    ) -> SyscallResult<F, S> {
+        let pipe = &self.priv_state.pipes[id];
+        if !self.pipe_has_readers(id) {
+            return Err(EPIPE);
+        } else if count == 0 {
+            return Ok(SyscallReturn::value(0));
+        } else if pipe.is_full() {
+            return self.pipe_block(ProcStatus::WritingPipe { pipe: id }, nonblock);
+        }
+        let len = count.min(PIPE_CAPACITY - pipe.buffer.len());
+        let (data, mut diffs) = self.memory_get_bytes(buf, len).map_err(|_| EFAULT)?;
+        diffs.push(PrivDiff::PipeWrite { pipe: id, data }.into_state_diff());
+        Ok(SyscallReturn::new(diffs, len as i64))
+    }
+
+    /// Creates a pipe, and stores a file descriptor for its read end followed by one for its
+    /// write end. Returns 0.
+    /// * pipefd - pointer to the two 32-bit words that receive the file descriptors
+    /// * flags - O_NONBLOCK to make reads and writes fail with EAGAIN rather than block; other
+    ///   flags are ignored
+    pub(super) fn syscall_pipe2(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let flags = <F::Syscalls as SyscallConvention<F, S>>::open_flags(args.unsigned(1));
+        let fds = self.free_fds(2)?;
+        let (read_fd, write_fd) = (fds[0], fds[1]);
+        let bytes: Vec<u8> = [read_fd as u32, write_fd as u32]
+            .iter()
+            .flat_map(|fd| fd.to_le_bytes())
+            .collect();
+        let mut diffs = self
+            .memory_set_bytes(args.addr(0), &bytes)
+            .map_err(|_| EFAULT)?;
+        let pipe = self.priv_state.pipes.len();
+        let nonblock = flags.nonblock;
+        diffs.push(PrivDiff::PipeCreate.into_state_diff());
+        for (fd, file) in [
+            (read_fd, OpenFile::PipeRead { pipe, nonblock }),
+            (write_fd, OpenFile::PipeWrite { pipe, nonblock }),
+        ] {
+            let update = PrivDiff::FdUpdate {
+                fd,
+                old: None,
+                new: Some(file),
+            };
+            diffs.push(update.into_state_diff());
+        }
+        Ok(SyscallReturn::new(diffs, 0))
+    }
+
+    /// Makes the lowest file descriptor not already open refer to the same file as another, and
+    /// returns it. Duplicates of a file in the filesystem share its offset, so reads and writes
+    /// through either move both.
+    /// * oldfd - file descriptor to duplicate
+    pub(super) fn syscall_dup(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let file = self.priv_state.open_file(args.fd(0)).ok_or(EBADF)?;
+        let fd = self.free_fds(1)?[0];
+        self.dup_to(file, fd)
+    }
+
+    /// Makes a file descriptor refer to the same file as another, closing it first if it was
+    /// open, and returns it. Does nothing if both are the same.
+    /// * oldfd - file descriptor to duplicate
+    /// * newfd - file descriptor to make refer to the file
+    pub(super) fn syscall_dup2(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let (oldfd, newfd) = (args.fd(0), args.fd(1));
+        let file = self.priv_state.open_file(oldfd).ok_or(EBADF)?;
+        if oldfd == newfd {
+            return Ok(SyscallReturn::value(newfd as i64));
+        }
+        self.dup_to(file, newfd)
+    }
+
+    /// Behaves like dup2, except that OLDFD and NEWFD may not be the same.
+    /// * oldfd, newfd - see dup2
+    /// * flags - O_CLOEXEC, which is ignored, or 0
+    pub(super) fn syscall_dup3(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let (oldfd, newfd) = (args.fd(0), args.fd(1));
+        let file = self.priv_state.open_file(oldfd).ok_or(EBADF)?;
+        if oldfd == newfd || args.unsigned(2) & !O_CLOEXEC != 0 {
+            return Err(EINVAL);
+        }
+        self.dup_to(file, newfd)
+    }
+
+    fn dup_to(&self, file: &OpenFile, fd: usize) -> SyscallResult<F, S> {
+        if fd >= MAX_FDS {
+            return Err(EBADF);
+        }
+        let update = PrivDiff::FdUpdate {
+            fd,
+            old: self.priv_state.open_file(fd).cloned(),
+            new: Some(file.clone()),
+        };
+        Ok(SyscallReturn::new(
+            vec![update.into_state_diff()],
+            fd as i64,
+        ))
+    }
+}
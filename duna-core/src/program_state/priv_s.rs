@@ -2,30 +2,63 @@
 //! to the running process, such as file descriptors and the page table.
 
 use super::{
+    layout::ProgramLayout,
     memory::*,
+    mm::Prot,
     phys::PhysMem,
+    pipe::Pipe,
     program::{DiffStack, ProgramState, StateDiff},
+    stdin::Stdin,
+    sysinfo::{CYCLE, CYCLEH, DEFAULT_SEED, INSTRET, INSTRETH, TIME, TIMEH},
+    vfs::{OpenFile, Vfs},
 };
 use crate::{arch::*, data_structures::*};
 use num_traits::cast::AsPrimitive;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// Contains architecture-agnostic program state that is visited only to privileged entities,
-/// i.e. a kernel thread.
-/// TODO add kernel thread information (tid, file descriptors, etc.)
+/// i.e. a kernel thread. The page table, heap, mappings, and file descriptors belong to the
+/// running process, and are swapped with those of another when the scheduler switches to it.
 pub struct PrivState<S: DataWidth> {
     // used for reset information
     original_heap_start: ByteAddrValue<S>,
     pub brk: ByteAddrValue<S>,
     pub heap_start: ByteAddrValue<S>,
     pub page_table: Box<dyn PageTable<S>>,
+    /// Maps the address of each page mapped by mmap to the accesses it allows.
+    pub(crate) mmaps: BTreeMap<u64, Prot>,
+    /// Where each section of the program was loaded. Stores to read-only sections fault.
+    /// Unset while the program is being loaded.
+    pub layout: Option<ProgramLayout>,
     /// Holds the contents of all bytes that have been printed to stdout (used mostly for testing)
     pub(crate) stdout: Vec<u8>,
     pub(crate) stderr: Vec<u8>,
-    // file_descriptors: Vec<Vec<u8>>
+    /// The input read by the program from stdin.
+    pub(crate) stdin: Stdin,
+    /// Maps each file descriptor to the file it refers to, if it is open.
+    pub(crate) fds: Vec<Option<OpenFile>>,
+    pub(crate) vfs: Vfs,
+    /// Every pipe that was created, indexed by ID. Pipes are shared by every process.
+    pub(crate) pipes: Vec<Pipe>,
+    /// The offset of every open file description in the filesystem, indexed by ID. Like pipes,
+    /// descriptions are shared by every process, so file descriptors that were duplicated or
+    /// inherited through fork move the same offset.
+    pub(crate) offsets: Vec<u64>,
+    /// The filesystem as it was when the program started, which is restored on reset.
+    original_vfs: Vfs,
+    /// The nanoseconds that have passed on the virtual clock since the program started.
+    pub(crate) clock: u64,
+    /// The number of instructions retired since the program started, by every hart.
+    pub(crate) instret: u64,
+    /// The state of the random number generator used by getrandom.
+    pub(crate) rng: u64,
+    /// The state the random number generator starts in, which is restored on reset.
+    seed: u64,
+    /// Where the errno of each failed syscall is stored, if anywhere.
+    pub errno_addr: Option<ByteAddrValue<S>>,
     /// Control registers used for managing exceptions and interrupts. Their usage is determined
     /// by architecture.
-    csrs: HashMap<usize, RegValue<S>>,
+    pub(crate) csrs: HashMap<usize, RegValue<S>>,
 }
 
 impl<S: DataWidth> PrivState<S> {
@@ -35,8 +68,21 @@ impl<S: DataWidth> PrivState<S> {
             brk: heap_start,
             heap_start,
             page_table,
+            mmaps: BTreeMap::new(),
+            layout: None,
             stdout: Vec::new(),
             stderr: Vec::new(),
+            stdin: Stdin::new(Default::default()),
+            fds: Self::initial_fds(),
+            vfs: Vfs::new(),
+            pipes: Vec::new(),
+            offsets: Vec::new(),
+            original_vfs: Vfs::new(),
+            clock: 0,
+            instret: 0,
+            rng: DEFAULT_SEED,
+            seed: DEFAULT_SEED,
+            errno_addr: None,
             csrs: HashMap::new(),
         }
     }
@@ -44,11 +90,47 @@ impl<S: DataWidth> PrivState<S> {
     pub fn reset(&mut self) {
         self.stdout.clear();
         self.stderr.clear();
+        self.stdin.rewind();
+        self.fds = Self::initial_fds();
+        self.vfs = self.original_vfs.clone();
+        self.pipes.clear();
+        self.offsets.clear();
+        self.clock = 0;
+        self.instret = 0;
+        self.rng = self.seed;
         self.page_table.reset();
+        self.mmaps.clear();
+        self.layout = None;
         self.brk = self.original_heap_start;
         self.heap_start = self.original_heap_start;
     }
 
+    /// Only the standard streams are open when a program starts.
+    fn initial_fds() -> Vec<Option<OpenFile>> {
+        vec![
+            Some(OpenFile::Stdin),
+            Some(OpenFile::Stdout),
+            Some(OpenFile::Stderr),
+        ]
+    }
+
+    /// Replaces the filesystem the program starts with.
+    pub fn set_vfs(&mut self, vfs: Vfs) {
+        self.vfs = vfs.clone();
+        self.original_vfs = vfs;
+    }
+
+    /// Replaces the seed of the random number generator.
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.rng = seed;
+        self.seed = seed;
+    }
+
+    /// Returns the file that FD refers to, if it is open.
+    pub fn open_file(&self, fd: usize) -> Option<&OpenFile> {
+        self.fds.get(fd).and_then(Option::as_ref)
+    }
+
     /// Applies a diff to the privileged state.
     ///
     /// Note that even for operations that seem like they would necessitate changes in user space
@@ -65,24 +147,70 @@ impl<S: DataWidth> PrivState<S> {
         use PrivDiff::*;
         match diff {
             FileWrite { fd, data } => {
-                // TODO impl for other files
                 let fd_idx: usize = {
                     let num: UnsignedValue<S> = (*fd).into();
                     AsPrimitive::<usize>::as_(num.raw())
                 };
-                match fd_idx {
-                    1 => {
+                // writes to files in the filesystem are FileSplices instead
+                match self.open_file(fd_idx) {
+                    Some(OpenFile::Stdout) => {
                         print!("{}", String::from_utf8_lossy(data));
                         self.stdout.extend(data);
                     }
-                    2 => {
+                    Some(OpenFile::Stderr) => {
                         eprint!("{}", String::from_utf8_lossy(data));
                         self.stderr.extend(data);
                     }
-                    _ => unimplemented!(),
+                    _ => unreachable!(),
                 }
                 Ok(())
             }
+            FileRead { data, .. } => {
+                self.stdin.consume(data);
+                Ok(())
+            }
+            FdUpdate { fd, new, .. } => {
+                if *fd >= self.fds.len() {
+                    self.fds.resize(fd + 1, None);
+                }
+                self.fds[*fd] = new.clone();
+                Ok(())
+            }
+            FileCreate { path, .. } => {
+                self.vfs.create(path);
+                Ok(())
+            }
+            FileSplice {
+                inode,
+                offset,
+                old,
+                new,
+            } => {
+                self.vfs.splice(*inode, *offset, old.len(), new);
+                Ok(())
+            }
+            PipeCreate => {
+                self.pipes.push(Pipe::default());
+                Ok(())
+            }
+            DescriptionCreate => {
+                self.offsets.push(0);
+                Ok(())
+            }
+            Seek {
+                description, new, ..
+            } => {
+                self.offsets[*description] = *new;
+                Ok(())
+            }
+            PipeWrite { pipe, data } => {
+                self.pipes[*pipe].buffer.extend(data);
+                Ok(())
+            }
+            PipeRead { pipe, data } => {
+                self.pipes[*pipe].buffer.drain(..data.len());
+                Ok(())
+            }
             Terminate(cause) => Err(*cause),
             PtUpdate(update) => {
                 self.page_table.apply_update(mem, update);
@@ -92,6 +220,22 @@ impl<S: DataWidth> PrivState<S> {
                 self.brk = *new;
                 Ok(())
             }
+            MmapUpdate { page, new, .. } => {
+                self.set_mmap(*page, *new);
+                Ok(())
+            }
+            ClockUpdate { new, .. } => {
+                self.clock = *new;
+                Ok(())
+            }
+            RngUpdate { new, .. } => {
+                self.rng = *new;
+                Ok(())
+            }
+            Retire => {
+                self.instret += 1;
+                Ok(())
+            }
             CsrWrite { addr, new, .. } => {
                 self.csrs.insert(*addr, *new);
                 Ok(())
@@ -103,12 +247,55 @@ impl<S: DataWidth> PrivState<S> {
     pub fn revert_diff<F: ArchFamily<S>>(&mut self, mem: &mut PhysMem, diff: &PrivDiff<S>) {
         use PrivDiff::*;
         match diff {
-            // TODO delete last len bytes from fd
-            FileWrite { fd: _, data: _ } => {}
+            FileWrite { fd, data } => {
+                // the output cannot be taken back, but it can be removed from what was recorded
+                let fd_idx: usize = {
+                    let num: UnsignedValue<S> = (*fd).into();
+                    AsPrimitive::<usize>::as_(num.raw())
+                };
+                let recorded = match self.open_file(fd_idx) {
+                    Some(OpenFile::Stdout) => &mut self.stdout,
+                    Some(OpenFile::Stderr) => &mut self.stderr,
+                    _ => unreachable!(),
+                };
+                recorded.truncate(recorded.len().saturating_sub(data.len()));
+            }
+            FileRead { data, .. } => self.stdin.unconsume(data),
+            FdUpdate { fd, old, .. } => self.fds[*fd] = old.clone(),
+            FileCreate { path, .. } => self.vfs.uncreate(path),
+            FileSplice {
+                inode,
+                offset,
+                old,
+                new,
+            } => self.vfs.splice(*inode, *offset, new.len(), old),
+            PipeCreate => {
+                self.pipes.pop();
+            }
+            DescriptionCreate => {
+                self.offsets.pop();
+            }
+            Seek {
+                description, old, ..
+            } => self.offsets[*description] = *old,
+            PipeWrite { pipe, data } => {
+                let buffer = &mut self.pipes[*pipe].buffer;
+                buffer.truncate(buffer.len() - data.len());
+            }
+            PipeRead { pipe, data } => {
+                let buffer = &mut self.pipes[*pipe].buffer;
+                for &byte in data.iter().rev() {
+                    buffer.push_front(byte);
+                }
+            }
             PtUpdate(update) => self.page_table.revert_update(mem, update),
             BrkUpdate { old, .. } => {
                 self.brk = *old;
             }
+            MmapUpdate { page, old, .. } => self.set_mmap(*page, *old),
+            ClockUpdate { old, .. } => self.clock = *old,
+            RngUpdate { old, .. } => self.rng = *old,
+            Retire => self.instret -= 1,
             CsrWrite { addr, old, .. } => {
                 self.csrs.insert(*addr, *old);
             }
@@ -116,8 +303,44 @@ impl<S: DataWidth> PrivState<S> {
         }
     }
 
+    fn set_mmap(&mut self, page: u64, prot: Option<Prot>) {
+        match prot {
+            Some(prot) => self.mmaps.insert(page, prot),
+            None => self.mmaps.remove(&page),
+        };
+    }
+
+    /// Returns the accesses allowed to the page containing the address, if it was mapped by mmap.
+    pub fn mmap_prot(&self, vaddr: ByteAddrValue<S>) -> Option<Prot> {
+        let page_size = self.layout?.page_size;
+        self.mmaps
+            .get(&(vaddr.bits() / page_size * page_size))
+            .copied()
+    }
+
+    /// Returns whether the address lies in a page that the program may not store to.
+    pub fn is_readonly(&self, vaddr: ByteAddrValue<S>) -> bool {
+        self.layout
+            .is_some_and(|layout| layout.is_readonly(vaddr.bits()))
+            || self.mmap_prot(vaddr).is_some_and(|prot| !prot.write)
+    }
+
+    /// Returns whether the address lies in a page that the program may not load from.
+    pub fn is_unreadable(&self, vaddr: ByteAddrValue<S>) -> bool {
+        self.mmap_prot(vaddr).is_some_and(|prot| !prot.read)
+    }
+
+    /// Returns whether the address lies in a page that the program may not execute.
+    pub fn is_unexecutable(&self, vaddr: ByteAddrValue<S>) -> bool {
+        self.mmap_prot(vaddr).is_some_and(|prot| !prot.exec)
+    }
+
     pub fn csr_read(&self, addr: usize) -> RegValue<S> {
-        *self.csrs.get(&addr).unwrap_or(&RegValue::<S>::zero())
+        match addr {
+            CYCLE | TIME | INSTRET => self.instret.into(),
+            CYCLEH | TIMEH | INSTRETH => (self.instret >> 32).into(),
+            _ => *self.csrs.get(&addr).unwrap_or(&RegValue::<S>::zero()),
+        }
     }
 
     pub fn csr_write(&mut self, addr: usize, value: RegValue<S>) {
@@ -138,11 +361,77 @@ pub enum PrivDiff<S: DataWidth> {
         fd: RegValue<S>,
         data: Vec<u8>,
     },
+    /// Represents a read from a file, which consumes its bytes.
+    /// * fd: the file descriptor
+    /// * data: the bytes being read
+    FileRead {
+        fd: RegValue<S>,
+        data: Vec<u8>,
+    },
+    /// Represents a file descriptor being opened or closed.
+    FdUpdate {
+        fd: usize,
+        old: Option<OpenFile>,
+        new: Option<OpenFile>,
+    },
+    /// Represents the creation of an empty file, which is given the next free inode.
+    FileCreate {
+        path: String,
+        inode: usize,
+    },
+    /// Represents a change to the contents of a file, where the bytes OLD starting at OFFSET are
+    /// replaced by NEW. Writes past the end of the file grow it.
+    FileSplice {
+        inode: usize,
+        offset: u64,
+        old: Vec<u8>,
+        new: Vec<u8>,
+    },
+    /// Represents the creation of an empty pipe, which is given the next free ID.
+    PipeCreate,
+    /// Represents a file in the filesystem being opened, which creates an open file description
+    /// at offset 0 that is given the next free ID.
+    DescriptionCreate,
+    /// Represents an open file description being moved to a new offset.
+    Seek {
+        description: usize,
+        old: u64,
+        new: u64,
+    },
+    /// Represents bytes being appended to the buffer of a pipe.
+    PipeWrite {
+        pipe: usize,
+        data: Vec<u8>,
+    },
+    /// Represents bytes being taken from the front of the buffer of a pipe.
+    PipeRead {
+        pipe: usize,
+        data: Vec<u8>,
+    },
     PtUpdate(PtUpdate),
     BrkUpdate {
         old: ByteAddrValue<S>,
         new: ByteAddrValue<S>,
     },
+    /// Represents a page being mapped or unmapped by mmap or munmap. The page table is updated
+    /// separately through PtUpdates.
+    MmapUpdate {
+        page: u64,
+        old: Option<Prot>,
+        new: Option<Prot>,
+    },
+    /// Represents the virtual clock advancing after it is read.
+    ClockUpdate {
+        old: u64,
+        new: u64,
+    },
+    /// Represents the random number generator advancing after random bytes are taken from it.
+    RngUpdate {
+        old: u64,
+        new: u64,
+    },
+    /// Represents an instruction being retired, which advances the counters.
+    Retire,
     CsrWrite {
         addr: usize,
         old: RegValue<S>,
@@ -179,6 +468,13 @@ pub enum TermCause {
     /// The program was terminated by a bus error, i.e. the program attempted to access a physically
     /// invalid address
     BusError,
+    /// The program was terminated for attempting to execute an illegal instruction.
+    IllegalInstruction,
+    /// The program was terminated by a breakpoint that no debugger handled.
+    Breakpoint,
+    /// The program was terminated because every process was blocked on another, so that it
+    /// would never make progress.
+    Deadlock,
 }
 
 impl<S: DataWidth> From<MemFault<S>> for TermCause {
@@ -210,6 +506,19 @@ impl TermCause {
                 program_state.write_stderr("bus error\n");
                 10u8 | ABNORMAL_MASK
             }
+            IllegalInstruction => {
+                program_state.write_stderr("illegal instruction\n");
+                4u8 | ABNORMAL_MASK
+            }
+            Breakpoint => {
+                program_state.write_stderr("trace/breakpoint trap\n");
+                5u8 | ABNORMAL_MASK
+            }
+            // reported as though the hung program had been killed
+            Deadlock => {
+                program_state.write_stderr("deadlock\n");
+                9u8 | ABNORMAL_MASK
+            }
         }
     }
 }
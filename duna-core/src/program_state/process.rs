@@ -0,0 +1,659 @@
+//! Models several processes, each with its own registers and address space, and the syscalls
+//! that create and wait for them.
+//!
+//! Only one process runs at a time. Its context lives in the user and privileged state, just as
+//! in a program with a single process, while the contexts of the others are saved in the process
+//! table until the scheduler switches to them. Since every page table manages physical memory on
+//! its own, each process also has its own physical memory. Processes share the filesystem and
+//! the standard streams, but a forked child gets copies of its parent's file descriptors, so
+//! their offsets are not shared. Pipes are shared, so that a child can talk to its parent.
+//!
+//! A process that makes a syscall that cannot complete yet, such as a read from an empty pipe,
+//! blocks until another process acts, and then makes the syscall again. If every process is
+//! blocked, the program ends with a deadlock.
+
+use super::{
+    errno::*,
+    memory::PageTable,
+    mm::Prot,
+    phys::PhysMem,
+    priv_s::{PrivDiff, TermCause},
+    program::{DiffStack, ProgramState, StateDiff, SyscallConvention},
+    syscall::*,
+    user::{UserDiff, UserState},
+    vfs::OpenFile,
+};
+use crate::{arch::*, data_structures::*};
+use std::{cell::RefCell, collections::BTreeMap, fmt, mem};
+
+/// The ID of the process a program starts as. The program ends when this process exits.
+pub const INIT_PID: u32 = 1;
+/// The number of instructions a process runs before the scheduler switches to another, unless
+/// it blocks or yields first.
+pub const DEFAULT_QUANTUM: u64 = 100;
+/// The most processes that may exist at once, including those not yet reaped.
+pub const MAX_PROCS: usize = 64;
+
+/// Makes wait4 return 0 instead of blocking when no child has exited.
+pub const WNOHANG: u64 = 1;
+/// The bits of the flags passed to clone that give the signal sent when the child exits. Every
+/// other flag shares something between parent and child, which is not supported.
+const CLONE_SIGNAL_MASK: u64 = 0xff;
+
+/// Signals that end a process abnormally, numbered as on most architectures, including RISC-V.
+pub const SIGILL: u32 = 4;
+pub const SIGTRAP: u32 = 5;
+pub const SIGBUS: u32 = 7;
+pub const SIGKILL: u32 = 9;
+pub const SIGSEGV: u32 = 11;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProcStatus {
+    Runnable,
+    /// Blocked in wait4 until a child matching PID exits, where -1 matches any child. The
+    /// syscall is made again when the process wakes.
+    Waiting {
+        pid: i64,
+    },
+    /// Blocked in read until the pipe has bytes in it or no writers.
+    ReadingPipe {
+        pipe: usize,
+    },
+    /// Blocked in write until the pipe has room in it or no readers.
+    WritingPipe {
+        pipe: usize,
+    },
+    /// Exited with the given wait status, but not yet reaped by its parent.
+    Zombie {
+        wstatus: u32,
+    },
+}
+
+impl ProcStatus {
+    /// Returns whether the process is blocked in a syscall, which it makes again when it wakes.
+    pub fn is_blocked(self) -> bool {
+        !matches!(self, ProcStatus::Runnable | ProcStatus::Zombie { .. })
+    }
+}
+
+impl fmt::Display for ProcStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcStatus::Runnable => write!(f, "runnable"),
+            ProcStatus::Waiting { pid: -1 } => write!(f, "waiting for any child"),
+            ProcStatus::Waiting { pid } => write!(f, "waiting for {}", pid),
+            ProcStatus::ReadingPipe { pipe } => write!(f, "reading from pipe {}", pipe),
+            ProcStatus::WritingPipe { pipe } => write!(f, "writing to pipe {}", pipe),
+            ProcStatus::Zombie { wstatus } => write!(f, "exited with status {:#x}", wstatus),
+        }
+    }
+}
+
+/// What the process table records about a process, apart from its context.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProcInfo {
+    /// The ID of the parent, which is 0 for the first process.
+    pub ppid: u32,
+    pub status: ProcStatus,
+}
+
+/// The parts of the program state that belong to a single process.
+pub struct ProcContext<F: ArchFamily<S>, S: DataWidth> {
+    pub user_state: UserState<F, S>,
+    page_table: Box<dyn PageTable<S>>,
+    brk: ByteAddrValue<S>,
+    heap_start: ByteAddrValue<S>,
+    mmaps: BTreeMap<u64, Prot>,
+    fds: Vec<Option<OpenFile>>,
+    phys_mem: PhysMem,
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> Clone for ProcContext<F, S> {
+    fn clone(&self) -> Self {
+        ProcContext {
+            user_state: self.user_state.clone(),
+            page_table: self.page_table.clone_box(),
+            brk: self.brk,
+            heap_start: self.heap_start,
+            mmaps: self.mmaps.clone(),
+            fds: self.fds.clone(),
+            phys_mem: self.phys_mem.clone(),
+        }
+    }
+}
+
+pub struct Process<F: ArchFamily<S>, S: DataWidth> {
+    pub info: ProcInfo,
+    /// The saved context of the process, which is none while it runs.
+    context: Option<ProcContext<F, S>>,
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> Clone for Process<F, S> {
+    fn clone(&self) -> Self {
+        Process {
+            info: self.info,
+            context: self.context.clone(),
+        }
+    }
+}
+
+/// Tracks every process, and which one is running.
+pub struct ProcTable<F: ArchFamily<S>, S: DataWidth> {
+    procs: BTreeMap<u32, Process<F, S>>,
+    current: u32,
+    next_pid: u32,
+    /// The number of instructions the running process has run since it was switched to.
+    ticks: u64,
+    quantum: u64,
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> Default for ProcTable<F, S> {
+    fn default() -> Self {
+        let mut table = ProcTable {
+            procs: BTreeMap::new(),
+            current: INIT_PID,
+            next_pid: INIT_PID,
+            ticks: 0,
+            quantum: DEFAULT_QUANTUM,
+        };
+        table.reset();
+        table
+    }
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> ProcTable<F, S> {
+    /// Discards every process but the first, which is made to run. The quantum is kept.
+    pub fn reset(&mut self) {
+        self.procs.clear();
+        self.procs.insert(
+            INIT_PID,
+            Process {
+                info: ProcInfo {
+                    ppid: 0,
+                    status: ProcStatus::Runnable,
+                },
+                context: None,
+            },
+        );
+        self.current = INIT_PID;
+        self.next_pid = INIT_PID + 1;
+        self.ticks = 0;
+    }
+
+    /// Returns the ID of the running process.
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    pub fn info(&self, pid: u32) -> Option<ProcInfo> {
+        self.procs.get(&pid).map(|proc| proc.info)
+    }
+
+    /// Returns the ID of every process in ascending order.
+    pub fn pids(&self) -> Vec<u32> {
+        self.procs.keys().copied().collect()
+    }
+
+    pub fn quantum(&self) -> u64 {
+        self.quantum
+    }
+
+    /// Sets how many instructions a process runs before another is switched to. A quantum of 0
+    /// is treated as 1.
+    pub fn set_quantum(&mut self, quantum: u64) {
+        self.quantum = quantum.max(1);
+    }
+
+    /// Returns whether any process but the running one exists, in which case the scheduler runs
+    /// after every instruction.
+    fn is_multiprocess(&self) -> bool {
+        self.procs.len() > 1
+    }
+
+    /// Returns the children of PARENT that PID matches, where -1 matches every child.
+    fn children(&self, parent: u32, pid: i64) -> impl Iterator<Item = (u32, ProcInfo)> + '_ {
+        self.procs
+            .iter()
+            .filter(move |(&child, proc)| {
+                proc.info.ppid == parent && (pid == -1 || pid == child as i64)
+            })
+            .map(|(&child, proc)| (child, proc.info))
+    }
+
+    /// Returns the IDs of every process but the running one, starting with the one after it in
+    /// order of ID, and wrapping around.
+    fn after_current(&self) -> impl Iterator<Item = u32> + '_ {
+        let after = self.procs.range(self.current + 1..).map(|(&pid, _)| pid);
+        let before = self.procs.range(..self.current).map(|(&pid, _)| pid);
+        after.chain(before)
+    }
+}
+
+/// Encodes a change to the process table.
+pub enum ProcDiff<F: ArchFamily<S>, S: DataWidth> {
+    /// Represents the creation of a process by fork or clone, which is given the next free ID.
+    /// The child is rebuilt from its parent whenever this is applied, so its memory is not kept
+    /// here.
+    Spawn {
+        pid: u32,
+        stack: Option<RegValue<S>>,
+    },
+    /// Represents a process that exited being removed once its parent waits for it. The process
+    /// is moved here while the diff is applied, and back into the table when it is reverted.
+    Reap {
+        pid: u32,
+        proc: RefCell<Option<Box<Process<F, S>>>>,
+    },
+    /// Represents a process changing status or parent.
+    InfoUpdate {
+        pid: u32,
+        old: ProcInfo,
+        new: ProcInfo,
+    },
+    /// Represents the scheduler switching from the running process to another, saving the
+    /// context of one and restoring that of the other.
+    Switch { from: u32, to: u32 },
+    /// Represents a change to the number of instructions the running process has run.
+    Ticks { old: u64, new: u64 },
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> ProcDiff<F, S> {
+    pub fn into_state_diff(self) -> StateDiff<F, S> {
+        StateDiff::Proc(self)
+    }
+}
+
+impl TermCause {
+    /// Returns the status reported by wait4 for a process ended for this cause.
+    pub fn wait_status(self) -> u32 {
+        match self {
+            TermCause::Exit(code) => (code & 0xff) << 8,
+            TermCause::SegFault => SIGSEGV,
+            TermCause::BusError => SIGBUS,
+            TermCause::IllegalInstruction => SIGILL,
+            TermCause::Breakpoint => SIGTRAP,
+            // never reported, since a deadlock ends every process
+            TermCause::Deadlock => SIGKILL,
+        }
+    }
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
+    /// Exchanges the context of the running process with CTX.
+    fn swap_context(&mut self, ctx: &mut ProcContext<F, S>) {
+        let priv_state = &mut self.priv_state;
+        mem::swap(&mut self.user_state, &mut ctx.user_state);
+        mem::swap(&mut priv_state.page_table, &mut ctx.page_table);
+        mem::swap(&mut priv_state.brk, &mut ctx.brk);
+        mem::swap(&mut priv_state.heap_start, &mut ctx.heap_start);
+        mem::swap(&mut priv_state.mmaps, &mut ctx.mmaps);
+        mem::swap(&mut priv_state.fds, &mut ctx.fds);
+        mem::swap(&mut self.phys_state.phys_mem, &mut ctx.phys_mem);
+    }
+
+    /// Saves the context of process FROM, which is running, and restores that of TO.
+    fn switch_process(&mut self, from: u32, to: u32) {
+        let procs = &mut self.procs.procs;
+        let mut ctx = procs
+            .get_mut(&to)
+            .and_then(|proc| proc.context.take())
+            .expect("only processes that are not running are switched to");
+        self.swap_context(&mut ctx);
+        self.procs.procs.get_mut(&from).unwrap().context = Some(ctx);
+        self.procs.current = to;
+    }
+
+    pub(super) fn apply_proc_diff(&mut self, diff: &ProcDiff<F, S>) {
+        match diff {
+            ProcDiff::Spawn { pid, stack } => {
+                let child = self.fork_process(*stack);
+                self.procs.procs.insert(*pid, child);
+                self.procs.next_pid = pid + 1;
+            }
+            ProcDiff::Reap { pid, proc } => {
+                let zombie = self.procs.procs.remove(pid).unwrap();
+                *proc.borrow_mut() = Some(Box::new(zombie));
+            }
+            ProcDiff::InfoUpdate { pid, new, .. } => {
+                self.procs.procs.get_mut(pid).unwrap().info = *new;
+            }
+            &ProcDiff::Switch { from, to } => self.switch_process(from, to),
+            ProcDiff::Ticks { new, .. } => self.procs.ticks = *new,
+        }
+    }
+
+    pub(super) fn revert_proc_diff(&mut self, diff: &ProcDiff<F, S>) {
+        match diff {
+            ProcDiff::Spawn { pid, .. } => {
+                self.procs.procs.remove(pid);
+                self.procs.next_pid = *pid;
+            }
+            ProcDiff::Reap { pid, proc } => {
+                let zombie = proc.borrow_mut().take().unwrap();
+                self.procs.procs.insert(*pid, *zombie);
+            }
+            ProcDiff::InfoUpdate { pid, old, .. } => {
+                self.procs.procs.get_mut(pid).unwrap().info = *old;
+            }
+            &ProcDiff::Switch { from, to } => self.switch_process(to, from),
+            ProcDiff::Ticks { old, .. } => self.procs.ticks = *old,
+        }
+    }
+
+    /// Returns the ID of the running process.
+    pub fn running_pid(&self) -> u32 {
+        self.procs.current
+    }
+
+    /// Returns the ID of every process in ascending order, along with what is known about it.
+    pub fn processes(&self) -> Vec<(u32, ProcInfo)> {
+        self.procs
+            .procs
+            .iter()
+            .map(|(&pid, proc)| (pid, proc.info))
+            .collect()
+    }
+
+    /// Returns the registers and program counter of a process, whether or not it is running.
+    pub fn process_user_state(&self, pid: u32) -> Option<&UserState<F, S>> {
+        if pid == self.procs.current {
+            return Some(&self.user_state);
+        }
+        let proc = self.procs.procs.get(&pid)?;
+        proc.context.as_ref().map(|ctx| &ctx.user_state)
+    }
+
+    /// Sets how many instructions a process runs before the scheduler switches to another.
+    pub fn set_quantum(&mut self, quantum: u64) {
+        self.procs.set_quantum(quantum);
+    }
+
+    /// Returns whether a process other than the first has run off the end of the text section,
+    /// which makes it exit as though it had returned from main.
+    pub(super) fn has_returned(&self) -> bool {
+        self.procs.current != INIT_PID
+            && self
+                .priv_state
+                .layout
+                .is_some_and(|layout| self.user_state.pc.bits() == layout.text.end())
+    }
+
+    /// Returns every file that a file descriptor of any process refers to.
+    pub(super) fn all_open_files(&self) -> impl Iterator<Item = &OpenFile> {
+        let saved = self
+            .procs
+            .procs
+            .values()
+            .filter_map(|proc| proc.context.as_ref());
+        self.priv_state
+            .fds
+            .iter()
+            .chain(saved.flat_map(|ctx| ctx.fds.iter()))
+            .flatten()
+    }
+
+    /// Returns whether process PID may be switched to. A blocked process may be once the syscall
+    /// it is blocked in would no longer block.
+    fn can_run(&self, pid: u32) -> bool {
+        match self.procs.procs[&pid].info.status {
+            ProcStatus::Runnable => true,
+            ProcStatus::Waiting { pid: target } => {
+                let mut children = self.procs.children(pid, target).peekable();
+                children.peek().is_none()
+                    || children.any(|(_, info)| matches!(info.status, ProcStatus::Zombie { .. }))
+            }
+            ProcStatus::ReadingPipe { pipe } => {
+                !self.priv_state.pipes[pipe].is_empty() || !self.pipe_has_writers(pipe)
+            }
+            ProcStatus::WritingPipe { pipe } => {
+                !self.priv_state.pipes[pipe].is_full() || !self.pipe_has_readers(pipe)
+            }
+            ProcStatus::Zombie { .. } => false,
+        }
+    }
+
+    /// Returns the result of a syscall that cannot complete until another process acts, which
+    /// blocks the running process with STATUS.
+    pub(super) fn block(&self, status: ProcStatus) -> SyscallResult<F, S> {
+        let pid = self.procs.current;
+        let old = self.procs.procs[&pid].info;
+        let new = ProcInfo { status, ..old };
+        let block = ProcDiff::InfoUpdate { pid, old, new };
+        Ok(SyscallReturn::noreturn(vec![block.into_state_diff()]))
+    }
+
+    /// Returns the diffs that end the running process for CAUSE. When the first process ends, so
+    /// does the program; any other process closes its file descriptors and becomes a zombie
+    /// until its parent waits for it, and its children are given to the first process.
+    pub(super) fn end_process(&self, cause: TermCause) -> DiffStack<F, S> {
+        let pid = self.procs.current;
+        if pid == INIT_PID {
+            return PrivDiff::Terminate(cause).into_diff_stack();
+        }
+        let mut diffs: DiffStack<F, S> = (0..)
+            .zip(&self.priv_state.fds)
+            .filter(|(_, file)| file.is_some())
+            .map(|(fd, file)| {
+                PrivDiff::FdUpdate {
+                    fd,
+                    old: file.clone(),
+                    new: None,
+                }
+                .into_state_diff()
+            })
+            .collect();
+        let old = self.procs.procs[&pid].info;
+        diffs.push(
+            ProcDiff::InfoUpdate {
+                pid,
+                old,
+                new: ProcInfo {
+                    status: ProcStatus::Zombie {
+                        wstatus: cause.wait_status(),
+                    },
+                    ..old
+                },
+            }
+            .into_state_diff(),
+        );
+        for (child, old) in self.procs.children(pid, -1) {
+            let new = ProcInfo {
+                ppid: INIT_PID,
+                ..old
+            };
+            diffs.push(
+                ProcDiff::InfoUpdate {
+                    pid: child,
+                    old,
+                    new,
+                }
+                .into_state_diff(),
+            );
+        }
+        diffs
+    }
+
+    /// Returns the diffs that switch to another process if the running one used up its quantum
+    /// or can no longer run, after it ran an instruction. Processes are switched to in order of
+    /// ID, skipping those that are blocked or have exited. If the running process cannot run and
+    /// neither can any other, the program ends with a deadlock.
+    pub(super) fn schedule(&self) -> DiffStack<F, S> {
+        let procs = &self.procs;
+        let from = procs.current;
+        let status = procs.procs[&from].info.status;
+        if status == ProcStatus::Runnable && !procs.is_multiprocess() {
+            return Vec::new();
+        }
+        let old = procs.ticks;
+        if status == ProcStatus::Runnable && old + 1 < procs.quantum {
+            return vec![ProcDiff::Ticks { old, new: old + 1 }.into_state_diff()];
+        }
+        let mut diffs = vec![ProcDiff::Ticks { old, new: 0 }.into_state_diff()];
+        let to = match procs.after_current().find(|&pid| self.can_run(pid)) {
+            Some(to) => to,
+            None if status == ProcStatus::Runnable => return diffs,
+            None => {
+                diffs.push(PrivDiff::Terminate(TermCause::Deadlock).into_state_diff());
+                return diffs;
+            }
+        };
+        if status.is_blocked() {
+            // the ecall advanced the program counter, so rewind it to make the syscall again
+            let pc = self.user_state.pc;
+            let restart = UserDiff::PcDiff {
+                old_pc: pc,
+                new_pc: (pc.bits() - 4).into(),
+            };
+            diffs.push(restart.into_state_diff());
+        }
+        diffs.push(ProcDiff::Switch { from, to }.into_state_diff());
+        let old = procs.procs[&to].info;
+        if old.status.is_blocked() {
+            let new = ProcInfo {
+                status: ProcStatus::Runnable,
+                ..old
+            };
+            diffs.push(ProcDiff::InfoUpdate { pid: to, old, new }.into_state_diff());
+        }
+        diffs
+    }
+
+    /// Creates a child process with a copy of the address space and file descriptors of the
+    /// running process, and returns the ID of the child. The child resumes after the syscall,
+    /// where it sees a return value of 0.
+    pub(super) fn syscall_fork(&self, _args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        self.fork(None)
+    }
+
+    /// Behaves like fork, except that the child may be given a new stack. Since processes never
+    /// share memory or file descriptors, no flags but the signal sent when the child exits may be
+    /// given.
+    /// * flags - the signal sent to the parent when the child exits, which is ignored
+    /// * stack - the stack pointer of the child, or null to keep that of the parent
+    pub(super) fn syscall_clone(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        if args.unsigned(0) & !CLONE_SIGNAL_MASK != 0 {
+            return Err(EINVAL);
+        }
+        let stack = args.reg(1);
+        self.fork((stack.bits() != 0).then_some(stack))
+    }
+
+    fn fork(&self, stack: Option<RegValue<S>>) -> SyscallResult<F, S> {
+        if self.is_multihart() {
+            // only the running hart would be copied, so programs with several harts cannot fork
+            return Err(ENOSYS);
+        }
+        if self.procs.procs.len() >= MAX_PROCS {
+            return Err(EAGAIN);
+        }
+        let pid = self.procs.next_pid;
+        let spawn = ProcDiff::Spawn { pid, stack };
+        Ok(SyscallReturn::new(
+            vec![spawn.into_state_diff()],
+            pid.into(),
+        ))
+    }
+
+    /// Returns a copy of the running process as a child of it, which resumes after the syscall
+    /// that created it with a return value of 0.
+    /// * stack - the stack pointer of the child, or none to keep that of the parent
+    fn fork_process(&self, stack: Option<RegValue<S>>) -> Process<F, S> {
+        let priv_state = &self.priv_state;
+        let mut user_state = self.user_state.clone();
+        user_state.pc = user_state.pc.plus_4();
+        let ret_reg = <F::Syscalls as SyscallConvention<F, S>>::syscall_return_regs()[0];
+        user_state.regfile.set(ret_reg, RegValue::<S>::zero());
+        if let Some(sp) = stack {
+            let sp_reg = <F::Syscalls as SyscallConvention<F, S>>::stack_pointer_reg();
+            user_state.regfile.set(sp_reg, sp);
+        }
+        Process {
+            info: ProcInfo {
+                ppid: self.procs.current,
+                status: ProcStatus::Runnable,
+            },
+            context: Some(ProcContext {
+                user_state,
+                page_table: priv_state.page_table.clone_box(),
+                brk: priv_state.brk,
+                heap_start: priv_state.heap_start,
+                mmaps: priv_state.mmaps.clone(),
+                fds: priv_state.fds.clone(),
+                phys_mem: self.phys_state.phys_mem.clone(),
+            }),
+        }
+    }
+
+    /// Waits for a child to exit, and returns its ID after removing it. Since every process is
+    /// in the same process group, any PID that is not positive waits for any child.
+    /// * pid - the ID of the child to wait for
+    /// * wstatus - pointer to the 32-bit word that receives the wait status of the child, or null
+    /// * options - WNOHANG to return 0 rather than block if no child has exited
+    /// * rusage - pointer to the struct rusage to fill in, which is left alone
+    pub(super) fn syscall_wait4(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let pid = match args.signed(0) {
+            pid if pid > 0 => pid,
+            _ => -1,
+        };
+        let (wstatus, options) = (args.addr(1), args.unsigned(2));
+        let current = self.procs.current;
+        let mut children = self.procs.children(current, pid).peekable();
+        if children.peek().is_none() {
+            return Err(ECHILD);
+        }
+        let zombie = children.find_map(|(child, info)| match info.status {
+            ProcStatus::Zombie { wstatus } => Some((child, wstatus)),
+            _ => None,
+        });
+        match zombie {
+            Some((child, status)) => {
+                let mut diffs = if wstatus.bits() != 0 {
+                    self.memory_set_bytes(wstatus, &status.to_le_bytes())
+                        .map_err(|_| EFAULT)?
+                } else {
+                    Vec::new()
+                };
+                let reap = ProcDiff::Reap {
+                    pid: child,
+                    proc: RefCell::new(None),
+                };
+                diffs.push(reap.into_state_diff());
+                Ok(SyscallReturn::new(diffs, child.into()))
+            }
+            None if options & WNOHANG != 0 => Ok(SyscallReturn::value(0)),
+            None => self.block(ProcStatus::Waiting { pid }),
+        }
+    }
+
+    /// Returns the ID of the process.
+    pub(super) fn syscall_getpid(&self, _args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        Ok(SyscallReturn::value(self.procs.current.into()))
+    }
+
+    /// Returns the ID of the parent of the process, which is 0 for the first process.
+    pub(super) fn syscall_getppid(&self, _args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let info = self.procs.info(self.procs.current).unwrap();
+        Ok(SyscallReturn::value(info.ppid.into()))
+    }
+
+    /// Gives up the rest of the quantum of the process, so that another process runs if any can.
+    pub(super) fn syscall_sched_yield(&self, _args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let old = self.procs.ticks;
+        let new = self.procs.quantum;
+        Ok(SyscallReturn::new(
+            vec![ProcDiff::Ticks { old, new }.into_state_diff()],
+            0,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_status() {
+        assert_eq!(TermCause::Exit(0x1ff).wait_status(), 0xff00);
+        assert_eq!(TermCause::SegFault.wait_status(), SIGSEGV);
+    }
+}
@@ -1,14 +1,31 @@
-use super::{memory::*, registers::RegFile};
+use super::{
+    errno,
+    hart::{HartDiff, HartSchedule, HartTable},
+    layout::ProgramLayout,
+    memory::*,
+    mm::MapFlags,
+    process::{ProcDiff, ProcTable, INIT_PID},
+    registers::RegFile,
+    startup::InitialStack,
+    stdin::{Stdin, StdinSource},
+    syscall::*,
+    vfs::{OpenFlags, Stat, Vfs},
+};
 pub use super::{phys::*, priv_s::*, user::*};
 use crate::{
     arch::*,
     assembler::{ErrorReport, Linker, SectionStore},
-    config::{MemConfig, SegmentStarts},
+    config::{MemConfig, PtKind},
     data_structures::*,
-    instruction::ConcreteInst,
+    instruction::{ConcreteInst, InstDecoder},
+};
+use num_traits::cast::AsPrimitive;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    rc::Rc,
+    str::FromStr,
 };
-use num_traits::{cast::AsPrimitive, ops::wrapping::WrappingSub};
-use std::str::FromStr;
 
 /// Defines architecture-specific behavior that defines the execution of a program.
 pub trait ProgramBehavior<F, S>
@@ -20,125 +37,335 @@ where
     fn sp_register() -> F::Register;
     /// Returns the register that holds function return values.
     fn return_register() -> F::Register;
+    /// Returns the register that holds the address a function returns to.
+    fn ra_register() -> F::Register;
+    /// Returns the registers that hold the first arguments of a function call, in order.
+    fn arg_registers() -> Vec<F::Register>;
 }
 
+/// Maps an address to the names of the labels that point to it.
+pub type SymbolTable = BTreeMap<u64, Vec<String>>;
+
 #[derive(Clone)]
 pub struct ProgramResetParams {
-    start_inst_idx: usize,
-    segment_starts: SegmentStarts,
-    sections: SectionStore,
-    mem_config: MemConfig,
+    /// The address of the first instruction to execute.
+    entry: u64,
+    layout: ProgramLayout,
+    /// Chunks of bytes to copy into memory, each paired with the address it begins at.
+    image: Vec<(u64, Vec<u8>)>,
+    /// The arguments of the program, starting with its name, which are placed on the stack.
+    args: Vec<String>,
+    /// The environment variables of the program, each of the form NAME=VALUE.
+    env: Vec<String>,
+    /// Whether argc, argv, and envp are also passed in the argument registers, as if the entry
+    /// point were called like main.
+    args_in_regs: bool,
 }
 
 pub struct Program<A: Architecture> {
     insts: Vec<<A::Family as ArchFamily<A::DataWidth>>::Instruction>,
     reset_params: ProgramResetParams,
     pub state: ProgramState<A::Family, A::DataWidth>,
-    text_start: ByteAddrValue<A::DataWidth>,
+    symbols: SymbolTable,
 }
 
 impl<A: Architecture> Program<A> {
     /// Initializes a new program instance from the provided instructions.
     ///
-    /// The instructions are loaded into memory at the start of the instruction section,
-    /// which is specified in SEGMENT_STARTS.
+    /// The instructions are loaded into memory at the start of the text section, and the data
+    /// given in SECTIONS is used to initialize the rodata, data, and bss sections. LAYOUT
+    /// determines where each of these sections is placed.
     ///
     /// The program counter is initialized to point to the instruction specified by START_INST_IDX.
-    ///
-    /// The data given in SECTIONS is used to initialize the data and rodata sections.
-    ///
-    /// Until paged memory is implemented, rodata is placed sequentially with data, and
-    /// no guarantees on read-onliness are enforced.
+    /// Since this may be main rather than a startup routine, argc, argv, and envp are passed in
+    /// the argument registers as well as on the stack.
     pub fn new(
         insts: Vec<<A::Family as ArchFamily<A::DataWidth>>::Instruction>,
         start_inst_idx: usize,
-        segment_starts: SegmentStarts,
+        layout: ProgramLayout,
         sections: SectionStore,
         mem_config: MemConfig,
     ) -> Self {
-        let pg_count = mem_config.phys_pn_bits;
-        let pg_ofs_len = mem_config.pg_ofs_bits;
+        let text = insts
+            .iter()
+            .flat_map(|inst| inst.to_machine_code().to_le_bytes())
+            .collect();
+        // bss is already zeroed
+        let image = vec![
+            (layout.text.start, text),
+            (layout.rodata.start, sections.rodata().to_vec()),
+            (layout.data.start, sections.data().to_vec()),
+        ];
+        let entry = layout.text.start + 4 * start_inst_idx as u64;
+        let mut p = Program::with_image(entry, layout, image, mem_config, true);
+        p.insts = insts;
+        p
+    }
+
+    /// Initializes a program whose memory is given directly by IMAGE, a list of byte strings
+    /// paired with the address at which each begins. Execution begins at ENTRY.
+    ///
+    /// Programs created this way were not assembled, and so have no list of instructions. As on
+    /// Linux, argc, argv, and envp are only passed on the stack.
+    pub fn from_image(
+        entry: u64,
+        layout: ProgramLayout,
+        image: Vec<(u64, Vec<u8>)>,
+        mem_config: MemConfig,
+    ) -> Self {
+        Program::with_image(entry, layout, image, mem_config, false)
+    }
+
+    fn with_image(
+        entry: u64,
+        layout: ProgramLayout,
+        image: Vec<(u64, Vec<u8>)>,
+        mem_config: MemConfig,
+        args_in_regs: bool,
+    ) -> Self {
+        let (pg_count, pg_ofs_len) = match mem_config.kind {
+            // virtual addresses are used as physical ones, so physical memory is a single page
+            // spanning the whole address space
+            PtKind::AllMapped => (1, 64),
+            PtKind::FifoLinearPaged => (1 << mem_config.phys_pn_bits, mem_config.pg_ofs_bits),
+        };
         let page_table = mem_config.build_mem();
-        let text_start: ByteAddrValue<A::DataWidth> = segment_starts.text();
         let state = ProgramState::new(pg_count, pg_ofs_len, page_table);
         let mut p = Program {
-            insts,
+            insts: Vec::new(),
             reset_params: ProgramResetParams {
-                start_inst_idx,
-                segment_starts,
-                sections,
-                mem_config,
+                entry,
+                layout,
+                image,
+                args: Vec::new(),
+                env: Vec::new(),
+                args_in_regs,
             },
             state,
-            text_start,
+            symbols: SymbolTable::new(),
         };
         p.reset();
         p
     }
 
+    /// Attaches the names of labels defined by the program, which are used for disassembly.
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Sets the arguments and environment variables passed to the program, and resets it so that
+    /// they are placed on its stack. ARGS should begin with the name of the program.
+    pub fn with_args(mut self, args: Vec<String>, env: Vec<String>) -> Self {
+        self.reset_params.args = args;
+        self.reset_params.env = env;
+        self.reset();
+        self
+    }
+
+    /// Sets the filesystem the program starts with.
+    pub fn with_vfs(mut self, vfs: Vfs) -> Self {
+        self.state.priv_state.set_vfs(vfs);
+        self.reset();
+        self
+    }
+
+    /// Sets the seed of the random number generator that getrandom draws from.
+    pub fn with_random_seed(mut self, seed: u64) -> Self {
+        self.state.priv_state.set_random_seed(seed);
+        self
+    }
+
+    /// Stores the errno of every failed syscall to the 32-bit word at ADDR, where a C library
+    /// keeps errno. Syscalls still return the negated errno as well.
+    pub fn with_errno_mirror(mut self, addr: u64) -> Self {
+        self.state.priv_state.errno_addr = Some(addr.into());
+        self
+    }
+
+    /// Handles syscall NUMBER with HANDLER, in place of any built-in syscall with that number.
+    /// The handler stays registered when the program is reset.
+    pub fn with_syscall_handler<H>(mut self, number: i64, handler: H) -> Self
+    where
+        H: Fn(
+                &ProgramState<A::Family, A::DataWidth>,
+                &SyscallArgs<A::DataWidth>,
+            ) -> SyscallResult<A::Family, A::DataWidth>
+            + 'static,
+    {
+        self.state.register_syscall(number, handler);
+        self
+    }
+
+    /// Sets how many instructions a process runs before the scheduler switches to another.
+    pub fn with_quantum(mut self, quantum: u64) -> Self {
+        self.state.set_quantum(quantum);
+        self
+    }
+
+    /// Runs the program on COUNT harts, which take turns running instructions according to
+    /// SCHEDULE, and resets it so that each hart starts at the entry point. Every hart but the
+    /// first is given a stack of a single page below the initial stack, and each stack is
+    /// separated from the next by an unmapped guard page.
+    pub fn with_harts(mut self, count: u32, schedule: HartSchedule) -> Self {
+        self.state.set_harts(count, schedule);
+        self.reset();
+        self
+    }
+
+    /// Sets where the program reads its standard input from.
+    pub fn with_stdin(mut self, source: StdinSource) -> Self {
+        self.state.set_stdin(source);
+        self
+    }
+
     /// Resets the state of this program.
     pub fn reset(&mut self) {
         let ProgramResetParams {
-            start_inst_idx,
-            segment_starts,
-            sections,
-            mem_config,
+            entry,
+            layout,
+            image,
+            args,
+            env,
+            args_in_regs,
         } = &self.reset_params;
-        let pg_ofs_len = mem_config.pg_ofs_bits;
-        let text_start: ByteAddrValue<A::DataWidth> = segment_starts.text();
-        let stack_start: ByteAddrValue<A::DataWidth> = segment_starts.stack();
-        let data_start: ByteAddrValue<A::DataWidth> = segment_starts.data();
+        let stack = InitialStack::new(
+            layout.stack_start,
+            std::mem::size_of::<<A::DataWidth as DataWidth>::U>(),
+            args,
+            env,
+            layout.page_size,
+            *entry,
+        );
         self.state.reset();
         let state = &mut self.state;
-        let mem = &mut state.phys_state.phys_mem;
-        let pt = &mut state.priv_state.page_table;
-        // Page in text, stack, and data
-        pt.force_map_page(mem, text_start).unwrap();
-        pt.force_map_page(mem, stack_start).unwrap();
-        pt.force_map_page(mem, data_start).unwrap();
-        let mut user_state = &mut state.user_state;
+        // every hart but the first runs on a page of its own below that of the one before it,
+        // with an unmapped page between them so that overflowing a stack faults rather than
+        // corrupting the next one
+        let stack_bottom = stack.sp / layout.page_size * layout.page_size;
+        let hart_sps: Vec<u64> = (1..state.harts.count() as u64)
+            .map(|id| stack_bottom - (2 * id - 1) * layout.page_size)
+            .collect();
+        state.priv_state.heap_start = layout.heap_start.into();
+        state.priv_state.brk = layout.heap_start.into();
+        // Page in every section, as well as the first pages of the stack and heap
+        // Sections may share a page, so each page is only mapped once
+        let pages = layout
+            .regions()
+            .iter()
+            .flat_map(|(_, region)| layout.pages(*region))
+            .chain((stack.sp..=layout.stack_start).step_by(layout.page_size as usize))
+            .chain([layout.stack_start, layout.heap_start])
+            .chain(hart_sps.iter().map(|sp| sp - layout.page_size))
+            .map(|addr| addr / layout.page_size * layout.page_size)
+            .collect::<BTreeSet<_>>();
+        for page in pages {
+            state
+                .priv_state
+                .page_table
+                .force_map_page(&mut state.phys_state.phys_mem, page.into())
+                .unwrap();
+        }
+        let user_state = &mut state.user_state;
         let sp = <A::ProgramBehavior as ProgramBehavior<A::Family, A::DataWidth>>::sp_register();
+        let ra = <A::ProgramBehavior as ProgramBehavior<A::Family, A::DataWidth>>::ra_register();
+        let text_end: ByteAddrValue<A::DataWidth> = layout.text.end().into();
+        let reg_value = |addr: u64| ByteAddrValue::<A::DataWidth>::from(addr).into();
         // Initialize SP and PC
-        user_state.regfile.set(sp, stack_start.into());
-        user_state.pc =
-            text_start + (UnsignedValue::<A::DataWidth>::from(4 * start_inst_idx)).into();
-        // store instructions
-        let mut next_addr: ByteAddrValue<A::DataWidth> = user_state.pc;
-        for inst in &self.insts {
-            state.memory_force_set(next_addr, DataLword::from(inst.to_machine_code()));
-            next_addr = next_addr.plus_4()
-        }
-        // store data
-        let all_data = sections
-            .data()
-            .iter()
-            .chain(sections.rodata().iter())
-            .cloned();
-        let data_start_usize = AsPrimitive::<usize>::as_(data_start.as_unsigned().raw());
-        let mut end_of_data: usize = data_start_usize;
-        for (offs, byte) in all_data.enumerate() {
-            let addr: ByteAddrValue<A::DataWidth> =
-                UnsignedValue::<A::DataWidth>::from(data_start_usize + offs).into();
-            state.memory_force_set(addr, byte.into());
-            end_of_data = data_start_usize + offs;
-        }
-        // Round up to next page
-        let heap_start = ((end_of_data >> pg_ofs_len) + 1) << 1;
-        // Can't reuse variables for lifetime reasons
-        state
-            .priv_state
-            .page_table
-            .force_map_page(
-                &mut state.phys_state.phys_mem,
-                UnsignedValue::<A::DataWidth>::from(heap_start).into(),
-            )
-            .unwrap();
+        user_state.regfile.set(sp, reg_value(stack.sp));
+        if *args_in_regs {
+            let arg_regs =
+                <A::ProgramBehavior as ProgramBehavior<A::Family, A::DataWidth>>::arg_registers();
+            let argc = args.len() as u64;
+            for (&reg, value) in arg_regs.iter().zip([argc, stack.argv, stack.envp]) {
+                user_state.regfile.set(reg, reg_value(value));
+            }
+        }
+        // Returning from the first function jumps to the end of text, which ends the program
+        user_state.regfile.set(ra, text_end.into());
+        user_state.pc = (*entry).into();
+        // store instructions, data, and the initial stack
+        let stack_bytes = (stack.sp, stack.bytes);
+        for (start, bytes) in image.iter().chain([&stack_bytes]) {
+            for (offs, byte) in bytes.iter().enumerate() {
+                state.memory_force_set((start + offs as u64).into(), DataByte::from(*byte));
+            }
+        }
+        // Only now can stores to read-only sections be forbidden
+        state.priv_state.layout = Some(*layout);
+        let mut user_states = vec![state.user_state.clone()];
+        for sp_value in hart_sps {
+            let mut user_state = state.user_state.clone();
+            user_state.regfile.set(sp, reg_value(sp_value));
+            user_states.push(user_state);
+        }
+        state.start_harts(user_states);
+    }
+
+    /// Returns the placement of each section of this program in memory.
+    pub fn layout(&self) -> &ProgramLayout {
+        &self.reset_params.layout
+    }
+
+    /// Returns the address of the first instruction to execute.
+    pub fn entry(&self) -> u64 {
+        self.reset_params.entry
+    }
+
+    /// Returns the bytes loaded into memory when this program is reset, each paired with the
+    /// address it begins at.
+    pub fn image(&self) -> &[(u64, Vec<u8>)] {
+        &self.reset_params.image
+    }
+
+    /// Returns the bytes of the image from START up to END, with zeroes wherever the image does
+    /// not place anything.
+    pub fn image_bytes(&self, start: u64, end: u64) -> Vec<u8> {
+        let mut bytes = vec![0; end.saturating_sub(start) as usize];
+        for (chunk_start, chunk) in self.image() {
+            for (i, &b) in chunk.iter().enumerate() {
+                let addr = chunk_start + i as u64;
+                if start <= addr && addr < end {
+                    bytes[(addr - start) as usize] = b;
+                }
+            }
+        }
+        bytes
     }
 
     pub fn insts(&self) -> &Vec<<A::Family as ArchFamily<A::DataWidth>>::Instruction> {
         &self.insts
     }
 
+    /// Returns the names of the labels at each address of this program.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Decodes the words in memory from START up to END, rendering one per line alongside its
+    /// address and machine code. Any labels pointing into the range are printed before the line
+    /// at their address, and words that do not encode an instruction are marked as illegal.
+    pub fn disassemble(&self, start: u64, end: u64) -> String {
+        let mut out = String::new();
+        for addr in (start..end).step_by(4) {
+            for name in self.symbols.get(&addr).into_iter().flatten() {
+                writeln!(out, "<{}>:", name).unwrap();
+            }
+            let line = match self.state.memory_get::<W32b>(addr.into()) {
+                Ok((word, _)) => {
+                    let code = u32::from(word);
+                    match <A::InstDecoder as InstDecoder<A::Family, A::DataWidth>>::decode(code) {
+                        Ok(inst) => format!("{:08x}  {}", code, inst),
+                        Err(e) => format!("{:08x}  <{}>", code, e),
+                    }
+                }
+                Err(_) => "<unmapped>".to_string(),
+            };
+            writeln!(out, "{:#010x}:  {}", addr, line).unwrap();
+        }
+        out
+    }
+
     /// Prints out all the instructions that this program contains.
     pub fn dump_insts(&self) {
         for inst in self.insts() {
@@ -151,14 +378,24 @@ impl<A: Architecture> Program<A> {
     /// The lower 7 bits are the value passed to the exit handler (or the default register for the
     /// first argument of a syscall if exit is not explicitly invoked), and will be truncated.
     pub fn run(&mut self) -> u8 {
-        // for now, just use the instruction vec to determine the next instruction
-        // for now, if we're out of instructions just call it a day
-        // if pc dipped below pc_start, panic for now is also fine
-        while let Some(inst) = self.insts.get(self.get_pc_word_index()) {
-            if let Err(cause) = self.state.apply_inst(inst) {
+        while !self.is_finished() {
+            if let Err(cause) = self.state.step_inst::<A::InstDecoder>() {
                 return cause.handle_exit(&mut self.state);
             }
         }
+        self.implicit_exit_code()
+    }
+
+    /// Returns true if the program counter of the first hart of the first process has run off the
+    /// end of the text section, which is treated as an exit without a call to the exit syscall.
+    fn is_finished(&self) -> bool {
+        self.state.running_pid() == INIT_PID
+            && self.state.running_hart() == 0
+            && self.state.get_pc().bits() == self.layout().text.end()
+    }
+
+    /// Returns the exit code of a program that finished without calling the exit syscall.
+    fn implicit_exit_code(&self) -> u8 {
         let a0 =
             <A::ProgramBehavior as ProgramBehavior<A::Family, A::DataWidth>>::return_register();
         let a0_val: UnsignedValue<A::DataWidth> = self.state.regfile_read(a0).into();
@@ -176,16 +413,6 @@ impl<A: Architecture> Program<A> {
     pub fn get_state(self) -> ProgramState<A::Family, A::DataWidth> {
         self.state
     }
-
-    fn get_pc_word_index(&self) -> usize {
-        let pc_start: ByteAddrValue<A::DataWidth> = self.text_start;
-        // all this logic calculates the next address (very verbose due to generic types)
-        let curr_pc: UnsignedValue<A::DataWidth> = self.state.user_state.pc.into();
-        let orig_pc: UnsignedValue<A::DataWidth> = pc_start.into();
-        let offs: ByteAddrValue<A::DataWidth> = curr_pc.wrapping_sub(&orig_pc).into();
-        let idx: usize = offs.to_word_address().as_();
-        idx
-    }
 }
 
 impl<A: Architecture> FromStr for Program<A> {
@@ -201,6 +428,9 @@ pub struct ProgramExecutor<A: Architecture> {
     /// Represents the history of instructions executed by the program.
     /// The diffs in each instruction should be executed in sequence.
     pub inst_stack: Vec<DiffStack<A::Family, A::DataWidth>>,
+    /// The hart that ran each instruction in inst_stack. Every diff of an instruction was
+    /// produced by that hart, except for the trailing diff that switches to another.
+    pub inst_harts: Vec<u32>,
     /// Represents the index of the next InstResult to be applied.
     /// For example, when this value is 1, the 0th InstResult was applied, and advancing
     /// to the next InstResult would either fail or apply the 1th InstResult.
@@ -217,6 +447,7 @@ impl<A: Architecture> ProgramExecutor<A> {
         ProgramExecutor {
             program,
             inst_stack: Vec::new(),
+            inst_harts: Vec::new(),
             curr_inst_idx: 0,
             curr_step_idx: 0,
         }
@@ -231,13 +462,19 @@ impl<A: Architecture> ProgramExecutor<A> {
     pub fn reset(&mut self) {
         self.program.reset();
         self.inst_stack.clear();
+        self.inst_harts.clear();
         self.curr_inst_idx = 0;
         self.curr_step_idx = 0;
     }
 
-    pub fn curr_inst(&self) -> Option<&<A::Family as ArchFamily<A::DataWidth>>::Instruction> {
+    /// Returns the instruction in memory at the program counter, or None if it cannot be fetched.
+    pub fn curr_inst(&self) -> Option<<A::Family as ArchFamily<A::DataWidth>>::Instruction> {
         assert!(self.curr_inst_idx <= self.inst_stack.len());
-        self.program.insts().get(self.program.get_pc_word_index())
+        self.program
+            .state
+            .fetch_inst::<A::InstDecoder>()
+            .ok()
+            .map(|(inst, _)| inst)
     }
 
     /// Runs the next instruction of the program.
@@ -248,24 +485,18 @@ impl<A: Architecture> ProgramExecutor<A> {
         let rv = if self.curr_inst_idx == self.inst_stack.len() {
             assert!(self.curr_step_idx == 0);
             // If the inst_stack was exhausted, apply a new instruction
-            // TODO gracefully handle out of bounds instructions
-            // for now, if we reach an oob instruction just report the return value
-            if let Some(inst) = program.insts.get(program.get_pc_word_index()) {
-                let exec_result = program.state.apply_inst(inst);
-                match exec_result {
+            if program.is_finished() {
+                Some(program.implicit_exit_code())
+            } else {
+                let hart = program.state.running_hart();
+                match program.state.step_inst::<A::InstDecoder>() {
                     Ok(inst_result) => {
                         self.inst_stack.push(inst_result);
+                        self.inst_harts.push(hart);
                         None
                     }
                     Err(cause) => Some(cause.handle_exit(&mut program.state)),
                 }
-            } else {
-                let a0 =
-                    <A::ProgramBehavior as ProgramBehavior<A::Family, A::DataWidth>>::return_register();
-                let a0_val: UnsignedValue<A::DataWidth> = program.state.regfile_read(a0).into();
-                // For a non-abnormal exit, downcast to u8 and set upper bit to 0
-                let val_u8 = AsPrimitive::<u8>::as_(a0_val.raw());
-                Some(val_u8 & 0b0111_1111)
             }
         } else {
             // Run current inst to completion
@@ -280,7 +511,7 @@ impl<A: Architecture> ProgramExecutor<A> {
             None
         };
         // hack to get around the fact that exits aren't stored
-        if rv == None {
+        if rv.is_none() {
             self.curr_inst_idx += 1;
         }
         self.curr_step_idx = 0;
@@ -331,6 +562,12 @@ pub struct ProgramState<F: ArchFamily<S>, S: DataWidth> {
     pub(crate) user_state: UserState<F, S>,
     pub(crate) priv_state: PrivState<S>,
     pub(crate) phys_state: PhysState,
+    /// Syscalls handled by the embedder rather than by the simulated kernel.
+    pub(crate) syscalls: SyscallRegistry<F, S>,
+    /// Every process, including the running one, whose context is held by the other states.
+    pub(crate) procs: ProcTable<F, S>,
+    /// Every hart, including the running one, whose context is held by the other states.
+    pub(crate) harts: HartTable<F, S>,
 }
 
 impl<F: ArchFamily<S>, S: DataWidth> Default for ProgramState<F, S> {
@@ -341,9 +578,11 @@ impl<F: ArchFamily<S>, S: DataWidth> Default for ProgramState<F, S> {
 }
 
 pub type MemGetResult<F, S, W> = (RegValue<W>, DiffStack<F, S>);
+/// Bytes read from memory, along with the state updates performed by the reads.
+pub type BytesGetResult<F, S> = (Vec<u8>, DiffStack<F, S>);
+/// A fetched instruction, along with the state updates performed by the fetch.
+pub type FetchResult<F, S> = (<F as ArchFamily<S>>::Instruction, DiffStack<F, S>);
 
-/// TODO put custom types for syscall args
-/// TODO put errno on user state at a thread-local statically known location
 impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
     pub fn get_stdout(&self) -> &[u8] {
         self.priv_state.stdout.as_slice()
@@ -353,6 +592,16 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
         self.priv_state.stderr.as_slice()
     }
 
+    /// Replaces the standard input of the program, discarding any input not yet read.
+    pub fn set_stdin(&mut self, source: StdinSource) {
+        self.priv_state.stdin = Stdin::new(source);
+    }
+
+    /// Returns the filesystem of the program.
+    pub fn vfs(&self) -> &Vfs {
+        &self.priv_state.vfs
+    }
+
     pub fn write_stderr(&mut self, string: &str) {
         self.priv_state.stderr.extend(string.as_bytes());
         eprint!("{}", string);
@@ -386,6 +635,30 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
         &self.user_state.regfile
     }
 
+    /// Returns the diff that sets REG to VAL, for syscall handlers that return more than one
+    /// value.
+    pub fn regfile_update(&self, reg: F::Register, val: RegValue<S>) -> StateDiff<F, S> {
+        UserDiff::reg_update(&self.user_state, reg, val).into_state_diff()
+    }
+
+    /// Handles syscall NUMBER with HANDLER, in place of any built-in syscall with that number.
+    /// Returns whether another handler was replaced.
+    pub fn register_syscall<H>(&mut self, number: i64, handler: H) -> bool
+    where
+        H: Fn(&ProgramState<F, S>, &SyscallArgs<S>) -> SyscallResult<F, S> + 'static,
+    {
+        self.syscalls.register(number, Rc::new(handler)).is_some()
+    }
+
+    /// Removes the handler registered for syscall NUMBER, returning whether there was one.
+    pub fn unregister_syscall(&mut self, number: i64) -> bool {
+        self.syscalls.unregister(number).is_some()
+    }
+
+    pub fn syscalls(&self) -> &SyscallRegistry<F, S> {
+        &self.syscalls
+    }
+
     pub fn get_pc(&self) -> ByteAddrValue<S> {
         self.user_state.pc
     }
@@ -398,6 +671,9 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
     ) -> Result<MemGetResult<F, S, W>, MemFault<S>> {
         // TODO how do we handle lookups spanning multiple pages? how do we handle a PT update that
         // failed on memory access due to an alignment error?
+        if self.priv_state.is_unreadable(vaddr) {
+            return Err(MemFault::segfault_at_addr(vaddr));
+        }
         let PtLookupData {
             diffs: pt_diffs,
             ppn,
@@ -421,6 +697,9 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
         data: DataEnum,
     ) -> Result<DiffStack<F, S>, MemFault<S>> {
         // TODO see memory_get
+        if self.priv_state.is_readonly(vaddr) {
+            return Err(MemFault::segfault_at_addr(vaddr));
+        }
         let PtLookupData {
             diffs: pt_diffs,
             ppn,
@@ -447,6 +726,9 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
         data: RegValue<W>,
     ) -> Result<DiffStack<F, S>, MemFault<S>> {
         // TODO see memory_get
+        if self.priv_state.is_readonly(vaddr) {
+            return Err(MemFault::segfault_at_addr(vaddr));
+        }
         let PtLookupData {
             diffs: pt_diffs,
             ppn,
@@ -465,6 +747,63 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
         Ok(diffs)
     }
 
+    /// Reads LEN bytes of memory starting at ADDR. Since LEN often comes from the program, space
+    /// for the bytes is only allocated as they are read, so a length far larger than the mapped
+    /// memory fails at the first unmapped byte.
+    pub fn memory_get_bytes(
+        &self,
+        addr: ByteAddrValue<S>,
+        len: usize,
+    ) -> Result<BytesGetResult<F, S>, MemFault<S>> {
+        let mut diffs = Vec::new();
+        let mut bytes = Vec::new();
+        for i in 0..len as u64 {
+            let (byte, byte_diffs) = self.memory_get::<W8b>(addr.bits().wrapping_add(i).into())?;
+            bytes.push(u8::from(byte));
+            diffs.extend(byte_diffs);
+        }
+        Ok((bytes, diffs))
+    }
+
+    /// Writes DATA to memory starting at ADDR.
+    pub fn memory_set_bytes(
+        &self,
+        addr: ByteAddrValue<S>,
+        data: &[u8],
+    ) -> Result<DiffStack<F, S>, MemFault<S>> {
+        let mut diffs = Vec::new();
+        for (i, &byte) in data.iter().enumerate() {
+            let byte_addr = addr.bits().wrapping_add(i as u64).into();
+            diffs.extend(self.memory_set::<W8b>(byte_addr, DataByte::from(byte))?);
+        }
+        Ok(diffs)
+    }
+
+    /// Reads the null-terminated string at ADDR, without its terminator, for a syscall. Fails with
+    /// an errno if the string cannot be read or is longer than PATH_MAX.
+    pub fn memory_get_cstring(&self, addr: ByteAddrValue<S>) -> Result<BytesGetResult<F, S>, i64> {
+        const PATH_MAX: u64 = 4096;
+        let mut diffs = Vec::new();
+        let mut bytes = Vec::new();
+        for i in 0..PATH_MAX {
+            let (byte, byte_diffs) = self
+                .memory_get::<W8b>(addr.bits().wrapping_add(i).into())
+                .map_err(|_| errno::EFAULT)?;
+            diffs.extend(byte_diffs);
+            match u8::from(byte) {
+                0 => return Ok((bytes, diffs)),
+                b => bytes.push(b),
+            }
+        }
+        Err(errno::ENAMETOOLONG)
+    }
+
+    /// Returns the diff that places VALUE in the register holding the result of a syscall.
+    pub(super) fn syscall_return(&self, value: i64) -> DiffStack<F, S> {
+        let ret_reg = <F::Syscalls as SyscallConvention<F, S>>::syscall_return_regs()[0];
+        UserDiff::reg_update(&self.user_state, ret_reg, value.into()).into_diff_stack()
+    }
+
     /// Used to inspect memory. Any page table updates will not be performed.
     pub fn memory_inspect_word(&self, addr: ByteAddrValue<S>) -> DataLword {
         let (v, _diffs) = self.memory_get::<W32b>(addr).unwrap();
@@ -513,137 +852,108 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
             TrapKind::MemFault(MemFault {
                 user_vaddr: _,
                 cause,
-            }) => Ok(match cause {
+            }) => Ok(self.end_process(match cause {
                 // even though the OS could attempt to map the page,
                 // we requite the user to manually call brk/sbrk/mmap etc.
-                MemFaultCause::PageFault => PrivDiff::Terminate(TermCause::SegFault),
-                MemFaultCause::SegFault => PrivDiff::Terminate(TermCause::SegFault),
-                MemFaultCause::BusError => PrivDiff::Terminate(TermCause::BusError),
-            }
-            .into_diff_stack()),
+                MemFaultCause::PageFault => TermCause::SegFault,
+                MemFaultCause::SegFault => TermCause::SegFault,
+                MemFaultCause::BusError => TermCause::BusError,
+            })),
+            TrapKind::IllegalInstruction(_) => Ok(self.end_process(TermCause::IllegalInstruction)),
+            // Linux reports misaligned instruction fetches as SIGBUS
+            TrapKind::InstAddrMisaligned(_) => Ok(self.end_process(TermCause::BusError)),
             _ => todo!(),
         }
     }
 
     pub fn dispatch_syscall(&self) -> InstResult<F, S> {
-        let rf = &self.user_state.regfile;
         let syscall_number_reg = <F::Syscalls as SyscallConvention<F, S>>::syscall_number_reg();
-        let arg_regs = <F::Syscalls as SyscallConvention<F, S>>::syscall_arg_regs();
-        let a0 = rf.read(arg_regs[0]);
-        let a1 = rf.read(arg_regs[1]);
-        let a2 = rf.read(arg_regs[2]);
-        if let Some(nr) = <F::Syscalls as SyscallConvention<F, S>>::number_to_syscall(
-            self.user_state.regfile.read(syscall_number_reg).into(),
-        ) {
-            match nr {
-                Syscall::Write => self.syscall_write(a0, a1.into(), a2),
-                Syscall::Exit => self.syscall_exit(a0),
-                Syscall::Brk => self.syscall_brk(a0.into()),
-                _ => unimplemented!(),
-            }
+        let args = SyscallArgs::from_regfile::<F>(&self.user_state.regfile);
+        let number: SignedValue<S> = self.user_state.regfile.read(syscall_number_reg).into();
+        let result = if let Some(handler) = self.syscalls.get(AsPrimitive::<i64>::as_(number.raw()))
+        {
+            handler(self, &args)
         } else {
-            self.syscall_unknown()
+            match <F::Syscalls as SyscallConvention<F, S>>::number_to_syscall(number) {
+                Some(syscall) => self.handle_syscall(syscall, &args),
+                // unknown syscalls fail without doing anything
+                None => Err(errno::ENOSYS),
+            }
+        };
+        Ok(self.finish_syscall(result))
+    }
+
+    /// Performs a syscall with the provided arguments.
+    pub fn handle_syscall(&self, syscall: Syscall, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        match syscall {
+            Syscall::Openat => self.syscall_openat(args),
+            Syscall::Open => self.syscall_open(args),
+            Syscall::Close => self.syscall_close(args),
+            Syscall::Read => self.syscall_read(args),
+            Syscall::Write => self.syscall_write(args),
+            Syscall::Lseek => self.syscall_lseek(args),
+            Syscall::Llseek => self.syscall_llseek(args),
+            Syscall::Fstat => self.syscall_fstat(args),
+            Syscall::Exit => self.syscall_exit(args),
+            Syscall::ExitGroup => self.syscall_exit_group(args),
+            Syscall::Brk => self.syscall_brk(args),
+            Syscall::Mmap => self.syscall_mmap(args),
+            Syscall::Munmap => self.syscall_munmap(args),
+            Syscall::Gettimeofday => self.syscall_gettimeofday(args),
+            Syscall::ClockGettime => self.syscall_clock_gettime(args),
+            Syscall::Getrandom => self.syscall_getrandom(args),
+            Syscall::Uname => self.syscall_uname(args),
+            Syscall::Getpid => self.syscall_getpid(args),
+            Syscall::Getppid => self.syscall_getppid(args),
+            Syscall::Fork => self.syscall_fork(args),
+            Syscall::Clone => self.syscall_clone(args),
+            Syscall::Wait4 => self.syscall_wait4(args),
+            Syscall::SchedYield => self.syscall_sched_yield(args),
+            Syscall::Pipe2 => self.syscall_pipe2(args),
+            Syscall::Dup => self.syscall_dup(args),
+            Syscall::Dup2 => self.syscall_dup2(args),
+            Syscall::Dup3 => self.syscall_dup3(args),
         }
     }
 
-    /// Writes contents to a specified file descriptor.
-    /// TODO for now, this is hardcoded to print to stdout regardless of the provided FD.
-    /// * fd - file descriptor
-    /// * buf - pointer to the buffer to be written
-    /// * len - the number of bytes to write
-    fn syscall_write(
-        &self,
-        fd: RegValue<S>,
-        buf: ByteAddrValue<S>,
-        len: RegValue<S>,
-    ) -> InstResult<F, S> {
-        let len_val: UnsignedValue<S> = len.into();
-        let count: usize = len_val.raw().as_();
-        let base_addr: UnsignedValue<S> = buf.into();
-        let ret_reg = <F::Syscalls as SyscallConvention<F, S>>::syscall_return_regs()[0];
-        let mut v = Vec::new();
-        let bytes: Vec<u8> = (0..count)
-            .map(|i| {
-                u8::from({
-                    let (val, diffs) = self
-                        .memory_get::<W8b>((base_addr + UnsignedValue::<S>::from(i)).into())
-                        .unwrap();
-                    let byte: DataByte = val;
-                    v.extend(diffs);
-                    byte
-                })
-            })
-            .collect();
-        // Awkward here, but changing type sig makes common case less ergonomic
-        v.push(PrivDiff::FileWrite { fd, data: bytes }.into_state_diff());
-        v.push(UserDiff::reg_update(&self.user_state, ret_reg, len).into_state_diff());
-        Ok(v)
-    }
-
-    /// Attempts to move the "program break" up to the indicated location.
-    /// For us, this means the OS will attempt to page in memory up to the designated address.
-    /// TODO unmap pages if brk goes down, and allocate multiple pages, also check edge case where
-    /// brk lands on page boundary
-    /// * addr - the address whose page should be mapped afterwards
-    fn syscall_brk(&self, addr: ByteAddrValue<S>) -> InstResult<F, S> {
-        let old_brk: RegValue<S> = self.priv_state.brk.into();
-        let ret_reg = <F::Syscalls as SyscallConvention<F, S>>::syscall_return_regs()[0];
-        if let Ok(lookup_result) = self.priv_state.page_table.lookup_page(addr) {
-            let mut diffs: Vec<StateDiff<F, S>> = lookup_result
-                .diffs
-                .into_iter()
-                .map(|u| u.into_state_diff())
-                .collect();
-            diffs.push(
-                PrivDiff::BrkUpdate {
-                    old: old_brk.into(),
-                    new: addr,
-                }
-                .into_state_diff(),
-            );
-            diffs.push(
-                UserDiff::reg_update(&self.user_state, ret_reg, SignedValue::<S>::zero().into())
-                    .into_state_diff(),
-            );
-            // Update brk, return the old value, and propagate PT state changes
-            Ok(diffs)
-        } else if let Ok(updates) = self.priv_state.page_table.map_page(addr) {
-            let mut diffs: Vec<StateDiff<F, S>> =
-                updates.into_iter().map(|u| u.into_state_diff()).collect();
-            diffs.push(
-                PrivDiff::BrkUpdate {
-                    old: old_brk.into(),
-                    new: addr,
-                }
-                .into_state_diff(),
-            );
-            diffs.push(
-                UserDiff::reg_update(&self.user_state, ret_reg, SignedValue::<S>::zero().into())
-                    .into_state_diff(),
-            );
-            Ok(diffs)
-        } else {
-            Ok(
-                UserDiff::reg_update(&self.user_state, ret_reg, SignedValue::from(-1isize).into())
-                    .into_diff_stack(),
-            )
+    /// Returns the diffs that complete a syscall, which place its return value in the return
+    /// register. As on Linux, a failed syscall returns its errno negated. If the program has an
+    /// errno mirror, the errno is also stored there, as a C library would do; a mirror that
+    /// cannot be written is ignored.
+    fn finish_syscall(&self, result: SyscallResult<F, S>) -> DiffStack<F, S> {
+        let (mut diffs, value) = match result {
+            Ok(SyscallReturn { diffs, value }) => (diffs, value),
+            Err(errno) => {
+                let mirror = self.priv_state.errno_addr.and_then(|addr| {
+                    self.memory_set::<W32b>(addr, DataLword::from(errno as u32))
+                        .ok()
+                });
+                (mirror.unwrap_or_default(), Some(-errno))
+            }
+        };
+        if let Some(value) = value {
+            diffs.extend(self.syscall_return(value));
         }
+        diffs
     }
 
-    /// Exits the program with the provided 32-bit code.
+    /// Exits the hart with the provided 32-bit code. Exiting the first hart exits the process,
+    /// which ends the program if this is the first process.
     /// Note that the shell will only see the lower 7-bits.
-    fn syscall_exit(&self, code: RegValue<S>) -> InstResult<F, S> {
+    fn syscall_exit(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
         // downcast to u32 no matter what
-        let val: UnsignedValue<S> = code.into();
-        Ok(
-            PrivDiff::Terminate(TermCause::Exit(AsPrimitive::<u32>::as_(val.raw())))
-                .into_diff_stack(),
-        )
+        let code = args.unsigned(0) as u32;
+        Ok(SyscallReturn::noreturn(
+            self.end_hart(TermCause::Exit(code)),
+        ))
     }
 
-    /// Handles an unknown syscall.
-    fn syscall_unknown(&self) -> InstResult<F, S> {
-        panic!("Unknown syscall")
+    /// Exits the process with the provided 32-bit code, no matter which hart makes the call.
+    fn syscall_exit_group(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let code = args.unsigned(0) as u32;
+        Ok(SyscallReturn::noreturn(
+            self.end_process(TermCause::Exit(code)),
+        ))
     }
 
     pub fn new(
@@ -657,6 +967,9 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
             priv_state: PrivState::new(RegValue::<S>::zero().into(), pt),
             // TODO make endianness/alignment configurable
             phys_state: PhysState::new(Endianness::default(), true, phys_pg_count, pg_ofs_len),
+            syscalls: SyscallRegistry::default(),
+            procs: ProcTable::default(),
+            harts: HartTable::default(),
         }
     }
 
@@ -664,12 +977,65 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
         self.user_state = UserState::new();
         self.priv_state.reset();
         self.phys_state.reset();
+        self.procs.reset();
+        self.harts.reset();
     }
 
     pub fn apply_inst(&mut self, inst: &F::Instruction) -> InstResult<F, S> {
         self.apply_diff_stack(inst.apply(self)?)
     }
 
+    /// Reads the word at the program counter and decodes it into an instruction. A trap is
+    /// returned if the program counter is misaligned, the read faults, or the word is not a
+    /// valid instruction.
+    pub fn fetch_inst<D: InstDecoder<F, S>>(&self) -> Result<FetchResult<F, S>, TrapKind<S>> {
+        let pc = self.user_state.pc;
+        if !pc.bits().is_multiple_of(4) {
+            return Err(TrapKind::InstAddrMisaligned(pc));
+        }
+        if self.priv_state.is_unexecutable(pc) {
+            return Err(MemFault::segfault_at_addr(pc).into());
+        }
+        let (word, diffs) = self.memory_get::<W32b>(pc)?;
+        let code = u32::from(word);
+        D::decode(code)
+            .map(|inst| (inst, diffs))
+            .map_err(|_| TrapKind::IllegalInstruction(code))
+    }
+
+    /// Fetches the instruction at the program counter from memory and applies it. Any trap raised
+    /// by the fetch is handled in place of the instruction. If there are several processes, the
+    /// scheduler may then switch to another.
+    pub fn step_inst<D: InstDecoder<F, S>>(&mut self) -> InstResult<F, S> {
+        let diffs = if self.hart_has_returned() {
+            let ret_reg = <F::Syscalls as SyscallConvention<F, S>>::syscall_return_regs()[0];
+            let code: UnsignedValue<S> = self.user_state.regfile.read(ret_reg).into();
+            self.end_hart(TermCause::Exit(AsPrimitive::<u32>::as_(code.raw())))
+        } else if self.has_returned() {
+            let ret_reg = <F::Syscalls as SyscallConvention<F, S>>::syscall_return_regs()[0];
+            let code: UnsignedValue<S> = self.user_state.regfile.read(ret_reg).into();
+            self.end_process(TermCause::Exit(AsPrimitive::<u32>::as_(code.raw())))
+        } else {
+            match self.fetch_inst::<D>() {
+                Ok((inst, mut diffs)) => match inst.apply(self) {
+                    Ok(inst_diffs) => {
+                        diffs.extend(inst_diffs);
+                        diffs.push(PrivDiff::Retire.into_state_diff());
+                        diffs
+                    }
+                    // only the first process takes the program down with it
+                    Err(cause) if self.procs.current() != INIT_PID => self.end_process(cause),
+                    Err(cause) => return Err(cause),
+                },
+                Err(trap_kind) => self.handle_trap(&trap_kind)?,
+            }
+        };
+        let mut diffs = self.apply_diff_stack(diffs)?;
+        diffs.extend(self.apply_diff_stack(self.schedule())?);
+        diffs.extend(self.apply_diff_stack(self.schedule_hart())?);
+        Ok(diffs)
+    }
+
     /// Asserts that applying the instruction does not fail.
     #[cfg(test)]
     pub fn apply_inst_test(&mut self, inst: &F::Instruction) {
@@ -699,6 +1065,14 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
                 self.phys_state.apply_diff(p);
                 Ok(())
             }
+            StateDiff::Proc(p) => {
+                self.apply_proc_diff(p);
+                Ok(())
+            }
+            StateDiff::Hart(h) => {
+                self.apply_hart_diff(h);
+                Ok(())
+            }
         }
     }
 
@@ -710,6 +1084,8 @@ impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
                 .priv_state
                 .revert_diff::<F>(&mut self.phys_state.phys_mem, p),
             StateDiff::Phys(p) => self.phys_state.revert_diff(p),
+            StateDiff::Proc(p) => self.revert_proc_diff(p),
+            StateDiff::Hart(h) => self.revert_hart_diff(h),
         }
     }
 }
@@ -725,6 +1101,28 @@ pub trait SyscallConvention<F: ArchFamily<S>, S: DataWidth> {
     fn syscall_arg_regs() -> Vec<F::Register>;
     /// Returns which registers are used to return arguments from syscalls.
     fn syscall_return_regs() -> Vec<F::Register>;
+    /// Returns the stack pointer, which clone may give a new value in the child.
+    fn stack_pointer_reg() -> F::Register;
+    /// Decodes the flags passed to open and openat.
+    fn open_flags(raw: u64) -> OpenFlags {
+        OpenFlags::from_generic(raw)
+    }
+    /// Lays out the struct filled in by fstat.
+    fn stat_bytes(stat: &Stat) -> Vec<u8> {
+        stat.to_generic_bytes(std::mem::size_of::<S::U>())
+    }
+    /// Decodes the flags passed to mmap.
+    fn map_flags(raw: u64) -> MapFlags {
+        MapFlags::from_generic(raw)
+    }
+    /// Lays out a struct timeval or timespec, given its seconds and its microseconds or
+    /// nanoseconds. By default both fields are 64 bits, as with the time64 syscalls of 32-bit
+    /// architectures.
+    fn time_bytes(secs: u64, frac: u64) -> Vec<u8> {
+        [secs.to_le_bytes(), frac.to_le_bytes()].concat()
+    }
+    /// Returns the machine reported by uname.
+    fn machine_name() -> &'static str;
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -732,10 +1130,30 @@ pub enum Syscall {
     Read,
     Write,
     Open,
+    Openat,
     Close,
+    Lseek,
+    Llseek,
+    Fstat,
     Exit,
+    ExitGroup,
     Brk,
     Mmap,
+    Munmap,
+    Gettimeofday,
+    ClockGettime,
+    Getrandom,
+    Uname,
+    Getpid,
+    Getppid,
+    Fork,
+    Clone,
+    Wait4,
+    SchedYield,
+    Pipe2,
+    Dup,
+    Dup2,
+    Dup3,
 }
 
 #[derive(Copy, Clone)]
@@ -757,13 +1175,16 @@ pub enum StateDiff<F: ArchFamily<S>, S: DataWidth> {
     User(UserDiff<F, S>),
     Priv(PrivDiff<S>),
     Phys(PhysDiff),
+    Proc(ProcDiff<F, S>),
+    Hart(HartDiff),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::architectures::riscv::RiscVRegister::*;
-    use crate::architectures::riscv::Rv32;
+    use crate::architectures::riscv::{RiscV, Rv32};
+    use crate::program_state::OpenFile;
 
     /// Makes sure the executor can step and revert instructions.
     #[test]
@@ -774,10 +1195,12 @@ mod tests {
             addi a0, zero, 16
             ";
         let mut executor = ProgramExecutor::<Rv32>::new(code.parse::<Program<Rv32>>().unwrap());
+        // a1 initially holds argv
+        let argv = executor.program.state.regfile_read(A1);
         // after one operation
         assert_eq!(executor.step(), None);
         assert_eq!(executor.program.state.regfile_read(A0), 4u32.into());
-        assert_eq!(executor.program.state.regfile_read(A1), 0u32.into());
+        assert_eq!(executor.program.state.regfile_read(A1), argv);
         // after two operations
         assert_eq!(executor.step(), None);
         assert_eq!(executor.program.state.regfile_read(A0), 4u32.into());
@@ -785,11 +1208,11 @@ mod tests {
         // rewind once
         assert_eq!(executor.revert(), Some(()));
         assert_eq!(executor.program.state.regfile_read(A0), 4u32.into());
-        assert_eq!(executor.program.state.regfile_read(A1), 0u32.into());
+        assert_eq!(executor.program.state.regfile_read(A1), argv);
         // rewind again
         assert_eq!(executor.revert(), Some(()));
         assert_eq!(executor.program.state.regfile_read(A0), 0u32.into());
-        assert_eq!(executor.program.state.regfile_read(A1), 0u32.into());
+        assert_eq!(executor.program.state.regfile_read(A1), argv);
         // next rewind should fail
         assert_eq!(executor.revert(), None);
         // step 2
@@ -823,11 +1246,12 @@ mod tests {
             v: .word 4
             ";
         let mut executor = ProgramExecutor::<Rv32>::new(code.parse::<Program<Rv32>>().unwrap());
-        // Sanity check of initialization
+        // Sanity check of initialization, where a1 holds argv
+        let argv = executor.program.state.regfile_read(A1);
         assert_eq!(executor.program.state.regfile_read(A0), 0u32.into());
         assert_eq!(executor.step(), None);
         assert_eq!(executor.program.state.regfile_read(A0), 5u32.into());
-        assert_eq!(executor.program.state.regfile_read(A1), 0u32.into());
+        assert_eq!(executor.program.state.regfile_read(A1), argv);
         // Observe after reset
         executor.reset();
         assert_eq!(executor.program.state.regfile_read(A0), 0u32.into());
@@ -858,4 +1282,372 @@ mod tests {
             4u32.into()
         );
     }
+
+    /// Makes sure that reverting or resetting a read puts its input back.
+    #[test]
+    fn test_read_revert() {
+        let code = "
+            addi sp, sp, -16
+            addi a7, zero, 63
+            addi a0, zero, 0
+            mv a1, sp
+            addi a2, zero, 3
+            ecall
+            addi a0, zero, 0
+            addi a1, sp, 3
+            ecall
+            ";
+        let program = code
+            .parse::<Program<Rv32>>()
+            .unwrap()
+            .with_stdin(StdinSource::Bytes(b"hello".to_vec()));
+        let mut executor = ProgramExecutor::new(program);
+        for _ in 0..9 {
+            assert_eq!(executor.step(), None);
+        }
+        // only two bytes were left for the second read
+        assert_eq!(executor.program.state.regfile_read(A0), 2u32.into());
+        let buf = executor.program.state.regfile_read(Sp).as_byte_addr();
+        assert_eq!(
+            executor.program.state.memory_inspect_word(buf),
+            u32::from_le_bytes(*b"hell").into()
+        );
+        executor.revert();
+        assert_eq!(
+            executor.program.state.priv_state.stdin.peek(5).unwrap(),
+            b"lo"
+        );
+        executor.reset();
+        assert_eq!(
+            executor.program.state.priv_state.stdin.peek(5).unwrap(),
+            b"hello"
+        );
+        for _ in 0..6 {
+            assert_eq!(executor.step(), None);
+        }
+        assert_eq!(executor.program.state.regfile_read(A0), 3u32.into());
+    }
+
+    /// Makes sure that reverting file syscalls restores the filesystem and file descriptors.
+    #[test]
+    fn test_file_revert() {
+        let code = "
+            .data
+            path: .string \"/f\"
+            .text
+            li a0, -100
+            la a1, path
+            li a2, 0x442
+            li a7, 56
+            ecall
+            mv s0, a0
+            la a1, path
+            li a2, 2
+            li a7, 64
+            ecall
+            mv a0, s0
+            li a7, 57
+            ecall
+            ";
+        let vfs = Vfs::new().with_file("/f", b"old");
+        let program = code.parse::<Program<Rv32>>().unwrap().with_vfs(vfs);
+        let mut executor = ProgramExecutor::new(program);
+        let mut steps = 0;
+        while executor.step().is_none() {
+            steps += 1;
+        }
+        // opened with O_RDWR | O_CREAT | O_APPEND
+        assert_eq!(executor.state().vfs().read_file("/f"), Some(&b"old/f"[..]));
+        assert_eq!(executor.state().priv_state.open_file(3), None);
+        // undo the close and the write
+        for _ in 0..5 {
+            executor.revert();
+        }
+        assert_eq!(executor.state().vfs().read_file("/f"), Some(&b"old"[..]));
+        let state = executor.state();
+        match state.priv_state.open_file(3) {
+            Some(OpenFile::Vfs { description, .. }) => {
+                assert_eq!(state.priv_state.offsets[*description], 0)
+            }
+            file => panic!("expected an open file, found {:?}", file),
+        }
+        let mut reverted = 5;
+        while executor.revert().is_some() {
+            reverted += 1;
+        }
+        assert_eq!(reverted, steps);
+        assert_eq!(executor.state().priv_state.open_file(3), None);
+    }
+
+    /// Tests custom syscall handlers, which override built-in syscalls and can be reverted.
+    #[test]
+    fn test_custom_syscall() {
+        let code = "
+            addi a0, sp, -16
+            li a1, 0x11223344
+            li a7, 500
+            ecall
+            mv s0, a0
+            li a0, 1
+            li a7, 64
+            ecall
+            ";
+        let program = code
+            .parse::<Program<Rv32>>()
+            .unwrap()
+            // stores a word, and returns it along with the sum of its bytes
+            .with_syscall_handler(500, |state, args| {
+                let value = args.unsigned(1) as u32;
+                let mut diffs = state
+                    .memory_set_bytes(args.addr(0), &value.to_le_bytes())
+                    .map_err(|_| errno::EFAULT)?;
+                let sum: u32 = value.to_le_bytes().iter().map(|&b| b as u32).sum();
+                diffs.push(state.regfile_update(A1, sum.into()));
+                Ok(SyscallReturn::new(diffs, value.into()))
+            })
+            .with_syscall_handler(64, |_, _| Err(errno::EIO));
+        let buf = ByteAddrValue::<W32b>::from(u32::from(program.state.regfile_read(Sp)) - 16);
+        let mut executor = ProgramExecutor::new(program);
+        while executor.step().is_none() {}
+        let state = executor.state();
+        assert_eq!(u32::from(state.regfile_read(S0)), 0x11223344);
+        assert_eq!(u32::from(state.regfile_read(A1)), 0xAA);
+        assert_eq!(i32::from(state.regfile_read(A0)), -errno::EIO as i32);
+        assert_eq!(state.memory_inspect_word(buf), 0x11223344u32.into());
+        // undo everything after the first ecall, then the ecall itself
+        for _ in 0..4 {
+            executor.revert();
+        }
+        assert_eq!(u32::from(executor.state().regfile_read(A1)), 0xAA);
+        executor.revert();
+        let state = executor.state();
+        assert_eq!(ByteAddrValue::from(state.regfile_read(A0)), buf);
+        assert_eq!(u32::from(state.regfile_read(A1)), 0x11223344);
+        assert_eq!(state.memory_inspect_word(buf), 0u32.into());
+    }
+
+    /// Makes sure that byte accesses running past the end of the address space wrap around to the
+    /// null address and fault, rather than overflowing.
+    #[test]
+    fn test_bytes_wrap() {
+        let mut state = ProgramState::<RiscV<W64b>, W64b>::default();
+        let last = ByteAddrValue::<W64b>::from(u64::MAX);
+        assert!(state.memory_set_bytes(last, b"ab").is_err());
+        let diffs = state.memory_set_bytes(last, b"a").unwrap();
+        state.apply_diff_stack(diffs).unwrap();
+        assert_eq!(state.memory_get_cstring(last).err(), Some(errno::EFAULT));
+    }
+
+    /// Makes sure that forking, switching between processes, and reaping a child can be reverted
+    /// and replayed.
+    #[test]
+    fn test_fork_revert() {
+        let code = "
+            li a0, 17
+            li a1, 0
+            li a7, 220
+            ecall
+            bnez a0, parent
+            li a0, 7
+            li a7, 93
+            ecall
+        parent:
+            mv s0, a0
+            addi a1, sp, -4
+            li a2, 0
+            li a7, 260
+            ecall
+            lw s1, -4(sp)
+            ";
+        let program = code.parse::<Program<Rv32>>().unwrap().with_quantum(2);
+        let start_pc = program.state.get_pc();
+        let mut executor = ProgramExecutor::new(program);
+        let mut steps = 0;
+        while executor.step().is_none() {
+            steps += 1;
+            // the parent runs two instructions after the fork before the child is switched to
+            if steps == 5 {
+                assert_eq!(executor.state().processes().len(), 2);
+                assert_eq!(executor.state().running_pid(), 2);
+            }
+        }
+        let check_end = |state: &ProgramState<RiscV<W32b>, W32b>| {
+            assert_eq!(state.running_pid(), INIT_PID);
+            assert_eq!(state.processes().len(), 1);
+            assert_eq!(u32::from(state.regfile_read(S0)), 2);
+            assert_eq!(u32::from(state.regfile_read(S1)), 7 << 8);
+        };
+        check_end(executor.state());
+        while executor.revert().is_some() {}
+        let state = executor.state();
+        assert_eq!(state.get_pc(), start_pc);
+        assert_eq!(state.processes().len(), 1);
+        assert_eq!(state.regfile_read(S1), 0u32.into());
+        while executor.step().is_none() {}
+        check_end(executor.state());
+    }
+
+    /// Makes sure that creating, writing to, and reading from a pipe, as well as duplicating its
+    /// ends, can be reverted and replayed.
+    #[test]
+    fn test_pipe_revert() {
+        let code = "
+            addi a0, sp, -8
+            li a1, 0
+            li a7, 59
+            ecall
+            li t0, 0x636261
+            sw t0, -16(sp)
+            lw a0, -4(sp)
+            addi a1, sp, -16
+            li a2, 3
+            li a7, 64
+            ecall
+            lw a0, -8(sp)
+            addi a1, sp, -12
+            li a2, 2
+            li a7, 63
+            ecall
+            lw a0, -8(sp)
+            li a7, 23
+            ecall
+            mv s0, a0
+            ";
+        let program = code.parse::<Program<Rv32>>().unwrap();
+        let mut executor = ProgramExecutor::new(program);
+        while executor.step().is_none() {}
+        let check_end = |state: &ProgramState<RiscV<W32b>, W32b>| {
+            let pipes = state.pipes();
+            assert_eq!(pipes.len(), 1);
+            assert_eq!(pipes[0].contents, b"c");
+            assert_eq!((pipes[0].readers, pipes[0].writers), (2, 1));
+            assert_eq!(u32::from(state.regfile_read(S0)), 5);
+        };
+        check_end(executor.state());
+        while executor.revert().is_some() {}
+        let state = executor.state();
+        assert!(state.pipes().is_empty());
+        assert!((3..6).all(|fd| state.priv_state.open_file(fd).is_none()));
+        while executor.step().is_none() {}
+        check_end(executor.state());
+    }
+
+    /// Makes sure that instructions run by several harts are attributed to them, and can be
+    /// reverted and replayed.
+    #[test]
+    fn test_hart_revert() {
+        // hart 0 spins for a while, so that the others exit first
+        let code = "
+            csrr a0, mhartid
+            bnez a0, store
+            li t0, 20
+        spin:
+            addi t0, t0, -1
+            bnez t0, spin
+        store:
+            addi a0, a0, 10
+            sw a0, -4(sp)
+            li a7, 93
+            ecall
+            ";
+        let program = code
+            .parse::<Program<Rv32>>()
+            .unwrap()
+            .with_harts(3, HartSchedule::Random { seed: 1 });
+        let start_pc = program.state.get_pc();
+        let mut executor = ProgramExecutor::new(program);
+        assert_eq!(executor.step_to_completion(200), Some(10));
+        // the exit of hart 0 ends the program and is not recorded, while the others halt
+        let hart_counts: Vec<usize> = (0..3)
+            .map(|id| executor.inst_harts.iter().filter(|&&h| h == id).count())
+            .collect();
+        assert_eq!(hart_counts, vec![46, 6, 6]);
+        let state = executor.state();
+        for (id, halted) in state.harts().into_iter().skip(1) {
+            assert!(halted);
+            // each hart has its own stack, on a page below that of the previous hart
+            let sp = u32::from(state.hart_user_state(id).unwrap().regfile.read(Sp));
+            let slot = ByteAddrValue::<W32b>::from(sp - 4);
+            assert_eq!(state.memory_inspect_word(slot), (10 + id).into());
+        }
+        while executor.revert().is_some() {}
+        let state = executor.state();
+        assert_eq!(state.running_hart(), 0);
+        assert_eq!(state.get_pc(), start_pc);
+        assert!(state.harts().iter().all(|&(_, halted)| !halted));
+        assert_eq!(executor.step_to_completion(200), Some(10));
+    }
+
+    /// Makes sure that reverting brk and mmap restores the mapped pages and their contents.
+    #[test]
+    fn test_mm_revert() {
+        let code = "
+            li a0, 0
+            li a7, 214
+            ecall
+            mv s0, a0
+            li t0, 0x2000
+            add a0, s0, t0
+            ecall
+            li t1, 7
+            sw t1, 0x7fc(s0)
+            mv a0, s0
+            ecall
+            li a0, 0
+            li a1, 0x1000
+            li a2, 3
+            li a3, 0x22
+            li a7, 222
+            ecall
+            sw t1, 0(a0)
+            li a1, 0x1000
+            li a7, 215
+            ecall
+            ";
+        let program = code.parse::<Program<Rv32>>().unwrap();
+        let heap_start = program.layout().heap_start;
+        let mut executor = ProgramExecutor::new(program);
+        let mut steps = 0;
+        while executor.step().is_none() {
+            steps += 1;
+        }
+        let state = executor.state();
+        assert_eq!(state.priv_state.brk.bits(), heap_start);
+        assert!(state.priv_state.mmaps.is_empty());
+        let page = ByteAddrValue::<W32b>::from(heap_start + 0x1000);
+        assert!(state.priv_state.page_table.lookup_page(page).is_err());
+        // undo munmap, which maps the page again
+        executor.revert();
+        executor.revert();
+        executor.revert();
+        let mmap_addr = executor.state().regfile_read(A0);
+        assert_eq!(executor.state().priv_state.mmaps.len(), 1);
+        assert_eq!(
+            executor.state().memory_inspect_word(mmap_addr.into()),
+            7u32.into()
+        );
+        let mut reverted = 3;
+        while executor.state().priv_state.brk.bits() == heap_start {
+            executor.revert();
+            reverted += 1;
+        }
+        // undoing the shrink of the heap restores its pages and their contents
+        assert_eq!(executor.state().priv_state.brk.bits(), heap_start + 0x2000);
+        assert_eq!(
+            executor
+                .state()
+                .memory_inspect_word((heap_start + 0x7fc).into()),
+            7u32.into()
+        );
+        while executor.revert().is_some() {
+            reverted += 1;
+        }
+        assert_eq!(reverted, steps);
+        assert!(executor
+            .state()
+            .priv_state
+            .page_table
+            .lookup_page(page)
+            .is_err());
+    }
 }
@@ -14,6 +14,15 @@ pub struct RegFile<R: IRegister, S: DataWidth> {
     _phantom: PhantomData<R>,
 }
 
+impl<R: IRegister, S: DataWidth> Clone for RegFile<R, S> {
+    fn clone(&self) -> Self {
+        RegFile {
+            store: self.store,
+            _phantom: PhantomData,
+        }
+    }
+}
+
 impl<R: IRegister, S: DataWidth> RegFile<R, S> {
     pub(in crate::program_state) fn new() -> RegFile<R, S> {
         RegFile {
@@ -0,0 +1,158 @@
+//! Builds the initial stack of a process, which holds its arguments, environment, and auxiliary
+//! vector as laid out by Linux.
+
+/// Auxiliary vector entry types.
+/// See https://github.com/torvalds/linux/blob/master/include/uapi/linux/auxvec.h.
+pub const AT_NULL: u64 = 0;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_ENTRY: u64 = 9;
+pub const AT_UID: u64 = 11;
+pub const AT_EUID: u64 = 12;
+pub const AT_GID: u64 = 13;
+pub const AT_EGID: u64 = 14;
+pub const AT_SECURE: u64 = 23;
+pub const AT_RANDOM: u64 = 25;
+
+/// The bytes AT_RANDOM points to, which libc uses to seed stack canaries. These are fixed so
+/// that runs are reproducible.
+const RANDOM_BYTES: [u8; 16] = *b"duna-not-random!";
+
+/// The initial stack of a process, as found by its entry point.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InitialStack {
+    /// The initial value of the stack pointer, which points to argc.
+    pub sp: u64,
+    /// The address of the argv array.
+    pub argv: u64,
+    /// The address of the envp array.
+    pub envp: u64,
+    /// The contents of memory from the stack pointer up to the top of the stack.
+    pub bytes: Vec<u8>,
+}
+
+impl InitialStack {
+    /// Lays out ARGS and ENV below TOP, where each address takes up WORD_BYTES.
+    ///
+    /// From the stack pointer upwards, the stack holds argc, the argv pointers, a null pointer,
+    /// the envp pointers, a null pointer, and then the auxiliary vector of (type, value) pairs
+    /// terminated by AT_NULL. The strings they point to are placed at the top of the stack, and
+    /// the stack pointer is aligned to 16 bytes.
+    pub fn new(
+        top: u64,
+        word_bytes: usize,
+        args: &[String],
+        env: &[String],
+        page_size: u64,
+        entry: u64,
+    ) -> Self {
+        let word = word_bytes as u64;
+        // the strings are placed first, starting from the top
+        let strings: Vec<u8> = args
+            .iter()
+            .chain(env)
+            .flat_map(|s| s.bytes().chain(std::iter::once(0)))
+            .collect();
+        let strings_start = top - strings.len() as u64;
+        let random_start = (strings_start - RANDOM_BYTES.len() as u64) & !(word - 1);
+        let mut string_addrs = Vec::new();
+        let mut addr = strings_start;
+        for s in args.iter().chain(env) {
+            string_addrs.push(addr);
+            addr += s.len() as u64 + 1;
+        }
+        let (arg_addrs, env_addrs) = string_addrs.split_at(args.len());
+        let auxv = [
+            (AT_PAGESZ, page_size),
+            (AT_ENTRY, entry),
+            (AT_UID, 0),
+            (AT_EUID, 0),
+            (AT_GID, 0),
+            (AT_EGID, 0),
+            (AT_SECURE, 0),
+            (AT_RANDOM, random_start),
+            (AT_NULL, 0),
+        ];
+        let mut words = vec![args.len() as u64];
+        words.extend(arg_addrs);
+        words.push(0);
+        words.extend(env_addrs);
+        words.push(0);
+        for (key, value) in &auxv {
+            words.push(*key);
+            words.push(*value);
+        }
+        let sp = (random_start - words.len() as u64 * word) & !0xF;
+        let mut bytes: Vec<u8> = words
+            .iter()
+            .flat_map(|w| w.to_le_bytes()[..word_bytes].to_vec())
+            .collect();
+        bytes.resize((random_start - sp) as usize, 0);
+        bytes.extend_from_slice(&RANDOM_BYTES);
+        bytes.resize((strings_start - sp) as usize, 0);
+        bytes.extend(strings);
+        InitialStack {
+            sp,
+            argv: sp + word,
+            envp: sp + word * (args.len() as u64 + 2),
+            bytes,
+        }
+    }
+
+    /// Reads the word at ADDR out of the stack, for tests.
+    #[cfg(test)]
+    fn word_at(&self, addr: u64, word_bytes: usize) -> u64 {
+        let offs = (addr - self.sp) as usize;
+        self.bytes[offs..offs + word_bytes]
+            .iter()
+            .rev()
+            .fold(0, |acc, &b| (acc << 8) | b as u64)
+    }
+
+    /// Reads the null-terminated string at ADDR out of the stack, for tests.
+    #[cfg(test)]
+    fn string_at(&self, addr: u64) -> String {
+        let offs = (addr - self.sp) as usize;
+        let len = self.bytes[offs..].iter().position(|&b| b == 0).unwrap();
+        String::from_utf8(self.bytes[offs..offs + len].to_vec()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initial_stack() {
+        let args = vec!["prog".to_string(), "hello".to_string()];
+        let env = vec!["HOME=/".to_string()];
+        let top = 0x7FFF_FFF0;
+        for &word in &[4, 8] {
+            let stack = InitialStack::new(top, word, &args, &env, 0x1000, 0x1000_0000);
+            assert_eq!(stack.sp % 16, 0);
+            assert_eq!(stack.sp + stack.bytes.len() as u64, top);
+            let w = word as u64;
+            assert_eq!(stack.word_at(stack.sp, word), 2);
+            assert_eq!(stack.argv, stack.sp + w);
+            assert_eq!(stack.string_at(stack.word_at(stack.argv, word)), "prog");
+            assert_eq!(
+                stack.string_at(stack.word_at(stack.argv + w, word)),
+                "hello"
+            );
+            assert_eq!(stack.word_at(stack.argv + 2 * w, word), 0);
+            assert_eq!(stack.envp, stack.argv + 3 * w);
+            assert_eq!(stack.string_at(stack.word_at(stack.envp, word)), "HOME=/");
+            assert_eq!(stack.word_at(stack.envp + w, word), 0);
+            // the auxiliary vector follows envp
+            let auxv = stack.envp + 2 * w;
+            assert_eq!(stack.word_at(auxv, word), AT_PAGESZ);
+            assert_eq!(stack.word_at(auxv + w, word), 0x1000);
+            assert_eq!(stack.word_at(auxv + 2 * w, word), AT_ENTRY);
+            assert_eq!(stack.word_at(auxv + 3 * w, word), 0x1000_0000);
+        }
+        // with no arguments, argv and envp are empty
+        let stack = InitialStack::new(top, 4, &[], &[], 0x1000, 0);
+        assert_eq!(stack.word_at(stack.sp, 4), 0);
+        assert_eq!(stack.word_at(stack.argv, 4), 0);
+        assert_eq!(stack.word_at(stack.envp, 4), 0);
+    }
+}
@@ -0,0 +1,134 @@
+//! Supplies the bytes a program reads from its standard input.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Write},
+    path::PathBuf,
+};
+
+/// Where the standard input of a program comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StdinSource {
+    /// The standard input of the simulator itself, which is read a line at a time.
+    Terminal,
+    /// A fixed sequence of bytes, after which the program reads end of file.
+    Bytes(Vec<u8>),
+    /// The contents of the file at the given path.
+    File(PathBuf),
+}
+
+impl Default for StdinSource {
+    fn default() -> Self {
+        StdinSource::Bytes(Vec::new())
+    }
+}
+
+/// The standard input of a program.
+///
+/// Bytes are taken from the source only when the program reads past what has already been
+/// taken, and every byte the program consumes is kept. Reverting a read puts its bytes back, and
+/// resetting the program puts back everything that was read, so the source is never read twice.
+pub struct Stdin {
+    source: StdinSource,
+    /// Reads from a byte buffer or file, opened on the first read. The terminal is read directly.
+    reader: RefCell<Option<Box<dyn BufRead>>>,
+    /// Bytes taken from the source that the program has yet to consume.
+    pending: RefCell<VecDeque<u8>>,
+    /// Bytes the program has consumed, in order.
+    consumed: Vec<u8>,
+}
+
+impl Stdin {
+    pub fn new(source: StdinSource) -> Self {
+        Stdin {
+            source,
+            reader: RefCell::new(None),
+            pending: RefCell::new(VecDeque::new()),
+            consumed: Vec::new(),
+        }
+    }
+
+    /// Returns up to COUNT of the next bytes of input without consuming them. If no input is
+    /// pending, this blocks until the source provides more, and returns nothing at end of file.
+    pub fn peek(&self, count: usize) -> io::Result<Vec<u8>> {
+        if count > 0 && self.pending.borrow().is_empty() {
+            self.fill()?;
+        }
+        let pending = self.pending.borrow();
+        Ok(pending.iter().take(count).copied().collect())
+    }
+
+    /// Takes the next chunk of bytes from the source and appends it to the pending input.
+    fn fill(&self) -> io::Result<()> {
+        let chunk = if self.source == StdinSource::Terminal {
+            // any prompt the program printed should be visible before blocking
+            io::stdout().flush()?;
+            // only one line is taken at a time, so that the rest remains available to the debugger
+            let mut line = Vec::new();
+            io::stdin().lock().read_until(b'\n', &mut line)?;
+            line
+        } else {
+            let mut reader = self.reader.borrow_mut();
+            if reader.is_none() {
+                *reader = Some(match &self.source {
+                    StdinSource::File(path) => Box::new(BufReader::new(File::open(path)?)),
+                    StdinSource::Bytes(bytes) => Box::new(Cursor::new(bytes.clone())),
+                    StdinSource::Terminal => unreachable!(),
+                });
+            }
+            let reader = reader.as_mut().unwrap();
+            let chunk = reader.fill_buf()?.to_vec();
+            reader.consume(chunk.len());
+            chunk
+        };
+        self.pending.borrow_mut().extend(chunk);
+        Ok(())
+    }
+
+    /// Consumes DATA, which must be the next bytes of pending input.
+    pub fn consume(&mut self, data: &[u8]) {
+        let pending = self.pending.get_mut();
+        debug_assert!(pending.iter().take(data.len()).eq(data.iter()));
+        pending.drain(..data.len());
+        self.consumed.extend(data);
+    }
+
+    /// Puts DATA, which must be the last bytes consumed, back at the front of the pending input.
+    pub fn unconsume(&mut self, data: &[u8]) {
+        let pending = self.pending.get_mut();
+        for _ in data {
+            pending.push_front(self.consumed.pop().unwrap());
+        }
+    }
+
+    /// Puts back every byte consumed so far, so that the program reads the same input again.
+    pub fn rewind(&mut self) {
+        let pending = self.pending.get_mut();
+        for byte in self.consumed.drain(..).rev() {
+            pending.push_front(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stdin() {
+        let mut stdin = Stdin::new(StdinSource::Bytes(b"hello".to_vec()));
+        assert_eq!(stdin.peek(0).unwrap(), b"");
+        assert_eq!(stdin.peek(3).unwrap(), b"hel");
+        stdin.consume(b"hel");
+        assert_eq!(stdin.peek(10).unwrap(), b"lo");
+        stdin.consume(b"lo");
+        // end of file
+        assert_eq!(stdin.peek(10).unwrap(), b"");
+        stdin.unconsume(b"lo");
+        assert_eq!(stdin.peek(10).unwrap(), b"lo");
+        stdin.rewind();
+        assert_eq!(stdin.peek(10).unwrap(), b"hello");
+    }
+}
@@ -0,0 +1,161 @@
+//! Typed arguments and results of syscalls, so that handlers never read or write registers
+//! themselves, and a registry of handlers that embedders provide at runtime.
+
+use super::{
+    program::{DiffStack, ProgramState},
+    registers::RegFile,
+};
+use crate::{arch::*, data_structures::*};
+use num_traits::cast::AsPrimitive;
+use std::{collections::HashMap, rc::Rc};
+
+/// The arguments of a syscall, in the order given by the architecture's calling convention.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyscallArgs<S: DataWidth> {
+    regs: Vec<RegValue<S>>,
+}
+
+impl<S: DataWidth> SyscallArgs<S> {
+    pub fn new(regs: Vec<RegValue<S>>) -> Self {
+        SyscallArgs { regs }
+    }
+
+    /// Reads the arguments from the registers the architecture passes them in.
+    pub fn from_regfile<F: ArchFamily<S>>(regfile: &RegFile<F::Register, S>) -> Self {
+        let arg_regs = <F::Syscalls as super::SyscallConvention<F, S>>::syscall_arg_regs();
+        SyscallArgs::new(arg_regs.into_iter().map(|reg| regfile.read(reg)).collect())
+    }
+
+    /// Returns argument N. Arguments the architecture cannot pass in registers are zero; o32
+    /// MIPS, for example, passes the fifth on the stack, and has no syscalls that need it.
+    pub fn reg(&self, n: usize) -> RegValue<S> {
+        self.regs
+            .get(n)
+            .copied()
+            .unwrap_or_else(RegValue::<S>::zero)
+    }
+
+    /// Returns argument N as a pointer.
+    pub fn addr(&self, n: usize) -> ByteAddrValue<S> {
+        self.reg(n).into()
+    }
+
+    /// Returns argument N as a signed number.
+    pub fn signed(&self, n: usize) -> i64 {
+        AsPrimitive::<i64>::as_(self.reg(n).as_signed().raw())
+    }
+
+    /// Returns argument N as an unsigned number.
+    pub fn unsigned(&self, n: usize) -> u64 {
+        self.reg(n).bits()
+    }
+
+    /// Returns argument N as a length in bytes.
+    pub fn size(&self, n: usize) -> usize {
+        AsPrimitive::<usize>::as_(self.reg(n).as_unsigned().raw())
+    }
+
+    /// Returns argument N as a file descriptor. Negative values become indices that are never
+    /// open.
+    pub fn fd(&self, n: usize) -> usize {
+        self.size(n)
+    }
+}
+
+/// What a syscall that succeeded did: the diffs that perform its effects, and the value it
+/// returns to the program.
+pub struct SyscallReturn<F: ArchFamily<S>, S: DataWidth> {
+    pub diffs: DiffStack<F, S>,
+    /// The value placed in the return register, or none if the syscall never returns, as with
+    /// exit.
+    pub value: Option<i64>,
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> SyscallReturn<F, S> {
+    pub fn new(diffs: DiffStack<F, S>, value: i64) -> Self {
+        SyscallReturn {
+            diffs,
+            value: Some(value),
+        }
+    }
+
+    /// A syscall that returns VALUE without doing anything else.
+    pub fn value(value: i64) -> Self {
+        SyscallReturn::new(Vec::new(), value)
+    }
+
+    /// A syscall that does not return to the program.
+    pub fn noreturn(diffs: DiffStack<F, S>) -> Self {
+        SyscallReturn { diffs, value: None }
+    }
+}
+
+/// The result of a syscall, which is the errno it fails with if it fails. Any diffs made before
+/// the failure are discarded, so a failed syscall changes nothing but the return register and
+/// the errno mirror.
+pub type SyscallResult<F, S> = Result<SyscallReturn<F, S>, i64>;
+
+/// A syscall handler provided at runtime. Like the built-in handlers, it describes its effects
+/// with diffs rather than applying them, so that syscalls it handles can be stepped through and
+/// reverted.
+pub type SyscallHandler<F, S> =
+    Rc<dyn Fn(&ProgramState<F, S>, &SyscallArgs<S>) -> SyscallResult<F, S>>;
+
+/// Maps syscall numbers to handlers provided at runtime, which take precedence over the
+/// syscalls built into the architecture.
+pub struct SyscallRegistry<F: ArchFamily<S>, S: DataWidth> {
+    handlers: HashMap<i64, SyscallHandler<F, S>>,
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> Default for SyscallRegistry<F, S> {
+    fn default() -> Self {
+        SyscallRegistry {
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> SyscallRegistry<F, S> {
+    /// Handles syscall NUMBER with HANDLER, returning the handler it replaces, if any.
+    pub fn register(
+        &mut self,
+        number: i64,
+        handler: SyscallHandler<F, S>,
+    ) -> Option<SyscallHandler<F, S>> {
+        self.handlers.insert(number, handler)
+    }
+
+    /// Removes the handler of syscall NUMBER, so that the built-in syscall with that number, if
+    /// any, is made instead.
+    pub fn unregister(&mut self, number: i64) -> Option<SyscallHandler<F, S>> {
+        self.handlers.remove(&number)
+    }
+
+    pub fn get(&self, number: i64) -> Option<&SyscallHandler<F, S>> {
+        self.handlers.get(&number)
+    }
+
+    /// Returns the numbers of every registered syscall in ascending order.
+    pub fn numbers(&self) -> Vec<i64> {
+        let mut numbers: Vec<i64> = self.handlers.keys().copied().collect();
+        numbers.sort_unstable();
+        numbers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syscall_args() {
+        let args = SyscallArgs::<W32b>::new(vec![DataLword::from(-100i32), 0x1000u32.into()]);
+        assert_eq!(args.signed(0), -100);
+        assert_eq!(args.unsigned(0), 0xFFFF_FF9C);
+        assert_eq!(args.fd(0), 0xFFFF_FF9C);
+        assert_eq!(args.addr(1).bits(), 0x1000);
+        assert_eq!(args.size(1), 0x1000);
+        // arguments that were not passed are zero
+        assert_eq!(args.unsigned(4), 0);
+    }
+}
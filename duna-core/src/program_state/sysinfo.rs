@@ -0,0 +1,179 @@
+//! Implements the syscalls that report information about the system: the time, random bytes, and
+//! the name of the kernel.
+//!
+//! So that every run of a program behaves the same, the time comes from a virtual clock and the
+//! random bytes from a seeded generator, both of which are restored when the program is reset.
+
+use super::{
+    errno::*,
+    priv_s::PrivDiff,
+    program::{DiffStack, ProgramState, StateDiff, SyscallConvention},
+    syscall::*,
+};
+use crate::{arch::*, data_structures::*};
+
+/// The time at which every program starts, in seconds since the Unix epoch. This is midnight UTC
+/// on January 1, 2000.
+pub const BOOT_TIME: u64 = 946_684_800;
+/// How far the virtual clock advances each time a program reads it, in nanoseconds, so that
+/// consecutive readings always differ.
+pub const CLOCK_TICK_NS: u64 = 1_000;
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// The seed of the random number generator unless another is given.
+pub const DEFAULT_SEED: u64 = 0x6475_6e61;
+
+/// Clock IDs accepted by clock_gettime.
+/// See https://github.com/torvalds/linux/blob/master/include/uapi/linux/time.h.
+pub const CLOCK_REALTIME: u64 = 0;
+pub const CLOCK_MONOTONIC: u64 = 1;
+pub const CLOCK_PROCESS_CPUTIME_ID: u64 = 2;
+pub const CLOCK_THREAD_CPUTIME_ID: u64 = 3;
+pub const CLOCK_MONOTONIC_RAW: u64 = 4;
+pub const CLOCK_REALTIME_COARSE: u64 = 5;
+pub const CLOCK_MONOTONIC_COARSE: u64 = 6;
+pub const CLOCK_BOOTTIME: u64 = 7;
+
+/// The numbers of the counters that may be read from user mode. The cycle and time counters
+/// advance along with the number of instructions retired, one for each instruction, since
+/// neither is modeled separately. The counters ending in H hold the upper 32 bits on RV32.
+pub const CYCLE: usize = 0xC00;
+pub const TIME: usize = 0xC01;
+pub const INSTRET: usize = 0xC02;
+pub const CYCLEH: usize = 0xC80;
+pub const TIMEH: usize = 0xC81;
+pub const INSTRETH: usize = 0xC82;
+
+/// The most bytes that getrandom fills in one call, as on Linux.
+const GETRANDOM_MAX: usize = 33_554_431;
+
+/// The length of each field of struct utsname.
+const UTSNAME_FIELD_LEN: usize = 65;
+
+/// Advances the state of the random number generator, returning the new state and the next 8
+/// random bytes. This is SplitMix64, which is small and passes common statistical tests.
+pub fn next_random(state: u64) -> (u64, [u8; 8]) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (state, (z ^ (z >> 31)).to_le_bytes())
+}
+
+impl<F: ArchFamily<S>, S: DataWidth> ProgramState<F, S> {
+    /// Reads the virtual clock, returning the nanoseconds since the program started along with
+    /// the diff that advances the clock.
+    fn read_clock(&self) -> (u64, StateDiff<F, S>) {
+        let now = self.priv_state.clock;
+        let tick = PrivDiff::ClockUpdate {
+            old: now,
+            new: now + CLOCK_TICK_NS,
+        };
+        (now, tick.into_state_diff())
+    }
+
+    /// Writes a struct timeval or timespec to ADDR.
+    fn set_time(
+        &self,
+        addr: ByteAddrValue<S>,
+        secs: u64,
+        frac: u64,
+    ) -> Result<DiffStack<F, S>, i64> {
+        let bytes = <F::Syscalls as SyscallConvention<F, S>>::time_bytes(secs, frac);
+        self.memory_set_bytes(addr, &bytes).map_err(|_| EFAULT)
+    }
+
+    /// Fills in the time of day in seconds and microseconds since the Unix epoch.
+    /// * tv - pointer to the struct timeval to fill in, or null
+    /// * tz - pointer to the struct timezone to fill in, or null, which is always UTC
+    pub(super) fn syscall_gettimeofday(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let (tv, tz) = (args.addr(0), args.addr(1));
+        let (now, tick) = self.read_clock();
+        let mut diffs = vec![tick];
+        if tv.bits() != 0 {
+            let secs = BOOT_TIME + now / NS_PER_SEC;
+            diffs.extend(self.set_time(tv, secs, now % NS_PER_SEC / 1000)?);
+        }
+        if tz.bits() != 0 {
+            // minutes west of Greenwich and the type of daylight saving time
+            diffs.extend(self.memory_set_bytes(tz, &[0; 8]).map_err(|_| EFAULT)?);
+        }
+        Ok(SyscallReturn::new(diffs, 0))
+    }
+
+    /// Fills in the time of a clock in seconds and nanoseconds. Clocks that measure wall time
+    /// count from the Unix epoch, and the rest count from when the program started.
+    /// * clockid - which clock to read
+    /// * tp - pointer to the struct timespec to fill in
+    pub(super) fn syscall_clock_gettime(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let start = match args.unsigned(0) {
+            CLOCK_REALTIME | CLOCK_REALTIME_COARSE => BOOT_TIME,
+            CLOCK_MONOTONIC
+            | CLOCK_PROCESS_CPUTIME_ID
+            | CLOCK_THREAD_CPUTIME_ID
+            | CLOCK_MONOTONIC_RAW
+            | CLOCK_MONOTONIC_COARSE
+            | CLOCK_BOOTTIME => 0,
+            _ => return Err(EINVAL),
+        };
+        let (now, tick) = self.read_clock();
+        let mut diffs = vec![tick];
+        let (secs, nsecs) = (start + now / NS_PER_SEC, now % NS_PER_SEC);
+        diffs.extend(self.set_time(args.addr(1), secs, nsecs)?);
+        Ok(SyscallReturn::new(diffs, 0))
+    }
+
+    /// Fills a buffer with bytes from the seeded random number generator, and returns the number
+    /// of bytes written, which is at most GETRANDOM_MAX. The flags are ignored, since the
+    /// generator never blocks.
+    /// * buf - pointer to the buffer to fill
+    /// * buflen - the number of bytes to write
+    pub(super) fn syscall_getrandom(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let (buf, len) = (args.addr(0).bits(), args.size(1).min(GETRANDOM_MAX));
+        let old = self.priv_state.rng;
+        let mut state = old;
+        let mut diffs = Vec::new();
+        // the bytes are stored as they are generated, so a bad buffer fails at its first byte
+        let mut written = 0;
+        while written < len {
+            let (next, chunk) = next_random(state);
+            state = next;
+            let chunk = &chunk[..chunk.len().min(len - written)];
+            let addr = buf.wrapping_add(written as u64).into();
+            diffs.extend(self.memory_set_bytes(addr, chunk).map_err(|_| EFAULT)?);
+            written += chunk.len();
+        }
+        diffs.push(PrivDiff::RngUpdate { old, new: state }.into_state_diff());
+        Ok(SyscallReturn::new(diffs, len as i64))
+    }
+
+    /// Fills in the name and version of the kernel, and the machine it runs on.
+    /// * buf - pointer to the struct utsname to fill in
+    pub(super) fn syscall_uname(&self, args: &SyscallArgs<S>) -> SyscallResult<F, S> {
+        let machine = <F::Syscalls as SyscallConvention<F, S>>::machine_name();
+        let fields = ["Linux", "duna", "6.1.0", "#1", machine, "(none)"];
+        let mut bytes = vec![0; fields.len() * UTSNAME_FIELD_LEN];
+        for (i, field) in fields.iter().enumerate() {
+            let start = i * UTSNAME_FIELD_LEN;
+            bytes[start..start + field.len()].copy_from_slice(field.as_bytes());
+        }
+        let diffs = self
+            .memory_set_bytes(args.addr(0), &bytes)
+            .map_err(|_| EFAULT)?;
+        Ok(SyscallReturn::new(diffs, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_random() {
+        let (state, first) = next_random(DEFAULT_SEED);
+        let (_, second) = next_random(state);
+        assert_ne!(first, second);
+        // the same seed always produces the same bytes
+        assert_eq!(next_random(DEFAULT_SEED), (state, first));
+    }
+}
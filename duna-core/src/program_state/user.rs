@@ -17,6 +17,15 @@ pub struct UserState<F: ArchFamily<S>, S: DataWidth> {
     pub regfile: RegFile<F::Register, S>,
 }
 
+impl<F: ArchFamily<S>, S: DataWidth> Clone for UserState<F, S> {
+    fn clone(&self) -> Self {
+        UserState {
+            pc: self.pc,
+            regfile: self.regfile.clone(),
+        }
+    }
+}
+
 impl<F: ArchFamily<S>, S: DataWidth> Default for UserState<F, S> {
     fn default() -> Self {
         UserState::new()
@@ -152,6 +161,13 @@ impl<F: ArchFamily<S>, S: DataWidth> UserDiff<F, S> {
         val: DataEnum,
     ) -> Result<DiffStack<F, S>, MemFault<S>> {
         let mut diffs = state.memory_set_unsized(addr, val)?;
+        let len = match val.width() {
+            DataWidthEnum::Byte => 1,
+            DataWidthEnum::Half => 2,
+            DataWidthEnum::Lword => 4,
+            DataWidthEnum::Dword => 8,
+        };
+        diffs.extend(state.release_reservations(addr.bits(), len));
         diffs.push(UserDiff::pc_p4(&state.user_state).into_state_diff());
         Ok(diffs)
     }
@@ -170,6 +186,10 @@ pub enum TrapKind<S: DataWidth> {
     Ecall,
     MemFault(MemFault<S>),
     IntOverflow,
+    /// The fetched word does not encode a supported instruction.
+    IllegalInstruction(u32),
+    /// The program counter is not aligned to an instruction boundary.
+    InstAddrMisaligned(ByteAddrValue<S>),
 }
 
 /// Converts a memory fault into a trap.
@@ -0,0 +1,339 @@
+//! An in-memory filesystem that programs access through file descriptors, which keeps them from
+//! touching the files of the host.
+
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+/// The file descriptor that refers to the current working directory in calls like openat.
+pub const AT_FDCWD: i64 = -100;
+/// The most file descriptors a process may have open at once.
+pub const MAX_FDS: usize = 1024;
+/// The largest size a file may grow to, which keeps a write far past the end of a file from
+/// allocating more memory than the host has.
+pub const MAX_FILE_SIZE: u64 = 1 << 26;
+
+pub const SEEK_SET: u64 = 0;
+pub const SEEK_CUR: u64 = 1;
+pub const SEEK_END: u64 = 2;
+
+/// File type bits of st_mode.
+pub const S_IFIFO: u32 = 0o010000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+
+/// The contents of a node in the filesystem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InodeKind {
+    File(Vec<u8>),
+    Dir,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Inode {
+    pub kind: InodeKind,
+    /// Whether the node was copied from the host, in which case it cannot be modified, and no
+    /// files can be created inside it.
+    pub readonly: bool,
+}
+
+impl Inode {
+    pub fn is_dir(&self) -> bool {
+        self.kind == InodeKind::Dir
+    }
+
+    /// Returns the contents of a file, or nothing for a directory.
+    pub fn data(&self) -> &[u8] {
+        match &self.kind {
+            InodeKind::File(data) => data,
+            InodeKind::Dir => &[],
+        }
+    }
+}
+
+/// A filesystem held entirely in memory. Paths are absolute and normalized, and the working
+/// directory of every process is the root.
+#[derive(Clone, Debug)]
+pub struct Vfs {
+    /// Maps each path to the index of its inode.
+    paths: BTreeMap<String, usize>,
+    inodes: Vec<Inode>,
+}
+
+impl Default for Vfs {
+    fn default() -> Self {
+        Vfs::new()
+    }
+}
+
+impl Vfs {
+    /// Creates a filesystem holding only an empty root directory.
+    pub fn new() -> Self {
+        let mut paths = BTreeMap::new();
+        paths.insert("/".to_string(), 0);
+        Vfs {
+            paths,
+            inodes: vec![Inode {
+                kind: InodeKind::Dir,
+                readonly: false,
+            }],
+        }
+    }
+
+    /// Adds a writable file at PATH holding DATA, creating any missing parent directories.
+    pub fn with_file(mut self, path: &str, data: &[u8]) -> Self {
+        let path = normalize("/", path);
+        self.add_parents(&path);
+        self.add(&path, InodeKind::File(data.to_vec()), false);
+        self
+    }
+
+    /// Copies the directory at HOST_DIR on the host into the filesystem at PATH. The copied files
+    /// and directories are read-only.
+    pub fn with_host_dir(mut self, host_dir: &Path, path: &str) -> io::Result<Self> {
+        let path = normalize("/", path);
+        self.add_parents(&path);
+        self.copy_host_dir(host_dir, &path)?;
+        Ok(self)
+    }
+
+    fn copy_host_dir(&mut self, host_dir: &Path, path: &str) -> io::Result<()> {
+        self.add(path, InodeKind::Dir, true);
+        for entry in fs::read_dir(host_dir)? {
+            let entry = entry?;
+            let child = join(path, &entry.file_name().to_string_lossy());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                self.copy_host_dir(&entry.path(), &child)?;
+            } else if file_type.is_file() {
+                self.add(&child, InodeKind::File(fs::read(entry.path())?), true);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_parents(&mut self, path: &str) {
+        let parent = parent(path);
+        if parent != path && !self.paths.contains_key(parent) {
+            self.add_parents(parent);
+            self.add(parent, InodeKind::Dir, false);
+        }
+    }
+
+    /// Places a new inode at PATH, replacing whatever was there.
+    fn add(&mut self, path: &str, kind: InodeKind, readonly: bool) {
+        self.paths.insert(path.to_string(), self.inodes.len());
+        self.inodes.push(Inode { kind, readonly });
+    }
+
+    /// Returns the index of the inode at PATH.
+    pub fn lookup(&self, path: &str) -> Option<usize> {
+        self.paths.get(path).copied()
+    }
+
+    pub fn inode(&self, ino: usize) -> &Inode {
+        &self.inodes[ino]
+    }
+
+    /// Returns the contents of the file at PATH.
+    pub fn read_file(&self, path: &str) -> Option<&[u8]> {
+        self.lookup(&normalize("/", path))
+            .map(|ino| &self.inodes[ino])
+            .filter(|inode| !inode.is_dir())
+            .map(Inode::data)
+    }
+
+    /// Returns the index the next inode created will have.
+    pub fn next_inode(&self) -> usize {
+        self.inodes.len()
+    }
+
+    /// Creates an empty file at PATH, which must not exist yet.
+    pub(super) fn create(&mut self, path: &str) {
+        self.add(path, InodeKind::File(Vec::new()), false);
+    }
+
+    /// Undoes the creation of the file at PATH, which must be the last inode created.
+    pub(super) fn uncreate(&mut self, path: &str) {
+        self.paths.remove(path);
+        self.inodes.pop();
+    }
+
+    /// Replaces the REMOVE_LEN bytes of a file starting at OFFSET with INSERT.
+    pub(super) fn splice(&mut self, ino: usize, offset: u64, remove_len: usize, insert: &[u8]) {
+        if let InodeKind::File(data) = &mut self.inodes[ino].kind {
+            let start = offset as usize;
+            data.splice(start..start + remove_len, insert.iter().copied());
+        }
+    }
+}
+
+/// Returns the normalized absolute path of PATH, which is relative to the directory DIR unless
+/// it begins with a slash. Since ".." at the root stays at the root, the result never escapes
+/// the filesystem.
+pub fn normalize(dir: &str, path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    let full = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        join(dir, path)
+    };
+    for part in full.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// Returns the directory containing the normalized path PATH. The root is its own parent.
+pub fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+/// A file opened by a process, which a file descriptor refers to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OpenFile {
+    Stdin,
+    Stdout,
+    Stderr,
+    /// A file or directory in the virtual filesystem.
+    Vfs {
+        path: String,
+        inode: usize,
+        /// The ID of the open file description holding where the next read or write begins,
+        /// which is shared by every file descriptor duplicated or inherited from this one.
+        description: usize,
+        readable: bool,
+        writable: bool,
+        /// Whether every write goes to the end of the file.
+        append: bool,
+    },
+    /// The end of a pipe that is read from.
+    PipeRead {
+        pipe: usize,
+        /// Whether reads fail with EAGAIN instead of blocking.
+        nonblock: bool,
+    },
+    /// The end of a pipe that is written to.
+    PipeWrite {
+        pipe: usize,
+        nonblock: bool,
+    },
+}
+
+/// The flags passed to open and openat, decoded from the architecture's encoding.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpenFlags {
+    pub readable: bool,
+    pub writable: bool,
+    pub create: bool,
+    pub excl: bool,
+    pub trunc: bool,
+    pub append: bool,
+    pub directory: bool,
+    pub nonblock: bool,
+}
+
+impl OpenFlags {
+    /// Decodes flags in the encoding shared by most architectures, including RISC-V.
+    /// See https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/fcntl.h.
+    pub fn from_generic(raw: u64) -> Self {
+        let access = raw & 0o3;
+        OpenFlags {
+            readable: access != 0o1,
+            writable: access != 0o0,
+            create: raw & 0o100 != 0,
+            excl: raw & 0o200 != 0,
+            trunc: raw & 0o1000 != 0,
+            append: raw & 0o2000 != 0,
+            directory: raw & 0o200000 != 0,
+            nonblock: raw & 0o4000 != 0,
+        }
+    }
+}
+
+/// The fields of a struct stat that are reported to programs. The rest are zero.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stat {
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub size: u64,
+    pub blksize: u32,
+    pub blocks: u64,
+}
+
+impl Stat {
+    /// Lays out the stat struct shared by most architectures, including RISC-V, for a word of
+    /// WORD_BYTES. 32-bit architectures use struct stat64, which only differs in its timestamps.
+    /// See https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/stat.h.
+    pub fn to_generic_bytes(&self, word_bytes: usize) -> Vec<u8> {
+        let mut bytes = vec![0; if word_bytes == 4 { 104 } else { 128 }];
+        let mut put = |offs: usize, field: &[u8]| {
+            bytes[offs..offs + field.len()].copy_from_slice(field);
+        };
+        put(8, &self.ino.to_le_bytes());
+        put(16, &self.mode.to_le_bytes());
+        put(20, &self.nlink.to_le_bytes());
+        put(48, &self.size.to_le_bytes());
+        put(56, &self.blksize.to_le_bytes());
+        put(64, &self.blocks.to_le_bytes());
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("/", "a/b"), "/a/b");
+        assert_eq!(normalize("/a", "./b/../c/"), "/a/c");
+        assert_eq!(normalize("/a", "/b"), "/b");
+        // paths cannot escape the root
+        assert_eq!(normalize("/", "../../etc/passwd"), "/etc/passwd");
+        assert_eq!(parent("/a/b"), "/a");
+        assert_eq!(parent("/a"), "/");
+        assert_eq!(parent("/"), "/");
+    }
+
+    #[test]
+    fn test_vfs() {
+        let mut vfs = Vfs::new().with_file("/a/b.txt", b"hello");
+        assert!(vfs.inode(vfs.lookup("/a").unwrap()).is_dir());
+        assert_eq!(vfs.read_file("a/b.txt"), Some(&b"hello"[..]));
+        let ino = vfs.lookup("/a/b.txt").unwrap();
+        vfs.splice(ino, 1, 3, b"ipp");
+        assert_eq!(vfs.read_file("/a/b.txt"), Some(&b"hippo"[..]));
+        vfs.splice(ino, 5, 0, b"s");
+        assert_eq!(vfs.read_file("/a/b.txt"), Some(&b"hippos"[..]));
+        vfs.create("/c");
+        assert_eq!(vfs.read_file("/c"), Some(&b""[..]));
+        vfs.uncreate("/c");
+        assert_eq!(vfs.lookup("/c"), None);
+    }
+
+    #[test]
+    fn test_host_dir() {
+        let host = std::env::temp_dir().join(format!("duna-vfs-{}", std::process::id()));
+        fs::create_dir_all(host.join("sub")).unwrap();
+        fs::write(host.join("sub/f"), b"data").unwrap();
+        let vfs = Vfs::new().with_host_dir(&host, "/mnt/host").unwrap();
+        fs::remove_dir_all(&host).unwrap();
+        assert_eq!(vfs.read_file("/mnt/host/sub/f"), Some(&b"data"[..]));
+        assert!(vfs.inode(vfs.lookup("/mnt/host/sub/f").unwrap()).readonly);
+        assert!(!vfs.inode(vfs.lookup("/mnt").unwrap()).readonly);
+    }
+}
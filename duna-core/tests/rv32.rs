@@ -1,7 +1,10 @@
 use duna_core::{
     architectures::riscv::{RiscVRegister, Rv32},
     assembler::{ErrorReport, Linker},
-    program_state::Program,
+    config::{AsmConfig, MachineConfig, MemConfig, PtKind},
+    program_state::{
+        next_random, HartSchedule, Program, StdinSource, Vfs, BOOT_TIME, DEFAULT_SEED,
+    },
 };
 use std::path::Path;
 
@@ -57,6 +60,13 @@ fn test_basic_mem() {
     check_a0_at_end("basic_mem.s", 0xABCD_0123u32);
 }
 
+/// Tests that a program may use more pages than there are bits in a physical page number, which
+/// once limited physical memory to that many pages.
+#[test]
+fn test_many_pages() {
+    check_a0_at_end("many_pages.s", 16);
+}
+
 /// Tests li, mv, and nop pseudo-instructions.
 #[test]
 fn test_pseudo() {
@@ -90,6 +100,56 @@ fn test_write_stdout() {
     );
 }
 
+/// Tests the read syscall by echoing standard input, which is read again after a reset.
+#[test]
+fn test_read_stdin() {
+    let input = "hello, world\n";
+    let mut program =
+        program_from_file("read_stdin.s").with_stdin(StdinSource::Bytes(input.as_bytes().to_vec()));
+    for _ in 0..2 {
+        program.run();
+        assert_eq!(
+            u32::from(program.state.regfile_read(RiscVRegister::A0)),
+            input.len() as u32
+        );
+        assert_eq!(program.state.get_stdout(), input.as_bytes());
+        program.reset();
+    }
+}
+
+/// Tests opening, reading, writing, seeking, and closing files in the virtual filesystem, along
+/// with the errnos of some calls that fail. The filesystem is restored on reset.
+#[test]
+fn test_files() {
+    let vfs = Vfs::new().with_file("/in.txt", b"hello, world");
+    let mut program = program_from_file("files.s").with_vfs(vfs);
+    for _ in 0..2 {
+        program.run();
+        assert_eq!(
+            u32::from(program.state.regfile_read(RiscVRegister::A0)),
+            1220
+        );
+        assert_eq!(
+            program.state.vfs().read_file("/out.txt"),
+            Some(&b"hJllo, world"[..])
+        );
+        program.reset();
+        assert_eq!(program.state.vfs().read_file("/out.txt"), None);
+    }
+}
+
+/// Tests that writes through a duplicated file descriptor, or one inherited by a forked child,
+/// continue from where writes through the original left off.
+#[test]
+fn test_dup_offset() {
+    let mut program = program_from_file("dup.s");
+    assert_eq!(program.run(), 0);
+    assert_eq!(
+        program.state.vfs().read_file("/dup.txt"),
+        Some(&b"abcdefghij"[..])
+    );
+}
+
 /// Tests jumping and branching to locally defined labels.
 #[test]
 fn test_local_labels() {
@@ -125,6 +185,34 @@ fn test_global_link() {
     );
 }
 
+/// Tests that a weak definition is used unless another file provides a strong one.
+#[test]
+fn test_weak_link() {
+    check_a0_at_end("weak_0.s", 1);
+    // weak_1.s is placed first so that execution ends after main
+    let mut program = Linker::with_main(&get_full_test_path("weak_1.s"))
+        .with_file(&get_full_test_path("weak_0.s"))
+        .link::<Rv32>(Default::default())
+        .unwrap();
+    program.dump_insts();
+    program.run();
+    assert_eq!(u32::from(program.state.regfile_read(RiscVRegister::A0)), 42);
+}
+
+/// Tests that unresolved labels point at the definitions that were probably intended.
+#[test]
+fn test_label_hints() {
+    let report = err_report_from_files("label_hints_0.s", vec!["label_hints_1.s"]);
+    // the error for each call is only reported once
+    assert_eq!(report.get_errs().len(), 3);
+    let report_string = format!("{:?}", report);
+    assert!(report_string.contains("similar name exists: 'helper'"));
+    assert!(report_string.contains("'local_only' is defined here, but not declared with .globl"));
+    assert!(report_string.contains("label_hints_1.s:2:0"));
+    assert!(report_string.contains("'other' is defined here, but not declared with .globl"));
+    assert!(report_string.contains("label_hints_1.s:4:0"));
+}
+
 /// Tests reporting errors in multiple linked files.
 #[test]
 fn test_link_multi_err() {
@@ -153,6 +241,8 @@ fn test_redefined_label() {
     // ensure that the error occurred on the second definition
     assert!(report_string.contains("redefined_label_1.s:3:0"));
     assert!(report_string.contains("end"));
+    // the previous definitions are also shown
+    assert!(report_string.contains("previous definition is here"));
 }
 
 /// Tests that reading from the null pointer segfaults.
@@ -162,6 +252,34 @@ fn test_npe_segfault() {
     assert_eq!(program_from_file("npe_segfault.s").run(), 11 | 0b1000_0000);
 }
 
+/// Tests that stores to read-only sections segfault.
+#[test]
+fn test_rodata_store_segfault() {
+    assert_eq!(program_from_file("rodata_store.s").run(), 11 | 0b1000_0000);
+}
+
+/// Tests that instructions are fetched from memory, so that code written at runtime is executed.
+#[test]
+fn test_exec_data() {
+    check_a0_at_end("exec_data.s", 8);
+}
+
+/// Tests that illegal instructions, misaligned jumps, and jumps to unmapped memory terminate the
+/// program with the corresponding signal.
+#[test]
+fn test_fetch_traps() {
+    for (a1, exp_code, exp_stderr) in [
+        (0u32, 4, "illegal instruction\n"),
+        (1, 10, "bus error\n"),
+        (2, 11, "Segmentation fault: 11\n"),
+    ] {
+        let mut program = program_from_file("fetch_traps.s");
+        program.state.regfile_set(RiscVRegister::A1, a1.into());
+        assert_eq!(program.run(), exp_code | 0b1000_0000);
+        assert_eq!(program.state.get_stderr(), exp_stderr.as_bytes());
+    }
+}
+
 /// Tests that reading from an unaligned word raises a bus error.
 /// Shells set the high bit on abnormal exits.
 #[test]
@@ -178,6 +296,71 @@ fn test_brk_single_page() {
     check_a0_at_end("brk_single_page.s", 0xDEAD_BEEFu32);
 }
 
+/// Tests growing and shrinking the heap with brk, under both kinds of page table.
+#[test]
+fn test_brk_grow_shrink() {
+    for (kind, exp_code) in [
+        // every page stays mapped, so the program exits normally
+        (PtKind::AllMapped, 0),
+        // the page freed by shrinking the heap can no longer be stored to
+        (PtKind::FifoLinearPaged, 11 | 0x80),
+    ] {
+        let config = AsmConfig {
+            machine: MachineConfig {
+                mem_config: MemConfig {
+                    kind,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut program: Program<Rv32> =
+            Linker::with_main(&get_full_test_path("brk_grow_shrink.s"))
+                .link::<Rv32>(config)
+                .unwrap();
+        assert_eq!(program.run(), exp_code, "{:?}", kind);
+    }
+}
+
+/// Tests mapping and unmapping anonymous pages.
+#[test]
+fn test_mmap() {
+    check_a0_at_end("mmap.s", 0x1000 + 42);
+}
+
+/// Tests that the time comes from the virtual clock and random bytes from the seeded generator.
+#[test]
+fn test_time_random() {
+    let mut program = program_from_file("time_random.s");
+    program.run();
+    let reg = |program: &Program<Rv32>, reg| u32::from(program.state.regfile_read(reg));
+    assert_eq!(reg(&program, RiscVRegister::A0), BOOT_TIME as u32);
+    assert_eq!(reg(&program, RiscVRegister::S0), 12);
+    let (state, first) = next_random(DEFAULT_SEED);
+    let (_, second) = next_random(state);
+    assert_eq!(reg(&program, RiscVRegister::S1).to_le_bytes(), first[..4]);
+    assert_eq!(reg(&program, RiscVRegister::S2).to_le_bytes(), second[..4]);
+    // -EFAULT
+    assert_eq!(reg(&program, RiscVRegister::S3) as i32, -14);
+    // resetting the program starts the generator over
+    let s1 = reg(&program, RiscVRegister::S1);
+    program.reset();
+    program.run();
+    assert_eq!(reg(&program, RiscVRegister::S1), s1);
+    // but a different seed gives different bytes
+    let mut program = program_from_file("time_random.s").with_random_seed(1);
+    program.run();
+    assert_ne!(reg(&program, RiscVRegister::S1), s1);
+}
+
+/// Tests that stores to pages mapped without PROT_WRITE fault.
+#[test]
+fn test_mmap_prot() {
+    let mut program = program_from_file("mmap_prot.s");
+    assert_eq!(program.run(), 11 | 0x80);
+}
+
 /// Tests labels for literal values declared by directive.
 #[test]
 fn test_directive_labels() {
@@ -260,6 +443,112 @@ fn test_global_main() {
     check_a0_at_end("global_main.s", 100);
 }
 
+/// Tests configuring the entry label, and that returning from it ends the program.
+#[test]
+fn test_entry() {
+    check_a0_at_end("entry.s", 100);
+    let config = AsmConfig {
+        entry: Some("start".to_string()),
+        ..Default::default()
+    };
+    let mut program = Linker::with_main(&get_full_test_path("entry.s"))
+        .link::<Rv32>(config)
+        .unwrap();
+    program.run();
+    assert_eq!(
+        u32::from(program.state.regfile_read(RiscVRegister::A0)),
+        200
+    );
+    let config = AsmConfig {
+        entry: Some("missing".to_string()),
+        ..Default::default()
+    };
+    let report = Linker::with_main(&get_full_test_path("entry.s"))
+        .link::<Rv32>(config)
+        .err()
+        .unwrap();
+    assert!(format!("{:?}", report).contains("entry label 'missing'"));
+}
+
+/// Tests that the startup routine calls main and exits with the value it returns.
+#[test]
+fn test_crt0() {
+    let config = AsmConfig {
+        crt0: true,
+        ..Default::default()
+    };
+    let mut program = Linker::with_main(&get_full_test_path("entry.s"))
+        .link::<Rv32>(config)
+        .unwrap();
+    assert_eq!(program.run(), 100);
+    // the startup routine needs a global main
+    let config = AsmConfig {
+        crt0: true,
+        ..Default::default()
+    };
+    let report = Linker::with_main_str("li a0, 1")
+        .link::<Rv32>(config)
+        .err()
+        .unwrap();
+    assert!(format!("{:?}", report).contains("label 'main' was declared but never defined"));
+}
+
+/// Tests that failed syscalls store their error number to the errno defined by the startup
+/// routine, as well as returning it negated.
+#[test]
+fn test_errno() {
+    let config = AsmConfig {
+        crt0: true,
+        ..Default::default()
+    };
+    let mut program = Linker::with_main(&get_full_test_path("errno.s"))
+        .link::<Rv32>(config)
+        .unwrap();
+    assert_eq!(program.run(), 99);
+    // without the startup routine, there is nowhere to store errno
+    let mut program = Linker::with_main_str(
+        "li a0, 99
+        li a7, 63
+        ecall",
+    )
+    .link::<Rv32>(AsmConfig::default())
+    .unwrap();
+    program.run();
+    assert_eq!(i32::from(program.state.regfile_read(RiscVRegister::A0)), -9);
+}
+
+/// Tests passing arguments and environment variables, both directly to main and through the
+/// stack to the startup routine, and that they are placed again when the program is reset.
+#[test]
+fn test_args() {
+    let args = vec!["prog".to_string(), "4".to_string(), "x".to_string()];
+    let env = vec!["X=1".to_string()];
+    for &crt0 in &[false, true] {
+        let config = AsmConfig {
+            crt0,
+            ..Default::default()
+        };
+        let mut program = Linker::with_main(&get_full_test_path("args.s"))
+            .link::<Rv32>(config)
+            .unwrap()
+            .with_args(args.clone(), env.clone());
+        let sp = program.state.regfile_read(RiscVRegister::Sp);
+        assert_eq!(u32::from(sp) % 16, 0);
+        program.run();
+        assert_eq!(
+            u32::from(program.state.regfile_read(RiscVRegister::A0)),
+            440
+        );
+        program.reset();
+        assert_eq!(program.state.regfile_read(RiscVRegister::Sp), sp);
+        program.run();
+        assert_eq!(
+            u32::from(program.state.regfile_read(RiscVRegister::A0)),
+            440
+        );
+    }
+}
+
 /// Tests that a main label declared in data is illegal.
 #[test]
 fn test_bad_data_main() {
@@ -270,3 +559,247 @@ fn test_bad_data_main() {
     assert!(report_string.contains("bad_data_main.s:5:0"));
     assert!(report_string.contains("main"));
 }
+
+/// Tests macros, including parameters with defaults, \@ labels, .rept, and .irp.
+#[test]
+fn test_macros() {
+    check_a0_at_end("macros.s", 42);
+}
+
+/// Tests that an error within a macro expansion reports both the macro body and the call site.
+#[test]
+fn test_macro_err() {
+    let report = err_report_from_files("macro_err.s", vec![]);
+    let errs = report.get_errs();
+    assert_eq!(errs.len(), 1);
+    let report_string = format!("{:?}", report);
+    assert!(report_string.contains("macro_err.s:3:"));
+    assert!(report_string.contains("macro_err.s:6:0"));
+    assert!(report_string.contains("set_reg a0, 0xggg"));
+}
+
+/// Tests .include and .incbin with paths relative to the including file.
+#[test]
+fn test_include() {
+    check_a0_at_end("include_main.s", 0xDEAD_BEEF);
+}
+
+/// Tests alignment and space directives in the data and text sections, including alignment
+/// within a file that is linked after another.
+#[test]
+fn test_align_directives() {
+    let mut program = Linker::with_main(&get_full_test_path("align_0.s"))
+        .with_file(&get_full_test_path("align_1.s"))
+        .link::<Rv32>(Default::default())
+        .unwrap();
+    program.dump_insts();
+    program.run();
+    assert_eq!(
+        u32::from(program.state.regfile_read(RiscVRegister::A0)),
+        0x1122_E065
+    );
+}
+
+/// Tests the layout of the rodata, data, and bss sections.
+#[test]
+fn test_sections() {
+    let program = program_from_file("sections.s");
+    let layout = *program.layout();
+    println!("{}", layout);
+    assert_eq!(layout.text.start, 0x1000_0000);
+    assert_eq!(layout.rodata.start, 0x1000_1000);
+    assert_eq!(layout.data.start, 0x2000_0000);
+    assert_eq!(layout.bss.start, 0x2000_1000);
+    assert_eq!(layout.bss.len, 24);
+    assert_eq!(layout.heap_start, 0x2000_2000);
+    check_a0_at_end("sections.s", 0x1234_5679);
+}
+
+/// Tests that local data labels are resolved within their own file when linking.
+#[test]
+fn test_local_data_link() {
+    let mut program = Linker::with_main(&get_full_test_path("local_data_link_0.s"))
+        .with_file(&get_full_test_path("local_data_link_1.s"))
+        .link::<Rv32>(Default::default())
+        .unwrap();
+    program.dump_insts();
+    program.run();
+    assert_eq!(
+        u32::from(program.state.regfile_read(RiscVRegister::A0)),
+        0x2222
+    );
+}
+
+/// Tests numeric local labels, and multiple labels on the same line.
+#[test]
+fn test_numeric_labels() {
+    check_a0_at_end("numeric_labels.s", 122);
+}
+
+/// Tests pseudo-instructions and directives emitted by GCC.
+#[test]
+fn test_gcc_syntax() {
+    check_a0_at_end("gcc_syntax.s", 0x3F);
+}
+
+/// Tests %hi and %lo, and instructions from the M and Zicsr extensions emitted by GCC.
+#[test]
+fn test_gcc_medlow() {
+    check_a0_at_end("gcc_medlow.s", 0x3F);
+}
+
+/// Tests that ebreak ends the program as though by SIGTRAP.
+#[test]
+fn test_ebreak() {
+    let mut program = program_from_file("ebreak.s");
+    assert_eq!(program.run(), 5 | 0b1000_0000);
+    assert_eq!(program.state.get_stderr(), b"trace/breakpoint trap\n");
+    assert_eq!(u32::from(program.state.regfile_read(RiscVRegister::A0)), 1);
+}
+
+/// Tests that errors within an included file are attributed to that file.
+#[test]
+fn test_include_err() {
+    let report = err_report_from_files("include_err.s", vec![]);
+    let errs = report.get_errs();
+    assert_eq!(errs.len(), 2);
+    let report_string = format!("{:?}", report);
+    assert!(report_string.contains("include/bad.s:3:0"));
+    assert!(report_string.contains("include/missing.s"));
+    assert!(report_string.contains("include_err.s:3:0"));
+}
+
+/// Tests that a file including itself is reported rather than expanded forever.
+#[test]
+fn test_include_cycle() {
+    let report = err_report_from_files("include_cycle.s", vec![]);
+    assert_eq!(report.get_errs().len(), 1);
+    let report_string = format!("{:?}", report);
+    assert!(report_string.contains("circular"));
+    assert!(report_string.contains("include/cycle.s:1:0"));
+}
+
+/// Tests conditional assembly with .equ constants and built-in symbols.
+#[test]
+fn test_conditional() {
+    check_a0_at_end("conditional.s", 34);
+}
+
+/// Tests that mismatched conditional blocks are reported.
+#[test]
+fn test_conditional_err() {
+    let report = err_report_from_files("conditional_err.s", vec![]);
+    assert_eq!(report.get_errs().len(), 2);
+    let report_string = format!("{:?}", report);
+    assert!(report_string.contains("conditional_err.s:3:0"));
+    assert!(report_string.contains("conditional_err.s:4:0"));
+}
+
+/// Tests that the text section disassembles to the instructions that were assembled, with the
+/// labels that point to them.
+#[test]
+fn test_disassemble() {
+    let program: Program<Rv32> = Linker::with_main_str(
+        "
+        .data
+        value: .word 0xFFFFFFFF
+        .text
+        main:
+            la a0, value
+        loop:
+            addi a1, a1, -1
+            bnez a1, loop
+            lw a0, 0(a0)
+        ",
+    )
+    .link::<Rv32>(Default::default())
+    .unwrap();
+    let text = program.layout().text;
+    let start = text.start;
+    let listing = program.disassemble(start, text.end());
+    println!("{}", listing);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines.len(), 7);
+    assert_eq!(lines[0], "<main>:");
+    assert_eq!(lines[3], "<loop>:");
+    assert!(lines[4].starts_with(&format!("{:#010x}:  fff58593  addi a1, a1, -1", start + 8)));
+    assert!(lines[5].ends_with("bne a1, zero, -4"));
+    for (line, inst) in lines
+        .iter()
+        .filter(|line| !line.starts_with('<'))
+        .zip(program.insts())
+    {
+        assert!(line.ends_with(&inst.to_string()));
+    }
+    // Data is not made of instructions
+    let data = program.layout().data.start;
+    assert_eq!(
+        program.disassemble(data, data + 4),
+        format!(
+            "<value>:\n{:#010x}:  ffffffff  <illegal instruction 0xffffffff>\n",
+            data
+        )
+    );
+}
+
+/// Tests that forked children have their own copy of memory, and that their parent can wait for
+/// them to exit or be killed.
+#[test]
+fn test_fork_wait() {
+    // 5 + 10 + ppid of 1, plus SIGSEGV, plus the parent's unchanged 5
+    check_a0_at_end("fork.s", 16 + 11 + 5);
+}
+
+/// Tests that a child can write to a pipe that its parent reads until the end of the file, and
+/// that a program blocked on a pipe nobody else can unblock is killed.
+#[test]
+fn test_pipe() {
+    let mut program = program_from_file("pipe.s");
+    // the length of "hello, pipe\n" plus 'h'
+    assert_eq!(program.run(), 12 + 104);
+    assert!(program.state.get_stdout().is_empty());
+    assert!(program
+        .state
+        .pipes()
+        .iter()
+        .all(|pipe| pipe.contents.is_empty()));
+    assert_eq!(program_from_file("pipe_deadlock.s").run(), 9 | 0b1000_0000);
+}
+
+/// Tests that harts share memory but not registers, and that interleaving them loses updates to
+/// a shared counter in a way that is the same on every run.
+#[test]
+fn test_harts() {
+    let counter_with = |schedule| {
+        let mut program = program_from_file("harts.s").with_harts(2, schedule);
+        program.run();
+        u32::from(program.state.regfile_read(RiscVRegister::A0))
+    };
+    // in lockstep, every increment by one hart overwrites that of the other
+    assert_eq!(counter_with(HartSchedule::RoundRobin), 100);
+    let random = counter_with(HartSchedule::Random { seed: 7 });
+    assert!((100..200).contains(&random));
+    assert_eq!(counter_with(HartSchedule::Random { seed: 7 }), random);
+}
+
+/// Tests that harts incrementing a shared counter with amoadd.w, or with lr.w and sc.w, never lose
+/// an update, however they are interleaved.
+#[test]
+fn test_harts_atomic() {
+    for file in &["harts_amo.s", "harts_lr_sc.s"] {
+        for schedule in &[HartSchedule::RoundRobin, HartSchedule::Random { seed: 7 }] {
+            let mut program = program_from_file(file).with_harts(2, *schedule);
+            program.run();
+            let counter = u32::from(program.state.regfile_read(RiscVRegister::A0));
+            assert_eq!(counter, 200, "{}", file);
+        }
+    }
+}
+
+/// Tests that a hart that overflows its stack faults on the guard page below it.
+#[test]
+fn test_hart_stack_guard() {
+    let mut program = program_from_file("hart_stack.s").with_harts(3, HartSchedule::RoundRobin);
+    // SIGSEGV
+    assert_eq!(program.run(), 11 | 0b1000_0000);
+}
@@ -0,0 +1,53 @@
+# Tests the .align, .p2align, .balign, .space, .skip, and .fill directives.
+# Some labels are declared in align_1.s.
+# Each check adds either a loaded value or the misaligned bits of an address to a0,
+# which should hold 0x1122_E065 on completion.
+.globl far_word
+.data
+    first: .byte 1
+    .balign 8
+    second: .byte 2
+    .space 3, 0xFF
+    .p2align 2
+    third: .word 0x11223344
+    .fill 2, 2, 0xABCD
+    .byte 3
+    # needs 3 bytes of padding, so this is skipped
+    .p2align 3,,2
+    fourth: .byte 4
+    .align 3
+    fifth: .skip 8
+.text
+    li a0, 0
+    la t0, second
+    andi t1, t0, 7
+    add a0, a0, t1
+    la t0, first
+    lbu t1, 1(t0)
+    add a0, a0, t1
+    lbu t1, 9(t0)
+    add a0, a0, t1
+    la t0, third
+    lw t1, 0(t0)
+    add a0, a0, t1
+    lhu t1, 6(t0)
+    add a0, a0, t1
+    la t0, fourth
+    lbu t1, 0(t0)
+    addi t1, t1, -4
+    add a0, a0, t1
+    la t0, fifth
+    andi t1, t0, 7
+    add a0, a0, t1
+    # pads with nops, which must be harmless to execute
+    .p2align 4
+aligned_code:
+    la t0, aligned_code
+    andi t1, t0, 15
+    add a0, a0, t1
+    .space 8
+    la t0, far_word
+    andi t1, t0, 15
+    add a0, a0, t1
+    lw t1, 0(t0)
+    add a0, a0, t1
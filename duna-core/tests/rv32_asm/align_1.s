@@ -0,0 +1,7 @@
+# Tests that alignment within a file survives linking it after another file.
+# Run from align_0.s.
+.globl far_word
+.data
+    .byte 0x77
+    .p2align 4
+    far_word: .word 0x55
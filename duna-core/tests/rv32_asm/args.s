@@ -0,0 +1,14 @@
+# Tests that arguments and environment variables are passed to main.
+# Returns argc * 100 + argv[1][0] + envp[0][0], which is 300 + '4' + 'X' = 440 for the arguments
+# ["prog", "4", "x"] and environment ["X=1"].
+.global main
+main:
+    li t0, 100
+    mul t0, a0, t0
+    lw t1, 4(a1)
+    lbu t1, 0(t1)
+    add t0, t0, t1
+    lw t1, 0(a2)
+    lbu t1, 0(t1)
+    add a0, t0, t1
+    ret
@@ -0,0 +1,33 @@
+# Grows the heap by several pages with brk, then shrinks it back to where it started.
+# Exits with 1 if brk does not return the requested break.
+    li a7, 214
+    li a0, 0
+    ecall
+    mv s0, a0
+    # Grow the heap to three pages and a word past where it started
+    li t0, 0x3004
+    add s1, s0, t0
+    mv a0, s1
+    ecall
+    bne a0, s1, fail
+    # Every page of the heap can be stored to
+    li t1, 0x1234
+    sw t1, 0(s0)
+    li t0, 0x1800
+    add t0, s0, t0
+    sw t1, 0(t0)
+    sw t1, -4(s1)
+    # Shrink the heap back, which leaves only its first page mapped
+    mv a0, s0
+    ecall
+    bne a0, s0, fail
+    sw t1, 0(s0)
+    # This faults unless every page is always mapped
+    sw t1, -4(s1)
+    li a0, 0
+    li a7, 93
+    ecall
+fail:
+    li a0, 1
+    li a7, 93
+    ecall
@@ -1,11 +1,14 @@
-# Tests that a brk syscall will page in at least the page containing the designated address.
-# Eventually, it should page in other addresses as well.
-# Set up syscall
-li s0, 0x4000_0000 # Address to page in
+# Tests that moving the program break with brk maps the page containing the new break.
+# brk(0) returns the current break without moving it
 li a7, 214
+li a0, 0
+ecall
+# Move the break a few pages up
+li t0, 0x3000
+add s0, a0, t0
 mv a0, s0
 ecall
 # Assume this doesn't segfault
 li t1, 0xDEAD_BEEF
-sw t1, 0(s0)
-lw a0, 0(s0)
\ No newline at end of file
+sw t1, -4(s0)
+lw a0, -4(s0)
@@ -0,0 +1,17 @@
+# Tests conditional assembly. This file is also assembled as RV64.
+# The final value of a0 should be __XLEN + 2.
+.equ DEBUG, 1
+.ifdef __ARCH_RV64
+    li a0, 64
+.elseif __XLEN == 32
+    li a0, 32
+.else
+    # Should never be reached
+    li a0, -1
+.endif
+.if DEBUG && (__XLEN >= 32)
+    addi a0, a0, 1
+.endif
+.ifndef RELEASE
+    addi a0, a0, DEBUG
+.endif
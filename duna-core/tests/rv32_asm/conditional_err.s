@@ -0,0 +1,5 @@
+# The .endif on line 3 has no matching .if, and the .if on line 4 is never closed.
+nop
+.endif
+.if 1
+nop
@@ -0,0 +1,70 @@
+# Tests that duplicated file descriptors, and those a forked child inherits, share the offset of
+# the file they refer to. Writes "ab" to /dup.txt, then "cd" through a duplicate, then "ef"
+# through the original, then "gh" from a child, and finally "ij" from the parent once the child
+# has exited, which leaves "abcdefghij" in the file.
+.data
+path: .string "/dup.txt"
+text: .string "abcdefghij"
+
+.text
+    # openat(AT_FDCWD, "/dup.txt", O_WRONLY | O_CREAT | O_TRUNC)
+    li a0, -100
+    la a1, path
+    li a2, 0x241
+    li a7, 56
+    ecall
+    mv s0, a0
+    la s2, text
+    # write(fd, "ab", 2)
+    mv a1, s2
+    li a2, 2
+    li a7, 64
+    ecall
+    # dup(fd)
+    mv a0, s0
+    li a7, 23
+    ecall
+    mv s1, a0
+    # write(dup, "cd", 2)
+    addi a1, s2, 2
+    li a2, 2
+    li a7, 64
+    ecall
+    # write(fd, "ef", 2)
+    mv a0, s0
+    addi a1, s2, 4
+    li a2, 2
+    li a7, 64
+    ecall
+    # clone(SIGCHLD, 0)
+    li a0, 17
+    li a1, 0
+    li a7, 220
+    ecall
+    bnez a0, parent
+    # write(dup, "gh", 2) in the child, then exit(0)
+    mv a0, s1
+    addi a1, s2, 6
+    li a2, 2
+    li a7, 64
+    ecall
+    li a0, 0
+    li a7, 93
+    ecall
+parent:
+    # wait4(-1, NULL, 0, NULL)
+    li a0, -1
+    li a1, 0
+    li a2, 0
+    li a3, 0
+    li a7, 260
+    ecall
+    # write(fd, "ij", 2), then exit(0)
+    mv a0, s0
+    addi a1, s2, 8
+    li a2, 2
+    li a7, 64
+    ecall
+    li a0, 0
+    li a7, 93
+    ecall
@@ -0,0 +1,4 @@
+# Tests that a breakpoint with no debugger ends the program as SIGTRAP would.
+	li	a0, 1
+	ebreak
+	li	a0, 2
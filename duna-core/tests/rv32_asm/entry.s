@@ -0,0 +1,14 @@
+# Tests choosing where execution begins. Returning from the function execution began in should
+# end the program, rather than continue with the code that follows.
+.global main
+.global start
+
+main:
+    li a0, 100
+    ret
+
+start:
+    li a0, 200
+    ret
+
+li a0, 300
@@ -0,0 +1,21 @@
+# Reads from a file descriptor that is not open, then returns errno * 10 plus the negated
+# result of the read. A successful syscall afterward leaves errno alone.
+.globl main
+.extern errno
+main:
+    li a0, 99
+    li a1, 0
+    li a2, 4
+    li a7, 63 # read
+    ecall
+    neg s0, a0
+    li a0, 1
+    li a2, 0
+    li a7, 64 # write
+    ecall
+    la t0, errno
+    lw t1, 0(t0)
+    li t2, 10
+    mul a0, t1, t2
+    add a0, a0, s0
+    ret
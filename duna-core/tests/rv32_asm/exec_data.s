@@ -0,0 +1,13 @@
+# Tests executing instructions that were written to the data section at runtime.
+.data
+code:
+    .word 0x02A00513 # addi a0, zero, 42
+    .word 0x00008067 # jalr zero, 0(ra)
+
+.text
+main:
+    la t0, code
+    li t1, 0x00700513 # addi a0, zero, 7
+    sw t1, 0(t0)
+    jalr ra, 0(t0)
+    addi a0, a0, 1
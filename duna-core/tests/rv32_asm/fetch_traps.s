@@ -0,0 +1,18 @@
+# Tests that bad instruction fetches trap. A1 selects the kind of fetch to attempt.
+.data
+bad_inst:
+    .word 0xFFFFFFFF
+
+.text
+main:
+    beqz a1, illegal
+    li t0, 1
+    beq a1, t0, misaligned
+    # Jump to the null pointer
+    jr zero
+illegal:
+    la t0, bad_inst
+    jr t0
+misaligned:
+    la t0, main
+    jalr zero, 2(t0)
@@ -0,0 +1,86 @@
+# Tests the file syscalls on the virtual filesystem, which must hold "hello, world" in /in.txt.
+# Copies /in.txt to /out.txt, changes its second letter to J, and then makes some calls fail.
+# Returns the size of /in.txt times 100 minus the sum of the negated errnos returned, which is
+# 12 * 100 + 9 + 2 + 9 = 1220.
+.data
+in_path: .string "/in.txt"
+out_path: .string "out.txt"
+missing_path: .string "/missing"
+.bss
+buf: .space 128
+statbuf: .space 104
+seek_result: .space 8
+
+.text
+    # openat(AT_FDCWD, "/in.txt", O_RDONLY)
+    li a0, -100
+    la a1, in_path
+    li a2, 0
+    li a7, 56
+    ecall
+    mv s0, a0
+    # read(in, buf, 128)
+    la a1, buf
+    li a2, 128
+    li a7, 63
+    ecall
+    mv s1, a0
+    # fstat(in, statbuf)
+    mv a0, s0
+    la a1, statbuf
+    li a7, 80
+    ecall
+    la t0, statbuf
+    lw s2, 48(t0)
+    # openat(AT_FDCWD, "out.txt", O_WRONLY | O_CREAT | O_TRUNC)
+    li a0, -100
+    la a1, out_path
+    li a2, 0x241
+    li a7, 56
+    ecall
+    mv s3, a0
+    # write(out, buf, len)
+    la a1, buf
+    mv a2, s1
+    li a7, 64
+    ecall
+    # _llseek(out, 0, 1, &seek_result, SEEK_SET)
+    mv a0, s3
+    li a1, 0
+    li a2, 1
+    la a3, seek_result
+    li a4, 0
+    li a7, 62
+    ecall
+    # write(out, "J", 1)
+    la a1, buf
+    li t0, 0x4a
+    sb t0, 0(a1)
+    mv a0, s3
+    li a2, 1
+    li a7, 64
+    ecall
+    # close(out) twice, where the second fails with EBADF
+    mv a0, s3
+    li a7, 57
+    ecall
+    mv a0, s3
+    ecall
+    mv s4, a0
+    # openat(AT_FDCWD, "/missing", O_RDONLY) fails with ENOENT
+    li a0, -100
+    la a1, missing_path
+    li a2, 0
+    li a7, 56
+    ecall
+    add s4, s4, a0
+    # write(in, buf, 1) fails with EBADF, since it was opened read-only
+    mv a0, s0
+    la a1, buf
+    li a2, 1
+    li a7, 64
+    ecall
+    add s4, s4, a0
+    li t0, 100
+    mul a0, s2, t0
+    sub a0, a0, s4
@@ -0,0 +1,63 @@
+# Forks a child that exits with its copy of a word plus the ID of its parent, then a child that
+# segfaults. The parent waits for both, and returns the sum of the exit code, the signal, and its
+# own copy of the word, which the first child changed. Each wait4 must return the ID of the child
+# it waited for, and a final wait4 must fail with ECHILD.
+.data
+value:
+    .word 5
+.text
+.globl main
+main:
+    li a0, 17 # SIGCHLD
+    li a1, 0
+    li a7, 220 # clone
+    ecall
+    beqz a0, child_exit
+    mv s0, a0
+    li a0, 17
+    li a1, 0
+    li a7, 220
+    ecall
+    beqz a0, child_fault
+    mv s1, a0
+    # the parent blocks until the first child exits
+    mv a0, s0
+    addi a1, sp, -4
+    li a2, 0
+    li a3, 0
+    li a7, 260 # wait4
+    ecall
+    bne a0, s0, fail
+    lw t0, -4(sp)
+    srli s2, t0, 8
+    li a0, -1
+    addi a1, sp, -4
+    li a7, 260
+    ecall
+    bne a0, s1, fail
+    lw t0, -4(sp)
+    add s2, s2, t0
+    li a0, -1
+    li a7, 260
+    ecall
+    li t0, -10 # -ECHILD
+    bne a0, t0, fail
+    la t0, value
+    lw t0, 0(t0)
+    add a0, s2, t0
+    ret
+fail:
+    li a0, 1
+    ret
+child_exit:
+    la t0, value
+    lw t1, 0(t0)
+    addi t1, t1, 10
+    sw t1, 0(t0)
+    li a7, 173 # getppid
+    ecall
+    add a0, a0, t1
+    li a7, 93 # exit
+    ecall
+child_fault:
+    lw a0, 0(zero)
@@ -0,0 +1,77 @@
+# Tests instructions emitted by GCC for the medlow code model and for the M and Zicsr
+# extensions. a0 should hold 0x3F on completion.
+	.text
+	.globl	main
+main:
+	li	s0, 0
+	# absolute addresses of data
+	lui	a5, %hi(val)
+	lw	a1, %lo(val)(a5)
+	addi	a1, a1, 1
+	sw	a1, %lo(val)(a5)
+	addi	a2, a5, %lo(val)
+	lw	a2, 0(a2)
+	li	a3, 8
+	bne	a2, a3, .Lfail
+	ori	s0, s0, 1
+	# absolute addresses of instructions
+	lui	a5, %hi(add_two)
+	addi	a5, a5, %lo(add_two)
+	jalr	ra, 0(a5)
+	# upper halves of products
+	li	a1, -2
+	li	a2, 3
+	mulh	a3, a1, a2
+	li	a4, -1
+	bne	a3, a4, .Lfail
+	mulhu	a3, a1, a2
+	li	a4, 2
+	bne	a3, a4, .Lfail
+	mulhsu	a3, a1, a2
+	li	a4, -1
+	bne	a3, a4, .Lfail
+	ori	s0, s0, 4
+	# control registers
+	csrwi	0x340, 6
+	csrsi	0x340, 1
+	csrci	0x340, 2
+	csrr	a3, mscratch
+	li	a4, 5
+	bne	a3, a4, .Lfail
+	li	a1, 9
+	csrw	0x340, a1
+	csrrwi	a3, 0x340, 0
+	bne	a3, a1, .Lfail
+	ori	s0, s0, 8
+	# counters advance with every instruction
+	rdinstret	a1
+	rdcycle	a2
+	rdtime	a3
+	sub	a2, a2, a1
+	sub	a3, a3, a1
+	li	a4, 1
+	bne	a2, a4, .Lfail
+	li	a4, 2
+	bne	a3, a4, .Lfail
+	rdinstreth	a1
+	rdcycleh	a2
+	rdtimeh	a3
+	or	a1, a1, a2
+	or	a1, a1, a3
+	bnez	a1, .Lfail
+	ori	s0, s0, 0x30
+	j	.Ldone
+.Lfail:
+	li	s0, -1
+.Ldone:
+	mv	a0, s0
+	li	a7, 93
+	ecall
+
+add_two:
+	ori	s0, s0, 2
+	ret
+
+	.data
+val:
+	.word	7
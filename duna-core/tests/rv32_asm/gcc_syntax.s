@@ -0,0 +1,86 @@
+# Tests pseudo-instructions and directives emitted by GCC and objdump.
+# a0 should hold 0x3F on completion.
+	.file	"gcc_syntax.c"
+	.option nopic
+	.attribute arch, "rv32i2p0_m2p0"
+	.text
+	.align	2
+	.globl	main
+	.type	main, @function
+main:
+	.cfi_startproc
+	li	s0, 0
+	# loads and stores through labels
+	lw	a1, .LC0
+	li	a2, 4
+	sw	a2, .LC0, t0
+	lw	a2, .LC0
+	sub	a1, a1, a2
+	seqz	a3, a1
+	snez	a4, a1
+	# a3 = 0, a4 = 1
+	add	s0, s0, a4
+	li	a5, -3
+	sltz	a3, a5
+	sgtz	a4, a5
+	# a3 = 1, a4 = 0
+	slli	a3, a3, 1
+	add	s0, s0, a3
+	sgt	a4, zero, a5
+	beqz	a4, .Lfail
+	sgtu	a4, a5, zero
+	beqz	a4, .Lfail
+	sgtu	a4, zero, a5
+	bnez	a4, .Lfail
+	# branch pseudo-instructions
+	bgtz	a5, .Lfail
+	bgez	a5, .Lfail
+	blez	a5, 1f
+	j	.Lfail
+1:	bltz	a5, 1f
+	j	.Lfail
+1:	beqz	a1, .Lfail
+	bnez	a1, 1f
+	j	.Lfail
+1:	bgt	a5, zero, .Lfail
+	ble	zero, a5, .Lfail
+	bleu	zero, a5, 1f
+	j	.Lfail
+1:	bgtu	zero, a5, .Lfail
+	# calls
+	call	add_four
+	la	t1, add_eight
+	jalr	ra, 0(t1)
+	la	t1, 1f
+	jalr	ra, t1
+	j	.Lfail
+1:	fence
+	fence	rw, w
+	fence.i
+	tail	.Ldone
+.Lfail:
+	li	s0, -1
+.Ldone:
+	addi	a0, s0, 0x30
+	li	a7, 93
+	ecall
+	unimp
+	.cfi_endproc
+	.size	main, .-main
+
+	.type	add_four, @function
+add_four:
+	addi	s0, s0, 4
+	ret
+	.size	add_four, .-add_four
+
+add_eight:
+	addi	s0, s0, 8
+	jr	ra
+
+	.section	.sdata,"aw"
+	.align	2
+.LC0:
+	.word	7
+	.ident	"GCC: (GNU) 10.2.0"
+	.section	.note.GNU-stack,"",@progbits
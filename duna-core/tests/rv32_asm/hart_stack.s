@@ -0,0 +1,19 @@
+# Hart 1 stores a page below its stack pointer, past the end of its stack, which must fault
+# rather than reach the stack of hart 2. Hart 0 otherwise returns 42 after a while.
+.text
+.globl main
+main:
+    csrr s0, mhartid
+    li t0, 1
+    beq s0, t0, overflow
+    li t1, 100
+wait:
+    addi t1, t1, -1
+    bnez t1, wait
+    li a0, 42
+    ret
+overflow:
+    li t0, 4096
+    sub t0, sp, t0
+    sw zero, -4(t0)
+    ret
@@ -0,0 +1,31 @@
+# Every hart increments a shared counter 100 times without synchronizing, so updates may be lost
+# when harts interleave. Hart 0 waits for hart 1 to finish, then returns the counter.
+.data
+counter:
+    .word 0
+done:
+    .word 0
+.text
+.globl main
+main:
+    csrr s0, mhartid
+    la t0, counter
+    li t1, 100
+loop:
+    lw t2, 0(t0)
+    addi t2, t2, 1
+    sw t2, 0(t0)
+    addi t1, t1, -1
+    bnez t1, loop
+    beqz s0, wait
+    la t0, done
+    li t1, 1
+    sw t1, 0(t0)
+    ret
+wait:
+    la t0, done
+    lw t1, 0(t0)
+    beqz t1, wait
+    la t0, counter
+    lw a0, 0(t0)
+    ret
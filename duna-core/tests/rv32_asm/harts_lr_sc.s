@@ -0,0 +1,33 @@
+# Every hart increments a shared counter 100 times with lr.w and sc.w, retrying whenever the
+# other hart wrote the counter in between, so no update is lost. Hart 0 waits for hart 1 to
+# finish, then returns the counter.
+.data
+counter:
+    .word 0
+done:
+    .word 0
+.text
+.globl main
+main:
+    csrr s0, mhartid
+    la t0, counter
+    li t1, 100
+loop:
+    lr.w t2, (t0)
+    addi t2, t2, 1
+    sc.w t3, t2, (t0)
+    bnez t3, loop
+    addi t1, t1, -1
+    bnez t1, loop
+    beqz s0, wait
+    la t0, done
+    li t1, 1
+    sw t1, 0(t0)
+    ret
+wait:
+    la t0, done
+    lw t1, 0(t0)
+    beqz t1, wait
+    la t0, counter
+    lw a0, 0(t0)
+    ret
@@ -0,0 +1,3 @@
+# Has an error on line 3
+nop
+addi a0, a0
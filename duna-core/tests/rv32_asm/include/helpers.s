@@ -0,0 +1,5 @@
+# Helpers for include_main.s
+.macro add_twice reg, amt
+    addi \reg, \reg, \amt
+    addi \reg, \reg, \amt
+.endm
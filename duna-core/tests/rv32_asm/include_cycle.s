@@ -0,0 +1,2 @@
+# Includes a file that includes this one.
+.include "include/cycle.s"
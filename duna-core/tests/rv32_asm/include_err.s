@@ -0,0 +1,3 @@
+# The error should be reported in the included file.
+.include "include/bad.s"
+.include "include/missing.s"
@@ -0,0 +1,13 @@
+# Tests .include and .incbin, which are resolved relative to this file.
+# The final value of a0 should be 0xDEADBEEF.
+.include "include/helpers.s"
+.data
+blob: .incbin "include/bytes.bin", 2, 4
+.text
+la t0, blob
+lw a0, 0(t0)
+li t1, 5
+add_twice t1, 3
+# a0 = a0 + 11 - 11
+addi a0, a0, -11
+add a0, a0, t1
@@ -0,0 +1,7 @@
+# Tests hints for labels that cannot be resolved (see label_hints_1.s).
+.global other
+jal helpr
+jal local_only
+jal other
+helper:
+    nop
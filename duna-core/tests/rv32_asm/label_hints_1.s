@@ -0,0 +1,5 @@
+# Tests hints for labels that cannot be resolved (see label_hints_0.s).
+local_only:
+    nop
+other:
+    nop
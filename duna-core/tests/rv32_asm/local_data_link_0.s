@@ -0,0 +1,13 @@
+# Tests that local data labels in files after the first resolve to that file's data.
+# See local_data_link_1.s. a0 should hold 0x2222 on completion.
+.global helper
+.data
+    value: .word 0x1111
+.text
+    jal helper
+    la t0, value
+    lw t1, 0(t0)
+    add a0, a0, t1
+    # exit, rather than falling through into the helper
+    li a7, 93
+    ecall
@@ -0,0 +1,11 @@
+# Tests local data labels in a file linked after another (see local_data_link_0.s).
+# The label shares its name with a local label in the other file.
+.global helper
+.data
+    padding: .byte 0xFF
+    value: .word 0x1111
+.text
+helper:
+    la t0, value
+    lw a0, 0(t0)
+    ret
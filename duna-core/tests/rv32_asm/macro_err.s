@@ -0,0 +1,6 @@
+# The error inside the macro body should also point at the line invoking the macro.
+.macro set_reg reg, val
+    addi \reg, zero, \val
+.endm
+set_reg a0, 1
+set_reg a0, 0xggg
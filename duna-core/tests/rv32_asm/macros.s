@@ -0,0 +1,36 @@
+# Tests macros with parameters, defaults, and unique labels, along with .rept and .irp.
+# The final value of a0 should be 42
+.macro push reg, size=4
+    addi sp, sp, -\size
+    sw \reg, 0(sp)
+.endm
+.macro pop reg, size=4
+    lw \reg, 0(sp)
+    addi sp, sp, \size
+.endm
+# Adds \amt to \reg by looping, using \@ for a unique loop label
+.macro add_slowly reg, amt
+    li t0, \amt
+loop\@:
+    addi \reg, \reg, 1
+    addi t0, t0, -1
+    bne t0, zero, loop\@
+.endm
+li a0, 0
+li s0, 10
+push s0
+li s0, 0
+pop s0
+# a0 = 10
+add a0, a0, s0
+# a0 = 13
+.rept 3
+    addi a0, a0, 1
+.endr
+# a0 = 13 + 1 + 2 + 3 = 19
+.irp n, 1, 2, 3
+    addi a0, a0, \n
+.endr
+# a0 = 42
+add_slowly a0, 20
+add_slowly a0, amt=3
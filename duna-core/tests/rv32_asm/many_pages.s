@@ -0,0 +1,17 @@
+# Moves the program break up by more pages than phys_pn_bits, which must all get a physical page
+# of their own.
+li a7, 214
+li a0, 0
+ecall
+mv s0, a0
+li s1, 16
+li t1, 4096
+li t0, 0
+loop:
+add s0, s0, t1
+addi t0, t0, 1
+mv a0, s0
+ecall
+sw t0, -4(s0)
+bne t0, s1, loop
+lw a0, -4(s0)
@@ -0,0 +1,37 @@
+# Maps anonymous pages with mmap, uses them, and then unmaps them with munmap.
+# Map two pages for reading and writing, with a length that is rounded up
+    li a0, 0
+    li a1, 0x1800
+    li a2, 3 # PROT_READ | PROT_WRITE
+    li a3, 0x22 # MAP_PRIVATE | MAP_ANONYMOUS
+    li a4, -1
+    li a5, 0
+    li a7, 222
+    ecall
+    mv s0, a0
+    # The last word of the second page starts out zeroed
+    li t0, 0x1ffc
+    add t0, s0, t0
+    lw s1, 0(t0)
+    li t1, 42
+    sw t1, 0(t0)
+    lw s2, 0(t0)
+    # Unmapping both pages returns 0
+    mv a0, s0
+    li a1, 0x2000
+    li a7, 215
+    ecall
+    mv s3, a0
+    # Mappings are placed as high as possible, so a single page takes the place of the second
+    li a0, 0
+    li a1, 0x1000
+    li a2, 3
+    li a3, 0x22
+    li a7, 222
+    ecall
+    lw s4, 0(a0)
+    sub a0, a0, s0
+    add a0, a0, s1
+    add a0, a0, s2
+    add a0, a0, s3
+    add a0, a0, s4
@@ -0,0 +1,10 @@
+# Maps a read-only page with mmap, which can be loaded from but not stored to.
+    li a0, 0
+    li a1, 0x1000
+    li a2, 1 # PROT_READ
+    li a3, 0x22 # MAP_PRIVATE | MAP_ANONYMOUS
+    li a7, 222
+    ecall
+    lw t0, 0(a0)
+    # This segfaults
+    sw t0, 0(a0)
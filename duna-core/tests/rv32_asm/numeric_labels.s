@@ -0,0 +1,34 @@
+# Tests numeric local labels and multiple labels per line.
+# a0 should hold 122 on completion.
+.data
+a: b:
+c: .word 100
+.text
+    li a0, 0
+    li t0, 5
+1:
+    add a0, a0, t0
+    addi t0, t0, -1
+    bne t0, zero, 1b
+    # the loop above leaves 15 in a0
+    j 1f
+    li a0, -1
+1:  j 2f
+1:  li a0, -1
+2: first: second:
+    # all three labels point to the same word
+    la t1, a
+    la t2, b
+    bne t1, t2, 3f
+    la t2, c
+    bne t1, t2, 3f
+    lw t2, 0(t1)
+    add a0, a0, t2
+    # numeric labels can refer to data that is defined later
+    la t1, 4f
+    lw t2, 0(t1)
+    add a0, a0, t2
+3:
+    nop
+.data
+4: .word 7
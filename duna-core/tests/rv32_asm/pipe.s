@@ -0,0 +1,96 @@
+# Forks a child that moves the write end of a pipe onto stdout and prints to it. The parent reads
+# everything the child printed until the end of the file, and returns the number of bytes read
+# plus the first of them. Writing to a pipe with no readers must fail with EPIPE.
+.data
+msg:
+    .string "hello, pipe\n"
+buf:
+    .space 64
+.text
+.globl main
+main:
+    addi sp, sp, -16
+    mv a0, sp
+    li a1, 0
+    li a7, 59 # pipe2
+    ecall
+    bnez a0, fail
+    li a0, 17 # SIGCHLD
+    li a1, 0
+    li a7, 220 # clone
+    ecall
+    beqz a0, child
+    mv s0, a0
+    # the parent must close its write end, or it would never see the end of the file
+    lw a0, 4(sp)
+    li a7, 57 # close
+    ecall
+    li s1, 0
+read_loop:
+    lw a0, 0(sp)
+    la a1, buf
+    add a1, a1, s1
+    li a2, 5
+    li a7, 63 # read
+    ecall
+    bltz a0, fail
+    beqz a0, read_done
+    add s1, s1, a0
+    j read_loop
+read_done:
+    mv a0, s0
+    li a1, 0
+    li a2, 0
+    li a3, 0
+    li a7, 260 # wait4
+    ecall
+    bne a0, s0, fail
+    addi a0, sp, 8
+    li a1, 0
+    li a7, 59
+    ecall
+    lw a0, 8(sp)
+    li a7, 57
+    ecall
+    lw a0, 12(sp)
+    la a1, msg
+    li a2, 1
+    li a7, 64 # write
+    ecall
+    li t0, -32 # -EPIPE
+    bne a0, t0, fail
+    la t0, buf
+    lbu t0, 0(t0)
+    add a0, s1, t0
+    addi sp, sp, 16
+    ret
+fail:
+    li a0, 1
+    addi sp, sp, 16
+    ret
+child:
+    lw a0, 4(sp)
+    li a1, 1
+    li a2, 0
+    li a7, 24 # dup3
+    ecall
+    li t0, 1
+    bne a0, t0, child_fail
+    lw a0, 0(sp)
+    li a7, 57
+    ecall
+    lw a0, 4(sp)
+    li a7, 57
+    ecall
+    li a0, 1
+    la a1, msg
+    li a2, 12
+    li a7, 64
+    ecall
+    li a0, 0
+    li a7, 93 # exit
+    ecall
+child_fail:
+    li a0, 2
+    li a7, 93
+    ecall
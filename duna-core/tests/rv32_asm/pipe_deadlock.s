@@ -0,0 +1,16 @@
+# Reads from an empty pipe whose write end is still open, which no other process can fill.
+.text
+.globl main
+main:
+    addi sp, sp, -8
+    mv a0, sp
+    li a1, 0
+    li a7, 59 # pipe2
+    ecall
+    lw a0, 0(sp)
+    mv a1, sp
+    li a2, 1
+    li a7, 63 # read
+    ecall
+    li a0, 0
+    ret
@@ -0,0 +1,20 @@
+# Tests the read syscall by copying standard input to standard out four bytes at a time.
+# Returns the number of bytes copied.
+    addi sp, sp, -16
+    li s0, 0
+loop:
+    li a7, 63
+    li a0, 0
+    mv a1, sp
+    li a2, 4
+    ecall
+    blez a0, done
+    add s0, s0, a0
+    mv a2, a0
+    li a7, 64
+    li a0, 1
+    mv a1, sp
+    ecall
+    j loop
+done:
+    mv a0, s0
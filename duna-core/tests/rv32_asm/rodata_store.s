@@ -0,0 +1,6 @@
+# Tests that storing to the rodata section segfaults.
+.section .rodata
+    constant: .word 1
+.text
+    la t0, constant
+    sw zero, 0(t0)
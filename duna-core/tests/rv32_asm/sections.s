@@ -0,0 +1,36 @@
+# Tests the rodata, data, and bss sections, including named sections.
+# Each section is laid out on its own page, and bss is zero-initialized.
+# a0 should hold 0x1234_5679 on completion.
+.section .rodata.str1.1,"aMS",@progbits,1
+    greeting: .string "hi"
+.data
+    counter: .word 0x1234_5678
+.bss
+    zeroes: .zero 16
+.section .mybss,"aw",@nobits
+    .balign 8
+    scratch: .space 8
+.text
+    la t0, counter
+    lw a0, 0(t0)
+    # rodata comes right after text, before data
+    la t1, greeting
+    bgeu t1, t0, bad
+    lbu t2, 1(t1)
+    addi t2, t2, -0x69 # 'i'
+    add a0, a0, t2
+    # bss comes after data, and should be zeroed
+    la t1, zeroes
+    bgeu t0, t1, bad
+    lw t2, 12(t1)
+    add a0, a0, t2
+    la t1, scratch
+    sw zero, 0(t1)
+    lw t2, 4(t1)
+    addi t2, t2, 1
+    add a0, a0, t2
+    beq zero, zero, end
+bad:
+    li a0, -1
+end:
+    nop
@@ -0,0 +1,29 @@
+# Reads the time of day and takes random bytes from getrandom.
+.bss
+tv: .space 16
+rand: .space 12
+.text
+    # gettimeofday(tv, NULL)
+    la a0, tv
+    li a1, 0
+    li a7, 169
+    ecall
+    # getrandom(rand, 12, 0)
+    la a0, rand
+    li a1, 12
+    li a2, 0
+    li a7, 278
+    ecall
+    mv s0, a0
+    la t0, rand
+    lw s1, 0(t0)
+    lw s2, 8(t0)
+    # getrandom(rand, -1, 0) fails once it reaches unmapped memory
+    la a0, rand
+    li a1, -1
+    li a7, 278
+    ecall
+    mv s3, a0
+    # the low word of the seconds since the epoch
+    la t0, tv
+    lw a0, 0(t0)
@@ -0,0 +1,11 @@
+# Tests overriding a weak definition (see weak_1.s).
+.global main
+.weak handler
+main:
+    jal handler
+    j end
+handler:
+    li a0, 1
+    ret
+end:
+    nop
@@ -0,0 +1,6 @@
+# Tests overriding a weak definition (see weak_0.s).
+.extern unused
+.global handler
+handler:
+    li a0, 42
+    ret
@@ -2,9 +2,28 @@
 use duna_core::{
     architectures::riscv::{RiscVRegister, Rv64},
     assembler::Linker,
-    program_state::Program,
+    config::{AsmConfig, LiStrategy},
+    program_state::{Program, Vfs, CLOCK_TICK_NS},
 };
 
+/// Tests that the startup routine reads argc with a 64-bit load and exits with the value main
+/// returns.
+#[test]
+fn test_crt0() {
+    let code = "
+        .global main
+        main:
+            addi a0, a0, 42
+            ret
+        ";
+    let config = AsmConfig {
+        crt0: true,
+        ..Default::default()
+    };
+    let mut program: Program<Rv64> = Linker::with_main_str(code).link::<Rv64>(config).unwrap();
+    assert_eq!(program.run(), 42);
+}
+
 /// Tests basic jump instructions.
 /// This is identical to the 32-bit local_labels test, with the only difference being a different
 /// target a0 value since the "lui/addi" version of li would inappropriately sign extend.
@@ -122,3 +141,228 @@ fn test_csr_sanity() {
     assert_eq!(state.regfile_read(RiscVRegister::A0), 0x234u64.into());
     assert_eq!(state.regfile_read(RiscVRegister::A1), 0xFF4u64.into());
 }
+
+/// Tests that conditional assembly sees the 64-bit built-in symbols.
+/// This uses the same file as the 32-bit conditional test.
+#[test]
+fn test_conditional() {
+    let mut program: Program<Rv64> = Linker::with_main("tests/rv32_asm/conditional.s")
+        .link::<Rv64>(Default::default())
+        .unwrap();
+    program.run();
+    assert_eq!(u64::from(program.state.regfile_read(RiscVRegister::A0)), 66);
+}
+
+/// Tests li with constants that need more than 32 bits, both when they are built with a
+/// sequence of instructions and when they are loaded from a literal pool.
+#[test]
+fn test_li_64() {
+    let code = "
+        li a0, 0xDEAD_BEEF_CAFE_BABE
+        li a1, 0x7FFF_FFFF_FFFF_FFFF
+        li a2, 0x1_0000_0001
+        li a3, 0xDEAD_BEEF_CAFE_BABE
+        li a4, -2
+        ";
+    for &li_strategy in [LiStrategy::Sequence, LiStrategy::LiteralPool].iter() {
+        let config = AsmConfig {
+            li_strategy,
+            ..Default::default()
+        };
+        let mut program: Program<Rv64> = Linker::with_main_str(code).link::<Rv64>(config).unwrap();
+        program.run();
+        let state = &program.state;
+        assert_eq!(
+            u64::from(state.regfile_read(RiscVRegister::A0)),
+            0xDEAD_BEEF_CAFE_BABE
+        );
+        assert_eq!(
+            u64::from(state.regfile_read(RiscVRegister::A1)),
+            0x7FFF_FFFF_FFFF_FFFF
+        );
+        assert_eq!(
+            u64::from(state.regfile_read(RiscVRegister::A2)),
+            0x1_0000_0001
+        );
+        assert_eq!(
+            u64::from(state.regfile_read(RiscVRegister::A3)),
+            0xDEAD_BEEF_CAFE_BABE
+        );
+        assert_eq!(i64::from(state.regfile_read(RiscVRegister::A4)), -2);
+        let rodata_len = program.layout().rodata.len;
+        if li_strategy == LiStrategy::LiteralPool {
+            // both uses of the same constant share a pool entry, and short sequences are
+            // still used for the others
+            assert_eq!(rodata_len, 16);
+        } else {
+            assert_eq!(rodata_len, 0);
+        }
+    }
+}
+
+/// Tests li with constants that fit in 32 bits but would be negative as a 32-bit value, which
+/// must be zero-extended rather than sign-extended.
+#[test]
+fn test_li_unsigned_32() {
+    let code = "
+        li a0, 0x8000_0000
+        li a1, 0xFFFF_FFFF
+        li a2, 0x1_0000_0000
+        ";
+    for &li_strategy in [LiStrategy::Sequence, LiStrategy::LiteralPool].iter() {
+        let config = AsmConfig {
+            li_strategy,
+            ..Default::default()
+        };
+        let mut program: Program<Rv64> = Linker::with_main_str(code).link::<Rv64>(config).unwrap();
+        program.run();
+        let state = &program.state;
+        assert_eq!(
+            u64::from(state.regfile_read(RiscVRegister::A0)),
+            0x8000_0000
+        );
+        assert_eq!(
+            u64::from(state.regfile_read(RiscVRegister::A1)),
+            0xFFFF_FFFF
+        );
+        assert_eq!(
+            u64::from(state.regfile_read(RiscVRegister::A2)),
+            0x1_0000_0000
+        );
+    }
+}
+
+/// Tests clock_gettime, getpid, uname, and a syscall that does not exist.
+#[test]
+fn test_sysinfo() {
+    let code = "
+        .bss
+        ts: .space 32
+        uts: .space 390
+        .text
+        # clock_gettime(CLOCK_MONOTONIC, ts) twice
+        li a0, 1
+        la a1, ts
+        li a7, 113
+        ecall
+        ld s0, 8(a1)
+        li a0, 1
+        addi a1, a1, 16
+        ecall
+        ld s1, 8(a1)
+        li a7, 172
+        ecall
+        mv s2, a0
+        la a0, uts
+        li a7, 160
+        ecall
+        # the last character of the machine, the fifth field
+        la t0, uts
+        lbu s3, 266(t0)
+        li a7, 999
+        ecall
+        mv s4, a0
+        ";
+    let mut program: Program<Rv64> = Linker::with_main_str(code)
+        .link::<Rv64>(Default::default())
+        .unwrap();
+    program.run();
+    let reg = |reg| u64::from(program.state.regfile_read(reg)) as i64;
+    // the clock advances every time it is read
+    assert_eq!(reg(RiscVRegister::S0), 0);
+    assert_eq!(reg(RiscVRegister::S1), CLOCK_TICK_NS as i64);
+    assert_eq!(reg(RiscVRegister::S2), 1);
+    assert_eq!(reg(RiscVRegister::S3), b'4' as i64);
+    // -ENOSYS
+    assert_eq!(reg(RiscVRegister::S4), -38);
+}
+
+/// Tests lseek and fstat, whose arguments and struct fields are 64 bits wide.
+#[test]
+fn test_lseek_fstat() {
+    let code = "
+        .data
+        path: .string \"/f\"
+        .bss
+        statbuf: .space 128
+        .text
+        li a0, -100
+        la a1, path
+        li a2, 0
+        li a7, 56
+        ecall
+        mv s0, a0
+        # lseek(fd, -2, SEEK_END)
+        li a1, -2
+        li a2, 2
+        li a7, 62
+        ecall
+        mv s1, a0
+        mv a0, s0
+        la a1, statbuf
+        li a7, 80
+        ecall
+        ld s2, 48(a1)
+        # returns offset * 100 + size
+        li t0, 100
+        mul a0, s1, t0
+        add a0, a0, s2
+        ";
+    let vfs = Vfs::new().with_file("/f", b"hello");
+    let mut program: Program<Rv64> = Linker::with_main_str(code)
+        .link::<Rv64>(Default::default())
+        .unwrap()
+        .with_vfs(vfs);
+    program.run();
+    assert_eq!(
+        u64::from(program.state.regfile_read(RiscVRegister::A0)),
+        305
+    );
+}
+
+/// Tests that writes far past the end of a file, and writes of more bytes than memory holds, fail
+/// rather than exhausting the memory of the host.
+#[test]
+fn test_write_huge() {
+    let code = "
+        .data
+        path: .string \"/f\"
+        .text
+        li a0, -100
+        la a1, path
+        # O_RDWR
+        li a2, 2
+        li a7, 56
+        ecall
+        mv s0, a0
+        # lseek(fd, 1 << 40, SEEK_SET)
+        li a1, 1
+        slli a1, a1, 40
+        li a2, 0
+        li a7, 62
+        ecall
+        mv a0, s0
+        la a1, path
+        li a2, 1
+        li a7, 64
+        ecall
+        mv s1, a0
+        # write(1, sp, -1)
+        li a0, 1
+        mv a1, sp
+        li a2, -1
+        ecall
+        mv s2, a0
+        ";
+    let vfs = Vfs::new().with_file("/f", b"hello");
+    let mut program: Program<Rv64> = Linker::with_main_str(code)
+        .link::<Rv64>(Default::default())
+        .unwrap()
+        .with_vfs(vfs);
+    program.run();
+    let reg = |reg| u64::from(program.state.regfile_read(reg)) as i64;
+    // -EFBIG
+    assert_eq!(reg(RiscVRegister::S1), -27);
+    // -EFAULT
+    assert_eq!(reg(RiscVRegister::S2), -14);
+}
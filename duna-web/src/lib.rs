@@ -1,15 +1,100 @@
 mod utils;
 
 use duna_core::{
+    arch::{ArchFamily, Architecture},
     architectures::mips::Mips32,
     architectures::riscv::Rv32,
     assembler::{ErrorReport, Linker},
     config::AsmConfig,
-    data_structures::ByteAddr32,
-    program_state::ProgramExecutor,
+    data_structures::{ByteAddr32, ByteAddrValue, W32b},
+    program_state::{
+        errno, Program, ProgramExecutor, ProgramState, StdinSource, SyscallArgs, SyscallResult,
+        SyscallReturn,
+    },
 };
+use std::{collections::BTreeMap, rc::Rc};
 use wasm_bindgen::prelude::*;
 
+#[wasm_bindgen(typescript_custom_section)]
+const ECALL_RESULT: &'static str = r#"
+/** What an ecall handler did. Every field is optional. */
+interface EcallResult {
+    /** The value returned to the program, or 0. A negative value fails with that errno negated. */
+    value?: number;
+    /** Pairs of a register number and the value written to it. */
+    regs?: Uint32Array | number[];
+    /** Pairs of an address and the byte stored to it. */
+    memory?: Uint32Array | number[];
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// A JS function that handles an ecall. It is passed the first six syscall arguments, and
+    /// returns the register and memory writes it makes rather than making them itself, so that
+    /// the ecall can be stepped through and reverted.
+    #[wasm_bindgen(typescript_type = "(args: Uint32Array) => EcallResult")]
+    pub type EcallHandler;
+
+    #[wasm_bindgen(method, catch, js_name = call)]
+    fn call(this: &EcallHandler, context: &JsValue, args: Vec<u32>)
+        -> Result<EcallResult, JsValue>;
+
+    #[wasm_bindgen(typescript_type = "EcallResult")]
+    pub type EcallResult;
+
+    #[wasm_bindgen(method, getter)]
+    fn value(this: &EcallResult) -> Option<i32>;
+
+    #[wasm_bindgen(method, getter)]
+    fn regs(this: &EcallResult) -> Option<Vec<u32>>;
+
+    #[wasm_bindgen(method, getter)]
+    fn memory(this: &EcallResult) -> Option<Vec<u32>>;
+}
+
+/// Wraps HANDLER in a syscall handler for the simulator. An ecall whose handler throws fails
+/// with EIO, and one that writes a register that does not exist fails with EINVAL.
+fn js_syscall_handler<F: ArchFamily<W32b> + 'static>(
+    handler: Rc<EcallHandler>,
+) -> impl Fn(&ProgramState<F, W32b>, &SyscallArgs<W32b>) -> SyscallResult<F, W32b> {
+    move |state, args| {
+        let regs: Vec<u32> = (0..6).map(|n| args.unsigned(n) as u32).collect();
+        let result = handler.call(&JsValue::NULL, regs).map_err(|_| errno::EIO)?;
+        let mut diffs = Vec::new();
+        for pair in result.memory().unwrap_or_default().chunks_exact(2) {
+            let addr = ByteAddrValue::<W32b>::from(pair[0]);
+            let stored = state
+                .memory_set_bytes(addr, &[pair[1] as u8])
+                .map_err(|_| errno::EFAULT)?;
+            diffs.extend(stored);
+        }
+        for pair in result.regs().unwrap_or_default().chunks_exact(2) {
+            if pair[0] >= 32 {
+                return Err(errno::EINVAL);
+            }
+            diffs.push(state.regfile_update(F::Register::from(pair[0] as u8), pair[1].into()));
+        }
+        match result.value().unwrap_or(0) {
+            value if value < 0 => Err(-(value as i64)),
+            value => Ok(SyscallReturn::new(diffs, value as i64)),
+        }
+    }
+}
+
+/// Registers every handler in ECALLS with PROGRAM.
+fn with_ecalls<A: Architecture<DataWidth = W32b>>(
+    program: Program<A>,
+    ecalls: &BTreeMap<i32, Rc<EcallHandler>>,
+) -> Program<A>
+where
+    A::Family: 'static,
+{
+    ecalls.iter().fold(program, |program, (&number, handler)| {
+        program.with_syscall_handler(number as i64, js_syscall_handler(handler.clone()))
+    })
+}
+
 /// A snapshot of a paused simulation.
 #[wasm_bindgen]
 pub struct SimSnapshot {
@@ -96,15 +181,23 @@ impl Executor {
         }
     }
 
-    fn link(active_str: &str, program_text: &str) -> Result<Executor, ErrorReport> {
+    fn link(
+        active_str: &str,
+        program_text: &str,
+        stdin: &[u8],
+        ecalls: &BTreeMap<i32, Rc<EcallHandler>>,
+    ) -> Result<Executor, ErrorReport> {
         let active = ActiveArch::from_str(active_str).unwrap();
+        let stdin = StdinSource::Bytes(stdin.to_vec());
         Ok(match active {
             ActiveArch::Rv32 => Executor {
                 active,
                 rv32i: Some(
                     Linker::with_main_str(program_text)
                         .link::<Rv32>(AsmConfig::default())
-                        .map(ProgramExecutor::new)?,
+                        .map(|program| {
+                            ProgramExecutor::new(with_ecalls(program, ecalls).with_stdin(stdin))
+                        })?,
                 ),
                 mips32: None,
             },
@@ -114,7 +207,9 @@ impl Executor {
                 mips32: Some(
                     Linker::with_main_str(program_text)
                         .link::<Mips32>(AsmConfig::default())
-                        .map(ProgramExecutor::new)?,
+                        .map(|program| {
+                            ProgramExecutor::new(with_ecalls(program, ecalls).with_stdin(stdin))
+                        })?,
                 ),
             },
         })
@@ -159,6 +254,10 @@ pub struct SimState {
     active_arch_str: String,
     assemble_result: Option<Result<Executor, ErrorReport>>,
     exit_code: Option<u8>,
+    /// The input the program reads from stdin.
+    stdin: Vec<u8>,
+    /// The JS handlers of ecalls, by syscall number.
+    ecalls: BTreeMap<i32, Rc<EcallHandler>>,
 }
 
 impl Default for SimState {
@@ -175,6 +274,8 @@ impl SimState {
             active_arch_str: "rv32i".to_string(),
             assemble_result: None,
             exit_code: None,
+            stdin: Vec::new(),
+            ecalls: BTreeMap::new(),
         }
     }
 
@@ -184,7 +285,28 @@ impl SimState {
     }
 
     pub fn assemble(&mut self, program_text: &str) {
-        self.assemble_result = Some(Executor::link(&self.active_arch_str, program_text));
+        self.assemble_result = Some(Executor::link(
+            &self.active_arch_str,
+            program_text,
+            &self.stdin,
+            &self.ecalls,
+        ));
+    }
+
+    /// Sets the input read from stdin by the next program to be assembled.
+    pub fn set_stdin(&mut self, input: &str) {
+        self.stdin = input.as_bytes().to_vec();
+    }
+
+    /// Handles syscall NUMBER with HANDLER, in place of any built-in syscall with that number,
+    /// in the next program to be assembled.
+    pub fn register_ecall(&mut self, number: i32, handler: EcallHandler) {
+        self.ecalls.insert(number, Rc::new(handler));
+    }
+
+    /// Removes the handler of syscall NUMBER from the next program to be assembled.
+    pub fn unregister_ecall(&mut self, number: i32) {
+        self.ecalls.remove(&number);
     }
 
     fn executor_mut(&mut self) -> Option<&mut Executor> {